@@ -0,0 +1,274 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Embeds the FVM the way a node would: imports a state snapshot into a blockstore, constructs a
+//! machine on top of it with the node's externs, applies a message, and flushes the new state.
+//!
+//! ```text
+//! embed [options] <snapshot.car> <message.cbor>
+//!
+//! options:
+//!   --db <dir>         store the state in a RocksDB database (requires the `rocksdb` feature)
+//!   --manifest <cid>   the builtin actors manifest (by default, the one in the system actor state)
+//!   --nv <version>     the network version (default: 15)
+//!   --epoch <epoch>    the epoch to apply the message at (default: 0)
+//!   --base-fee <fee>   the base fee, in attoFIL (default: 100)
+//!   --implicit         apply the message as an implicit (system) message
+//! ```
+//!
+//! The snapshot is a CAR file whose first root is the state tree to apply the message to, and must
+//! contain the code of the builtin actors. The message is CBOR encoded, signed or not.
+
+use std::env;
+use std::path::PathBuf;
+use std::process::exit;
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm::prelude::*;
+use fvm_ipld_car::load_car;
+use fvm_shared::address::Protocol;
+use fvm_shared::blockstore::{Blockstore, MemoryBlockstore};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::crypto::signature::SECP_SIG_LEN;
+use fvm_shared::encoding::{blake2b_256, Cbor};
+use fvm_shared::message::{Message, SignedMessage};
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::version::NetworkVersion;
+
+type NodeKernel<B> = DefaultKernel<DefaultCallManager<DefaultMachine<B, NodeExterns>>>;
+
+/// A blockstore backed by a RocksDB database, keyed by CID.
+#[cfg(feature = "rocksdb")]
+struct RocksDbBlockstore(rocksdb::DB);
+
+#[cfg(feature = "rocksdb")]
+impl RocksDbBlockstore {
+    fn open(path: &std::path::Path) -> anyhow::Result<Self> {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        let db = rocksdb::DB::open(&options, path)
+            .with_context(|| format!("failed to open RocksDB database {}", path.display()))?;
+        Ok(RocksDbBlockstore(db))
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Blockstore for RocksDbBlockstore {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self.0.get(k.to_bytes())?)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        Ok(self.0.put(k.to_bytes(), block)?)
+    }
+}
+
+/// Stand-ins for the services a node provides to the FVM. A real node draws randomness from its
+/// chain and beacon, and looks up tipsets and block headers in its chain store.
+struct NodeExterns;
+
+impl Externs for NodeExterns {}
+
+impl CodePolicy for NodeExterns {}
+
+impl CircSupply for NodeExterns {}
+
+impl Rand for NodeExterns {
+    fn get_chain_randomness(
+        &self,
+        pers: DomainSeparationTag,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
+        Ok(draw_randomness(b"tickets", pers, round, entropy))
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        pers: DomainSeparationTag,
+        round: ChainEpoch,
+        entropy: &[u8],
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
+        Ok(draw_randomness(b"beacon", pers, round, entropy))
+    }
+}
+
+impl Chain for NodeExterns {
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        Err(anyhow!("no chain to look up epoch {} in", epoch))
+    }
+}
+
+impl Consensus for NodeExterns {
+    fn verify_consensus_fault(
+        &self,
+        _h1: &[u8],
+        _h2: &[u8],
+        _extra: &[u8],
+    ) -> anyhow::Result<(Option<ConsensusFault>, i64)> {
+        // No block headers to check the fault against: report that there was none.
+        Ok((None, 0))
+    }
+}
+
+/// Deterministic randomness derived from the request, like the chain's, but without a chain.
+fn draw_randomness(
+    source: &[u8],
+    pers: DomainSeparationTag,
+    round: ChainEpoch,
+    entropy: &[u8],
+) -> [u8; RANDOMNESS_LENGTH] {
+    let mut data = source.to_vec();
+    data.extend_from_slice(&(pers as i64).to_be_bytes());
+    data.extend_from_slice(&round.to_be_bytes());
+    data.extend_from_slice(entropy);
+    blake2b_256(&data)
+}
+
+struct Options {
+    db: Option<PathBuf>,
+    manifest: Option<Cid>,
+    nv: NetworkVersion,
+    epoch: ChainEpoch,
+    base_fee: u128,
+    implicit: bool,
+    snapshot: PathBuf,
+    message: PathBuf,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> anyhow::Result<Self> {
+        let mut db = None;
+        let mut manifest = None;
+        let mut nv = NetworkVersion::V15;
+        let mut epoch = 0;
+        let mut base_fee = 100;
+        let mut implicit = false;
+        let mut positional = Vec::new();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("{} needs a value", arg));
+            match arg.as_str() {
+                "--db" => db = Some(PathBuf::from(value()?)),
+                "--manifest" => manifest = Some(value()?.parse().context("invalid manifest")?),
+                "--nv" => {
+                    let v: u32 = value()?.parse().context("invalid network version")?;
+                    nv = NetworkVersion::try_from(v)
+                        .map_err(|_| anyhow!("unknown network version {}", v))?;
+                }
+                "--epoch" => epoch = value()?.parse().context("invalid epoch")?,
+                "--base-fee" => base_fee = value()?.parse().context("invalid base fee")?,
+                "--implicit" => implicit = true,
+                _ if arg.starts_with("--") => return Err(anyhow!("unknown option {}", arg)),
+                _ => positional.push(PathBuf::from(arg)),
+            }
+        }
+        let (snapshot, message) = match <[PathBuf; 2]>::try_from(positional) {
+            Ok([snapshot, message]) => (snapshot, message),
+            Err(_) => return Err(anyhow!("expected a snapshot and a message")),
+        };
+        Ok(Options {
+            db,
+            manifest,
+            nv,
+            epoch,
+            base_fee,
+            implicit,
+            snapshot,
+            message,
+        })
+    }
+}
+
+/// Decodes a message, returning it along with its size on chain.
+fn read_message(opts: &Options) -> anyhow::Result<(Message, usize)> {
+    let bytes = std::fs::read(&opts.message)
+        .with_context(|| format!("failed to read message {}", opts.message.display()))?;
+    if let Ok(signed) = SignedMessage::unmarshal_cbor(&bytes) {
+        return Ok((signed.message, bytes.len()));
+    }
+    let msg = Message::unmarshal_cbor(&bytes).map_err(|e| anyhow!("invalid message: {}", e))?;
+    let mut raw_length = bytes.len();
+    if !opts.implicit && msg.from.protocol() == Protocol::Secp256k1 {
+        // 65 bytes signature + 1 byte type + 3 bytes for field info.
+        raw_length += SECP_SIG_LEN + 4;
+    }
+    Ok((msg, raw_length))
+}
+
+fn run<B: Blockstore + 'static>(bs: B, opts: &Options) -> anyhow::Result<()> {
+    // Import the snapshot.
+    let roots = async_std::task::block_on(async {
+        let file = async_std::fs::File::open(&opts.snapshot).await?;
+        anyhow::Ok(load_car(&bs, file).await?)
+    })
+    .with_context(|| format!("failed to import {}", opts.snapshot.display()))?;
+    let state_root = *roots
+        .first()
+        .ok_or_else(|| anyhow!("the snapshot has no roots"))?;
+    println!("imported state root {}", state_root);
+
+    let (msg, raw_length) = read_message(opts)?;
+
+    // Construct the machine, as a validating node would.
+    let engine = Engine::new(&Profile::Validator.engine_config())?;
+    let machine = DefaultMachine::new(
+        Config::preset(Profile::Validator),
+        engine,
+        opts.epoch,
+        opts.base_fee.into(),
+        Default::default(),
+        opts.nv,
+        state_root,
+        (0, opts.manifest),
+        bs,
+        NodeExterns,
+    )
+    .context("failed to construct the machine")?;
+    let mut executor = DefaultExecutor::<NodeKernel<B>>::new(machine);
+
+    // Apply the message and commit the resulting state to the blockstore.
+    let apply_kind = if opts.implicit {
+        ApplyKind::Implicit
+    } else {
+        ApplyKind::Explicit
+    };
+    let ret = executor.execute_message(msg, apply_kind, raw_length)?;
+    let new_root = executor.flush()?;
+
+    println!("exit code: {}", ret.msg_receipt.exit_code as u32);
+    println!("gas used: {}", ret.msg_receipt.gas_used);
+    println!("return data: {:?}", ret.msg_receipt.return_data.bytes());
+    println!("miner tip: {}", ret.miner_tip);
+    println!("penalty: {}", ret.penalty);
+    if let Some(failure) = ret.failure_info {
+        println!("failure: {}", failure);
+    }
+    println!("new state root: {}", new_root);
+    Ok(())
+}
+
+fn main() {
+    let opts = match Options::parse(env::args().skip(1)) {
+        Ok(opts) => opts,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            eprintln!("usage: embed [options] <snapshot.car> <message.cbor>");
+            exit(2);
+        }
+    };
+
+    let result = match &opts.db {
+        #[cfg(feature = "rocksdb")]
+        Some(path) => RocksDbBlockstore::open(path).and_then(|bs| run(bs, &opts)),
+        #[cfg(not(feature = "rocksdb"))]
+        Some(_) => Err(anyhow!("--db requires the `rocksdb` feature")),
+        None => run(MemoryBlockstore::default(), &opts),
+    };
+    if let Err(e) = result {
+        eprintln!("error: {:#}", e);
+        exit(1);
+    }
+}
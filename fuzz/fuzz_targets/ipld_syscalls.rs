@@ -0,0 +1,114 @@
+//! Drives the IPLD (and hashing) syscalls with arbitrary offsets, lengths, codecs, hash functions
+//! and block IDs. Syscalls may fail, but must never trap or panic.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use fvm_fuzz::Harness;
+use libfuzzer_sys::fuzz_target;
+
+#[derive(Arbitrary, Debug)]
+enum Op {
+    Open {
+        ret: u32,
+        cid_off: u32,
+    },
+    Create {
+        ret: u32,
+        codec: u64,
+        data_off: u32,
+        data_len: u32,
+    },
+    Read {
+        ret: u32,
+        id: u32,
+        offset: u32,
+        obuf_off: u32,
+        obuf_len: u32,
+    },
+    Stat {
+        ret: u32,
+        id: u32,
+    },
+    Cid {
+        ret: u32,
+        id: u32,
+        hash_fun: u64,
+        hash_len: u32,
+        cid_off: u32,
+        cid_len: u32,
+    },
+    HashBlake2b {
+        ret: u32,
+        data_off: u32,
+        data_len: u32,
+    },
+}
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    memory: Vec<u8>,
+    ops: Vec<Op>,
+}
+
+fuzz_target!(|input: Input| {
+    let mut harness = Harness::new(&input.memory);
+    for op in input.ops {
+        // Wasm has no unsigned types; the bindings reinterpret the bits.
+        match op {
+            Op::Open { ret, cid_off } => harness.call("ipld_open", (ret as i32, cid_off as i32)),
+            Op::Create {
+                ret,
+                codec,
+                data_off,
+                data_len,
+            } => harness.call(
+                "ipld_create",
+                (ret as i32, codec as i64, data_off as i32, data_len as i32),
+            ),
+            Op::Read {
+                ret,
+                id,
+                offset,
+                obuf_off,
+                obuf_len,
+            } => harness.call(
+                "ipld_read",
+                (
+                    ret as i32,
+                    id as i32,
+                    offset as i32,
+                    obuf_off as i32,
+                    obuf_len as i32,
+                ),
+            ),
+            Op::Stat { ret, id } => harness.call("ipld_stat", (ret as i32, id as i32)),
+            Op::Cid {
+                ret,
+                id,
+                hash_fun,
+                hash_len,
+                cid_off,
+                cid_len,
+            } => harness.call(
+                "ipld_cid",
+                (
+                    ret as i32,
+                    id as i32,
+                    hash_fun as i64,
+                    hash_len as i32,
+                    cid_off as i32,
+                    cid_len as i32,
+                ),
+            ),
+            Op::HashBlake2b {
+                ret,
+                data_off,
+                data_len,
+            } => harness.call(
+                "crypto_hash_blake2b",
+                (ret as i32, data_off as i32, data_len as i32),
+            ),
+        }
+    }
+});
@@ -0,0 +1,116 @@
+//! A harness for fuzzing syscalls: a kernel over a small in-memory state, invoked through the real
+//! syscall bindings by way of a wasm module that re-exports them.
+
+use fvm::gas::Gas;
+use fvm::machine::Engine;
+use fvm::syscalls::{bind_syscalls, InvocationData, WASM_PAGE_SIZE};
+use fvm::testing::{MockCallManager, MockMachine};
+use fvm::DefaultKernel;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ErrorNumber;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::ActorID;
+use num_traits::{FromPrimitive, Zero};
+use wasmtime::{Instance, Linker, Module, Store, WasmParams};
+
+/// The actor the kernel executes as.
+const ACTOR: ActorID = 100;
+
+/// Syscalls re-exported under `<module>_<name>`, with the same signatures as their bindings: the
+/// first parameter of syscalls returning a value is the return pointer.
+const MODULE: &str = r#"
+(module
+  (import "ipld" "open" (func $ipld_open (param i32 i32) (result i32)))
+  (import "ipld" "create" (func $ipld_create (param i32 i64 i32 i32) (result i32)))
+  (import "ipld" "read" (func $ipld_read (param i32 i32 i32 i32 i32) (result i32)))
+  (import "ipld" "stat" (func $ipld_stat (param i32 i32) (result i32)))
+  (import "ipld" "cid" (func $ipld_cid (param i32 i32 i64 i32 i32 i32) (result i32)))
+  (import "crypto" "hash_blake2b" (func $crypto_hash_blake2b (param i32 i32 i32) (result i32)))
+
+  (memory (export "memory") 1 1)
+
+  (func (export "ipld_open") (param i32 i32) (result i32)
+    local.get 0 local.get 1
+    call $ipld_open)
+  (func (export "ipld_create") (param i32 i64 i32 i32) (result i32)
+    local.get 0 local.get 1 local.get 2 local.get 3
+    call $ipld_create)
+  (func (export "ipld_read") (param i32 i32 i32 i32 i32) (result i32)
+    local.get 0 local.get 1 local.get 2 local.get 3 local.get 4
+    call $ipld_read)
+  (func (export "ipld_stat") (param i32 i32) (result i32)
+    local.get 0 local.get 1
+    call $ipld_stat)
+  (func (export "ipld_cid") (param i32 i32 i64 i32 i32 i32) (result i32)
+    local.get 0 local.get 1 local.get 2 local.get 3 local.get 4 local.get 5
+    call $ipld_cid)
+  (func (export "crypto_hash_blake2b") (param i32 i32 i32) (result i32)
+    local.get 0 local.get 1 local.get 2
+    call $crypto_hash_blake2b)
+)
+"#;
+
+pub type FuzzKernel = DefaultKernel<MockCallManager>;
+
+pub struct Harness {
+    store: Store<InvocationData<FuzzKernel>>,
+    instance: Instance,
+}
+
+impl Harness {
+    /// Instantiates the syscall module over a fresh kernel, with (a prefix of) `memory` as the
+    /// initial contents of the actor's single page of memory.
+    pub fn new(memory: &[u8]) -> Self {
+        let machine = MockMachine::new(NetworkVersion::V15).expect("failed to create machine");
+        let call_manager =
+            MockCallManager::new(machine, Gas::new(i64::MAX), Address::new_id(ACTOR), 0);
+        let kernel = DefaultKernel::new(call_manager, ACTOR, ACTOR, 0, TokenAmount::zero());
+
+        let engine = Engine::default();
+        let mut linker = Linker::new(&engine);
+        bind_syscalls(&mut linker).expect("failed to bind syscalls");
+        let module = Module::new(&engine, MODULE).expect("invalid syscall module");
+
+        let mut store = Store::new(
+            &engine,
+            InvocationData {
+                kernel,
+                last_error: None,
+                memory_limit: WASM_PAGE_SIZE,
+                memory_grow_error: None,
+                syscall_trace: None,
+            },
+        );
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .expect("failed to instantiate syscall module");
+
+        let len = memory.len().min(WASM_PAGE_SIZE);
+        instance
+            .get_memory(&mut store, "memory")
+            .expect("syscall module has no memory")
+            .write(&mut store, 0, &memory[..len])
+            .expect("failed to initialize memory");
+
+        Harness { store, instance }
+    }
+
+    /// Invokes a re-exported syscall, panicking if it traps or returns an unknown error number.
+    /// Syscall errors are fine, as long as they're reported to the actor.
+    pub fn call<Params: WasmParams>(&mut self, name: &str, params: Params) {
+        let func = self
+            .instance
+            .get_typed_func::<Params, i32, _>(&mut self.store, name)
+            .unwrap_or_else(|e| panic!("no syscall {}: {}", name, e));
+        let code = func
+            .call(&mut self.store, params)
+            .unwrap_or_else(|e| panic!("syscall {} trapped: {}", name, e));
+        assert!(
+            code == 0 || ErrorNumber::from_i32(code).is_some(),
+            "syscall {} returned an unknown error number: {}",
+            name,
+            code
+        );
+    }
+}
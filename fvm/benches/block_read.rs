@@ -0,0 +1,47 @@
+//! Measures reading 1MiB blocks into actor memory: directly from the block registry, as the
+//! `ipld::read` syscall does, versus through an intermediate owned buffer.
+
+use criterion::*;
+use fvm::call_manager::CallManager;
+use fvm::gas::Gas;
+use fvm::kernel::BlockOps;
+use fvm::testing::{MockCallManager, MockMachine};
+use fvm::{DefaultKernel, Kernel};
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::encoding::DAG_CBOR;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+const ACTOR: ActorID = 100;
+const BLOCK_SIZE: usize = 1 << 20;
+
+fn bench_block_read(c: &mut Criterion) {
+    let machine = MockMachine::new(NetworkVersion::V15).unwrap();
+    let call_manager = MockCallManager::new(machine, Gas::new(i64::MAX), Address::new_id(ACTOR), 0);
+    let mut kernel = DefaultKernel::new(call_manager, ACTOR, ACTOR, 2, TokenAmount::zero());
+    let id = kernel
+        .block_create(DAG_CBOR, &vec![0xa5; BLOCK_SIZE])
+        .unwrap();
+    // Stands in for the actor's memory.
+    let mut memory = vec![0u8; BLOCK_SIZE];
+
+    let mut group = c.benchmark_group("block_read");
+    group.throughput(Throughput::Bytes(BLOCK_SIZE as u64));
+    group.bench_function("direct", |b| {
+        b.iter(|| kernel.block_read(id, 0, black_box(&mut memory)).unwrap())
+    });
+    group.bench_function("intermediate buffer", |b| {
+        b.iter(|| {
+            let mut buf = vec![0; BLOCK_SIZE];
+            let read = kernel.block_read(id, 0, &mut buf).unwrap();
+            black_box(&mut memory).copy_from_slice(&buf);
+            read
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_block_read);
+criterion_main!(benches);
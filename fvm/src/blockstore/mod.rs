@@ -1,4 +0,0 @@
-//! Private blockstores for use in the FVM.
-
-mod buffered;
-pub use buffered::BufferedBlockstore;
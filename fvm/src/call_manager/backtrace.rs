@@ -116,3 +116,47 @@ impl Display for Cause {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(source: ActorID, code: ExitCode) -> Frame {
+        Frame {
+            source,
+            method: 2,
+            params: RawBytes::default(),
+            code,
+            message: format!("actor {} aborted", source),
+        }
+    }
+
+    #[test]
+    fn frames_from_top_to_cause() {
+        let mut bt = Backtrace::default();
+        assert!(bt.is_empty());
+
+        bt.push_frame(frame(100, ExitCode::ErrNotFound));
+        bt.set_cause(Cause::new(
+            "ipld",
+            "open",
+            SyscallError::new(ErrorNumber::NotFound, "no such block"),
+        ));
+        // Setting the cause drops the frames recorded before the failing syscall.
+        assert!(bt.frames.is_empty());
+
+        bt.push_frame(frame(101, ExitCode::ErrNotFound));
+        bt.push_frame(frame(102, ExitCode::ErrIllegalArgument));
+        let lines: Vec<_> = bt.to_string().lines().map(String::from).collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("00: ") && lines[0].contains("actor 102 aborted"));
+        assert!(lines[1].starts_with("01: ") && lines[1].contains("actor 101 aborted"));
+        assert_eq!(
+            lines[2],
+            "--> caused by: ipld::open -- no such block (6: resource not found)"
+        );
+
+        bt.clear();
+        assert!(bt.is_empty());
+    }
+}
@@ -1,19 +1,27 @@
 use anyhow::Context as _;
 use derive_more::{Deref, DerefMut};
 use fvm_shared::actor::builtin::Type;
-use fvm_shared::address::{Address, Protocol};
+use fvm_shared::address::Address;
+use fvm_shared::constants::{METHOD_CONSTRUCTOR, METHOD_SEND};
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::{RawBytes, DAG_CBOR};
 use fvm_shared::error::ExitCode;
-use fvm_shared::{ActorID, MethodNum, METHOD_SEND};
+use fvm_shared::{ActorID, MethodNum};
 use num_traits::Zero;
 
-use super::{Backtrace, CallManager, InvocationResult, NO_DATA_BLOCK_ID};
+use super::{
+    Backtrace, CallManager, FinishRet, InvocationResult, RandomnessCache, ReceiverCreation,
+    NO_DATA_BLOCK_ID,
+};
 use crate::call_manager::backtrace::Frame;
-use crate::gas::GasTracker;
+use crate::gas::{Gas, GasCharge, GasTracker};
 use crate::kernel::{ClassifyResult, ExecutionError, Kernel, Result};
 use crate::machine::Machine;
 use crate::syscalls::error::Abort;
+use crate::syscalls::{charge_for_exec, refuel};
+use crate::trace::{
+    CallReturnTrace, CallTrace, ExecutionEvent, ExecutionTrace, GasChargeTrace, StateChangeTrace,
+};
 use crate::{account_actor, syscall_error};
 
 /// The default [`CallManager`] implementation.
@@ -35,10 +43,19 @@ pub struct InnerDefaultCallManager<M> {
     nonce: u64,
     /// Number of actors created in this call stack.
     num_actors_created: u64,
+    /// Number of invocations (kernels) created in this call stack.
+    num_invocations: u32,
     /// Current call-stack depth.
     call_stack_depth: u32,
     /// The current chain of errors, if any.
     backtrace: Backtrace,
+    /// The events recorded so far, if tracing is enabled.
+    exec_trace: ExecutionTrace,
+    /// The randomness drawn so far.
+    randomness_cache: RandomnessCache,
+    /// The calls in progress, timed if method stats are enabled.
+    #[cfg(feature = "method-stats")]
+    call_timer: crate::executor::CallTimer,
 }
 
 #[doc(hidden)]
@@ -63,15 +80,21 @@ where
 {
     type Machine = M;
 
-    fn new(machine: M, gas_limit: i64, origin: Address, nonce: u64) -> Self {
+    fn new(machine: M, gas_limit: Gas, origin: Address, nonce: u64) -> Self {
+        let gas_meter = machine.config().gas_meter.clone();
         DefaultCallManager(Some(InnerDefaultCallManager {
             machine,
-            gas_tracker: GasTracker::new(gas_limit, 0),
+            gas_tracker: GasTracker::with_meter(gas_limit, Gas::ZERO, gas_meter),
             origin,
             nonce,
             num_actors_created: 0,
+            num_invocations: 0,
             call_stack_depth: 0,
             backtrace: Backtrace::default(),
+            exec_trace: ExecutionTrace::new(),
+            randomness_cache: RandomnessCache::default(),
+            #[cfg(feature = "method-stats")]
+            call_timer: Default::default(),
         }))
     }
 
@@ -91,8 +114,38 @@ where
                 syscall_error!(LimitExceeded, "message execution exceeds call depth").into(),
             );
         }
+        let trace_calls = self.machine.config().trace_calls;
+
         self.call_stack_depth += 1;
+        let _span = tracing::debug_span!(
+            "send",
+            from,
+            to = %to,
+            method,
+            depth = self.call_stack_depth,
+        )
+        .entered();
+        if trace_calls {
+            self.exec_trace.push(ExecutionEvent::Call(CallTrace {
+                from,
+                to,
+                method,
+                value: value.clone(),
+                depth: self.call_stack_depth,
+                gas_available: self.gas_remaining(),
+            }));
+        }
+
         let result = self.send_unchecked::<K>(from, to, method, params, value);
+
+        if trace_calls {
+            self.exec_trace
+                .push(ExecutionEvent::CallReturn(CallReturnTrace {
+                    exit_code: result.as_ref().ok().map(InvocationResult::exit_code),
+                    depth: self.call_stack_depth,
+                    gas_available: self.gas_remaining(),
+                }));
+        }
         self.call_stack_depth -= 1;
         result
     }
@@ -110,12 +163,33 @@ where
         res
     }
 
-    fn finish(mut self) -> (i64, Backtrace, Self::Machine) {
-        let gas_used = self.gas_tracker.gas_used().max(0);
+    fn charge_gas(&mut self, charge: GasCharge) -> Result<()> {
+        let charge = self.gas_tracker.meter().meter(charge);
+        if self.machine.context().debug {
+            tracing::debug!(
+                name = charge.name,
+                compute = charge.compute_gas.as_i64(),
+                storage = charge.storage_gas.as_i64(),
+                "gas charge"
+            );
+        }
+        self.trace(ExecutionEvent::GasCharge(GasChargeTrace::from(&charge)));
+        self.gas_tracker.charge_metered(charge)
+    }
+
+    fn finish(mut self) -> (FinishRet, Self::Machine) {
+        let gas_used = self.gas_tracker.gas_used().max(Gas::ZERO);
 
         let inner = self.0.take().expect("call manager is poisoned");
         // TODO: Having to check against zero here is fishy, but this is what lotus does.
-        (gas_used, inner.backtrace, inner.machine)
+        (
+            FinishRet {
+                gas_used,
+                backtrace: inner.backtrace,
+                exec_trace: inner.exec_trace,
+            },
+            inner.machine,
+        )
     }
 
     // Accessor methods so the trait can implement some common methods by default.
@@ -153,12 +227,38 @@ where
         self.num_actors_created += 1;
         ret
     }
+
+    fn next_invocation_idx(&mut self) -> u32 {
+        let ret = self.num_invocations;
+        self.num_invocations = self.num_invocations.wrapping_add(1);
+        ret
+    }
+
+    fn randomness_cache_mut(&mut self) -> &mut RandomnessCache {
+        &mut self.randomness_cache
+    }
+
+    fn trace(&mut self, event: ExecutionEvent) {
+        let config = self.machine.config();
+        let traced = match event {
+            ExecutionEvent::Syscall(_) => config.trace_syscalls,
+            _ => config.trace_calls,
+        };
+        if traced {
+            self.exec_trace.push(event);
+        }
+    }
 }
 
 impl<M> DefaultCallManager<M>
 where
     M: Machine,
 {
+    /// Returns the gas that can still be used, for tracing.
+    fn gas_remaining(&self) -> Gas {
+        self.gas_tracker.gas_available() - self.gas_tracker.gas_used()
+    }
+
     fn create_account_actor<K>(&mut self, addr: &Address) -> Result<ActorID>
     where
         K: Kernel<CallManager = Self>,
@@ -172,14 +272,14 @@ where
         }
 
         // Create the actor in the state tree.
-        let id = {
-            let code_cid = self
-                .builtin_actors()
-                .get_by_right(&Type::Account)
-                .expect("failed to determine account actor CodeCID");
-            let state = account_actor::zero_state(*code_cid);
-            self.create_actor(addr, state)?
-        };
+        let code_cid = *self
+            .builtin_actors()
+            .get_by_right(&Type::Account)
+            .expect("failed to determine account actor CodeCID");
+        let id = self.create_actor(addr, account_actor::zero_state(code_cid))?;
+        self.trace(ExecutionEvent::StateChange(
+            StateChangeTrace::ActorCreated { id, code: code_cid },
+        ));
 
         // Now invoke the constructor; first create the parameters, then
         // instantiate a new kernel to invoke the constructor.
@@ -190,7 +290,7 @@ where
         self.send_resolved::<K>(
             account_actor::SYSTEM_ACTOR_ID,
             id,
-            fvm_shared::METHOD_CONSTRUCTOR,
+            METHOD_CONSTRUCTOR,
             &params,
             &TokenAmount::from(0u32),
         )?;
@@ -214,13 +314,13 @@ where
         // TODO: What kind of errors should we be using here?
         let to = match self.state_tree().lookup_id(&to)? {
             Some(addr) => addr,
-            None => match to.protocol() {
-                Protocol::BLS | Protocol::Secp256k1 => {
-                    // Try to create an account actor if the receiver is a key address.
-                    self.create_account_actor::<K>(&to)?
+            None => {
+                let policy = ReceiverCreation::for_network_version(self.context().network_version);
+                if !policy.creates(&to) {
+                    return Err(syscall_error!(NotFound; "actor does not exist: {}", to).into());
                 }
-                _ => return Err(syscall_error!(NotFound; "actor does not exist: {}", to).into()),
-            },
+                self.create_account_actor::<K>(&to)?
+            }
         };
 
         // Do the actual send.
@@ -260,12 +360,58 @@ where
             return Ok(InvocationResult::Return(Default::default()));
         }
 
+        // The node may veto the code before it runs.
+        let rejected = self
+            .externs()
+            .check_code(&state.code)
+            .with_context(|| format!("failed to check the code of actor {}", to))
+            .or_fatal()?;
+        if let Some(code) = rejected {
+            if code.is_success() {
+                return Err(ExecutionError::Fatal(anyhow::anyhow!(
+                    "code policy rejected code {} with a success exit code",
+                    state.code
+                )));
+            }
+            log::trace!("code {} of actor {} rejected: {:?}", state.code, to, code);
+            self.backtrace.push_frame(Frame {
+                source: to,
+                method,
+                message: format!("code {} rejected by the node's code policy", state.code),
+                params: params.clone(),
+                code,
+            });
+            return Ok(InvocationResult::Failure(code));
+        }
+
         // This is a cheap operation as it doesn't actually clone the struct,
         // it returns a referenced copy.
         let engine = self.engine().clone();
+        let config = self.machine.config().clone();
+
+        // Compile the actor's code if it hasn't been loaded yet.
+        engine
+            .preload(config.bundles.code_loader(self.blockstore()), [&state.code])
+            .with_context(|| format!("failed to load the code of actor {}", to))
+            .or_fatal()?;
+
+        // Charge for the code by its size, so that invoking an actor costs the same whether or not
+        // its code was already compiled.
+        let code_size = engine
+            .code_size(&state.code)
+            .with_context(|| format!("size of code {} unknown after loading it", state.code))
+            .or_fatal()?;
+        self.charge_gas(self.price_list().on_code_load(code_size))?;
+
+        #[cfg(feature = "method-stats")]
+        let method_stats = config.method_stats.clone();
+        #[cfg(feature = "method-stats")]
+        if method_stats.is_some() {
+            self.call_timer.enter(self.gas_tracker.gas_used());
+        }
 
         log::trace!("calling {} -> {}::{}", from, to, method);
-        self.map_mut(|cm| {
+        let ret = self.map_mut(|cm| {
             // Make the kernel.
             let mut kernel = K::new(cm, from, to, method, value.clone());
 
@@ -280,8 +426,11 @@ where
                 super::NO_DATA_BLOCK_ID
             };
 
-            // Make a store.
-            let mut store = engine.new_store(kernel);
+            // Make a store, and give the actor the fuel it can pay for.
+            let mut store = engine.new_store(kernel, &config);
+            if let Err(err) = refuel(&mut store) {
+                return (Err(err), store.into_data().kernel.take());
+            }
 
             // Instantiate the module.
             let instance = match engine
@@ -296,10 +445,14 @@ where
             // From this point on, there are no more syscall errors, only aborts.
             let result: std::result::Result<RawBytes, Abort> = (|| {
                 // Lookup the invoke method.
-                let invoke: wasmtime::TypedFunc<(u32,), u32> = instance
-                    .get_typed_func(&mut store, "invoke")
-                    // All actors will have an invoke method.
-                    .map_err(Abort::Fatal)?;
+                // An actor without a (valid) invoke method can't handle any method.
+                let invoke: wasmtime::TypedFunc<(u32,), u32> =
+                    instance.get_typed_func(&mut store, "invoke").map_err(|e| {
+                        Abort::Exit(
+                            ExitCode::SysErrInvalidMethod,
+                            format!("actor has no invoke method: {}", e),
+                        )
+                    })?;
 
                 // Invoke it.
                 let return_block_id = invoke.call(&mut store, (param_id,))?;
@@ -320,9 +473,28 @@ where
                 Ok(return_value)
             })();
 
+            // Charge for the code executed since the last syscall. If the actor trapped because it
+            // ran out of fuel, this runs out of gas.
+            let exec_charge = charge_for_exec(&mut store);
             let invocation_data = store.into_data();
             let last_error = invocation_data.last_error;
-            let mut cm = invocation_data.kernel.take();
+            // If charging for memory growth failed, the actor was denied memory and most likely
+            // aborted because of it. Report the charging failure instead.
+            let result = match (result, exec_charge, invocation_data.memory_grow_error) {
+                (_, Err(err), _) | (Err(_), _, Some(err)) => {
+                    Err(Abort::from_error(ExitCode::SysErrActorPanic, err))
+                }
+                (result, _, _) => result,
+            };
+            // Only keep the new state of invocations that return successfully.
+            let mut kernel = invocation_data.kernel;
+            let result = result.and_then(|value| {
+                kernel
+                    .flush_committed()
+                    .map(|_| value)
+                    .map_err(|e| Abort::from_error(ExitCode::SysErrIllegalActor, e))
+            });
+            let mut cm = kernel.take();
 
             // Process the result, updating the backtrace if necessary.
             let ret = match result {
@@ -333,6 +505,11 @@ where
                     }
 
                     let (code, message, res) = match abort {
+                        Abort::Exit(ExitCode::SysErrInvalidMethod, message) => (
+                            ExitCode::SysErrInvalidMethod,
+                            format!("unhandled method {}: {}", method, message),
+                            Ok(InvocationResult::Failure(ExitCode::SysErrInvalidMethod)),
+                        ),
                         Abort::Exit(code, message) => {
                             (code, message, Ok(InvocationResult::Failure(code)))
                         }
@@ -346,7 +523,11 @@ where
                             // introduce the new exit codes.
                             ExitCode::SysErrIllegalArgument,
                             "fatal error".to_owned(),
-                            Err(ExecutionError::Fatal(err)),
+                            // Name the actor (and, for nested sends, every caller) that hit it.
+                            Err(ExecutionError::Fatal(err.context(format!(
+                                "fatal error while executing actor {} (method {})",
+                                to, method
+                            )))),
                         ),
                     };
 
@@ -377,7 +558,14 @@ where
             }
 
             (ret, cm)
-        })
+        });
+
+        #[cfg(feature = "method-stats")]
+        if let Some(stats) = method_stats {
+            let (wall_time, gas) = self.call_timer.exit(self.gas_tracker.gas_used());
+            stats.record(state.code, method, wall_time, gas);
+        }
+        ret
     }
 
     fn map_mut<F, T>(&mut self, f: F) -> T
@@ -387,3 +575,207 @@ where
         replace_with::replace_with_and_return(self, || DefaultCallManager(None), f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use cid::Cid;
+    use fvm_shared::blockstore::Blockstore;
+    use fvm_shared::version::NetworkVersion;
+    use fvm_shared::IPLD_RAW;
+
+    use super::*;
+    use crate::state_tree::ActorState;
+    use crate::testing::MockMachine;
+    use crate::{DefaultKernel, EMPTY_ARR_CID};
+
+    type TestKernel = DefaultKernel<DefaultCallManager<MockMachine>>;
+
+    const SENDER: ActorID = 100;
+    const RECEIVER: ActorID = 1000;
+
+    /// Returns a machine with the network's prices, where the given actors are deployed.
+    fn machine(actors: &[(ActorID, &str)]) -> MockMachine {
+        let mut machine = MockMachine::new(NetworkVersion::V16).unwrap();
+        for (id, wat) in actors {
+            let wasm = wat::parse_str(wat).unwrap();
+            let code = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(&wasm));
+            machine.engine.load_bytecode(&code, &wasm).unwrap();
+            machine.state_tree.store().put_keyed(&code, &wasm).unwrap();
+            let state = ActorState::new(code, *EMPTY_ARR_CID, TokenAmount::zero(), 0);
+            machine.state_tree.set_actor_id(*id, state).unwrap();
+        }
+        machine
+    }
+
+    /// Invokes the receiver from the sender. Returns the machine along with the results.
+    fn invoke(machine: MockMachine) -> (Result<InvocationResult>, FinishRet, MockMachine) {
+        let origin = Address::new_id(SENDER);
+        let mut cm = DefaultCallManager::new(machine, Gas::new(10_000_000_000), origin, 0);
+        let res = cm.with_transaction(|cm| {
+            cm.send::<TestKernel>(
+                SENDER,
+                Address::new_id(RECEIVER),
+                2,
+                &RawBytes::default(),
+                &TokenAmount::zero(),
+            )
+        });
+        let (ret, machine) = cm.finish();
+        (res, ret, machine)
+    }
+
+    #[test]
+    fn actors_without_invoke_have_no_methods() {
+        let machine = machine(&[(RECEIVER, "(module)")]);
+        let (res, _, _) = invoke(machine);
+        assert_eq!(res.unwrap().exit_code(), ExitCode::SysErrInvalidMethod);
+    }
+
+    #[test]
+    fn syscalls_are_traced_in_order() {
+        const CALLEE: ActorID = 1001;
+        // Sends to the callee (whose ID address is encoded at 64) and returns.
+        let caller = r#"(module
+          (import "send" "send"
+            (func $send (param i32 i32 i32 i64 i32 i64 i64) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 64) "\00\e9\07")
+          (func (export "invoke") (param i32) (result i32)
+            (if (call $send (i32.const 0) (i32.const 64) (i32.const 3) (i64.const 2)
+                  (i32.const 0) (i64.const 0) (i64.const 0))
+              (then unreachable))
+            (i32.const 0)))"#;
+        let callee = r#"(module
+          (func (export "invoke") (param i32) (result i32) (i32.const 0)))"#;
+        let mut machine = machine(&[(RECEIVER, caller), (CALLEE, callee)]);
+        machine.config.trace_calls = true;
+        machine.config.trace_syscalls = true;
+        let (res, ret, _) = invoke(machine);
+        assert_eq!(res.unwrap().exit_code(), ExitCode::Ok);
+
+        let events: Vec<_> = ret
+            .exec_trace
+            .iter()
+            .filter_map(|event| match event {
+                ExecutionEvent::Call(call) => Some(format!("call {}", call.to)),
+                ExecutionEvent::CallReturn(_) => Some("return".to_owned()),
+                ExecutionEvent::Syscall(syscall) => {
+                    Some(format!("{}::{}", syscall.module, syscall.name))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            events,
+            [
+                "call f01000",
+                "call f01001",
+                "return",
+                "send::send",
+                "return"
+            ]
+        );
+    }
+
+    #[test]
+    fn memory_growth_from_empty_is_charged() {
+        let actor = r#"(module
+          (memory 0)
+          (func (export "invoke") (param i32) (result i32)
+            (if (i32.ne (memory.grow (i32.const 2)) (i32.const 0))
+              (then unreachable))
+            (i32.const 0)))"#;
+        let run = |per_page| {
+            let mut machine = machine(&[(RECEIVER, actor)]);
+            machine.context.price_list.memory_grow_per_page = per_page;
+            let (res, ret, _) = invoke(machine);
+            assert_eq!(res.unwrap().exit_code(), ExitCode::Ok);
+            ret.gas_used
+        };
+        let price = machine(&[]).context.price_list.memory_grow_per_page;
+        assert!(price > Gas::ZERO);
+        assert_eq!(run(price), run(Gas::ZERO) + price * 2);
+    }
+
+    #[test]
+    fn syscall_gas_is_not_charged_as_execution() {
+        // Charges the given amount of gas, then loops for a while.
+        let actor = |amount: u64| {
+            format!(
+                r#"(module
+          (import "gas" "charge" (func $charge (param i32 i32 i64) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "a")
+          (func (export "invoke") (param i32) (result i32)
+            (local $i i32)
+            (if (call $charge (i32.const 0) (i32.const 1) (i64.const {}))
+              (then unreachable))
+            (loop $loop
+              (local.set $i (i32.add (local.get $i) (i32.const 1)))
+              (br_if $loop (i32.lt_u (local.get $i) (i32.const 1000))))
+            (i32.const 0)))"#,
+                amount
+            )
+        };
+        let run = |amount| {
+            let wat = actor(amount);
+            let (res, ret, _) = invoke(machine(&[(RECEIVER, wat.as_str())]));
+            assert_eq!(res.unwrap().exit_code(), ExitCode::Ok);
+            ret.gas_used
+        };
+        assert!(machine(&[]).context.price_list.wasm_exec_per_fuel > Gas::ZERO);
+        // Both actors execute the same code, so only the charge differs.
+        assert_eq!(run(12345), run(0) + Gas::new(12345));
+    }
+
+    #[test]
+    fn tables_are_bounded() {
+        let actor = r#"(module
+          (table 1 funcref)
+          (func (export "invoke") (param i32) (result i32)
+            (if (i32.ne (table.grow (ref.null func) (i32.const 100)) (i32.const 1))
+              (then unreachable))
+            (if (i32.ne (table.grow (ref.null func) (i32.const 100)) (i32.const -1))
+              (then unreachable))
+            (i32.const 0)))"#;
+        let mut machine = machine(&[(RECEIVER, actor)]);
+        machine.config.max_table_elements = 150;
+        let (res, _, _) = invoke(machine);
+        assert_eq!(res.unwrap().exit_code(), ExitCode::Ok);
+    }
+
+    #[test]
+    fn state_is_only_written_on_success() {
+        // Sets its state root to a new block, then returns or aborts.
+        let actor = |end: &str| {
+            format!(
+                r#"(module
+          (import "ipld" "create" (func $create (param i32 i64 i32 i32) (result i32)))
+          (import "ipld" "cid" (func $cid (param i32 i32 i64 i32 i32 i32) (result i32)))
+          (import "self" "set_root" (func $set_root (param i32) (result i32)))
+          (memory (export "memory") 1)
+          (data (i32.const 0) "\81\01")
+          (func (export "invoke") (param i32) (result i32)
+            (if (call $create (i32.const 16) (i64.const 0x71) (i32.const 0) (i32.const 2))
+              (then unreachable))
+            (if (call $cid (i32.const 20) (i32.load (i32.const 16)) (i64.const 0xb220)
+                  (i32.const 32) (i32.const 64) (i32.const 100))
+              (then unreachable))
+            (if (call $set_root (i32.const 64))
+              (then unreachable))
+            {}))"#,
+                end
+            )
+        };
+        let root = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(&[0x81, 1]));
+        let run = |end: &str| {
+            let wat = actor(end);
+            let (res, _, machine) = invoke(machine(&[(RECEIVER, wat.as_str())]));
+            let written = machine.blockstore().has(&root).unwrap();
+            (res.unwrap().exit_code(), written)
+        };
+        assert_eq!(run("(i32.const 0)"), (ExitCode::Ok, true));
+        assert_eq!(run("unreachable"), (ExitCode::SysErrActorPanic, false));
+    }
+}
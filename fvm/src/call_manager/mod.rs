@@ -4,16 +4,21 @@ use fvm_shared::encoding::RawBytes;
 use fvm_shared::error::ExitCode;
 use fvm_shared::{ActorID, MethodNum};
 
-use crate::gas::{GasCharge, GasTracker, PriceList};
+use crate::gas::{Gas, GasCharge, GasTracker, PriceList};
 use crate::kernel::Result;
 use crate::machine::{Machine, MachineContext};
 use crate::state_tree::StateTree;
+use crate::trace::{ExecutionEvent, ExecutionTrace};
 use crate::Kernel;
 
 pub mod backtrace;
 pub use backtrace::Backtrace;
 mod default;
 pub use default::DefaultCallManager;
+mod randomness;
+pub use randomness::{RandomnessCache, RandomnessSource};
+mod receiver;
+pub use receiver::ReceiverCreation;
 
 /// BlockID representing nil parameters or return data.
 pub const NO_DATA_BLOCK_ID: u32 = 0;
@@ -38,7 +43,7 @@ pub trait CallManager: 'static {
     type Machine: Machine;
 
     /// Construct a new call manager.
-    fn new(machine: Self::Machine, gas_limit: i64, origin: Address, nonce: u64) -> Self;
+    fn new(machine: Self::Machine, gas_limit: Gas, origin: Address, nonce: u64) -> Self;
 
     /// Send a message. The type parameter `K` specifies the the _kernel_ on top of which the target
     /// actor should execute.
@@ -57,8 +62,9 @@ pub trait CallManager: 'static {
         f: impl FnOnce(&mut Self) -> Result<InvocationResult>,
     ) -> Result<InvocationResult>;
 
-    /// Finishes execution, returning the gas used and the machine.
-    fn finish(self) -> (i64, backtrace::Backtrace, Self::Machine);
+    /// Finishes execution, returning the gas used, the backtrace, the execution trace, and the
+    /// machine.
+    fn finish(self) -> (FinishRet, Self::Machine);
 
     /// Returns a reference to the machine.
     fn machine(&self) -> &Self::Machine;
@@ -70,15 +76,27 @@ pub trait CallManager: 'static {
     /// Returns a mutable reference to the gas tracker.
     fn gas_tracker_mut(&mut self) -> &mut GasTracker;
 
-    /// Getter for origin actor.
+    /// Returns the sender of the top-level message that started this call stack.
     fn origin(&self) -> Address;
 
-    /// Getter for message nonce.
+    /// Returns the nonce of the top-level message that started this call stack.
     fn nonce(&self) -> u64;
 
     /// Gets and increment the call-stack actor creation index.
     fn next_actor_idx(&mut self) -> u64;
 
+    /// Gets and increments the call-stack invocation index, which tags the block handles of each
+    /// invocation (see [`BlockId`](crate::kernel::BlockId)).
+    fn next_invocation_idx(&mut self) -> u32;
+
+    /// Returns the randomness drawn so far in this call stack.
+    fn randomness_cache_mut(&mut self) -> &mut RandomnessCache;
+
+    /// Records an event in the execution trace, if events of its kind are traced: syscalls if
+    /// [`Config::trace_syscalls`](crate::Config::trace_syscalls) is set, everything else if
+    /// [`Config::trace_calls`](crate::Config::trace_calls) is.
+    fn trace(&mut self, event: ExecutionEvent);
+
     /// Returns the current price list.
     fn price_list(&self) -> &PriceList {
         &self.machine().context().price_list
@@ -116,6 +134,16 @@ pub trait CallManager: 'static {
     }
 }
 
+/// The result of a finished call stack, see [`CallManager::finish`].
+pub struct FinishRet {
+    /// The gas used by the call stack.
+    pub gas_used: Gas,
+    /// The backtrace of the failure, if any.
+    pub backtrace: Backtrace,
+    /// The execution trace. This is empty unless tracing is enabled.
+    pub exec_trace: ExecutionTrace,
+}
+
 /// The result of a method invocation.
 pub enum InvocationResult {
     /// Indicates that the actor sucessfully returned. The value may be empty.
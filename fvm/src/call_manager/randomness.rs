@@ -0,0 +1,62 @@
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::collections::FixedHashMap;
+use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+
+/// Where randomness is drawn from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RandomnessSource {
+    /// Randomness from the chain's tickets.
+    Tickets,
+    /// Randomness from the drand beacon.
+    Beacon,
+}
+
+type Key = (RandomnessSource, DomainSeparationTag, ChainEpoch, Vec<u8>);
+
+/// The randomness drawn from the externs within a call stack, keyed by the full request.
+///
+/// Actors sometimes request the same randomness several times while executing a message, and every
+/// request would otherwise walk the node's chain again. Gas is still charged for every request.
+#[derive(Default)]
+pub struct RandomnessCache {
+    entries: FixedHashMap<Key, [u8; RANDOMNESS_LENGTH]>,
+}
+
+impl RandomnessCache {
+    /// Returns the randomness previously drawn for the given request, if any.
+    pub fn get(
+        &self,
+        source: RandomnessSource,
+        tag: DomainSeparationTag,
+        epoch: ChainEpoch,
+        entropy: &[u8],
+    ) -> Option<[u8; RANDOMNESS_LENGTH]> {
+        self.entries
+            .get(&(source, tag, epoch, entropy.to_vec()))
+            .copied()
+    }
+
+    /// Records the randomness drawn for the given request.
+    pub fn insert(
+        &mut self,
+        source: RandomnessSource,
+        tag: DomainSeparationTag,
+        epoch: ChainEpoch,
+        entropy: &[u8],
+        randomness: [u8; RANDOMNESS_LENGTH],
+    ) {
+        self.entries
+            .insert((source, tag, epoch, entropy.to_vec()), randomness);
+    }
+
+    /// Returns the number of distinct requests recorded.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no randomness has been drawn yet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
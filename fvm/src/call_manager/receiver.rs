@@ -0,0 +1,59 @@
+use fvm_shared::address::{Address, Protocol};
+use fvm_shared::version::NetworkVersion;
+
+/// What happens when a message is sent to an address no actor lives at, which changes across
+/// network versions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReceiverCreation {
+    /// Sending to a key (BLS or secp256k1) address creates an account actor for it. Sending to any
+    /// other missing address fails.
+    KeyAddressAccounts,
+    /// Nothing is created: sending to a missing address fails.
+    Disabled,
+}
+
+/// The receiver creation policy of each network version, by the first version it applies to.
+/// Changing the policy at a network upgrade is a matter of adding an entry.
+const POLICIES: &[(NetworkVersion, ReceiverCreation)] =
+    &[(NetworkVersion::V0, ReceiverCreation::KeyAddressAccounts)];
+
+impl ReceiverCreation {
+    /// Returns the policy in force at the given network version.
+    pub fn for_network_version(nv: NetworkVersion) -> Self {
+        POLICIES
+            .iter()
+            .rev()
+            .find(|(since, _)| nv >= *since)
+            .map(|&(_, policy)| policy)
+            .expect("no receiver creation policy before the first network version")
+    }
+
+    /// Returns true if sending to the given missing address creates an actor there.
+    pub fn creates(self, addr: &Address) -> bool {
+        match self {
+            ReceiverCreation::KeyAddressAccounts => {
+                matches!(addr.protocol(), Protocol::BLS | Protocol::Secp256k1)
+            }
+            ReceiverCreation::Disabled => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_per_network_version() {
+        let key = Address::new_secp256k1(&[4; 65]).unwrap();
+        let actor = Address::new_actor(b"actor");
+        for nv in [NetworkVersion::V14, NetworkVersion::V15] {
+            let policy = ReceiverCreation::for_network_version(nv);
+            assert_eq!(policy, ReceiverCreation::KeyAddressAccounts, "{}", nv);
+            assert!(policy.creates(&key));
+            assert!(!policy.creates(&actor));
+            assert!(!policy.creates(&Address::new_id(1000)));
+        }
+        assert!(!ReceiverCreation::Disabled.creates(&key));
+    }
+}
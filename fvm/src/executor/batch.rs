@@ -0,0 +1,326 @@
+use std::fmt::Display;
+
+use fvm_shared::collections::FixedHashMap;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::encoding::Cbor;
+use fvm_shared::message::Message;
+use num_traits::Zero;
+
+use super::{ApplyKind, ApplyRet, Executor};
+
+/// What a [`BatchExecutor`] does after a message fails with a fatal error.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum FatalErrorPolicy {
+    /// Stop applying the batch: the remaining messages are skipped.
+    Halt,
+    /// Record the error and apply the remaining messages on top of whatever state the failed
+    /// message left behind.
+    Continue,
+}
+
+/// A message to apply as part of a batch.
+#[derive(Clone, Debug)]
+pub struct BatchMessage {
+    pub msg: Message,
+    pub apply_kind: ApplyKind,
+    /// The length of the message as it appears on-chain (see [`Executor::execute_message`]).
+    pub raw_length: usize,
+}
+
+/// Why a message of a batch wasn't applied.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum SkipReason {
+    /// An earlier message failed fatally, and the batch was halted.
+    Halted,
+    /// The same message was already included by an earlier block of the tipset, at the given
+    /// block and message indices. Only the first occurrence of a message is applied.
+    Duplicate { block: usize, index: usize },
+}
+
+impl Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Halted => write!(f, "an earlier message failed with a fatal error"),
+            SkipReason::Duplicate { block, index } => write!(
+                f,
+                "duplicate of message {} of block {} of the tipset",
+                index, block
+            ),
+        }
+    }
+}
+
+/// The outcome of a message of a batch.
+#[derive(Debug)]
+pub enum MessageOutcome {
+    /// The message was applied (successfully or not), and produced a receipt.
+    Applied(ApplyRet),
+    /// The message wasn't applied.
+    Skipped(SkipReason),
+    /// Applying the message failed with a fatal error, and it produced no receipt.
+    Fatal(anyhow::Error),
+}
+
+impl MessageOutcome {
+    /// Returns the result of applying the message, if it was applied.
+    pub fn applied(&self) -> Option<&ApplyRet> {
+        match self {
+            MessageOutcome::Applied(ret) => Some(ret),
+            _ => None,
+        }
+    }
+
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, MessageOutcome::Fatal(_))
+    }
+}
+
+/// The outcomes of the messages of a block of a tipset.
+#[derive(Debug)]
+pub struct BlockOutcome {
+    /// One outcome per message included in the block.
+    pub messages: Vec<MessageOutcome>,
+    /// The sum of the miner tips of the messages applied as part of this block, owed to the
+    /// block's miner. Duplicate messages tip the miner of the block that first included them.
+    pub gas_reward: TokenAmount,
+}
+
+/// Applies batches of messages (e.g., the messages of a tipset) with an [`Executor`], reporting
+/// the outcome of every message instead of stopping at the first fatal error.
+pub struct BatchExecutor<E> {
+    executor: E,
+    policy: FatalErrorPolicy,
+}
+
+impl<E: Executor> BatchExecutor<E> {
+    pub fn new(executor: E, policy: FatalErrorPolicy) -> Self {
+        BatchExecutor { executor, policy }
+    }
+
+    pub fn policy(&self) -> FatalErrorPolicy {
+        self.policy
+    }
+
+    pub fn executor(&self) -> &E {
+        &self.executor
+    }
+
+    pub fn executor_mut(&mut self) -> &mut E {
+        &mut self.executor
+    }
+
+    pub fn into_inner(self) -> E {
+        self.executor
+    }
+
+    /// Applies the messages in order, returning one outcome per message.
+    pub fn apply_batch(
+        &mut self,
+        msgs: impl IntoIterator<Item = BatchMessage>,
+    ) -> Vec<MessageOutcome> {
+        let mut halted = false;
+        msgs.into_iter()
+            .map(|m| self.apply_message(m, &mut halted))
+            .collect()
+    }
+
+    /// Applies the messages of the blocks of a tipset, in order, returning the outcomes of each
+    /// block's messages.
+    ///
+    /// A message included by several blocks (or several times by the same block) is only applied
+    /// where it first appears; later occurrences are skipped as duplicates.
+    pub fn apply_tipset<B>(&mut self, blocks: impl IntoIterator<Item = B>) -> Vec<BlockOutcome>
+    where
+        B: IntoIterator<Item = BatchMessage>,
+    {
+        let mut halted = false;
+        let mut seen = FixedHashMap::default();
+        let mut outcomes = Vec::new();
+        for (block, msgs) in blocks.into_iter().enumerate() {
+            let mut messages = Vec::new();
+            let mut gas_reward = TokenAmount::zero();
+            for (index, m) in msgs.into_iter().enumerate() {
+                let outcome = if halted {
+                    MessageOutcome::Skipped(SkipReason::Halted)
+                } else {
+                    match m.msg.cid() {
+                        Ok(cid) => match seen.get(&cid) {
+                            Some(&(first_block, first_index)) => {
+                                MessageOutcome::Skipped(SkipReason::Duplicate {
+                                    block: first_block,
+                                    index: first_index,
+                                })
+                            }
+                            None => {
+                                seen.insert(cid, (block, index));
+                                self.apply_message(m, &mut halted)
+                            }
+                        },
+                        Err(e) => {
+                            halted = self.policy == FatalErrorPolicy::Halt;
+                            MessageOutcome::Fatal(e.into())
+                        }
+                    }
+                };
+                if let Some(ret) = outcome.applied() {
+                    gas_reward += &ret.miner_tip;
+                }
+                messages.push(outcome);
+            }
+            outcomes.push(BlockOutcome {
+                messages,
+                gas_reward,
+            });
+        }
+        outcomes
+    }
+
+    fn apply_message(&mut self, m: BatchMessage, halted: &mut bool) -> MessageOutcome {
+        if *halted {
+            return MessageOutcome::Skipped(SkipReason::Halted);
+        }
+        let ret = self
+            .executor
+            .execute_message(m.msg, m.apply_kind, m.raw_length);
+        match ret {
+            Ok(ret) => MessageOutcome::Applied(ret),
+            Err(e) => {
+                *halted = self.policy == FatalErrorPolicy::Halt;
+                MessageOutcome::Fatal(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use fvm_shared::address::Address;
+    use fvm_shared::error::ExitCode;
+    use num_traits::Zero;
+
+    use super::*;
+    use crate::testing::MockCallManager;
+    use crate::DefaultKernel;
+
+    /// Fails fatally on messages with an odd sequence number, and records the sequence numbers of
+    /// the messages it was asked to apply. Applied messages tip their sequence number.
+    #[derive(Default)]
+    struct OddFailingExecutor {
+        applied: Vec<u64>,
+    }
+
+    impl Executor for OddFailingExecutor {
+        type Kernel = DefaultKernel<MockCallManager>;
+
+        fn execute_message(
+            &mut self,
+            msg: Message,
+            _: ApplyKind,
+            _: usize,
+        ) -> anyhow::Result<ApplyRet> {
+            self.applied.push(msg.sequence);
+            if msg.sequence % 2 == 1 {
+                return Err(anyhow!("fatal error"));
+            }
+            let mut ret = ApplyRet::prevalidation_fail(ExitCode::Ok, "not applied", Zero::zero());
+            ret.miner_tip = msg.sequence.into();
+            Ok(ret)
+        }
+    }
+
+    fn batch(len: u64) -> Vec<BatchMessage> {
+        messages(0..len)
+    }
+
+    fn messages(sequences: impl IntoIterator<Item = u64>) -> Vec<BatchMessage> {
+        sequences
+            .into_iter()
+            .map(|sequence| BatchMessage {
+                msg: Message {
+                    version: 0,
+                    from: Address::new_id(100),
+                    to: Address::new_id(101),
+                    sequence,
+                    value: Zero::zero(),
+                    method_num: 0,
+                    params: Default::default(),
+                    gas_limit: 0,
+                    gas_fee_cap: Zero::zero(),
+                    gas_premium: Zero::zero(),
+                },
+                apply_kind: ApplyKind::Explicit,
+                raw_length: 0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn halt_on_fatal_error() {
+        let mut exec = BatchExecutor::new(OddFailingExecutor::default(), FatalErrorPolicy::Halt);
+        let outcomes = exec.apply_batch(batch(4));
+        assert!(outcomes[0].applied().is_some());
+        assert!(outcomes[1].is_fatal());
+        assert!(matches!(
+            outcomes[2..],
+            [
+                MessageOutcome::Skipped(SkipReason::Halted),
+                MessageOutcome::Skipped(SkipReason::Halted)
+            ]
+        ));
+        assert_eq!(exec.into_inner().applied, vec![0, 1]);
+    }
+
+    #[test]
+    fn continue_on_fatal_error() {
+        let mut exec =
+            BatchExecutor::new(OddFailingExecutor::default(), FatalErrorPolicy::Continue);
+        let outcomes = exec.apply_batch(batch(4));
+        let fatal: Vec<bool> = outcomes.iter().map(MessageOutcome::is_fatal).collect();
+        assert_eq!(fatal, vec![false, true, false, true]);
+        assert_eq!(exec.into_inner().applied, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn tipset_duplicates() {
+        let mut exec = BatchExecutor::new(OddFailingExecutor::default(), FatalErrorPolicy::Halt);
+        let blocks = exec.apply_tipset(vec![messages([0, 2]), messages([2, 4, 0, 4])]);
+
+        assert!(blocks[0].messages.iter().all(|o| o.applied().is_some()));
+        assert_eq!(blocks[0].gas_reward, TokenAmount::from(2));
+
+        let skipped: Vec<Option<SkipReason>> = blocks[1]
+            .messages
+            .iter()
+            .map(|o| match o {
+                MessageOutcome::Skipped(reason) => Some(*reason),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                Some(SkipReason::Duplicate { block: 0, index: 1 }),
+                None,
+                Some(SkipReason::Duplicate { block: 0, index: 0 }),
+                Some(SkipReason::Duplicate { block: 1, index: 1 }),
+            ]
+        );
+        // Only the message first included by the second block counts towards its reward.
+        assert_eq!(blocks[1].gas_reward, TokenAmount::from(4));
+        assert_eq!(exec.into_inner().applied, vec![0, 2, 4]);
+    }
+
+    #[test]
+    fn tipset_halts_across_blocks() {
+        let mut exec = BatchExecutor::new(OddFailingExecutor::default(), FatalErrorPolicy::Halt);
+        let blocks = exec.apply_tipset(vec![messages([0, 1]), messages([1, 2])]);
+        assert!(blocks[0].messages[1].is_fatal());
+        // Halting takes precedence over deduplication.
+        assert!(blocks[1]
+            .messages
+            .iter()
+            .all(|o| matches!(o, MessageOutcome::Skipped(SkipReason::Halted))));
+        assert_eq!(exec.into_inner().applied, vec![0, 1]);
+    }
+}
@@ -6,6 +6,7 @@ use cid::Cid;
 use fvm_shared::actor::builtin::Type;
 use fvm_shared::address::Address;
 use fvm_shared::bigint::{BigInt, Sign};
+use fvm_shared::constants::{BURNT_FUNDS_ACTOR_ADDR, REWARD_ACTOR_ADDR};
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::{ErrorNumber, ExitCode};
 use fvm_shared::message::Message;
@@ -13,13 +14,20 @@ use fvm_shared::receipt::Receipt;
 use fvm_shared::ActorID;
 use num_traits::Zero;
 
-use super::{ApplyFailure, ApplyKind, ApplyRet, Executor};
-use crate::call_manager::{backtrace, CallManager, InvocationResult};
-use crate::gas::{GasCharge, GasOutputs};
+use super::{compare_receipts, ApplyFailure, ApplyKind, ApplyRet, Executor, Replay};
+use crate::call_manager::{backtrace, CallManager, FinishRet, InvocationResult};
+use crate::gas::{Gas, GasCharge, GasOutputs};
 use crate::kernel::{ClassifyResult, Context as _, ExecutionError, Kernel};
-use crate::machine::{Machine, BURNT_FUNDS_ACTOR_ADDR, REWARD_ACTOR_ADDR};
+use crate::machine::Machine;
+use crate::trace::ExecutionTrace;
 
 /// The default [`Executor`].
+///
+/// The executor owns the [`Machine`] (the state tree, the engine and the chain context of an
+/// epoch), and handles everything specific to applying a message on it: pre-validation, gas
+/// accounting, settlement and receipts. Create one per tipset and apply its messages in order:
+/// each is applied on the state left by the previous ones. Then [`flush`](DefaultExecutor::flush)
+/// the state, and [`consume`](DefaultExecutor::consume) the executor to get the machine back.
 // If the inner value is `None` it means the machine got poisoned and is unusable.
 #[repr(transparent)]
 pub struct DefaultExecutor<K: Kernel>(Option<<K::CallManager as CallManager>::Machine>);
@@ -51,6 +59,45 @@ where
         apply_kind: ApplyKind,
         raw_length: usize,
     ) -> anyhow::Result<ApplyRet> {
+        let stats_before = self
+            .config()
+            .state_tree_stats
+            .then(|| self.state_tree().stats());
+        let mut ret = self.apply_message(msg, apply_kind, raw_length)?;
+        ret.state_tree_stats = stats_before.map(|before| self.state_tree().stats().since(&before));
+        #[cfg(feature = "method-stats")]
+        if let Some(stats) = &self.config().method_stats {
+            stats.message_applied();
+        }
+        Ok(ret)
+    }
+}
+
+impl<K> DefaultExecutor<K>
+where
+    K: Kernel,
+{
+    /// Create a new [`DefaultExecutor`] for executing messages on the [`Machine`].
+    pub fn new(m: <K::CallManager as CallManager>::Machine) -> Self {
+        Self(Some(m))
+    }
+
+    /// Applies a message, as described by [`Executor::execute_message`].
+    fn apply_message(
+        &mut self,
+        msg: Message,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<ApplyRet> {
+        let _span = tracing::info_span!(
+            "message",
+            from = %msg.from,
+            to = %msg.to,
+            method = msg.method_num,
+            nonce = msg.sequence,
+        )
+        .entered();
+
         // Validate if the message was correct, charge for it, and extract some preliminary data.
         let (sender_id, gas_cost, inclusion_cost) =
             match self.preflight_message(&msg, apply_kind, raw_length)? {
@@ -59,12 +106,13 @@ where
             };
 
         // Apply the message.
-        let (res, gas_used, mut backtrace) = self.map_machine(|machine| {
-            let mut cm = K::CallManager::new(machine, msg.gas_limit, msg.from, msg.sequence);
+        let (res, gas_used, mut backtrace, exec_trace) = self.map_machine(|machine| {
+            let mut cm =
+                K::CallManager::new(machine, gas_limit(&msg, apply_kind), msg.from, msg.sequence);
             // This error is fatal because it should have already been acounted for inside
             // preflight_message.
             if let Err(e) = cm.charge_gas(inclusion_cost) {
-                return (Err(e), cm.finish().2);
+                return (Err(e), cm.finish().1);
             }
 
             let result = cm.with_transaction(|cm| {
@@ -79,9 +127,19 @@ where
 
                 Ok(ret)
             });
-            let (gas_used, backtrace, machine) = cm.finish();
-            (Ok((result, gas_used, backtrace)), machine)
+            #[cfg(all(debug_assertions, feature = "check-receipts"))]
+            check_gas_charges(cm.gas_tracker());
+            let (
+                FinishRet {
+                    gas_used,
+                    backtrace,
+                    exec_trace,
+                },
+                machine,
+            ) = cm.finish();
+            (Ok((result, gas_used, backtrace, exec_trace)), machine)
         })?;
+        let gas_used = gas_used.as_i64();
 
         // Extract the exit code and build the result of the message application.
         let receipt = match res {
@@ -148,28 +206,52 @@ where
             Some(ApplyFailure::MessageBacktrace(backtrace))
         };
 
+        #[cfg(all(debug_assertions, feature = "check-receipts"))]
+        check_receipt(
+            &receipt,
+            failure_info.as_ref(),
+            gas_limit(&msg, apply_kind).as_i64(),
+        );
+
         match apply_kind {
-            ApplyKind::Explicit => self.finish_message(msg, receipt, failure_info, gas_cost),
+            ApplyKind::Explicit => {
+                self.finish_message(msg, receipt, failure_info, exec_trace, gas_cost)
+            }
             ApplyKind::Implicit => Ok(ApplyRet {
                 msg_receipt: receipt,
                 failure_info,
                 penalty: TokenAmount::zero(),
                 miner_tip: TokenAmount::zero(),
+                exec_trace,
+                state_tree_stats: None,
             }),
         }
     }
-}
 
-impl<K> DefaultExecutor<K>
-where
-    K: Kernel,
-{
-    /// Create a new [`DefaultExecutor`] for executing messages on the [`Machine`].
-    pub fn new(m: <K::CallManager as CallManager>::Machine) -> Self {
-        Self(Some(m))
+    /// Re-executes a message that was already applied on chain, on top of the state it was applied
+    /// to, and compares the outcome with its on-chain receipt. The returned [`Replay`] holds the
+    /// execution trace, and any mismatch with the receipt.
+    ///
+    /// The machine must record traces (see [`Config::trace_calls`](crate::Config::trace_calls)):
+    /// tracing doesn't change the gas charged, so the receipt must be reproduced exactly. As with
+    /// [`Executor::execute_message`], the message's state changes are kept.
+    pub fn replay(
+        &mut self,
+        msg: Message,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+        expected: &Receipt,
+    ) -> anyhow::Result<Replay> {
+        if !self.config().trace_calls {
+            return Err(anyhow!("replaying messages requires call tracing"));
+        }
+        let ret = self.execute_message(msg, apply_kind, raw_length)?;
+        let mismatches = compare_receipts(expected, &ret.msg_receipt);
+        Ok(Replay { ret, mismatches })
     }
 
-    /// Flush the state-tree to the underlying blockstore.
+    /// Flush the state-tree to the underlying blockstore, returning the new state root (see
+    /// [`Machine::flush`]).
     pub fn flush(&mut self) -> anyhow::Result<Cid> {
         let k = (&mut **self).flush()?;
         Ok(k)
@@ -192,24 +274,30 @@ where
         apply_kind: ApplyKind,
         raw_length: usize,
     ) -> Result<StdResult<(ActorID, TokenAmount, GasCharge<'static>), ApplyRet>> {
-        msg.check().or_fatal()?;
+        // Implicit messages may leave their gas limit unset, to run without one.
+        if apply_kind == ApplyKind::Explicit || msg.gas_limit != 0 {
+            msg.check().or_fatal()?;
+        }
 
         // TODO We don't like having price lists _inside_ the FVM, but passing
         //  these across the boundary is also a no-go.
         let pl = &self.context().price_list;
 
         let (inclusion_cost, miner_penalty_amount) = match apply_kind {
-            ApplyKind::Implicit => (GasCharge::new("none", 0, 0), Default::default()),
+            ApplyKind::Implicit => (
+                GasCharge::new("none", Gas::ZERO, Gas::ZERO),
+                Default::default(),
+            ),
             ApplyKind::Explicit => {
                 let inclusion_cost = pl.on_chain_message(raw_length);
                 let inclusion_total = inclusion_cost.total();
 
                 // Verify the cost of the message is not over the message gas limit.
-                if inclusion_total > msg.gas_limit {
+                if inclusion_total > Gas::new(msg.gas_limit) {
                     return Ok(Err(ApplyRet::prevalidation_fail(
                         ExitCode::SysErrOutOfGas,
                         format!("Out of gas ({} > {})", inclusion_total, msg.gas_limit),
-                        &self.context().base_fee * inclusion_total,
+                        &self.context().base_fee * BigInt::from(inclusion_total),
                     )));
                 }
 
@@ -218,6 +306,14 @@ where
             }
         };
 
+        if !msg.is_version_supported(self.context().network_version) {
+            return Ok(Err(ApplyRet::prevalidation_fail(
+                ExitCode::SysErrSenderInvalid,
+                format!("Unsupported message version {}", msg.version),
+                miner_penalty_amount,
+            )));
+        }
+
         // Load sender actor state.
         let sender_id = match self
             .state_tree()
@@ -308,6 +404,7 @@ where
         msg: Message,
         receipt: Receipt,
         failure_info: Option<ApplyFailure>,
+        exec_trace: ExecutionTrace,
         gas_cost: BigInt,
     ) -> anyhow::Result<ApplyRet> {
         // NOTE: we don't support old network versions in the FVM, so we always burn.
@@ -319,8 +416,8 @@ where
             miner_penalty,
             ..
         } = GasOutputs::compute(
-            receipt.gas_used,
-            msg.gas_limit,
+            Gas::new(receipt.gas_used),
+            Gas::new(msg.gas_limit),
             &self.context().base_fee,
             &msg.gas_fee_cap,
             &msg.gas_premium,
@@ -361,6 +458,8 @@ where
             failure_info,
             penalty: miner_penalty,
             miner_tip,
+            exec_trace,
+            state_tree_stats: None,
         })
     }
 
@@ -380,3 +479,144 @@ where
         )
     }
 }
+
+/// Checks that the gas used by a message is exactly what was charged. Call managers start with no
+/// gas used, so the charges must add up to the total, unless the message ran out of gas (in which
+/// case the failed charge isn't recorded and all available gas is used).
+#[cfg(all(debug_assertions, feature = "check-receipts"))]
+fn check_gas_charges(gas_tracker: &crate::gas::GasTracker) {
+    if gas_tracker.gas_used() == gas_tracker.gas_available() {
+        return;
+    }
+    let charged: Gas = gas_tracker
+        .charges()
+        .iter()
+        .map(crate::trace::GasChargeTrace::total)
+        .sum();
+    assert_eq!(
+        charged,
+        gas_tracker.gas_used(),
+        "gas used doesn't match the gas charged"
+    );
+}
+
+/// Returns the gas available to a message: its gas limit, or as much gas as can be tracked for an
+/// implicit message without one.
+fn gas_limit(msg: &Message, apply_kind: ApplyKind) -> Gas {
+    match apply_kind {
+        ApplyKind::Implicit if msg.gas_limit == 0 => Gas::new(i64::MAX),
+        _ => Gas::new(msg.gas_limit),
+    }
+}
+
+/// Checks that a receipt is internally consistent.
+#[cfg(all(debug_assertions, feature = "check-receipts"))]
+fn check_receipt(receipt: &Receipt, failure_info: Option<&ApplyFailure>, gas_limit: i64) {
+    assert!(
+        (0..=gas_limit).contains(&receipt.gas_used),
+        "receipt gas used {} is outside of [0, {}]",
+        receipt.gas_used,
+        gas_limit
+    );
+    if receipt.exit_code.is_success() {
+        assert!(
+            failure_info.is_none(),
+            "successful receipt has failure info: {:?}",
+            failure_info
+        );
+    } else {
+        assert!(
+            receipt.return_data.is_empty(),
+            "failed receipt (exit code {:?}) has return data",
+            receipt.exit_code
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::encoding::RawBytes;
+    use fvm_shared::version::NetworkVersion;
+
+    use super::*;
+    use crate::call_manager::DefaultCallManager;
+    use crate::state_tree::ActorState;
+    use crate::testing::{cbor_blake2b_cid, MockMachine};
+    use crate::{DefaultKernel, EMPTY_ARR_CID};
+
+    type TestExecutor = DefaultExecutor<DefaultKernel<DefaultCallManager<MockMachine>>>;
+
+    const ACCOUNT: ActorID = 100;
+    const MULTISIG: ActorID = 101;
+
+    /// Returns an executor over a state holding a funded account and a multisig.
+    fn executor() -> TestExecutor {
+        let mut machine = MockMachine::new(NetworkVersion::V15).unwrap();
+        for (id, typ) in [(ACCOUNT, Type::Account), (MULTISIG, Type::Multisig)] {
+            let code = cbor_blake2b_cid(format!("{:?}", typ).as_bytes());
+            machine.builtin_actors.insert(code, typ);
+            let state = ActorState::new(code, *EMPTY_ARR_CID, TokenAmount::from(1000), 0);
+            machine.state_tree.set_actor_id(id, state).unwrap();
+        }
+        DefaultExecutor::new(machine)
+    }
+
+    /// Returns a message sending nothing from the given actor to itself.
+    fn self_send(from: ActorID, sequence: u64) -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(from),
+            to: Address::new_id(from),
+            sequence,
+            value: TokenAmount::zero(),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::zero(),
+            gas_premium: TokenAmount::zero(),
+        }
+    }
+
+    fn apply(exec: &mut TestExecutor, msg: Message) -> ExitCode {
+        exec.execute_message(msg, ApplyKind::Explicit, 100)
+            .unwrap()
+            .msg_receipt
+            .exit_code
+    }
+
+    #[test]
+    fn applies_messages_from_accounts() {
+        let mut exec = executor();
+        assert_eq!(apply(&mut exec, self_send(ACCOUNT, 0)), ExitCode::Ok);
+        assert_eq!(apply(&mut exec, self_send(ACCOUNT, 1)), ExitCode::Ok);
+        let sender = exec.state_tree().get_actor_id(ACCOUNT).unwrap().unwrap();
+        assert_eq!(sender.sequence, 2);
+    }
+
+    #[test]
+    fn rejects_invalid_senders() {
+        let mut exec = executor();
+        let code = apply(&mut exec, self_send(MULTISIG, 0));
+        assert_eq!(code, ExitCode::SysErrSenderInvalid);
+        let code = apply(&mut exec, self_send(MULTISIG + 1, 0));
+        assert_eq!(code, ExitCode::SysErrSenderInvalid);
+    }
+
+    #[test]
+    fn rejects_invalid_sender_state() {
+        let mut exec = executor();
+        let code = apply(&mut exec, self_send(ACCOUNT, 1));
+        assert_eq!(code, ExitCode::SysErrSenderStateInvalid);
+
+        // The sender can't cover the gas limit at this fee cap.
+        let msg = Message {
+            gas_fee_cap: TokenAmount::from(1),
+            ..self_send(ACCOUNT, 0)
+        };
+        assert_eq!(apply(&mut exec, msg), ExitCode::SysErrSenderStateInvalid);
+
+        // Rejected messages don't bump the sequence.
+        let sender = exec.state_tree().get_actor_id(ACCOUNT).unwrap().unwrap();
+        assert_eq!(sender.sequence, 0);
+    }
+}
@@ -0,0 +1,201 @@
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use cid::Cid;
+use fvm_shared::collections::FixedHashMap;
+use fvm_shared::MethodNum;
+
+use crate::gas::Gas;
+
+/// The wall time and gas spent in an actor method, excluding the calls it made.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodTotals {
+    /// The number of times the method was called.
+    pub calls: u64,
+    /// The wall time spent executing the method.
+    pub wall_time: Duration,
+    /// The gas charged while executing the method.
+    pub gas: Gas,
+}
+
+#[derive(Default)]
+struct Inner {
+    methods: FixedHashMap<(Cid, MethodNum), MethodTotals>,
+    messages: u64,
+}
+
+/// Wall time and gas spent in every actor method, by actor code and method number, aggregated over
+/// all the messages applied by the machines it's installed in (through
+/// [`Config::method_stats`](crate::Config::method_stats)). Meant to find the slowest methods while
+/// replaying the chain.
+///
+/// Time and gas are attributed to the method that spent them: a method calling another actor is
+/// only charged for the time and gas spent outside of the call. Clones share the same totals.
+#[derive(Clone, Default)]
+pub struct MethodStats {
+    inner: Arc<Mutex<Inner>>,
+    report_every: u64,
+}
+
+impl std::fmt::Debug for MethodStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MethodStats")
+            .field("report_every", &self.report_every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MethodStats {
+    /// Creates empty stats, logging a [report](MethodStats::report) (at the info level) every
+    /// `report_every` messages, or never if zero.
+    pub fn new(report_every: u64) -> Self {
+        MethodStats {
+            inner: Default::default(),
+            report_every,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().expect("method stats poisoned")
+    }
+
+    /// Records a call to the given method.
+    pub(crate) fn record(&self, code: Cid, method: MethodNum, wall_time: Duration, gas: Gas) {
+        let mut inner = self.lock();
+        let totals = inner.methods.entry((code, method)).or_default();
+        totals.calls += 1;
+        totals.wall_time += wall_time;
+        totals.gas += gas;
+    }
+
+    /// Counts an applied message, logging the report if it's due.
+    pub(crate) fn message_applied(&self) {
+        let messages = {
+            let mut inner = self.lock();
+            inner.messages += 1;
+            inner.messages
+        };
+        if self.report_every > 0 && messages % self.report_every == 0 {
+            log::info!("{}", self.report());
+        }
+    }
+
+    /// Returns the totals of every method called so far, the slowest first.
+    pub fn methods(&self) -> Vec<(Cid, MethodNum, MethodTotals)> {
+        let mut methods: Vec<_> = self
+            .lock()
+            .methods
+            .iter()
+            .map(|(&(code, method), &totals)| (code, method, totals))
+            .collect();
+        methods.sort_by_key(|(_, _, totals)| std::cmp::Reverse(totals.wall_time));
+        methods
+    }
+
+    /// Formats the totals of every method called so far, one per line, the slowest first.
+    pub fn report(&self) -> String {
+        let methods = self.methods();
+        let mut report = format!("method stats after {} messages:", self.lock().messages);
+        for (code, method, totals) in methods {
+            let _ = write!(
+                report,
+                "\n\t{} method {}: {} calls, {:?}, {} gas",
+                code, method, totals.calls, totals.wall_time, totals.gas
+            );
+        }
+        report
+    }
+
+    /// Forgets all the calls and messages recorded so far.
+    pub fn clear(&self) {
+        *self.lock() = Inner::default();
+    }
+}
+
+struct Frame {
+    start: Instant,
+    gas_used: Gas,
+    nested_time: Duration,
+    nested_gas: Gas,
+}
+
+/// Tracks the calls in progress in a call stack, to tell the time and gas spent by each method
+/// from the time and gas spent by the calls it makes.
+#[derive(Default)]
+pub(crate) struct CallTimer {
+    frames: Vec<Frame>,
+}
+
+impl CallTimer {
+    /// Starts timing a call, given the gas used so far by the call stack.
+    pub fn enter(&mut self, gas_used: Gas) {
+        self.frames.push(Frame {
+            start: Instant::now(),
+            gas_used,
+            nested_time: Duration::ZERO,
+            nested_gas: Gas::ZERO,
+        });
+    }
+
+    /// Stops timing the innermost call, given the gas used so far by the call stack. Returns the
+    /// wall time and gas spent by the call, excluding the calls it made.
+    pub fn exit(&mut self, gas_used: Gas) -> (Duration, Gas) {
+        let frame = self.frames.pop().expect("no call to exit");
+        let time = frame.start.elapsed();
+        let gas = gas_used - frame.gas_used;
+        if let Some(caller) = self.frames.last_mut() {
+            caller.nested_time += time;
+            caller.nested_gas += gas;
+        }
+        (
+            time.saturating_sub(frame.nested_time),
+            gas - frame.nested_gas,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::cbor_blake2b_cid;
+
+    #[test]
+    fn nested_calls_are_excluded() {
+        let mut timer = CallTimer::default();
+        timer.enter(Gas::new(10));
+        timer.enter(Gas::new(15));
+        let (_, nested_gas) = timer.exit(Gas::new(40));
+        assert_eq!(nested_gas, Gas::new(25));
+        let (time, gas) = timer.exit(Gas::new(50));
+        assert_eq!(gas, Gas::new(15));
+        assert!(time < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn aggregates_per_method() {
+        let stats = MethodStats::new(0);
+        let code = cbor_blake2b_cid(b"code");
+        let ms = Duration::from_millis;
+        stats.record(code, 2, ms(1), Gas::new(10));
+        stats.record(code, 3, ms(5), Gas::new(1));
+        stats.clone().record(code, 2, ms(2), Gas::new(20));
+        stats.message_applied();
+
+        let methods = stats.methods();
+        assert_eq!(methods.len(), 2);
+        assert_eq!((methods[0].0, methods[0].1), (code, 3));
+        assert_eq!(
+            methods[1].2,
+            MethodTotals {
+                calls: 2,
+                wall_time: ms(3),
+                gas: Gas::new(30)
+            }
+        );
+        assert!(stats.report().starts_with("method stats after 1 messages:"));
+
+        stats.clear();
+        assert!(stats.methods().is_empty());
+    }
+}
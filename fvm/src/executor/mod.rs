@@ -1,16 +1,32 @@
+mod batch;
 mod default;
+#[cfg(feature = "method-stats")]
+mod method_stats;
+mod replay;
+mod speculative;
 
 use std::fmt::Display;
 
+pub use batch::{
+    BatchExecutor, BatchMessage, BlockOutcome, FatalErrorPolicy, MessageOutcome, SkipReason,
+};
 pub use default::DefaultExecutor;
 use fvm_shared::bigint::{BigInt, Sign};
 use fvm_shared::encoding::RawBytes;
 use fvm_shared::error::ExitCode;
 use fvm_shared::message::Message;
 use fvm_shared::receipt::Receipt;
+#[cfg(feature = "method-stats")]
+pub(crate) use method_stats::CallTimer;
+#[cfg(feature = "method-stats")]
+pub use method_stats::{MethodStats, MethodTotals};
 use num_traits::Zero;
+pub use replay::{compare_receipts, ReceiptMismatch, Replay};
+pub use speculative::{SpeculativeExecutor, StateOverrides};
 
 use crate::call_manager::Backtrace;
+use crate::state_tree::StateTreeStats;
+use crate::trace::ExecutionTrace;
 use crate::Kernel;
 
 /// An executor executes messages on the underlying machine/kernel. It's responsible for:
@@ -71,6 +87,11 @@ pub struct ApplyRet {
     pub miner_tip: BigInt,
     /// Additional failure information for debugging, if any.
     pub failure_info: Option<ApplyFailure>,
+    /// The execution trace, if tracing is enabled.
+    pub exec_trace: ExecutionTrace,
+    /// The work done by the state tree while applying the message, if enabled with
+    /// [`Config::state_tree_stats`](crate::Config::state_tree_stats).
+    pub state_tree_stats: Option<StateTreeStats>,
 }
 
 impl ApplyRet {
@@ -89,6 +110,8 @@ impl ApplyRet {
             penalty: miner_penalty,
             failure_info: Some(ApplyFailure::PreValidation(message.into())),
             miner_tip: BigInt::zero(),
+            exec_trace: ExecutionTrace::new(),
+            state_tree_stats: None,
         }
     }
 
@@ -102,7 +125,7 @@ impl ApplyRet {
 /// 1. Explicit messages may only come from account actors and charge the sending account for gas
 /// consumed.
 /// 2. Implicit messages may come from any actor, ignore the nonce, and charge no gas (but still
-/// account for it).
+/// account for it). An implicit message with a zero gas limit runs without one.
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum ApplyKind {
     Explicit,
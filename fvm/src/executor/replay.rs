@@ -0,0 +1,120 @@
+use std::fmt::Display;
+
+use fvm_shared::encoding::RawBytes;
+use fvm_shared::error::ExitCode;
+use fvm_shared::receipt::Receipt;
+
+use super::ApplyRet;
+
+/// A field of a replayed message's receipt that differs from the receipt recorded on chain.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReceiptMismatch {
+    ExitCode {
+        expected: ExitCode,
+        actual: ExitCode,
+    },
+    ReturnData {
+        expected: RawBytes,
+        actual: RawBytes,
+    },
+    GasUsed {
+        expected: i64,
+        actual: i64,
+    },
+}
+
+impl Display for ReceiptMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReceiptMismatch::ExitCode { expected, actual } => write!(
+                f,
+                "exit code: expected {}, got {}",
+                *expected as u32, *actual as u32
+            ),
+            ReceiptMismatch::ReturnData { expected, actual } => write!(
+                f,
+                "return data: expected {:?}, got {:?}",
+                expected.bytes(),
+                actual.bytes()
+            ),
+            ReceiptMismatch::GasUsed { expected, actual } => {
+                write!(f, "gas used: expected {}, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+/// Returns the fields of the `actual` receipt that differ from the `expected` one.
+pub fn compare_receipts(expected: &Receipt, actual: &Receipt) -> Vec<ReceiptMismatch> {
+    let mut mismatches = Vec::new();
+    if expected.exit_code != actual.exit_code {
+        mismatches.push(ReceiptMismatch::ExitCode {
+            expected: expected.exit_code,
+            actual: actual.exit_code,
+        });
+    }
+    if expected.return_data != actual.return_data {
+        mismatches.push(ReceiptMismatch::ReturnData {
+            expected: expected.return_data.clone(),
+            actual: actual.return_data.clone(),
+        });
+    }
+    if expected.gas_used != actual.gas_used {
+        mismatches.push(ReceiptMismatch::GasUsed {
+            expected: expected.gas_used,
+            actual: actual.gas_used,
+        });
+    }
+    mismatches
+}
+
+/// The outcome of replaying a message (see
+/// [`DefaultExecutor::replay`](super::DefaultExecutor::replay)).
+#[derive(Clone, Debug)]
+pub struct Replay {
+    /// The result of applying the message, including its execution trace.
+    pub ret: ApplyRet,
+    /// How the receipt differs from the expected one. Empty if the message was reproduced.
+    pub mismatches: Vec<ReceiptMismatch>,
+}
+
+impl Replay {
+    /// Returns true if the replay reproduced the expected receipt.
+    pub fn is_reproduced(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mismatched_fields() {
+        let expected = Receipt {
+            exit_code: ExitCode::Ok,
+            return_data: RawBytes::new(vec![1]),
+            gas_used: 100,
+        };
+        assert!(compare_receipts(&expected, &expected.clone()).is_empty());
+
+        let actual = Receipt {
+            exit_code: ExitCode::SysErrOutOfGas,
+            gas_used: 101,
+            ..expected.clone()
+        };
+        assert_eq!(
+            compare_receipts(&expected, &actual),
+            [
+                ReceiptMismatch::ExitCode {
+                    expected: ExitCode::Ok,
+                    actual: ExitCode::SysErrOutOfGas,
+                },
+                ReceiptMismatch::GasUsed {
+                    expected: 100,
+                    actual: 101,
+                },
+            ]
+        );
+    }
+}
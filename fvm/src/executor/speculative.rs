@@ -0,0 +1,204 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_shared::actor::builtin::Manifest;
+use fvm_shared::address::Address;
+use fvm_shared::blockstore::{Blockstore, ReadOnlyBlockstore};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::Message;
+
+use super::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use crate::call_manager::CallManager;
+use crate::externs::Externs;
+use crate::kernel::Context as _;
+use crate::machine::{DefaultMachine, Engine, Machine, MachineContext, ReadOnlyMachine};
+use crate::state_tree::ActorState;
+use crate::{Config, Kernel};
+
+/// Everything needed to construct a machine over the base state.
+struct Snapshot<B, E> {
+    config: Config,
+    engine: Engine,
+    context: MachineContext,
+    builtin_actors: Manifest,
+    blockstore: B,
+    externs: E,
+}
+
+/// Changes made to the base state before a [dry run](SpeculativeExecutor::dry_run), e.g. to
+/// simulate messages from an account that doesn't have the funds yet.
+#[derive(Clone, Debug, Default)]
+pub struct StateOverrides {
+    blocks: Vec<(Cid, Vec<u8>)>,
+    balances: Vec<(Address, TokenAmount)>,
+    heads: Vec<(Address, Cid)>,
+}
+
+impl StateOverrides {
+    /// Makes a block available to the dry run, e.g. the root of a replacement state.
+    pub fn with_block(mut self, k: Cid, block: Vec<u8>) -> Self {
+        self.blocks.push((k, block));
+        self
+    }
+
+    /// Replaces the balance of an existing actor.
+    pub fn with_balance(mut self, actor: Address, balance: TokenAmount) -> Self {
+        self.balances.push((actor, balance));
+        self
+    }
+
+    /// Replaces the state head of an existing actor. The new state must be in the blockstore, or
+    /// added with [`with_block`](Self::with_block).
+    pub fn with_state(mut self, actor: Address, head: Cid) -> Self {
+        self.heads.push((actor, head));
+        self
+    }
+
+    fn apply<B, E>(&self, machine: &mut ReadOnlyMachine<B, E>) -> anyhow::Result<()>
+    where
+        B: Blockstore + 'static,
+        E: Externs + 'static,
+    {
+        for (k, block) in &self.blocks {
+            machine.blockstore().put_keyed(k, block)?;
+        }
+        for (actor, balance) in &self.balances {
+            override_actor(machine, actor, |act| act.balance = balance.clone())?;
+        }
+        for (actor, head) in &self.heads {
+            if !machine.blockstore().has(head)? {
+                return Err(anyhow!(
+                    "state {} of {} isn't in the blockstore",
+                    head,
+                    actor
+                ));
+            }
+            override_actor(machine, actor, |act| act.state = *head)?;
+        }
+        Ok(())
+    }
+}
+
+fn override_actor<B, E>(
+    machine: &mut ReadOnlyMachine<B, E>,
+    actor: &Address,
+    f: impl FnOnce(&mut ActorState),
+) -> anyhow::Result<()>
+where
+    B: Blockstore + 'static,
+    E: Externs + 'static,
+{
+    let state_tree = machine.state_tree_mut();
+    let id = state_tree
+        .lookup_id(actor)
+        .with_context(|| format!("failed to lookup actor {}", actor))?
+        .ok_or_else(|| anyhow!("can't override missing actor {}", actor))?;
+    state_tree.mutate_actor_id(id, |act| {
+        f(act);
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Applies messages on top of a fixed base state without ever modifying it, e.g. to validate
+/// pending messages in a mempool against the head of the chain.
+///
+/// Each message is applied by a fresh machine, in a throwaway layer: it only ever sees the base
+/// state (never the effects of other messages), and everything it writes is discarded once it has
+/// been applied. Machines are put together from the snapshot's already loaded parts, and the
+/// executor is cheap to clone, so messages can be applied concurrently from several threads.
+pub struct SpeculativeExecutor<B, E> {
+    snapshot: Arc<Snapshot<B, E>>,
+}
+
+impl<B, E> Clone for SpeculativeExecutor<B, E> {
+    fn clone(&self) -> Self {
+        SpeculativeExecutor {
+            snapshot: self.snapshot.clone(),
+        }
+    }
+}
+
+impl<B, E> SpeculativeExecutor<B, E>
+where
+    B: Blockstore + Clone + 'static,
+    E: Externs + Clone + 'static,
+{
+    /// Takes a snapshot of the given machine's initial state root and context. Any changes made
+    /// through the machine are ignored.
+    ///
+    /// The blockstore and externs are cloned for every message applied, so they should be cheap
+    /// to clone (e.g. wrapped in an [`Arc`]).
+    pub fn new(machine: ReadOnlyMachine<B, E>) -> Self {
+        let (config, engine, context, builtin_actors, blockstore, externs) = machine.into_parts();
+        SpeculativeExecutor {
+            snapshot: Arc::new(Snapshot {
+                config,
+                engine,
+                context,
+                builtin_actors,
+                blockstore: blockstore.consume(),
+                externs,
+            }),
+        }
+    }
+
+    /// Returns the context messages are applied in, including the base state root.
+    pub fn context(&self) -> &MachineContext {
+        &self.snapshot.context
+    }
+
+    /// Applies a single message on top of the base state, and discards its effects.
+    ///
+    /// See [`Executor::execute_message`] for the meaning of the arguments.
+    pub fn execute_message<K>(
+        &self,
+        msg: Message,
+        apply_kind: ApplyKind,
+        raw_length: usize,
+    ) -> anyhow::Result<ApplyRet>
+    where
+        K: Kernel,
+        K::CallManager: CallManager<Machine = ReadOnlyMachine<B, E>>,
+    {
+        DefaultExecutor::<K>::new(self.machine()?).execute_message(msg, apply_kind, raw_length)
+    }
+
+    /// Applies a sequence of messages, each on top of the effects of the previous ones, to the base
+    /// state with the given overrides applied, and discards their effects. Returns their receipts
+    /// (and execution traces, if enabled in the machine's config).
+    ///
+    /// Messages are applied as in a block: messages failing validation (e.g., with a wrong nonce)
+    /// get a failed receipt, without stopping the run.
+    pub fn dry_run<K, I>(
+        &self,
+        overrides: &StateOverrides,
+        msgs: I,
+    ) -> anyhow::Result<Vec<ApplyRet>>
+    where
+        K: Kernel,
+        K::CallManager: CallManager<Machine = ReadOnlyMachine<B, E>>,
+        I: IntoIterator<Item = (Message, ApplyKind, usize)>,
+    {
+        let mut machine = self.machine()?;
+        overrides.apply(&mut machine)?;
+        let mut exec = DefaultExecutor::<K>::new(machine);
+        msgs.into_iter()
+            .map(|(msg, apply_kind, raw_length)| exec.execute_message(msg, apply_kind, raw_length))
+            .collect()
+    }
+
+    /// Puts together a machine over a throwaway layer on top of the base state.
+    fn machine(&self) -> anyhow::Result<ReadOnlyMachine<B, E>> {
+        let snapshot = &*self.snapshot;
+        DefaultMachine::from_parts(
+            snapshot.config.clone(),
+            snapshot.engine.clone(),
+            snapshot.context.clone(),
+            snapshot.builtin_actors.clone(),
+            ReadOnlyBlockstore::new(snapshot.blockstore.clone()),
+            snapshot.externs.clone(),
+        )
+    }
+}
@@ -1,10 +1,46 @@
 //! This module contains the logic to invoke the node by traversing Boundary A.
 
+use cid::Cid;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
 
-pub trait Externs: Rand + Consensus {}
+pub trait Externs: Rand + Consensus + Chain + CodePolicy + CircSupply {}
+
+/// A node's policy on the code actors may run, e.g. a blocklist of exploited code on a private
+/// network. The default allows all code, as mainnet does.
+pub trait CodePolicy {
+    /// Checks the code of an actor before it's invoked, returning the (non-zero) exit code to
+    /// fail the invocation with if the code is rejected. Plain value transfers aren't checked, as
+    /// they don't run any code.
+    ///
+    /// Every node of a network must apply the same policy, and the result must only depend on the
+    /// code CID: anything else makes nodes disagree on the outcome of messages. Errors are fatal.
+    fn check_code(&self, _code: &Cid) -> anyhow::Result<Option<ExitCode>> {
+        Ok(None)
+    }
+}
+
+/// The circulating supply of FIL, as computed by the node.
+pub trait CircSupply {
+    /// Returns the circulating supply at the beginning of the given epoch, reported to actors by
+    /// the `network::total_fil_circ_supply` syscall from network version 15 on. By default (when
+    /// `None`), the supply the machine was constructed with is reported instead, which is only
+    /// correct if the machine doesn't outlive the epoch. Errors are fatal.
+    fn total_fil_circ_supply(&self, _epoch: ChainEpoch) -> anyhow::Result<Option<TokenAmount>> {
+        Ok(None)
+    }
+}
+
+/// Chain related methods.
+pub trait Chain {
+    /// Gets the CID of the tipset at the given epoch of the chain being executed. If the epoch is
+    /// a null round, returns the CID of the last tipset before it.
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid>;
+}
 
 /// Consensus related methods.
 pub trait Consensus {
@@ -26,7 +62,7 @@ pub trait Rand {
         pers: DomainSeparationTag,
         round: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]>;
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]>;
 
     /// Gets 32 bytes of randomness for ChainRand paramaterized by the DomainSeparationTag,
     /// ChainEpoch, Entropy from the latest beacon entry.
@@ -35,5 +71,5 @@ pub trait Rand {
         pers: DomainSeparationTag,
         round: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]>;
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]>;
 }
@@ -0,0 +1,43 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::{GasCharge, PriceList};
+use crate::syscalls::WASM_PAGE_SIZE;
+
+/// The metering policy: how much gas operations actually cost, on top of the prices of the
+/// [`PriceList`].
+///
+/// Every charge goes through [`GasMeter::meter`] before being applied by the
+/// [`GasTracker`](super::GasTracker), and the charge for memory growth is computed by
+/// [`GasMeter::on_memory_grow`]. The defaults implement the Filecoin metering rules
+/// ([`StandardGasMeter`]); experimental policies (e.g., per instruction class, or charging for
+/// memory over time) can override them and be installed in the
+/// [`Config`](crate::Config::gas_meter) of a machine.
+///
+/// The meter is shared by all the call stacks of a machine (and its clones), so stateful policies
+/// need interior mutability. Any policy other than the standard one makes the FVM compute results
+/// that differ from the network's.
+pub trait GasMeter: Send + Sync {
+    /// Returns the charge to actually apply for the given charge.
+    fn meter<'a>(&self, charge: GasCharge<'a>) -> GasCharge<'a> {
+        charge
+    }
+
+    /// Returns the charge for an actor growing its memory from `current` to `desired` bytes. The
+    /// initial memory of an actor isn't charged for.
+    fn on_memory_grow(
+        &self,
+        price_list: &PriceList,
+        current: usize,
+        desired: usize,
+    ) -> GasCharge<'static> {
+        let pages = (desired - current + WASM_PAGE_SIZE - 1) / WASM_PAGE_SIZE;
+        price_list.on_memory_grow(pages)
+    }
+}
+
+/// The Filecoin gas metering rules: charges are applied as priced.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardGasMeter;
+
+impl GasMeter for StandardGasMeter {}
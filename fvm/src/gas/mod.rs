@@ -1,31 +1,65 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use std::sync::Arc;
+
 pub use self::charge::GasCharge;
+pub use self::meter::{GasMeter, StandardGasMeter};
 pub(crate) use self::outputs::GasOutputs;
-pub use self::price_list::{price_list_by_network_version, PriceList};
+pub use self::price_list::{inclusion_gas, price_list_by_network_version, PriceList};
+pub use self::registry::PriceListRegistry;
+pub use self::units::Gas;
 use crate::kernel::{ExecutionError, Result};
+#[cfg(feature = "check-receipts")]
+use crate::trace::GasChargeTrace;
 
 mod charge;
+mod meter;
 mod outputs;
 mod price_list;
+mod registry;
+mod units;
 
 pub struct GasTracker {
-    gas_available: i64,
-    gas_used: i64,
+    gas_available: Gas,
+    gas_used: Gas,
+    meter: Arc<dyn GasMeter>,
+    /// The charges successfully applied so far.
+    #[cfg(feature = "check-receipts")]
+    charges: Vec<GasChargeTrace>,
 }
 
 impl GasTracker {
-    pub fn new(gas_available: i64, gas_used: i64) -> Self {
+    pub fn new(gas_available: Gas, gas_used: Gas) -> Self {
+        Self::with_meter(gas_available, gas_used, Arc::new(StandardGasMeter))
+    }
+
+    /// Creates a tracker metering charges with the given policy.
+    pub fn with_meter(gas_available: Gas, gas_used: Gas, meter: Arc<dyn GasMeter>) -> Self {
         Self {
             gas_available,
             gas_used,
+            meter,
+            #[cfg(feature = "check-receipts")]
+            charges: Vec::new(),
         }
     }
 
+    /// Returns the metering policy of this tracker.
+    pub fn meter(&self) -> &dyn GasMeter {
+        &*self.meter
+    }
+
     /// Safely consumes gas and returns an out of gas error if there is not sufficient
     /// enough gas remaining for charge.
     pub fn charge_gas(&mut self, charge: GasCharge) -> Result<()> {
+        let charge = self.meter.meter(charge);
+        self.charge_metered(charge)
+    }
+
+    /// Like [`GasTracker::charge_gas`], for a charge that has already gone through
+    /// [`GasMeter::meter`].
+    pub fn charge_metered(&mut self, charge: GasCharge) -> Result<()> {
         let to_use = charge.total();
         match self.gas_used.checked_add(to_use) {
             None => {
@@ -40,6 +74,8 @@ impl GasTracker {
                     self.gas_used = self.gas_available;
                     Err(ExecutionError::OutOfGas)
                 } else {
+                    #[cfg(feature = "check-receipts")]
+                    self.charges.push(GasChargeTrace::from(&charge));
                     self.gas_used = used;
                     Ok(())
                 }
@@ -48,14 +84,21 @@ impl GasTracker {
     }
 
     /// Getter for gas available.
-    pub fn gas_available(&self) -> i64 {
+    pub fn gas_available(&self) -> Gas {
         self.gas_available
     }
 
     /// Getter for gas used.
-    pub fn gas_used(&self) -> i64 {
+    pub fn gas_used(&self) -> Gas {
         self.gas_used
     }
+
+    /// Returns the charges successfully applied since this tracker was created. Charges that ran
+    /// out of gas aren't included.
+    #[cfg(feature = "check-receipts")]
+    pub fn charges(&self) -> &[GasChargeTrace] {
+        &self.charges
+    }
 }
 
 #[cfg(test)]
@@ -64,11 +107,63 @@ mod tests {
 
     #[test]
     fn basic_gas_tracker() {
-        let mut t = GasTracker::new(20, 10);
-        t.charge_gas(GasCharge::new("", 5, 0)).unwrap();
-        assert_eq!(t.gas_used(), 15);
-        t.charge_gas(GasCharge::new("", 5, 0)).unwrap();
-        assert_eq!(t.gas_used(), 20);
-        assert!(t.charge_gas(GasCharge::new("", 1, 0)).is_err())
+        let mut t = GasTracker::new(Gas::new(20), Gas::new(10));
+        t.charge_gas(GasCharge::new("", Gas::new(5), Gas::ZERO))
+            .unwrap();
+        assert_eq!(t.gas_used(), Gas::new(15));
+        t.charge_gas(GasCharge::new("", Gas::new(5), Gas::ZERO))
+            .unwrap();
+        assert_eq!(t.gas_used(), Gas::new(20));
+        assert!(t
+            .charge_gas(GasCharge::new("", Gas::new(1), Gas::ZERO))
+            .is_err())
+    }
+
+    #[test]
+    fn custom_gas_meter() {
+        /// Doubles the compute gas of every charge.
+        struct DoubleCompute;
+
+        impl GasMeter for DoubleCompute {
+            fn meter<'a>(&self, charge: GasCharge<'a>) -> GasCharge<'a> {
+                GasCharge::new(charge.name, charge.compute_gas * 2, charge.storage_gas)
+            }
+        }
+
+        let mut t = GasTracker::with_meter(Gas::new(20), Gas::ZERO, Arc::new(DoubleCompute));
+        t.charge_gas(GasCharge::new("", Gas::new(5), Gas::new(3)))
+            .unwrap();
+        assert_eq!(t.gas_used(), Gas::new(13));
+        t.charge_metered(GasCharge::new("", Gas::new(5), Gas::ZERO))
+            .unwrap();
+        assert_eq!(t.gas_used(), Gas::new(18));
+        assert!(t
+            .charge_gas(GasCharge::new("", Gas::new(2), Gas::ZERO))
+            .is_err());
+    }
+
+    #[test]
+    fn unlimited_gas_tracker() {
+        let max = Gas::new(i64::MAX);
+        let mut t = GasTracker::new(max, Gas::ZERO);
+        t.charge_gas(GasCharge::new("", max, max)).unwrap();
+        assert_eq!(t.gas_used(), max);
+        assert!(t
+            .charge_gas(GasCharge::new("", Gas::new(1), Gas::ZERO))
+            .is_err());
+        assert_eq!(t.gas_used(), max);
+    }
+
+    #[cfg(feature = "check-receipts")]
+    #[test]
+    fn gas_tracker_records_charges() {
+        let mut t = GasTracker::new(Gas::new(20), Gas::ZERO);
+        t.charge_gas(GasCharge::new("a", Gas::new(5), Gas::new(5)))
+            .unwrap();
+        assert!(t
+            .charge_gas(GasCharge::new("b", Gas::new(15), Gas::ZERO))
+            .is_err());
+        let names: Vec<_> = t.charges().iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["a"]);
     }
 }
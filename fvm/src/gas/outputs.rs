@@ -2,8 +2,11 @@ use std::convert::TryFrom;
 
 use fvm_shared::bigint::BigInt;
 use fvm_shared::econ::TokenAmount;
+use num_traits::ToPrimitive;
 
-#[derive(Clone, Default)]
+use super::Gas;
+
+#[derive(Clone, Debug, Default, PartialEq)]
 pub(crate) struct GasOutputs {
     pub base_fee_burn: TokenAmount,
     pub over_estimation_burn: TokenAmount,
@@ -11,14 +14,72 @@ pub(crate) struct GasOutputs {
     pub miner_tip: TokenAmount,
     pub refund: TokenAmount,
 
-    pub gas_refund: i64,
-    pub gas_burned: i64,
+    pub gas_refund: Gas,
+    pub gas_burned: Gas,
 }
 
 impl GasOutputs {
     pub fn compute(
-        gas_used: i64,
-        gas_limit: i64,
+        gas_used: Gas,
+        gas_limit: Gas,
+        base_fee: &TokenAmount,
+        fee_cap: &TokenAmount,
+        gas_premium: &TokenAmount,
+    ) -> Self {
+        // Fees and their products with gas amounts fit in 128 bits in practice, sparing the
+        // allocations of big integer arithmetic on every message.
+        Self::compute_u128(gas_used, gas_limit, base_fee, fee_cap, gas_premium).unwrap_or_else(
+            || Self::compute_big(gas_used, gas_limit, base_fee, fee_cap, gas_premium),
+        )
+    }
+
+    /// Computes the outputs with 128-bit arithmetic. Returns `None` if an input or an
+    /// intermediate value doesn't fit in a `u128`, in which case the outputs must be computed
+    /// with [`GasOutputs::compute_big`].
+    fn compute_u128(
+        gas_used: Gas,
+        gas_limit: Gas,
+        base_fee: &TokenAmount,
+        fee_cap: &TokenAmount,
+        gas_premium: &TokenAmount,
+    ) -> Option<Self> {
+        let used = u128::try_from(gas_used.as_i64()).ok()?;
+        let limit = u128::try_from(gas_limit.as_i64()).ok()?;
+        let base_fee = base_fee.to_u128()?;
+        let fee_cap = fee_cap.to_u128()?;
+        let gas_premium = gas_premium.to_u128()?;
+
+        let base_fee_to_pay = base_fee.min(fee_cap);
+        let miner_tip = gas_premium.min(fee_cap - base_fee_to_pay);
+
+        let (gas_refund, gas_burned) = compute_gas_overestimation_burn(gas_used, gas_limit);
+        let burned = u128::try_from(gas_burned.as_i64()).ok()?;
+
+        let base_fee_burn = base_fee_to_pay.checked_mul(used)?;
+        let over_estimation_burn = base_fee_to_pay.checked_mul(burned)?;
+        let miner_penalty = (base_fee - base_fee_to_pay).checked_mul(used.checked_add(burned)?)?;
+        let miner_tip = miner_tip.checked_mul(limit)?;
+        let refund = fee_cap
+            .checked_mul(limit)?
+            .checked_sub(base_fee_burn)?
+            .checked_sub(miner_tip)?
+            .checked_sub(over_estimation_burn)?;
+
+        Some(GasOutputs {
+            base_fee_burn: base_fee_burn.into(),
+            over_estimation_burn: over_estimation_burn.into(),
+            miner_penalty: miner_penalty.into(),
+            miner_tip: miner_tip.into(),
+            refund: refund.into(),
+            gas_refund,
+            gas_burned,
+        })
+    }
+
+    /// Computes the outputs with big integer arithmetic, whatever the inputs.
+    fn compute_big(
+        gas_used: Gas,
+        gas_limit: Gas,
         base_fee: &TokenAmount,
         fee_cap: &TokenAmount,
         gas_premium: &TokenAmount,
@@ -29,26 +90,26 @@ impl GasOutputs {
 
         if base_fee > fee_cap {
             base_fee_to_pay = fee_cap;
-            out.miner_penalty = (base_fee - fee_cap) * gas_used
+            out.miner_penalty = (base_fee - fee_cap) * BigInt::from(gas_used)
         }
 
-        out.base_fee_burn = base_fee_to_pay * gas_used;
+        out.base_fee_burn = base_fee_to_pay * BigInt::from(gas_used);
 
         let mut miner_tip = gas_premium.clone();
         if &(base_fee_to_pay + &miner_tip) > fee_cap {
             miner_tip = fee_cap - base_fee_to_pay;
         }
-        out.miner_tip = &miner_tip * gas_limit;
+        out.miner_tip = &miner_tip * BigInt::from(gas_limit);
 
         let (out_gas_refund, out_gas_burned) = compute_gas_overestimation_burn(gas_used, gas_limit);
         out.gas_refund = out_gas_refund;
         out.gas_burned = out_gas_burned;
 
-        if out.gas_burned != 0 {
-            out.over_estimation_burn = base_fee_to_pay * out.gas_burned;
-            out.miner_penalty += (base_fee - base_fee_to_pay) * out.gas_burned;
+        if out.gas_burned != Gas::ZERO {
+            out.over_estimation_burn = base_fee_to_pay * BigInt::from(out.gas_burned);
+            out.miner_penalty += (base_fee - base_fee_to_pay) * BigInt::from(out.gas_burned);
         }
-        let required_funds = fee_cap * gas_limit;
+        let required_funds = fee_cap * BigInt::from(gas_limit);
         let refund =
             required_funds - &out.base_fee_burn - &out.miner_tip - &out.over_estimation_burn;
         out.refund = refund;
@@ -57,27 +118,145 @@ impl GasOutputs {
     }
 }
 
-fn compute_gas_overestimation_burn(gas_used: i64, gas_limit: i64) -> (i64, i64) {
+fn compute_gas_overestimation_burn(gas_used: Gas, gas_limit: Gas) -> (Gas, Gas) {
     const GAS_OVERUSE_NUM: i64 = 11;
     const GAS_OVERUSE_DENOM: i64 = 10;
 
-    if gas_used == 0 {
-        return (0, gas_limit);
+    if gas_used == Gas::ZERO {
+        return (Gas::ZERO, gas_limit);
     }
 
-    let mut over = gas_limit - (GAS_OVERUSE_NUM * gas_used) / GAS_OVERUSE_DENOM;
-    if over < 0 {
-        return (gas_limit - gas_used, 0);
+    let mut over = gas_limit - (gas_used * GAS_OVERUSE_NUM) / GAS_OVERUSE_DENOM;
+    if over.is_negative() {
+        return (gas_limit - gas_used, Gas::ZERO);
     }
 
     if over > gas_used {
         over = gas_used;
     }
 
-    let mut gas_to_burn: BigInt = (gas_limit - gas_used).into();
-    gas_to_burn *= over;
-    gas_to_burn /= gas_used;
+    // (gas_limit - gas_used) * over can overflow an i64, but not an i128.
+    let gas_to_burn = i128::from((gas_limit - gas_used).as_i64()) * i128::from(over.as_i64())
+        / i128::from(gas_used.as_i64());
 
-    let gas_to_burn = i64::try_from(gas_to_burn).unwrap();
+    let gas_to_burn = Gas::new(i64::try_from(gas_to_burn).unwrap());
     (gas_limit - gas_used - gas_to_burn, gas_to_burn)
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::BLOCK_GAS_LIMIT;
+    use num_traits::{Signed, Zero};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Generates a gas limit and an amount of gas used within it.
+    fn gas() -> impl Strategy<Value = (Gas, Gas)> {
+        (0..=BLOCK_GAS_LIMIT)
+            .prop_flat_map(|limit| (Just(limit), 0..=limit))
+            .prop_map(|(limit, used)| (Gas::new(limit), Gas::new(used)))
+    }
+
+    fn token_amount() -> impl Strategy<Value = TokenAmount> {
+        prop_oneof![
+            Just(TokenAmount::zero()),
+            (0..=1_000u64).prop_map(TokenAmount::from),
+            any::<u64>().prop_map(TokenAmount::from),
+            // Overflow 128 bits, on their own or once multiplied by an amount of gas.
+            any::<u128>().prop_map(TokenAmount::from),
+            any::<u128>().prop_map(|n| TokenAmount::from(n) << 64),
+        ]
+    }
+
+    fn compute(used: i64, limit: i64, base_fee: u64, fee_cap: u64, premium: u64) -> GasOutputs {
+        GasOutputs::compute(
+            Gas::new(used),
+            Gas::new(limit),
+            &base_fee.into(),
+            &fee_cap.into(),
+            &premium.into(),
+        )
+    }
+
+    #[test]
+    fn known_outputs() {
+        // Of the 1000 unused units of gas, 900 are burned for over-estimation.
+        let out = compute(1000, 2000, 10, 20, 5);
+        assert_eq!(
+            (out.gas_refund, out.gas_burned),
+            (Gas::new(100), Gas::new(900))
+        );
+        assert_eq!(out.base_fee_burn, TokenAmount::from(10_000));
+        assert_eq!(out.over_estimation_burn, TokenAmount::from(9_000));
+        assert_eq!(out.miner_tip, TokenAmount::from(10_000));
+        assert_eq!(out.refund, TokenAmount::from(11_000));
+        assert!(out.miner_penalty.is_zero());
+
+        // The fee cap is below the base fee: the miner pays the difference on all the gas used
+        // and burned, and gets no tip.
+        let out = compute(1000, 2000, 30, 20, 5);
+        assert_eq!(out.base_fee_burn, TokenAmount::from(20_000));
+        assert_eq!(out.over_estimation_burn, TokenAmount::from(18_000));
+        assert_eq!(out.miner_penalty, TokenAmount::from(19_000));
+        assert!(out.miner_tip.is_zero());
+        assert_eq!(out.refund, TokenAmount::from(2_000));
+
+        // Nothing used: the whole limit is burned.
+        let out = compute(0, 2000, 10, 20, 5);
+        assert_eq!(out.gas_burned, Gas::new(2000));
+        assert_eq!(out.over_estimation_burn, TokenAmount::from(20_000));
+        assert_eq!(out.refund, TokenAmount::from(10_000));
+    }
+
+    proptest! {
+        #[test]
+        fn settlement_balances(
+            (gas_limit, gas_used) in gas(),
+            base_fee in token_amount(),
+            fee_cap in token_amount(),
+            gas_premium in token_amount(),
+        ) {
+            let out = GasOutputs::compute(gas_used, gas_limit, &base_fee, &fee_cap, &gas_premium);
+
+            // Every unit of gas is either used, refunded, or burned.
+            prop_assert_eq!(gas_used + out.gas_refund + out.gas_burned, gas_limit);
+            prop_assert!(!out.gas_refund.is_negative());
+            prop_assert!(!out.gas_burned.is_negative());
+
+            // The sender is charged fee_cap * gas_limit up front, which must be exactly accounted
+            // for by the burns, the miner tip, and the refund.
+            let deducted = &fee_cap * BigInt::from(gas_limit);
+            prop_assert_eq!(
+                &out.base_fee_burn + &out.over_estimation_burn + &out.miner_tip + &out.refund,
+                deducted
+            );
+            for amount in [
+                &out.base_fee_burn,
+                &out.over_estimation_burn,
+                &out.miner_penalty,
+                &out.miner_tip,
+                &out.refund,
+            ] {
+                prop_assert!(!amount.is_negative(), "negative output: {}", amount);
+            }
+        }
+
+        #[test]
+        fn u128_matches_big(
+            (gas_limit, gas_used) in gas(),
+            base_fee in token_amount(),
+            fee_cap in token_amount(),
+            premium in token_amount(),
+        ) {
+            let big = GasOutputs::compute_big(gas_used, gas_limit, &base_fee, &fee_cap, &premium);
+            match GasOutputs::compute_u128(gas_used, gas_limit, &base_fee, &fee_cap, &premium) {
+                Some(out) => prop_assert_eq!(out, big),
+                // Only big fees may not fit.
+                None => prop_assert!(
+                    [&base_fee, &fee_cap, &premium].iter().any(|fee| fee.bits() > 64)
+                ),
+            }
+        }
+    }
+}
@@ -1,61 +1,95 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use ahash::AHashMap;
+use anyhow::anyhow;
+use fvm_shared::collections::FixedHashMap;
+use fvm_shared::constants::METHOD_SEND;
 use fvm_shared::crypto::signature::SignatureType;
 use fvm_shared::econ::TokenAmount;
+use fvm_shared::message::SignedMessage;
 use fvm_shared::piece::PieceInfo;
 use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredPoStProof, RegisteredSealProof, ReplicaUpdateInfo,
     SealVerifyInfo, WindowPoStVerifyInfo,
 };
 use fvm_shared::version::NetworkVersion;
-use fvm_shared::{MethodNum, METHOD_SEND};
+use fvm_shared::MethodNum;
 use lazy_static::lazy_static;
 use num_traits::Zero;
+use serde::{Deserialize, Serialize};
 
-use super::GasCharge;
+use super::{Gas, GasCharge};
 
 lazy_static! {
     static ref OH_SNAP_PRICES: PriceList = PriceList {
         compute_gas_multiplier: 1,
         storage_gas_multiplier: 1300,
 
-        on_chain_message_compute_base: 38863,
-        on_chain_message_storage_base: 36,
-        on_chain_message_storage_per_byte: 1,
+        on_chain_message_compute_base: Gas::new(38863),
+        on_chain_message_storage_base: Gas::new(36),
+        on_chain_message_storage_per_byte: Gas::new(1),
 
-        on_chain_return_value_per_byte: 1,
+        on_chain_return_value_per_byte: Gas::new(1),
 
-        send_base: 29233,
-        send_transfer_funds: 27500,
-        send_transfer_only_premium: 159672,
-        send_invoke_method: -5377,
+        send_base: Gas::new(29233),
+        send_transfer_funds: Gas::new(27500),
+        send_transfer_only_premium: Gas::new(159672),
+        send_invoke_method: Gas::new(-5377),
+        // Parameters passed between actors are covered by the IPLD charges of the blocks holding
+        // them on this network (see SKYR_PRICES).
+        send_params_per_byte: Gas::ZERO,
 
-        ipld_get_base: 114617,
-        ipld_put_base: 353640,
-        ipld_put_per_byte: 1,
+        ipld_get_base: Gas::new(114617),
+        ipld_put_base: Gas::new(353640),
+        ipld_put_per_byte: Gas::new(1),
 
-        create_actor_compute: 1108454,
-        create_actor_storage: 36 + 40,
-        delete_actor: -(36 + 40),
+        // Memory growth is priced from network version 16 on (see SKYR_PRICES).
+        memory_grow_per_page: Gas::ZERO,
 
-        bls_sig_cost: 16598605,
-        secp256k1_sig_cost: 1637292,
+        // Actors only pay for their syscalls on this network, not for the code they execute.
+        wasm_exec_per_fuel: Gas::ZERO,
 
-        hashing_base: 31355,
-        compute_unsealed_sector_cid_base: 98647,
-        verify_seal_base: 2000, // TODO revisit potential removal of this
+        // Loading actor code is covered by the invocation base price on this network.
+        code_load_per_byte: Gas::ZERO,
 
-        verify_aggregate_seal_base: 0,
+        // Scanning new state for links is covered by the IPLD put prices on this network.
+        block_scan_per_byte: Gas::ZERO,
+
+        // Resolving a single address is free on this network, so batches are too (see
+        // SKYR_PRICES).
+        resolve_address: Gas::ZERO,
+
+        // Drawing randomness is free on this network, whatever the entropy (see SKYR_PRICES).
+        get_randomness_base: Gas::ZERO,
+        get_randomness_per_byte: Gas::ZERO,
+
+        get_tipset_cid: Gas::new(50000), // TODO benchmark
+
+        create_actor_compute: Gas::new(1108454),
+        create_actor_storage: Gas::new(36 + 40),
+        delete_actor: Gas::new(-(36 + 40)),
+
+        bls_sig_cost: Gas::new(16598605),
+        secp256k1_sig_cost: Gas::new(1637292),
+        // Signatures and hashes are priced per operation on this network, whatever the input size
+        // (see SKYR_PRICES).
+        bls_sig_per_byte: Gas::ZERO,
+        secp256k1_sig_per_byte: Gas::ZERO,
+
+        hashing_base: Gas::new(31355),
+        hashing_per_byte: Gas::ZERO,
+        compute_unsealed_sector_cid_base: Gas::new(98647),
+        verify_seal_base: Gas::new(2000), // TODO revisit potential removal of this
+
+        verify_aggregate_seal_base: Gas::ZERO,
         verify_aggregate_seal_per: [
             (
                 RegisteredSealProof::StackedDRG32GiBV1P1,
-                449900
+                Gas::new(449900)
             ),
             (
                 RegisteredSealProof::StackedDRG64GiBV1P1,
-                359272
+                Gas::new(359272)
             )
         ].iter().copied().collect(),
         verify_aggregate_seal_steps: [
@@ -63,14 +97,14 @@ lazy_static! {
                 RegisteredSealProof::StackedDRG32GiBV1P1,
                 StepCost (
                     vec![
-                        Step{start: 4, cost: 103994170},
-                        Step{start: 7, cost: 112356810},
-                        Step{start: 13, cost: 122912610},
-                        Step{start: 26, cost: 137559930},
-                        Step{start: 52, cost: 162039100},
-                        Step{start: 103, cost: 210960780},
-                        Step{start: 205, cost: 318351180},
-                        Step{start: 410, cost: 528274980},
+                        Step{start: 4, cost: Gas::new(103994170)},
+                        Step{start: 7, cost: Gas::new(112356810)},
+                        Step{start: 13, cost: Gas::new(122912610)},
+                        Step{start: 26, cost: Gas::new(137559930)},
+                        Step{start: 52, cost: Gas::new(162039100)},
+                        Step{start: 103, cost: Gas::new(210960780)},
+                        Step{start: 205, cost: Gas::new(318351180)},
+                        Step{start: 410, cost: Gas::new(528274980)},
                     ]
                 )
             ),
@@ -78,14 +112,14 @@ lazy_static! {
                 RegisteredSealProof::StackedDRG64GiBV1P1,
                 StepCost (
                     vec![
-                        Step{start: 4, cost: 102581240},
-                        Step{start: 7, cost: 110803030},
-                        Step{start: 13, cost: 120803700},
-                        Step{start: 26, cost: 134642130},
-                        Step{start: 52, cost: 157357890},
-                        Step{start: 103, cost: 203017690},
-                        Step{start: 205, cost: 304253590},
-                        Step{start: 410, cost: 509880640},
+                        Step{start: 4, cost: Gas::new(102581240)},
+                        Step{start: 7, cost: Gas::new(110803030)},
+                        Step{start: 13, cost: Gas::new(120803700)},
+                        Step{start: 26, cost: Gas::new(134642130)},
+                        Step{start: 52, cost: Gas::new(157357890)},
+                        Step{start: 103, cost: Gas::new(203017690)},
+                        Step{start: 205, cost: Gas::new(304253590)},
+                        Step{start: 410, cost: Gas::new(509880640)},
                     ]
                 )
             )
@@ -93,28 +127,28 @@ lazy_static! {
         .cloned()
         .collect(),
 
-        verify_consensus_fault: 495422,
-        verify_replica_update: 36316136,
+        verify_consensus_fault: Gas::new(495422),
+        verify_replica_update: Gas::new(36316136),
         verify_post_lookup: [
             (
                 RegisteredPoStProof::StackedDRGWindow512MiBV1,
                 ScalingCost {
-                    flat: 117680921,
-                    scale: 43780,
+                    flat: Gas::new(117680921),
+                    scale: Gas::new(43780),
                 },
             ),
             (
                 RegisteredPoStProof::StackedDRGWindow32GiBV1,
                 ScalingCost {
-                    flat: 117680921,
-                    scale: 43780,
+                    flat: Gas::new(117680921),
+                    scale: Gas::new(43780),
                 },
             ),
             (
                 RegisteredPoStProof::StackedDRGWindow64GiBV1,
                 ScalingCost {
-                    flat: 117680921,
-                    scale: 43780,
+                    flat: Gas::new(117680921),
+                    scale: Gas::new(43780),
                 },
             ),
         ]
@@ -123,25 +157,54 @@ lazy_static! {
         .collect(),
         verify_post_discount: false,
     };
+
+    /// The prices of network version 16, where actors start paying for their own resource usage
+    /// rather than only for their syscalls.
+    static ref SKYR_PRICES: PriceList = PriceList {
+        // A page is 64KiB: about a tenth of a gas unit per byte.
+        memory_grow_per_page: Gas::new(6554),
+        // Roughly one gas unit per wasm instruction.
+        wasm_exec_per_fuel: Gas::new(1),
+        // Invoking an actor costs the same whether or not its code was compiled already, so the
+        // price covers reading and compiling the code: a 1MiB actor costs about 4M gas to invoke.
+        code_load_per_byte: Gas::new(4),
+        // Scanning a block for links costs about as much per byte as a wasm instruction.
+        block_scan_per_byte: Gas::new(1),
+        // Blake2b hashes about a byte per nanosecond, i.e. 10 gas per byte. Signed plaintexts are
+        // hashed before verifying the signature, so they cost as much per byte.
+        hashing_per_byte: Gas::new(10),
+        bls_sig_per_byte: Gas::new(10),
+        secp256k1_sig_per_byte: Gas::new(10),
+        // Parameters are copied into the callee's block registry, then read by the callee.
+        send_params_per_byte: Gas::new(2),
+        // The entropy is hashed along with the drawn randomness.
+        get_randomness_per_byte: Gas::new(10),
+        // Each address resolved in a batch walks the init actor's address map, mostly over blocks
+        // that are cached already: a fraction of the price of loading a block.
+        resolve_address: Gas::new(20000),
+        ..OH_SNAP_PRICES.clone()
+    };
 }
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct ScalingCost {
-    flat: i64,
-    scale: i64,
+    flat: Gas,
+    scale: Gas,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct StepCost(Vec<Step>);
 
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub(crate) struct Step {
     start: i64,
-    cost: i64,
+    cost: Gas,
 }
 
 impl StepCost {
-    pub(crate) fn lookup(&self, x: i64) -> i64 {
+    pub(crate) fn lookup(&self, x: i64) -> Gas {
         let mut i: i64 = 0;
         while i < self.0.len() as i64 {
             if self.0[i as usize].start > x {
@@ -151,14 +214,19 @@ impl StepCost {
         }
         i -= 1;
         if i < 0 {
-            return 0;
+            return Gas::ZERO;
         }
         self.0[i as usize].cost
     }
 }
 
 /// Provides prices for operations in the VM
-#[derive(Clone, Debug)]
+///
+/// Price lists can be (de)serialized, e.g. to define custom prices for a devnet (see
+/// [`PriceListRegistry`](super::PriceListRegistry)). Every field must be specified, and proof
+/// specific costs are given as lists of `{ proof = <registered proof number>, cost = ... }` entries.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct PriceList {
     /// Compute gas charge multiplier
     // * This multiplier is not currently applied to anything, but is matching lotus.
@@ -174,79 +242,181 @@ pub struct PriceList {
     /// Together, these account for the cost of message propagation and validation,
     /// up to but excluding any actual processing by the VM.
     /// This is the cost a block producer burns when including an invalid message.
-    pub(crate) on_chain_message_compute_base: i64,
-    pub(crate) on_chain_message_storage_base: i64,
-    pub(crate) on_chain_message_storage_per_byte: i64,
+    pub(crate) on_chain_message_compute_base: Gas,
+    pub(crate) on_chain_message_storage_base: Gas,
+    pub(crate) on_chain_message_storage_per_byte: Gas,
 
     /// Gas cost charged to the originator of a non-nil return value produced
     /// by an on-chain message is given by:
     ///   len(return value)*OnChainReturnValuePerByte
-    pub(crate) on_chain_return_value_per_byte: i64,
+    pub(crate) on_chain_return_value_per_byte: Gas,
 
     /// Gas cost for any message send execution(including the top-level one
     /// initiated by an on-chain message).
     /// This accounts for the cost of loading sender and receiver actors and
     /// (for top-level messages) incrementing the sender's sequence number.
     /// Load and store of actor sub-state is charged separately.
-    pub(crate) send_base: i64,
+    pub(crate) send_base: Gas,
 
     /// Gas cost charged, in addition to SendBase, if a message send
     /// is accompanied by any nonzero currency amount.
     /// Accounts for writing receiver's new balance (the sender's state is
     /// already accounted for).
-    pub(crate) send_transfer_funds: i64,
+    pub(crate) send_transfer_funds: Gas,
 
     /// Gas cost charged, in addition to SendBase, if message only transfers funds.
-    pub(crate) send_transfer_only_premium: i64,
+    pub(crate) send_transfer_only_premium: Gas,
 
     /// Gas cost charged, in addition to SendBase, if a message invokes
     /// a method on the receiver.
     /// Accounts for the cost of loading receiver code and method dispatch.
-    pub(crate) send_invoke_method: i64,
+    pub(crate) send_invoke_method: Gas,
+
+    /// Gas cost charged for every byte of parameters an actor passes when sending a message to
+    /// another actor.
+    pub(crate) send_params_per_byte: Gas,
 
     /// Gas cost (Base + len*PerByte) for any Get operation to the IPLD store
     /// in the runtime VM context.
-    pub(crate) ipld_get_base: i64,
+    pub(crate) ipld_get_base: Gas,
 
     /// Gas cost (Base + len*PerByte) for any Put operation to the IPLD store
     /// in the runtime VM context.
     /// Note: these costs should be significantly higher than the costs for Get
     /// operations, since they reflect not only serialization/deserialization
     /// but also persistent storage of chain data.
-    pub(crate) ipld_put_base: i64,
-    pub(crate) ipld_put_per_byte: i64,
+    pub(crate) ipld_put_base: Gas,
+    pub(crate) ipld_put_per_byte: Gas,
+
+    /// Gas cost charged for every wasm page (64KiB) by which an actor grows its memory.
+    pub(crate) memory_grow_per_page: Gas,
+
+    /// Gas cost charged for every unit of wasmtime fuel consumed by an actor (roughly, for every
+    /// wasm instruction executed).
+    pub(crate) wasm_exec_per_fuel: Gas,
+
+    /// Gas cost charged for every byte of wasm bytecode of the actor an invocation targets,
+    /// whether or not its code was already compiled.
+    pub(crate) code_load_per_byte: Gas,
+
+    /// Gas cost charged for every byte of the new blocks scanned for links when an actor sets its
+    /// state root, to find the blocks to keep.
+    pub(crate) block_scan_per_byte: Gas,
+
+    /// Gas cost charged for each address resolved in a batch.
+    pub(crate) resolve_address: Gas,
+
+    /// Gas cost for drawing randomness from the ticket chain or the beacon.
+    pub(crate) get_randomness_base: Gas,
+    /// Gas cost for every byte of entropy mixed into drawn randomness.
+    pub(crate) get_randomness_per_byte: Gas,
+
+    /// Gas cost for looking up the CID of a past tipset.
+    pub(crate) get_tipset_cid: Gas,
 
     /// Gas cost for creating a new actor (via InitActor's Exec method).
     /// Note: this costs assume that the extra will be partially or totally refunded while
     /// the base is covering for the put.
-    pub(crate) create_actor_compute: i64,
-    pub(crate) create_actor_storage: i64,
+    pub(crate) create_actor_compute: Gas,
+    pub(crate) create_actor_storage: Gas,
 
     /// Gas cost for deleting an actor.
     /// Note: this partially refunds the create cost to incentivise the deletion of the actors.
-    pub(crate) delete_actor: i64,
+    pub(crate) delete_actor: Gas,
 
     /// Gas cost for verifying bls signature
-    pub(crate) bls_sig_cost: i64,
+    pub(crate) bls_sig_cost: Gas,
     /// Gas cost for verifying secp256k1 signature
-    pub(crate) secp256k1_sig_cost: i64,
-
-    pub(crate) hashing_base: i64,
-
-    pub(crate) compute_unsealed_sector_cid_base: i64,
-    pub(crate) verify_seal_base: i64,
+    pub(crate) secp256k1_sig_cost: Gas,
+    /// Gas cost for every byte of plaintext covered by a bls signature being verified
+    pub(crate) bls_sig_per_byte: Gas,
+    /// Gas cost for every byte of plaintext covered by a secp256k1 signature being verified
+    pub(crate) secp256k1_sig_per_byte: Gas,
+
+    /// Gas cost (Base + len*PerByte) for hashing data with blake2b.
+    pub(crate) hashing_base: Gas,
+    pub(crate) hashing_per_byte: Gas,
+
+    pub(crate) compute_unsealed_sector_cid_base: Gas,
+    pub(crate) verify_seal_base: Gas,
     #[allow(unused)]
-    pub(crate) verify_aggregate_seal_base: i64,
-    pub(crate) verify_aggregate_seal_per: AHashMap<RegisteredSealProof, i64>,
-    pub(crate) verify_aggregate_seal_steps: AHashMap<RegisteredSealProof, StepCost>,
-
-    pub(crate) verify_post_lookup: AHashMap<RegisteredPoStProof, ScalingCost>,
+    pub(crate) verify_aggregate_seal_base: Gas,
+    #[serde(with = "proof_costs")]
+    pub(crate) verify_aggregate_seal_per: FixedHashMap<RegisteredSealProof, Gas>,
+    #[serde(with = "proof_costs")]
+    pub(crate) verify_aggregate_seal_steps: FixedHashMap<RegisteredSealProof, StepCost>,
+
+    #[serde(with = "proof_costs")]
+    pub(crate) verify_post_lookup: FixedHashMap<RegisteredPoStProof, ScalingCost>,
     pub(crate) verify_post_discount: bool,
-    pub(crate) verify_consensus_fault: i64,
-    pub(crate) verify_replica_update: i64,
+    pub(crate) verify_consensus_fault: Gas,
+    pub(crate) verify_replica_update: Gas,
 }
 
 impl PriceList {
+    /// Checks that the prices are sane. This is only needed for price lists that weren't compiled
+    /// in, e.g. ones loaded from a file.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.compute_gas_multiplier <= 0 || self.storage_gas_multiplier <= 0 {
+            return Err(anyhow!("gas multipliers must be positive"));
+        }
+        macro_rules! check_non_negative {
+            ($prices:ident; $($field:ident),* $(,)?) => {$(
+                if $prices.$field.is_negative() {
+                    return Err(anyhow!(
+                        "{} must not be negative, got {}",
+                        stringify!($field),
+                        $prices.$field
+                    ));
+                }
+            )*};
+        }
+        // The invoke method discount and the delete actor refund are the only negative prices.
+        check_non_negative!(self;
+            on_chain_message_compute_base,
+            on_chain_message_storage_base,
+            on_chain_message_storage_per_byte,
+            on_chain_return_value_per_byte,
+            send_base,
+            send_transfer_funds,
+            send_transfer_only_premium,
+            send_params_per_byte,
+            ipld_get_base,
+            ipld_put_base,
+            ipld_put_per_byte,
+            memory_grow_per_page,
+            wasm_exec_per_fuel,
+            code_load_per_byte,
+            block_scan_per_byte,
+            resolve_address,
+            get_randomness_base,
+            get_randomness_per_byte,
+            get_tipset_cid,
+            create_actor_compute,
+            create_actor_storage,
+            bls_sig_cost,
+            secp256k1_sig_cost,
+            bls_sig_per_byte,
+            secp256k1_sig_per_byte,
+            hashing_base,
+            hashing_per_byte,
+            compute_unsealed_sector_cid_base,
+            verify_seal_base,
+            verify_aggregate_seal_base,
+            verify_consensus_fault,
+            verify_replica_update,
+        );
+        for (proof, steps) in &self.verify_aggregate_seal_steps {
+            if !steps.0.windows(2).all(|w| w[0].start < w[1].start) {
+                return Err(anyhow!(
+                    "aggregate seal steps for {:?} must be sorted by start",
+                    proof
+                ));
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the gas required for storing a message of a given size in the chain.
     #[inline]
     pub fn on_chain_message(&self, msg_size: usize) -> GasCharge<'static> {
@@ -263,8 +433,8 @@ impl PriceList {
     pub fn on_chain_return_value(&self, data_size: usize) -> GasCharge<'static> {
         GasCharge::new(
             "OnChainReturnValue",
-            0,
-            data_size as i64 * self.on_chain_return_value_per_byte * self.storage_gas_multiplier,
+            Gas::ZERO,
+            self.on_chain_return_value_per_byte * data_size as i64 * self.storage_gas_multiplier,
         )
     }
     /// Returns the gas required when invoking a method.
@@ -284,12 +454,21 @@ impl PriceList {
         if method_num != METHOD_SEND {
             ret += self.send_invoke_method;
         }
-        GasCharge::new("OnMethodInvocation", ret, 0)
+        GasCharge::new("OnMethodInvocation", ret, Gas::ZERO)
+    }
+    /// Returns the gas required for passing parameters from an actor to another.
+    #[inline]
+    pub fn on_send_params(&self, params_size: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnSendParams",
+            self.send_params_per_byte * params_size as i64,
+            Gas::ZERO,
+        )
     }
     /// Returns the gas required for storing an object.
     #[inline]
     pub fn on_ipld_get(&self) -> GasCharge<'static> {
-        GasCharge::new("OnIpldGet", self.ipld_get_base, 0)
+        GasCharge::new("OnIpldGet", self.ipld_get_base, Gas::ZERO)
     }
     /// Returns the gas required for storing an object.
     #[inline]
@@ -297,9 +476,72 @@ impl PriceList {
         GasCharge::new(
             "OnIpldPut",
             self.ipld_put_base,
-            data_size as i64 * self.ipld_put_per_byte * self.storage_gas_multiplier,
+            self.ipld_put_per_byte * data_size as i64 * self.storage_gas_multiplier,
+        )
+    }
+    /// Returns the gas required for growing an actor's memory by the given number of pages.
+    #[inline]
+    pub fn on_memory_grow(&self, pages: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnMemoryGrow",
+            self.memory_grow_per_page
+                .saturating_mul(i64::try_from(pages).unwrap_or(i64::MAX)),
+            Gas::ZERO,
+        )
+    }
+    /// Returns the gas required for executing wasm code consuming the given amount of fuel.
+    #[inline]
+    pub fn on_wasm_exec(&self, fuel: u64) -> GasCharge<'static> {
+        let fuel = i64::try_from(fuel).unwrap_or(i64::MAX);
+        GasCharge::new(
+            "OnWasmExec",
+            self.wasm_exec_per_fuel.saturating_mul(fuel),
+            Gas::ZERO,
+        )
+    }
+    /// Returns the gas required for loading actor code of the given size.
+    #[inline]
+    pub fn on_code_load(&self, code_size: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnCodeLoad",
+            self.code_load_per_byte
+                .saturating_mul(i64::try_from(code_size).unwrap_or(i64::MAX)),
+            Gas::ZERO,
+        )
+    }
+    /// Returns the gas required for scanning new blocks of the given total size for links.
+    #[inline]
+    pub fn on_block_scan(&self, bytes: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnBlockScan",
+            self.block_scan_per_byte
+                .saturating_mul(i64::try_from(bytes).unwrap_or(i64::MAX)),
+            Gas::ZERO,
+        )
+    }
+    /// Returns the gas required for resolving a batch of addresses.
+    #[inline]
+    pub fn on_resolve_addresses(&self, count: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnResolveAddresses",
+            self.resolve_address * count as i64,
+            Gas::ZERO,
         )
     }
+    /// Returns the gas required for drawing randomness with the given amount of entropy.
+    #[inline]
+    pub fn on_get_randomness(&self, entropy_size: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnGetRandomness",
+            self.get_randomness_base + self.get_randomness_per_byte * entropy_size as i64,
+            Gas::ZERO,
+        )
+    }
+    /// Returns the gas required for looking up the CID of a past tipset.
+    #[inline]
+    pub fn on_get_tipset_cid(&self) -> GasCharge<'static> {
+        GasCharge::new("OnGetTipsetCid", self.get_tipset_cid, Gas::ZERO)
+    }
     /// Returns the gas required for creating an actor.
     #[inline]
     pub fn on_create_actor(&self) -> GasCharge<'static> {
@@ -314,23 +556,35 @@ impl PriceList {
     pub fn on_delete_actor(&self) -> GasCharge<'static> {
         GasCharge::new(
             "OnDeleteActor",
-            0,
+            Gas::ZERO,
             self.delete_actor * self.storage_gas_multiplier,
         )
     }
     /// Returns gas required for signature verification.
     #[inline]
-    pub fn on_verify_signature(&self, sig_type: SignatureType) -> GasCharge<'static> {
-        let val = match sig_type {
-            SignatureType::BLS => self.bls_sig_cost,
-            SignatureType::Secp256k1 => self.secp256k1_sig_cost,
+    pub fn on_verify_signature(
+        &self,
+        sig_type: SignatureType,
+        plaintext_len: usize,
+    ) -> GasCharge<'static> {
+        let (base, per_byte) = match sig_type {
+            SignatureType::BLS => (self.bls_sig_cost, self.bls_sig_per_byte),
+            SignatureType::Secp256k1 => (self.secp256k1_sig_cost, self.secp256k1_sig_per_byte),
         };
-        GasCharge::new("OnVerifySignature", val, 0)
+        GasCharge::new(
+            "OnVerifySignature",
+            base + per_byte * plaintext_len as i64,
+            Gas::ZERO,
+        )
     }
     /// Returns gas required for hashing data.
     #[inline]
-    pub fn on_hashing(&self, _: usize) -> GasCharge<'static> {
-        GasCharge::new("OnHashing", self.hashing_base, 0)
+    pub fn on_hashing(&self, data_len: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnHashing",
+            self.hashing_base + self.hashing_per_byte * data_len as i64,
+            Gas::ZERO,
+        )
     }
     /// Returns gas required for computing unsealed sector Cid.
     #[inline]
@@ -342,13 +596,13 @@ impl PriceList {
         GasCharge::new(
             "OnComputeUnsealedSectorCid",
             self.compute_unsealed_sector_cid_base,
-            0,
+            Gas::ZERO,
         )
     }
     /// Returns gas required for seal verification.
     #[inline]
     pub fn on_verify_seal(&self, _info: &SealVerifyInfo) -> GasCharge<'static> {
-        GasCharge::new("OnVerifySeal", self.verify_seal_base, 0)
+        GasCharge::new("OnVerifySeal", self.verify_seal_base, Gas::ZERO)
     }
     #[inline]
     pub fn on_verify_aggregate_seals(
@@ -381,14 +635,18 @@ impl PriceList {
         let num = aggregate.infos.len() as i64;
         GasCharge::new(
             "OnVerifyAggregateSeals",
-            per_proof * num + step.lookup(num),
-            0,
+            *per_proof * num + step.lookup(num),
+            Gas::ZERO,
         )
     }
     /// Returns gas required for replica verification.
     #[inline]
     pub fn on_verify_replica_update(&self, _replica: &ReplicaUpdateInfo) -> GasCharge<'static> {
-        GasCharge::new("OnVerifyReplicaUpdate", self.verify_replica_update, 0)
+        GasCharge::new(
+            "OnVerifyReplicaUpdate",
+            self.verify_replica_update,
+            Gas::ZERO,
+        )
     }
     /// Returns gas required for PoSt verification.
     #[inline]
@@ -404,21 +662,180 @@ impl PriceList {
                 .expect("512MiB lookup must exist in price table")
         });
 
-        let mut gas_used = cost.flat + info.challenged_sectors.len() as i64 * cost.scale;
+        let mut gas_used = cost.flat + cost.scale * info.challenged_sectors.len() as i64;
         if self.verify_post_discount {
-            gas_used /= 2;
+            gas_used = gas_used / 2;
         }
 
-        GasCharge::new("OnVerifyPost", gas_used, 0)
+        GasCharge::new("OnVerifyPost", gas_used, Gas::ZERO)
     }
     /// Returns gas required for verifying consensus fault.
     #[inline]
     pub fn on_verify_consensus_fault(&self) -> GasCharge<'static> {
-        GasCharge::new("OnVerifyConsensusFault", self.verify_consensus_fault, 0)
+        GasCharge::new(
+            "OnVerifyConsensusFault",
+            self.verify_consensus_fault,
+            Gas::ZERO,
+        )
+    }
+}
+
+/// (De)serializes proof-indexed costs as a list of `{ proof, cost }` entries, sorted by proof.
+/// Proofs can't be map keys in most formats (e.g., TOML only supports string keys).
+mod proof_costs {
+    use std::hash::Hash;
+
+    use fvm_shared::collections::FixedHashMap;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(deny_unknown_fields)]
+    struct Entry<P, C> {
+        proof: P,
+        cost: C,
+    }
+
+    pub fn serialize<S, P, C>(costs: &FixedHashMap<P, C>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        P: Serialize + Copy,
+        i64: From<P>,
+        C: Serialize,
+    {
+        let mut entries: Vec<_> = costs
+            .iter()
+            .map(|(proof, cost)| Entry {
+                proof: *proof,
+                cost,
+            })
+            .collect();
+        entries.sort_by_key(|e| i64::from(e.proof));
+        entries.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D, P, C>(deserializer: D) -> Result<FixedHashMap<P, C>, D::Error>
+    where
+        D: Deserializer<'de>,
+        P: Deserialize<'de> + Eq + Hash,
+        C: Deserialize<'de>,
+    {
+        let entries: Vec<Entry<P, C>> = Deserialize::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|e| (e.proof, e.cost)).collect())
     }
 }
 
 /// Returns gas price list by NetworkVersion for gas consumption.
-pub fn price_list_by_network_version(_: NetworkVersion) -> PriceList {
-    OH_SNAP_PRICES.clone()
+pub fn price_list_by_network_version(nv: NetworkVersion) -> PriceList {
+    if nv >= NetworkVersion::V16 {
+        SKYR_PRICES.clone()
+    } else {
+        OH_SNAP_PRICES.clone()
+    }
+}
+
+/// Returns the gas charged for including a message on chain at the given network version, which
+/// its gas limit must cover at least. Takes the length of the serialized unsigned message and the
+/// type of signature it will be signed with (see [`SignedMessage::chain_length`]), so that
+/// wallets can estimate it before signing.
+///
+/// These are the network's prices: machines configured with custom prices (see
+/// [`PriceListRegistry`](super::PriceListRegistry)) may charge differently.
+pub fn inclusion_gas(nv: NetworkVersion, unsigned_len: usize, sig_type: SignatureType) -> Gas {
+    let chain_length = SignedMessage::chain_length(unsigned_len, sig_type);
+    price_list_by_network_version(nv)
+        .on_chain_message(chain_length)
+        .total()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resource_usage_is_priced_from_v16() {
+        for nv in [NetworkVersion::V14, NetworkVersion::V15] {
+            let prices = price_list_by_network_version(nv);
+            assert_eq!(prices.on_memory_grow(1).total(), Gas::ZERO);
+            assert_eq!(prices.on_wasm_exec(1).total(), Gas::ZERO);
+            assert_eq!(prices.on_code_load(1).total(), Gas::ZERO);
+            assert_eq!(prices.on_block_scan(1).total(), Gas::ZERO);
+        }
+
+        let prices = price_list_by_network_version(NetworkVersion::V16);
+        assert_eq!(prices.on_memory_grow(2).total(), Gas::new(2 * 6554));
+        assert_eq!(prices.on_wasm_exec(3).total(), Gas::new(3));
+        assert_eq!(prices.on_code_load(4).total(), Gas::new(4 * 4));
+        assert_eq!(prices.on_block_scan(5).total(), Gas::new(5));
+
+        // Everything else is unchanged.
+        assert_eq!(prices.send_base, OH_SNAP_PRICES.send_base);
+    }
+
+    #[test]
+    fn hashing_and_signatures_are_priced_by_length_from_v16() {
+        for nv in [NetworkVersion::V15, NetworkVersion::V16] {
+            let prices = price_list_by_network_version(nv);
+            let hashing = prices.on_hashing(100).total() - prices.on_hashing(0).total();
+            let mut sigs = [SignatureType::BLS, SignatureType::Secp256k1]
+                .map(|typ| {
+                    prices.on_verify_signature(typ, 100).total()
+                        - prices.on_verify_signature(typ, 0).total()
+                })
+                .into_iter();
+            if nv < NetworkVersion::V16 {
+                assert_eq!(hashing, Gas::ZERO);
+                assert!(sigs.all(|sig| sig == Gas::ZERO));
+            } else {
+                assert_eq!(hashing, Gas::new(1000));
+                assert!(sigs.all(|sig| sig == Gas::new(1000)));
+            }
+        }
+    }
+
+    #[test]
+    fn send_params_are_priced_by_length_from_v16() {
+        let charge = |nv| {
+            price_list_by_network_version(nv)
+                .on_send_params(100)
+                .total()
+        };
+        assert_eq!(charge(NetworkVersion::V15), Gas::ZERO);
+        assert_eq!(charge(NetworkVersion::V16), Gas::new(200));
+    }
+
+    #[test]
+    fn randomness_is_priced_by_entropy_from_v16() {
+        let charge = |nv, len| {
+            price_list_by_network_version(nv)
+                .on_get_randomness(len)
+                .total()
+        };
+        assert_eq!(
+            charge(NetworkVersion::V15, 100),
+            charge(NetworkVersion::V15, 0)
+        );
+        let per_byte = charge(NetworkVersion::V16, 100) - charge(NetworkVersion::V16, 0);
+        assert_eq!(per_byte, Gas::new(1000));
+    }
+
+    #[test]
+    fn address_resolution_is_priced_from_v16() {
+        let charge = |nv| {
+            price_list_by_network_version(nv)
+                .on_resolve_addresses(3)
+                .total()
+        };
+        assert_eq!(charge(NetworkVersion::V15), Gas::ZERO);
+        assert_eq!(charge(NetworkVersion::V16), Gas::new(3 * 20000));
+    }
+
+    #[test]
+    fn resource_charges_saturate() {
+        let prices = price_list_by_network_version(NetworkVersion::V16);
+        let max = Gas::new(i64::MAX);
+        assert_eq!(prices.on_memory_grow(usize::MAX).total(), max);
+        assert_eq!(prices.on_wasm_exec(u64::MAX).total(), max);
+        assert_eq!(prices.on_code_load(usize::MAX).total(), max);
+        assert_eq!(prices.on_block_scan(usize::MAX).total(), max);
+    }
 }
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[cfg(feature = "price-list-files")]
+use anyhow::{anyhow, Context as _};
+use fvm_shared::version::NetworkVersion;
+
+use super::{price_list_by_network_version, PriceList};
+
+/// Price lists by network version. Network versions without a registered price list use the
+/// compiled-in prices.
+///
+/// With the `price-list-files` feature, registries can be loaded from TOML or JSON definitions
+/// mapping network versions to price lists (see [`PriceList`] for the format), letting devnets
+/// experiment with gas prices without recompiling:
+///
+/// ```toml
+/// [15]
+/// compute_gas_multiplier = 1
+/// storage_gas_multiplier = 1300
+/// # ...
+///
+/// [[15.verify_post_lookup]]
+/// proof = 5
+/// cost = { flat = 117680921, scale = 43780 }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct PriceListRegistry {
+    price_lists: Arc<BTreeMap<NetworkVersion, PriceList>>,
+}
+
+impl PriceListRegistry {
+    /// Registers the price list to use for the given network version, replacing the existing one.
+    pub fn with_price_list(mut self, nv: NetworkVersion, price_list: PriceList) -> Self {
+        Arc::make_mut(&mut self.price_lists).insert(nv, price_list);
+        self
+    }
+
+    /// Returns the price list for the given network version.
+    pub fn price_list(&self, nv: NetworkVersion) -> PriceList {
+        match self.price_lists.get(&nv) {
+            Some(price_list) => price_list.clone(),
+            None => price_list_by_network_version(nv),
+        }
+    }
+
+    /// Returns true if no custom price lists have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.price_lists.is_empty()
+    }
+
+    /// Loads a registry from a TOML definition.
+    #[cfg(feature = "price-list-files")]
+    pub fn from_toml(definition: &str) -> anyhow::Result<Self> {
+        let price_lists = toml::from_str(definition).context("invalid price list definition")?;
+        Self::from_definitions(price_lists)
+    }
+
+    /// Loads a registry from a JSON definition.
+    #[cfg(feature = "price-list-files")]
+    pub fn from_json(definition: &str) -> anyhow::Result<Self> {
+        let price_lists =
+            serde_json::from_str(definition).context("invalid price list definition")?;
+        Self::from_definitions(price_lists)
+    }
+
+    #[cfg(feature = "price-list-files")]
+    fn from_definitions(price_lists: BTreeMap<String, PriceList>) -> anyhow::Result<Self> {
+        let mut registry = PriceListRegistry::default();
+        for (nv, price_list) in price_lists {
+            let nv = nv
+                .parse::<u32>()
+                .ok()
+                .and_then(|nv| NetworkVersion::try_from(nv).ok())
+                .ok_or_else(|| anyhow!("unknown network version: {}", nv))?;
+            price_list
+                .validate()
+                .with_context(|| format!("invalid price list for network version {}", nv))?;
+            registry = registry.with_price_list(nv, price_list);
+        }
+        Ok(registry)
+    }
+}
+
+#[cfg(all(test, feature = "price-list-files"))]
+mod tests {
+    use super::*;
+    use crate::gas::Gas;
+
+    /// Serializes the compiled-in price list for the given version as a TOML definition.
+    fn default_definition(nv: NetworkVersion) -> toml::Value {
+        let mut definition = toml::value::Table::new();
+        definition.insert(
+            nv.to_string(),
+            toml::Value::try_from(price_list_by_network_version(nv)).unwrap(),
+        );
+        toml::Value::Table(definition)
+    }
+
+    fn set_price(definition: &mut toml::Value, nv: NetworkVersion, field: &str, price: i64) {
+        definition[&nv.to_string()]
+            .as_table_mut()
+            .unwrap()
+            .insert(field.into(), toml::Value::Integer(price));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut definition = default_definition(NetworkVersion::V15);
+        set_price(&mut definition, NetworkVersion::V15, "send_base", 1);
+
+        let registry = PriceListRegistry::from_toml(&definition.to_string()).unwrap();
+        assert!(!registry.is_empty());
+        assert_eq!(
+            registry.price_list(NetworkVersion::V15).send_base,
+            Gas::new(1)
+        );
+        // Other versions keep the compiled-in prices.
+        assert_eq!(
+            registry.price_list(NetworkVersion::V14).send_base,
+            price_list_by_network_version(NetworkVersion::V14).send_base
+        );
+
+        let json = serde_json::to_string(&registry.price_list(NetworkVersion::V15)).unwrap();
+        let registry = PriceListRegistry::from_json(&format!(r#"{{"15": {}}}"#, json)).unwrap();
+        assert_eq!(
+            registry.price_list(NetworkVersion::V15).send_base,
+            Gas::new(1)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_definitions() {
+        let mut definition = default_definition(NetworkVersion::V15);
+        set_price(&mut definition, NetworkVersion::V15, "no_such_price", 1);
+        assert!(PriceListRegistry::from_toml(&definition.to_string()).is_err());
+
+        let mut definition = default_definition(NetworkVersion::V15);
+        set_price(&mut definition, NetworkVersion::V15, "ipld_get_base", -1);
+        assert!(PriceListRegistry::from_toml(&definition.to_string()).is_err());
+
+        let mut definition = default_definition(NetworkVersion::V15);
+        definition["15"].as_table_mut().unwrap().remove("send_base");
+        assert!(PriceListRegistry::from_toml(&definition.to_string()).is_err());
+
+        let definition = default_definition(NetworkVersion::V15)
+            .to_string()
+            .replace("[15", "[99");
+        assert!(PriceListRegistry::from_toml(&definition).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use fvm_shared::bigint::BigInt;
+use serde::{Deserialize, Serialize};
+
+/// An amount of gas.
+///
+/// Gas can be negative (some operations are refunds). Amounts of gas can be added together, and
+/// scaled by plain integers (e.g., a per-byte price times a number of bytes), but they only turn
+/// into token amounts when explicitly converted to a [`BigInt`] during settlement.
+#[derive(
+    Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Gas(i64);
+
+impl Gas {
+    pub const ZERO: Gas = Gas(0);
+
+    pub const fn new(gas: i64) -> Self {
+        Gas(gas)
+    }
+
+    /// Returns the raw amount, e.g. to fill in a receipt or a trace.
+    pub const fn as_i64(self) -> i64 {
+        self.0
+    }
+
+    pub const fn is_negative(self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, rhs: Gas) -> Option<Gas> {
+        self.0.checked_add(rhs.0).map(Gas)
+    }
+
+    pub fn checked_sub(self, rhs: Gas) -> Option<Gas> {
+        self.0.checked_sub(rhs.0).map(Gas)
+    }
+
+    pub fn saturating_add(self, rhs: Gas) -> Gas {
+        Gas(self.0.saturating_add(rhs.0))
+    }
+
+    pub fn saturating_sub(self, rhs: Gas) -> Gas {
+        Gas(self.0.saturating_sub(rhs.0))
+    }
+
+    pub fn saturating_mul(self, rhs: i64) -> Gas {
+        Gas(self.0.saturating_mul(rhs))
+    }
+}
+
+impl fmt::Display for Gas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<Gas> for BigInt {
+    fn from(gas: Gas) -> Self {
+        BigInt::from(gas.0)
+    }
+}
+
+impl Add for Gas {
+    type Output = Gas;
+
+    fn add(self, rhs: Gas) -> Gas {
+        Gas(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Gas {
+    fn add_assign(&mut self, rhs: Gas) {
+        self.0 += rhs.0
+    }
+}
+
+impl Sub for Gas {
+    type Output = Gas;
+
+    fn sub(self, rhs: Gas) -> Gas {
+        Gas(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign for Gas {
+    fn sub_assign(&mut self, rhs: Gas) {
+        self.0 -= rhs.0
+    }
+}
+
+impl Neg for Gas {
+    type Output = Gas;
+
+    fn neg(self) -> Gas {
+        Gas(-self.0)
+    }
+}
+
+impl Mul<i64> for Gas {
+    type Output = Gas;
+
+    fn mul(self, rhs: i64) -> Gas {
+        Gas(self.0 * rhs)
+    }
+}
+
+impl Div<i64> for Gas {
+    type Output = Gas;
+
+    fn div(self, rhs: i64) -> Gas {
+        Gas(self.0 / rhs)
+    }
+}
+
+impl Sum for Gas {
+    fn sum<I: Iterator<Item = Gas>>(iter: I) -> Gas {
+        iter.fold(Gas::ZERO, Add::add)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let price = Gas::new(3);
+        assert_eq!(price * 4 + Gas::new(1), Gas::new(13));
+        assert_eq!(-(price - Gas::new(5)), Gas::new(2));
+        assert_eq!((price * 5) / 2, Gas::new(7));
+        assert_eq!([price, price].into_iter().sum::<Gas>(), Gas::new(6));
+        assert_eq!(BigInt::from(Gas::new(-7)), BigInt::from(-7));
+    }
+
+    #[test]
+    fn overflow() {
+        let max = Gas::new(i64::MAX);
+        assert_eq!(max.checked_add(Gas::new(1)), None);
+        assert_eq!(Gas::new(i64::MIN).checked_sub(Gas::new(1)), None);
+        assert_eq!(max.saturating_add(Gas::new(1)), max);
+        assert_eq!(max.saturating_mul(2), max);
+        assert_eq!(max.checked_sub(max), Some(Gas::ZERO));
+    }
+}
@@ -18,15 +18,13 @@ use cid::Cid;
 use fvm_ipld_hamt::Hamt;
 use fvm_shared::address::{Address, Payload};
 use fvm_shared::blockstore::{Blockstore, CborStore};
+use fvm_shared::constants::INIT_ACTOR_ADDR;
 use fvm_shared::encoding::tuple::*;
 use fvm_shared::encoding::Cbor;
 use fvm_shared::{ActorID, HAMT_BIT_WIDTH};
 
-use crate::state_tree::{ActorState, StateTree};
-
-pub const INIT_ACTOR_ADDR: Address = Address::new_id(1);
-
 use crate::kernel::{ClassifyResult, Result};
+use crate::state_tree::{ActorState, StateTree};
 
 #[derive(Serialize_tuple, Deserialize_tuple, Debug)]
 pub struct State {
@@ -87,6 +85,10 @@ impl State {
     /// * Ok and None if the address was not an ID address, and no mapping was
     ///   found during resolution.
     /// * Err, if state was inconsistent.
+    ///
+    /// The address map is flat: it maps addresses directly to actor IDs, never to other
+    /// addresses, so this is a single lookup. [`StateTree::lookup_id`] still bounds resolution as
+    /// a whole, in case the state is corrupted.
     pub fn resolve_address<B>(&self, store: B, addr: &Address) -> Result<Option<u64>>
     where
         B: Blockstore,
@@ -100,4 +102,68 @@ impl State {
 
         Ok(map.get(&addr.to_bytes()).or_fatal()?.copied())
     }
+
+    /// Calls `f` with every address in the address map and the actor ID it's mapped to, in the
+    /// map's order. The map is loaded from the store as it's iterated over rather than all at
+    /// once, so that indexers can scan the address map of a large network (e.g., loading the init
+    /// actor state from a [`StateTree`] over the state root they're indexing).
+    ///
+    /// Iteration stops at the first error returned by `f`.
+    pub fn for_each_address<B, F>(&self, store: B, mut f: F) -> Result<()>
+    where
+        B: Blockstore,
+        F: FnMut(Address, ActorID) -> anyhow::Result<()>,
+    {
+        let map = Hamt::<B, ActorID>::load_with_bit_width(&self.address_map, store, HAMT_BIT_WIDTH)
+            .or_fatal()?;
+        map.for_each_uncached(|key, id| {
+            let addr = Address::from_bytes(key).context("invalid address in address map")?;
+            f(addr, *id)
+        })
+        .or_fatal()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use fvm_shared::blockstore::MemoryBlockstore;
+
+    use super::*;
+
+    #[test]
+    fn iterates_over_addresses() {
+        let bs = MemoryBlockstore::default();
+        let address_map = Hamt::<_, ActorID>::new_with_bit_width(&bs, HAMT_BIT_WIDTH)
+            .flush()
+            .unwrap();
+        let mut state = State {
+            address_map,
+            next_id: 100,
+            network_name: "test".into(),
+        };
+        let addrs: Vec<_> = (0u8..10).map(|i| Address::new_actor(&[i])).collect();
+        for addr in &addrs {
+            state.map_address_to_new_id(&bs, addr).unwrap();
+        }
+
+        let mut mapped = Vec::new();
+        state
+            .for_each_address(&bs, |addr, id| {
+                mapped.push((id, addr));
+                Ok(())
+            })
+            .unwrap();
+        mapped.sort_by_key(|&(id, _)| id);
+        let expected: Vec<_> = (100..).zip(addrs).collect();
+        assert_eq!(mapped, expected);
+
+        let mut calls = 0;
+        let res = state.for_each_address(&bs, |_, _| {
+            calls += 1;
+            Err(anyhow!("stop"))
+        });
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
 }
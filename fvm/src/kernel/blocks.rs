@@ -1,20 +1,45 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::convert::TryInto;
+use std::io::Cursor;
 
 use cid::Cid;
+use fvm_shared::blockstore::scan_for_links;
+use fvm_shared::encoding::DAG_CBOR;
 use thiserror::Error;
 
 #[derive(Default)]
 pub(crate) struct BlockRegistry {
-    blocks: Vec<Block>,
+    /// The generation tagging the handles of this registry.
+    generation: u32,
+    /// Dropped blocks leave an empty slot behind, so that handles are never reused.
+    blocks: Vec<Option<Block>>,
+    /// The blocks linked by the invocation that haven't been written to the blockstore yet.
+    pending: BTreeMap<Cid, Block>,
+    /// The pending blocks reachable from a state root set by the invocation, to be written once it
+    /// returns successfully.
+    committed: BTreeSet<Cid>,
 }
 
-/// Blocks in the block registry are addressed by an ordinal, starting from 1 (`FIRST_ID`).
-/// The zero value is reserved to mean "no data", such as when actor invocations
-/// receive or return no data.
+/// Blocks in the block registry are addressed by an ordinal, starting from 1 (`FIRST_ID`), in the
+/// low [`INDEX_BITS`] bits of their handle. The zero value is reserved to mean "no data", such as
+/// when actor invocations receive or return no data.
+///
+/// Every invocation has its own registry, and the high bits of the handles hold the registry's
+/// generation (the invocation's index in the call stack, modulo [`MAX_GENERATIONS`]), so that a
+/// handle used in an invocation other than the one it was returned to is detected as stale,
+/// rather than silently referring to another block.
 pub type BlockId = u32;
 
 const FIRST_ID: BlockId = 1;
 
+/// The number of bits of a [`BlockId`] holding the block's ordinal.
+pub const INDEX_BITS: u32 = 20;
+
+/// The number of distinct generations; generations wrap around past it.
+pub const MAX_GENERATIONS: u32 = 1 << (BlockId::BITS - INDEX_BITS);
+
+const INDEX_MASK: BlockId = (1 << INDEX_BITS) - 1;
+
 #[derive(Copy, Clone)]
 pub struct BlockStat {
     pub codec: u64,
@@ -68,6 +93,8 @@ pub enum BlockError {
     TooManyBlocks,
     #[error("block handle {0} does not exist, or is illegal")]
     InvalidHandle(BlockId),
+    #[error("block handle {0} belongs to another invocation")]
+    StaleHandle(BlockId),
     #[error("invalid multihash length or code")]
     InvalidMultihashSpec { length: u32, code: u64 },
     #[error("invalid or forbidden ipld codec")]
@@ -77,8 +104,14 @@ pub enum BlockError {
 }
 
 impl BlockRegistry {
-    pub(crate) fn new() -> Self {
-        Self { blocks: Vec::new() }
+    /// Creates the registry of the invocation with the given index in the call stack.
+    pub(crate) fn new(invocation: u32) -> Self {
+        Self {
+            generation: invocation % MAX_GENERATIONS,
+            blocks: Vec::new(),
+            pending: BTreeMap::new(),
+            committed: BTreeSet::new(),
+        }
     }
 }
 
@@ -86,39 +119,175 @@ impl BlockRegistry {
     /// Adds a new block to the registry, and returns a handle to refer to it.
     pub fn put(&mut self, block: Block) -> Result<BlockId, BlockError> {
         // TODO: limit the code types we allow.
-        let mut id: u32 = self
-            .blocks
-            .len()
-            .try_into()
-            .map_err(|_| BlockError::TooManyBlocks)?;
-        id += FIRST_ID;
-        self.blocks.push(block);
-        Ok(id)
+        let ordinal = u32::try_from(self.blocks.len())
+            .ok()
+            .and_then(|len| len.checked_add(FIRST_ID))
+            .filter(|&ordinal| ordinal <= INDEX_MASK)
+            .ok_or(BlockError::TooManyBlocks)?;
+        self.blocks.push(Some(block));
+        Ok((self.generation << INDEX_BITS) | ordinal)
     }
 
     /// Gets the block associated with a block handle.
     pub fn get(&self, id: BlockId) -> Result<&Block, BlockError> {
-        if id < FIRST_ID {
-            return Err(BlockError::InvalidHandle(id));
-        }
-        id.try_into()
-            .ok()
-            .and_then(|idx: usize| self.blocks.get(idx - FIRST_ID as usize))
+        self.index(id)?
+            .and_then(|idx| self.blocks.get(idx))
+            .and_then(Option::as_ref)
             .ok_or(BlockError::InvalidHandle(id))
     }
 
     /// Returns the size & codec of the specified block.
     pub fn stat(&self, id: BlockId) -> Result<BlockStat, BlockError> {
-        if id < FIRST_ID {
-            return Err(BlockError::InvalidHandle(id));
-        }
-        id.try_into()
-            .ok()
-            .and_then(|idx: usize| self.blocks.get(idx - FIRST_ID as usize))
+        self.get(id).map(Block::stat)
+    }
+
+    /// Releases the block associated with a block handle. The handle can't be used afterwards.
+    pub fn remove(&mut self, id: BlockId) -> Result<(), BlockError> {
+        self.index(id)?
+            .and_then(|idx| self.blocks.get_mut(idx))
+            .and_then(Option::take)
+            .map(|_| ())
             .ok_or(BlockError::InvalidHandle(id))
-            .map(|b| BlockStat {
-                codec: b.codec(),
-                size: b.size(),
-            })
+    }
+
+    /// Records a block linked under the given CID. Linked blocks are kept here until they're
+    /// taken to be written to the blockstore, and discarded with the registry otherwise.
+    pub fn link(&mut self, k: Cid, block: Block) {
+        self.pending.insert(k, block);
+    }
+
+    /// Returns the block linked under the given CID, if it hasn't been taken yet.
+    pub fn linked(&self, k: &Cid) -> Option<&Block> {
+        self.pending.get(k)
+    }
+
+    /// Commits the linked blocks reachable from `root` (including `root` itself) through DAG-CBOR
+    /// links, so that they're taken by [`take_committed`](Self::take_committed). Links to blocks
+    /// that weren't linked by this invocation aren't followed, and neither are the links of blocks
+    /// committed already. Returns the number of bytes scanned for links. Nothing is committed if a
+    /// reachable block can't be decoded.
+    pub fn commit_reachable(&mut self, root: &Cid) -> anyhow::Result<usize> {
+        let mut reachable = BTreeSet::new();
+        let mut scanned = 0;
+        let mut stack = vec![*root];
+        while let Some(k) = stack.pop() {
+            let block = match self.pending.get(&k) {
+                Some(block) if !self.committed.contains(&k) && reachable.insert(k) => block,
+                _ => continue,
+            };
+            if block.codec() == DAG_CBOR {
+                scanned += block.data().len();
+                scan_for_links(&mut Cursor::new(block.data()), |link| {
+                    stack.push(link);
+                    Ok(())
+                })?;
+            }
+        }
+        self.committed.extend(reachable);
+        Ok(scanned)
+    }
+
+    /// Takes the committed blocks. The other linked blocks are left pending.
+    pub fn take_committed(&mut self) -> Vec<(Cid, Block)> {
+        std::mem::take(&mut self.committed)
+            .into_iter()
+            .filter_map(|k| self.pending.remove_entry(&k))
+            .collect()
+    }
+
+    /// Takes all the linked blocks, committed or not.
+    pub fn take_linked(&mut self) -> Vec<(Cid, Block)> {
+        self.committed.clear();
+        std::mem::take(&mut self.pending).into_iter().collect()
+    }
+
+    /// Returns the index of a block handle in the registry, if it's a valid ordinal. Fails if the
+    /// handle was issued by a registry of another generation.
+    fn index(&self, id: BlockId) -> Result<Option<usize>, BlockError> {
+        if id >> INDEX_BITS != self.generation {
+            return Err(BlockError::StaleHandle(id));
+        }
+        Ok((id & INDEX_MASK)
+            .checked_sub(FIRST_ID)
+            .and_then(|idx| idx.try_into().ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handles_are_tagged_by_generation() {
+        let mut caller = BlockRegistry::new(0);
+        let mut callee = BlockRegistry::new(1);
+        let caller_id = caller.put(Block::new(0x55, vec![1])).unwrap();
+        let callee_id = callee.put(Block::new(0x55, vec![2])).unwrap();
+        // The first invocation's handles are plain ordinals.
+        assert_eq!(caller_id, FIRST_ID);
+        assert_ne!(caller_id, callee_id);
+
+        assert_eq!(caller.get(caller_id).unwrap().data(), [1]);
+        assert_eq!(callee.get(callee_id).unwrap().data(), [2]);
+        assert!(matches!(
+            caller.get(callee_id),
+            Err(BlockError::StaleHandle(id)) if id == callee_id
+        ));
+        assert!(matches!(
+            callee.remove(caller_id),
+            Err(BlockError::StaleHandle(_))
+        ));
+        assert!(matches!(
+            caller.stat(caller_id + 1),
+            Err(BlockError::InvalidHandle(_))
+        ));
+
+        // Generations wrap around.
+        let mut wrapped = BlockRegistry::new(MAX_GENERATIONS);
+        assert_eq!(wrapped.put(Block::new(0x55, vec![3])).unwrap(), FIRST_ID);
+    }
+
+    #[test]
+    fn commits_reachable_linked_blocks() {
+        use cid::multihash::{Code, MultihashDigest};
+        use fvm_shared::encoding::to_vec;
+
+        let mut registry = BlockRegistry::new(0);
+        let mut link = |codec, data: Vec<u8>| {
+            let k = Cid::new_v1(codec, Code::Blake2b256.digest(&data));
+            registry.link(k, Block::new(codec, data));
+            k
+        };
+        let leaf = link(0x55, vec![1]);
+        let garbage = link(0x55, vec![2]);
+        // Links to a block that wasn't linked by the invocation, e.g. from the previous state.
+        let stored = Cid::new_v1(DAG_CBOR, Code::Blake2b256.digest(b"stored"));
+        let node = link(DAG_CBOR, to_vec(&(leaf, stored)).unwrap());
+        let root = link(DAG_CBOR, to_vec(&(node, leaf)).unwrap());
+        // A CID tag on something other than a byte string.
+        let invalid = link(DAG_CBOR, vec![0xd8, 0x2a, 0x01]);
+
+        // Only the DAG-CBOR blocks are scanned, and only once.
+        let size = |k| registry.linked(k).unwrap().data().len();
+        let cbor_size = size(&root) + size(&node);
+        assert_eq!(registry.commit_reachable(&root).unwrap(), cbor_size);
+        assert_eq!(registry.commit_reachable(&root).unwrap(), 0);
+        // Committed blocks can still be read until they're taken.
+        assert!(registry.linked(&root).is_some());
+        assert!(registry.commit_reachable(&invalid).is_err());
+
+        let taken: Vec<_> = registry
+            .take_committed()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(taken.len(), 3);
+        assert!([leaf, node, root].iter().all(|k| taken.contains(k)));
+        assert!(registry.linked(&root).is_none());
+        assert!(registry.linked(&garbage).is_some());
+        assert!(registry.linked(&invalid).is_some());
+        assert!(registry.take_committed().is_empty());
+        assert_eq!(registry.take_linked().len(), 2);
+        assert!(registry.linked(&garbage).is_none());
     }
 }
@@ -1,49 +1,41 @@
-use std::collections::BTreeMap;
+use std::cell::RefCell;
 use std::convert::{TryFrom, TryInto};
 
 use anyhow::{anyhow, Context as _};
-use byteorder::{BigEndian, WriteBytesExt};
 use cid::Cid;
-use filecoin_proofs_api::seal::{
-    compute_comm_d, verify_aggregate_seal_commit_proofs, verify_seal as proofs_verify_seal,
-};
-use filecoin_proofs_api::update::verify_empty_sector_update_proof;
-use filecoin_proofs_api::{self as proofs, post, seal, ProverId, PublicReplicaInfo, SectorId};
 use fvm_shared::actor::builtin::Type;
-use fvm_shared::address::Protocol;
+use fvm_shared::address::{derive_actor_address, Protocol};
 use fvm_shared::bigint::{BigInt, Zero};
 use fvm_shared::blockstore::{Blockstore, CborStore};
-use fvm_shared::commcid::{
-    cid_to_data_commitment_v1, cid_to_replica_commitment_v1, data_commitment_v1_to_cid,
-};
+use fvm_shared::clock::TIPSET_CID_LOOKBACK;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::econ::TokenAmount;
-use fvm_shared::encoding::{blake2b_256, bytes_32, to_vec, RawBytes};
-use fvm_shared::error::ErrorNumber;
-use fvm_shared::piece::{zero_piece_commitment, PaddedPieceSize};
-use fvm_shared::sector::SectorInfo;
+use fvm_shared::encoding::{blake2b_256, to_vec, RawBytes};
+use fvm_shared::error::{ErrorNumber, ExitCode};
+use fvm_shared::receipt::Receipt;
 use fvm_shared::version::NetworkVersion;
-use fvm_shared::{ActorID, FILECOIN_PRECISION};
+use fvm_shared::{ActorID, FILECOIN_PRECISION, IPLD_RAW};
 use lazy_static::lazy_static;
-use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 
 use super::blocks::{Block, BlockRegistry};
 use super::error::Result;
+use super::proofs;
 use super::*;
-use crate::call_manager::{CallManager, InvocationResult};
-use crate::externs::{Consensus, Rand};
-use crate::gas::GasCharge;
+use crate::call_manager::{CallManager, InvocationResult, RandomnessSource};
+use crate::externs::{Chain, CircSupply, Consensus, Rand};
+use crate::gas::{Gas, GasCharge};
+use crate::machine::ProofsVerifier;
 use crate::market_actor::State as MarketActorState;
 use crate::power_actor::State as PowerActorState;
 use crate::reward_actor::State as RewardActorState;
 use crate::state_tree::ActorState;
+use crate::trace::{ExecutionEvent, StateChangeTrace};
 use crate::{syscall_error, EMPTY_ARR_CID};
 
 pub const BURN_ACTOR_ID: ActorID = 99;
 pub const RESERVE_ACTOR_ID: ActorID = 90;
 
 lazy_static! {
-    static ref NUM_CPUS: usize = num_cpus::get();
     static ref INITIAL_RESERVE_BALANCE: BigInt = BigInt::from(300_000_000) * FILECOIN_PRECISION;
 }
 
@@ -64,8 +56,13 @@ pub struct DefaultKernel<C> {
     /// Tracks block data and organizes it through index handles so it can be
     /// referred to.
     ///
-    /// This does not yet reason about reachability.
+    /// Linked blocks are only written to the blockstore once the invocation returns successfully,
+    /// if they're reachable from the actor's state root, or before calling another actor (which
+    /// may be passed their CIDs). The others are discarded when the invocation returns.
     blocks: BlockRegistry,
+    /// This actor's balance, cached by `current_balance` until the next operation that may change
+    /// it (a send, which may transfer funds in either direction, or a self-destruct).
+    balance: RefCell<Option<TokenAmount>>,
 }
 
 // Even though all children traits are implemented, Rust needs to know that the
@@ -76,6 +73,10 @@ where
 {
     type CallManager = C;
 
+    fn trace(&mut self, event: ExecutionEvent) {
+        self.call_manager.trace(event)
+    }
+
     fn take(self) -> Self::CallManager
     where
         Self: Sized,
@@ -83,20 +84,27 @@ where
         self.call_manager
     }
 
+    fn flush_committed(&mut self) -> Result<()> {
+        let blocks = self.blocks.take_committed();
+        self.write_blocks(blocks)
+    }
+
     fn new(
-        mgr: C,
+        mut mgr: C,
         caller: ActorID,
         actor_id: ActorID,
         method: MethodNum,
         value_received: TokenAmount,
     ) -> Self {
+        let invocation = mgr.next_invocation_idx();
         DefaultKernel {
             call_manager: mgr,
-            blocks: BlockRegistry::new(),
+            blocks: BlockRegistry::new(invocation),
             caller,
             actor_id,
             method,
             value_received,
+            balance: RefCell::new(None),
         }
     }
 }
@@ -105,6 +113,34 @@ impl<C> DefaultKernel<C>
 where
     C: CallManager,
 {
+    /// Returns true if proofs should be accepted without verifying them (after charging gas as
+    /// usual), see [`Config::verify_proofs`](crate::Config::verify_proofs).
+    fn skip_proof_verification(&self) -> bool {
+        !self.call_manager.machine().config().verify_proofs
+    }
+
+    /// The verifier installed in the machine's config.
+    fn proofs_verifier(&self) -> &dyn ProofsVerifier {
+        &*self.call_manager.machine().config().proofs_verifier
+    }
+
+    /// The number of threads proofs may be verified on.
+    fn verifier_threads(&self) -> usize {
+        self.call_manager.machine().config().verifier_threads
+    }
+
+    /// Writes linked blocks to the blockstore.
+    fn write_blocks(&self, blocks: Vec<(Cid, Block)>) -> Result<()> {
+        self.call_manager
+            .blockstore()
+            .put_many_keyed(blocks.iter().map(|(k, block)| (*k, block.data())))
+            .context("failed to write linked blocks")
+            .or_fatal()
+    }
+
+    /// Resolves an address to the key address of the account actor it refers to. This takes a
+    /// single step (an account records its key address directly), and an account recording any
+    /// other kind of address is reported as state corruption instead of being followed.
     fn resolve_to_key_addr(&mut self, addr: &Address, charge_gas: bool) -> Result<Address> {
         if addr.protocol() == Protocol::BLS || addr.protocol() == Protocol::Secp256k1 {
             return Ok(*addr);
@@ -140,12 +176,19 @@ where
             .state_tree()
             .store()
             .get_cbor(&act.state)
-            .context("failed to decode actor state as an account")
+            .with_context(|| format!("failed to load account actor state {}", act.state))
             .or_fatal()? // because we've checked and this should be an account.
-            .context("account actor state not found")
+            .with_context(|| format!("account actor state {} not found", act.state))
             .or_fatal()?; // because the state should exist.
 
-        Ok(state.address)
+        match state.address.protocol() {
+            Protocol::BLS | Protocol::Secp256k1 => Ok(state.address),
+            _ => Err(ExecutionError::Fatal(anyhow!(
+                "state corruption: account actor {} records non-key address {}",
+                addr,
+                state.address
+            ))),
+        }
     }
 
     fn get_burnt_funds(&self) -> Result<TokenAmount> {
@@ -229,15 +272,34 @@ where
     }
 
     fn set_root(&mut self, new: Cid) -> Result<()> {
+        let scanned = self
+            .blocks
+            .commit_reachable(&new)
+            .or_illegal_argument()
+            .context("failed to find the blocks of the new state")?;
+        self.call_manager
+            .charge_gas(self.call_manager.price_list().on_block_scan(scanned))?;
         self.mutate_self(|actor_state| {
             actor_state.state = new;
             Ok(())
-        })
+        })?;
+        self.call_manager.trace(ExecutionEvent::StateChange(
+            StateChangeTrace::StateRootSet {
+                id: self.actor_id,
+                root: new,
+            },
+        ));
+        Ok(())
     }
 
     fn current_balance(&self) -> Result<TokenAmount> {
+        if let Some(balance) = self.balance.borrow().as_ref() {
+            return Ok(balance.clone());
+        }
         // If the actor doesn't exist, it has zero balance.
-        Ok(self.get_self()?.map(|a| a.balance).unwrap_or_default())
+        let balance = self.get_self()?.map(|a| a.balance).unwrap_or_default();
+        *self.balance.borrow_mut() = Some(balance.clone());
+        Ok(balance)
     }
 
     fn self_destruct(&mut self, beneficiary: &Address) -> Result<()> {
@@ -247,6 +309,7 @@ where
             .charge_gas(self.call_manager.price_list().on_delete_actor())?;
 
         let balance = self.current_balance()?;
+        *self.balance.get_mut() = None;
         if balance != TokenAmount::zero() {
             // Starting from network version v7, the runtime checks if the beneficiary
             // exists; if missing, it fails the self destruct.
@@ -272,7 +335,11 @@ where
         // Delete the executing actor
         self.call_manager
             .state_tree_mut()
-            .delete_actor_id(self.actor_id)
+            .delete_actor_id(self.actor_id)?;
+        self.call_manager.trace(ExecutionEvent::StateChange(
+            StateChangeTrace::ActorDeleted { id: self.actor_id },
+        ));
+        Ok(())
     }
 }
 
@@ -284,19 +351,25 @@ where
         self.call_manager
             .charge_gas(self.call_manager.price_list().on_ipld_get())?;
 
-        let data = self
-            .call_manager
-            .blockstore()
-            .get(cid)
-            .or_fatal()?
-            .ok_or_else(|| anyhow!("missing state: {}", cid))
-            // Missing state is a fatal error because it means we have a bug. Once we do
-            // reachability checking (for user actors) we won't get here unless the block is known
-            // to be in the state-tree.
-            .or_fatal()?;
+        let block = match self.blocks.linked(cid) {
+            Some(block) => block.clone(),
+            None => {
+                let data = self
+                    .call_manager
+                    .blockstore()
+                    .get(cid)
+                    .with_context(|| format!("failed to get block {}", cid))
+                    .or_fatal()?
+                    .ok_or_else(|| anyhow!("missing state: block {} not found", cid))
+                    // Missing state is a fatal error because it means we have a bug. Once we do
+                    // reachability checking (for user actors) we won't get here unless the block
+                    // is known to be in the state-tree.
+                    .or_fatal()?;
+                Block::new(cid.codec(), data)
+            }
+        };
 
         // We charge on open, not read, to emulate the current gas model.
-        let block = Block::new(cid.codec(), data);
         let stat = block.stat();
 
         // TODO: I mean, this means you put 4M blocks in a single message. That's not actually possible?
@@ -333,29 +406,31 @@ where
             );
         }
         let k = Cid::new_v1(block.codec(), hash.truncate(hash_len as u8));
-        // TODO: for now, we _put_ the block here. In the future, we should put it into a write
-        // cache, then flush it later.
-        self.call_manager
-            .blockstore()
-            .put_keyed(&k, block.data())
-            .or_fatal()?;
+        let block = block.clone();
+        self.blocks.link(k, block);
         Ok(k)
     }
 
     fn block_read(&self, id: BlockId, offset: u32, buf: &mut [u8]) -> Result<u32> {
         let data = self.blocks.get(id).or_illegal_argument()?.data();
-        Ok(if offset as usize >= data.len() {
-            0
-        } else {
-            let len = buf.len().min(data.len());
-            buf.copy_from_slice(&data[offset as usize..][..len]);
-            len as u32
-        })
+        let data = data.get(offset as usize..).unwrap_or_default();
+        let len = buf.len().min(data.len());
+        buf[..len].copy_from_slice(&data[..len]);
+        Ok(len as u32)
+    }
+
+    fn block_get(&self, id: BlockId) -> Result<(u64, Vec<u8>)> {
+        let block = self.blocks.get(id).or_illegal_argument()?;
+        Ok((block.codec(), block.data().to_vec()))
     }
 
     fn block_stat(&self, id: BlockId) -> Result<BlockStat> {
         self.blocks.stat(id).or_illegal_argument()
     }
+
+    fn block_drop(&mut self, id: BlockId) -> Result<()> {
+        self.blocks.remove(id).or_illegal_argument()
+    }
 }
 
 impl<C> MessageOps for DefaultKernel<C>
@@ -377,6 +452,14 @@ where
     fn msg_value_received(&self) -> TokenAmount {
         self.value_received.clone()
     }
+
+    fn msg_origin(&self) -> Address {
+        self.call_manager.origin()
+    }
+
+    fn msg_nonce(&self) -> u64 {
+        self.call_manager.nonce()
+    }
 }
 
 impl<C> SendOps for DefaultKernel<C>
@@ -389,10 +472,44 @@ where
         method: MethodNum,
         params: &RawBytes,
         value: &TokenAmount,
-    ) -> Result<InvocationResult> {
-        let from = self.actor_id;
+    ) -> Result<Receipt> {
+        let max_params_size = self.call_manager.machine().config().max_send_params_size;
+        if params.len() > max_params_size {
+            return Err(syscall_error!(ParamsTooLarge;
+                "send parameters of {} bytes exceed the limit of {} bytes",
+                params.len(),
+                max_params_size
+            )
+            .into());
+        }
         self.call_manager
-            .with_transaction(|cm| cm.send::<Self>(from, *recipient, method, params, value))
+            .charge_gas(self.call_manager.price_list().on_send_params(params.len()))?;
+
+        // The callee may be passed the CID of any block linked so far.
+        let blocks = self.blocks.take_linked();
+        self.write_blocks(blocks)?;
+
+        let from = self.actor_id;
+        *self.balance.get_mut() = None;
+        // The gas tracker is shared by the whole call stack: whatever it records during the call
+        // was consumed by the callee's frame and the frames below it.
+        let gas_before = self.call_manager.gas_tracker().gas_used();
+        let result = self
+            .call_manager
+            .with_transaction(|cm| cm.send::<Self>(from, *recipient, method, params, value))?;
+        let gas_used = (self.call_manager.gas_tracker().gas_used() - gas_before).as_i64();
+        Ok(match result {
+            InvocationResult::Return(return_data) => Receipt {
+                exit_code: ExitCode::Ok,
+                return_data,
+                gas_used,
+            },
+            InvocationResult::Failure(exit_code) => Receipt {
+                exit_code,
+                return_data: RawBytes::default(),
+                gas_used,
+            },
+        })
     }
 }
 
@@ -416,7 +533,12 @@ where
             // From v15 and onwards, Filecoin mainnet was fixed to use a static circ supply per epoch.
             // The value reported to the FVM from clients is now the static value,
             // the FVM simply reports that value to actors.
-            self.call_manager.context().circ_supply.clone()
+            let context = self.call_manager.context();
+            self.call_manager
+                .externs()
+                .total_fil_circ_supply(context.epoch)
+                .or_fatal()?
+                .unwrap_or_else(|| context.circ_supply.clone())
         };
         Ok(circ_supply)
     }
@@ -435,7 +557,7 @@ where
         self.call_manager.charge_gas(
             self.call_manager
                 .price_list()
-                .on_verify_signature(signature.signature_type()),
+                .on_verify_signature(signature.signature_type(), plaintext.len()),
         )?;
 
         // Resolve to key address before verifying signature.
@@ -461,88 +583,28 @@ where
                 .on_compute_unsealed_sector_cid(proof_type, pieces),
         )?;
 
-        let ssize = proof_type.sector_size().or_illegal_argument()? as u64;
-
-        let mut all_pieces = Vec::<proofs::PieceInfo>::with_capacity(pieces.len());
-
-        let pssize = PaddedPieceSize(ssize);
-        if pieces.is_empty() {
-            all_pieces.push(proofs::PieceInfo {
-                size: pssize.unpadded().into(),
-                commitment: zero_piece_commitment(pssize),
-            })
-        } else {
-            // pad remaining space with 0 piece commitments
-            let mut sum = PaddedPieceSize(0);
-            let pad_to = |pads: Vec<PaddedPieceSize>,
-                          all_pieces: &mut Vec<proofs::PieceInfo>,
-                          sum: &mut PaddedPieceSize| {
-                for p in pads {
-                    all_pieces.push(proofs::PieceInfo {
-                        size: p.unpadded().into(),
-                        commitment: zero_piece_commitment(p),
-                    });
-
-                    sum.0 += p.0;
-                }
-            };
-            for p in pieces {
-                let (ps, _) = get_required_padding(sum, p.size);
-                pad_to(ps, &mut all_pieces, &mut sum);
-
-                all_pieces.push(proofs::PieceInfo::try_from(p).or_illegal_argument()?);
-                sum.0 += p.size.0;
-            }
-
-            let (ps, _) = get_required_padding(sum, pssize);
-            pad_to(ps, &mut all_pieces, &mut sum);
-        }
-
-        let comm_d = compute_comm_d(proof_type.try_into().or_illegal_argument()?, &all_pieces)
-            .or_illegal_argument()?;
-
-        data_commitment_v1_to_cid(&comm_d).or_illegal_argument()
+        proofs::compute_unsealed_sector_cid(proof_type, pieces)
     }
 
     /// Verify seal proof for sectors. This proof verifies that a sector was sealed by the miner.
     fn verify_seal(&mut self, vi: &SealVerifyInfo) -> Result<bool> {
         self.call_manager
             .charge_gas(self.call_manager.price_list().on_verify_seal(vi))?;
-        verify_seal(vi)
+        if self.skip_proof_verification() {
+            return Ok(true);
+        }
+        self.proofs_verifier().verify_seal(vi)
     }
 
     fn verify_post(&mut self, verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
         self.call_manager
             .charge_gas(self.call_manager.price_list().on_verify_post(verify_info))?;
+        if self.skip_proof_verification() {
+            return Ok(true);
+        }
 
-        let WindowPoStVerifyInfo {
-            ref proofs,
-            ref challenged_sectors,
-            prover,
-            ..
-        } = verify_info;
-
-        let Randomness(mut randomness) = verify_info.randomness.clone();
-
-        // Necessary to be valid bls12 381 element.
-        randomness[31] &= 0x3f;
-
-        // Convert sector info into public replica
-        let replicas = to_fil_public_replica_infos(challenged_sectors, ProofType::Window)?;
-
-        // Convert PoSt proofs into proofs-api format
-        let proofs: Vec<(proofs::RegisteredPoStProof, _)> = proofs
-            .iter()
-            .map(|p| Ok((p.post_proof.try_into()?, p.proof_bytes.as_ref())))
-            .collect::<core::result::Result<_, String>>()
-            .or_illegal_argument()?;
-
-        // Generate prover bytes from ID
-        let prover_id = prover_id_from_u64(*prover);
-
-        // Verify Proof
-        post::verify_window_post(&bytes_32(&randomness), &proofs, &replicas, prover_id)
-            .or_illegal_argument()
+        self.proofs_verifier()
+            .verify_post(self.verifier_threads(), verify_info)
     }
 
     fn verify_consensus_fault(
@@ -561,51 +623,22 @@ where
             .externs()
             .verify_consensus_fault(h1, h2, extra)
             .or_illegal_argument()?;
-        self.call_manager
-            .charge_gas(GasCharge::new("verify_consensus_fault_accesses", gas, 0))?;
+        self.call_manager.charge_gas(GasCharge::new(
+            "verify_consensus_fault_accesses",
+            Gas::new(gas),
+            Gas::ZERO,
+        ))?;
         Ok(fault)
     }
 
     fn batch_verify_seals(&mut self, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
         // NOTE: gas has already been charged by the power actor when the batch verify was enqueued.
         // Lotus charges "virtual" gas here for tracing only.
-        log::debug!("batch verify seals start");
-        let out = vis
-            .par_iter()
-            .with_min_len(vis.len() / *NUM_CPUS)
-            .map(|seal| {
-                let verify_seal_result = std::panic::catch_unwind(|| verify_seal(seal));
-                match verify_seal_result {
-                    Ok(res) => {
-                        match res {
-                            Ok(correct) => {
-                                if !correct {
-                                    log::debug!(
-                                        "seal verify in batch failed (miner: {}) (err: Invalid Seal proof)",
-                                        seal.sector_id.miner
-                                    );
-                                }
-                                correct // all ok
-                            }
-                            Err(err) => {
-                                log::debug!(
-                                    "seal verify in batch failed (miner: {}) (err: {})",
-                                    seal.sector_id.miner,
-                                    err
-                                );
-                                false
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        log::error!("seal verify internal fail (miner: {}) (err: {:?})", seal.sector_id.miner, e);
-                        false
-                    }
-                }
-            })
-            .collect();
-        log::debug!("batch verify seals end");
-        Ok(out)
+        if self.skip_proof_verification() {
+            return Ok(vec![true; vis.len()]);
+        }
+        self.proofs_verifier()
+            .batch_verify_seals(self.verifier_threads(), vis)
     }
 
     fn verify_aggregate_seals(
@@ -617,69 +650,13 @@ where
                 .price_list()
                 .on_verify_aggregate_seals(aggregate),
         )?;
+        if self.skip_proof_verification() {
+            return Ok(true);
+        }
         if aggregate.infos.is_empty() {
             return Err(syscall_error!(IllegalArgument; "no seal verify infos").into());
         }
-        let spt: proofs::RegisteredSealProof =
-            aggregate.seal_proof.try_into().or_illegal_argument()?;
-        let prover_id = prover_id_from_u64(aggregate.miner);
-        struct AggregationInputs {
-            // replica
-            commr: [u8; 32],
-            // data
-            commd: [u8; 32],
-            sector_id: SectorId,
-            ticket: [u8; 32],
-            seed: [u8; 32],
-        }
-        let inputs: Vec<AggregationInputs> = aggregate
-            .infos
-            .iter()
-            .map(|info| {
-                let commr = cid_to_replica_commitment_v1(&info.sealed_cid)?;
-                let commd = cid_to_data_commitment_v1(&info.unsealed_cid)?;
-                Ok(AggregationInputs {
-                    commr,
-                    commd,
-                    ticket: bytes_32(&info.randomness.0),
-                    seed: bytes_32(&info.interactive_randomness.0),
-                    sector_id: SectorId::from(info.sector_number),
-                })
-            })
-            .collect::<core::result::Result<Vec<_>, &'static str>>()
-            .or_illegal_argument()?;
-
-        let inp: Vec<Vec<_>> = inputs
-            .par_iter()
-            .map(|input| {
-                seal::get_seal_inputs(
-                    spt,
-                    input.commr,
-                    input.commd,
-                    prover_id,
-                    input.sector_id,
-                    input.ticket,
-                    input.seed,
-                )
-            })
-            .try_reduce(Vec::new, |mut acc, current| {
-                acc.extend(current);
-                Ok(acc)
-            })
-            .or_illegal_argument()?;
-
-        let commrs: Vec<[u8; 32]> = inputs.iter().map(|input| input.commr).collect();
-        let seeds: Vec<[u8; 32]> = inputs.iter().map(|input| input.seed).collect();
-
-        verify_aggregate_seal_commit_proofs(
-            spt,
-            aggregate.aggregate_proof.try_into().or_illegal_argument()?,
-            aggregate.proof.clone(),
-            &commrs,
-            &seeds,
-            inp,
-        )
-        .or_illegal_argument()
+        self.proofs_verifier().verify_aggregate_seals(aggregate)
     }
 
     fn verify_replica_update(&mut self, replica: &ReplicaUpdateInfo) -> Result<bool> {
@@ -688,18 +665,11 @@ where
                 .price_list()
                 .on_verify_replica_update(replica),
         )?;
+        if self.skip_proof_verification() {
+            return Ok(true);
+        }
 
-        let up: proofs::RegisteredUpdateProof =
-            replica.update_proof_type.try_into().or_illegal_argument()?;
-
-        let commr_old =
-            cid_to_replica_commitment_v1(&replica.old_sealed_cid).or_illegal_argument()?;
-        let commr_new =
-            cid_to_replica_commitment_v1(&replica.new_sealed_cid).or_illegal_argument()?;
-        let commd = cid_to_data_commitment_v1(&replica.new_unsealed_cid).or_illegal_argument()?;
-
-        verify_empty_sector_update_proof(up, &replica.proof, commr_old, commr_new, commd)
-            .or_illegal_argument()
+        self.proofs_verifier().verify_replica_update(replica)
     }
 }
 
@@ -707,10 +677,22 @@ impl<C> GasOps for DefaultKernel<C>
 where
     C: CallManager,
 {
-    fn charge_gas(&mut self, name: &str, compute: i64) -> Result<()> {
-        let charge = GasCharge::new(name, compute, 0);
+    fn charge_gas(&mut self, name: &str, compute: Gas) -> Result<()> {
+        let charge = GasCharge::new(name, compute, Gas::ZERO);
         self.call_manager.charge_gas(charge)
     }
+
+    fn gas_used(&self) -> Gas {
+        self.call_manager.gas_tracker().gas_used()
+    }
+
+    fn gas_available(&self) -> Gas {
+        self.call_manager.gas_tracker().gas_available()
+    }
+
+    fn price_list(&self) -> &PriceList {
+        self.call_manager.price_list()
+    }
 }
 
 impl<C> NetworkOps for DefaultKernel<C>
@@ -728,6 +710,46 @@ where
     fn network_base_fee(&self) -> &TokenAmount {
         &self.call_manager.context().base_fee
     }
+
+    fn tipset_cid(&mut self, epoch: ChainEpoch) -> Result<Cid> {
+        self.call_manager
+            .charge_gas(self.call_manager.price_list().on_get_tipset_cid())?;
+
+        let curr_epoch = self.network_epoch();
+        if epoch < 0 || epoch >= curr_epoch || epoch < curr_epoch - TIPSET_CID_LOOKBACK {
+            return Err(syscall_error!(IllegalArgument;
+                "cannot look up the tipset at epoch {} from epoch {}", epoch, curr_epoch)
+            .into());
+        }
+
+        self.call_manager.externs().get_tipset_cid(epoch).or_fatal()
+    }
+}
+
+impl<C> PrecompileOps for DefaultKernel<C>
+where
+    C: CallManager,
+{
+    fn call_precompile(&mut self, id: u64, input: &[u8]) -> Result<BlockId> {
+        let nv = self.network_version();
+        let precompile = self
+            .call_manager
+            .machine()
+            .config()
+            .precompiles
+            .get(id, nv)
+            .cloned()
+            .ok_or_else(
+                || syscall_error!(NotFound; "no precompile {} at network version {}", id, nv),
+            )?;
+        self.call_manager.charge_gas(precompile.gas(input.len()))?;
+
+        let output = precompile
+            .call(input)
+            .or_illegal_argument()
+            .context(format_args!("precompile {} failed", id))?;
+        self.block_create(IPLD_RAW, &output)
+    }
 }
 
 impl<C> RandomnessOps for DefaultKernel<C>
@@ -736,31 +758,81 @@ where
 {
     #[allow(unused)]
     fn get_randomness_from_tickets(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
     ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_get_randomness(entropy.len()),
+        )?;
+
+        let source = RandomnessSource::Tickets;
+        if let Some(randomness) = self.call_manager.randomness_cache_mut().get(
+            source,
+            personalization,
+            rand_epoch,
+            entropy,
+        ) {
+            return Ok(randomness);
+        }
+
         // TODO: Check error code
-        self.call_manager
+        let randomness = self
+            .call_manager
             .externs()
             .get_chain_randomness(personalization, rand_epoch, entropy)
-            .or_illegal_argument()
+            .or_illegal_argument()?;
+        self.call_manager.randomness_cache_mut().insert(
+            source,
+            personalization,
+            rand_epoch,
+            entropy,
+            randomness,
+        );
+        Ok(randomness)
     }
 
     #[allow(unused)]
     fn get_randomness_from_beacon(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
     ) -> Result<[u8; RANDOMNESS_LENGTH]> {
+        self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_get_randomness(entropy.len()),
+        )?;
+
+        let source = RandomnessSource::Beacon;
+        if let Some(randomness) = self.call_manager.randomness_cache_mut().get(
+            source,
+            personalization,
+            rand_epoch,
+            entropy,
+        ) {
+            return Ok(randomness);
+        }
+
         // TODO: Check error code
         // Hyperdrive and above only.
-        self.call_manager
+        let randomness = self
+            .call_manager
             .externs()
             .get_beacon_randomness(personalization, rand_epoch, entropy)
-            .or_illegal_argument()
+            .or_illegal_argument()?;
+        self.call_manager.randomness_cache_mut().insert(
+            source,
+            personalization,
+            rand_epoch,
+            entropy,
+            randomness,
+        );
+        Ok(randomness)
     }
 }
 
@@ -772,11 +844,23 @@ where
         self.call_manager.state_tree().lookup_id(address)
     }
 
-    fn get_actor_code_cid(&self, addr: &Address) -> Result<Option<Cid>> {
+    fn resolve_addresses(&mut self, addresses: &[Address]) -> Result<Vec<Option<ActorID>>> {
+        self.call_manager.charge_gas(
+            self.call_manager
+                .price_list()
+                .on_resolve_addresses(addresses.len()),
+        )?;
+        addresses
+            .iter()
+            .map(|addr| self.call_manager.state_tree().lookup_id(addr))
+            .collect()
+    }
+
+    fn get_actor_code_cid(&self, id: ActorID) -> Result<Option<Cid>> {
         Ok(self
             .call_manager
             .state_tree()
-            .get_actor(addr)
+            .get_actor_id(id)
             .context("failed to lookup actor to get code CID")
             .or_fatal()?
             .map(|act| act.code))
@@ -784,25 +868,32 @@ where
 
     fn new_actor_address(&mut self) -> Result<Address> {
         let oa = self
-            .resolve_to_key_addr(&self.call_manager.origin(), false)
+            .resolve_to_key_addr(&self.msg_origin(), false)
             // This is already an execution error, but we're _making_ it fatal.
             .or_fatal()?;
 
-        let mut b = to_vec(&oa)
-            .or_fatal()
-            .context("could not serialize address in new_actor_address")?;
-        b.write_u64::<BigEndian>(self.call_manager.nonce())
-            .or_fatal()
-            .context("writing nonce into a buffer")?;
-        b.write_u64::<BigEndian>(self.call_manager.next_actor_idx())
+        let nonce = self.msg_nonce();
+        let actor_idx = self.call_manager.next_actor_idx();
+        derive_actor_address(&oa, nonce, actor_idx)
             .or_fatal()
-            .context("writing actor index in buffer")?;
-        let addr = Address::new_actor(&b);
-        Ok(addr)
+            .context("could not serialize address in new_actor_address")
     }
 
     // TODO merge new_actor_address and create_actor into a single syscall.
     fn create_actor(&mut self, code_id: Cid, actor_id: ActorID) -> Result<()> {
+        if self
+            .call_manager
+            .machine()
+            .config()
+            .singletons
+            .is_singleton_code(&code_id)
+        {
+            return Err(
+                syscall_error!(IllegalArgument; "can only have one instance of singleton actors")
+                    .into(),
+            );
+        }
+
         let typ = self
             .resolve_builtin_actor_type(&code_id)
             .ok_or_else(|| syscall_error!(IllegalArgument; "can only create built-in actors"))?;
@@ -826,7 +917,14 @@ where
         state_tree.set_actor_id(
             actor_id,
             ActorState::new(code_id, *EMPTY_ARR_CID, 0.into(), 0),
-        )
+        )?;
+        self.call_manager.trace(ExecutionEvent::StateChange(
+            StateChangeTrace::ActorCreated {
+                id: actor_id,
+                code: code_id,
+            },
+        ));
+        Ok(())
     }
 
     fn resolve_builtin_actor_type(&self, code_cid: &Cid) -> Option<actor::builtin::Type> {
@@ -853,7 +951,7 @@ where
     C: CallManager,
 {
     fn log(&self, msg: String) {
-        println!("{}", msg)
+        tracing::info!(target: "fvm::actor", actor = self.actor_id, "{}", msg)
     }
 
     fn debug_enabled(&self) -> bool {
@@ -861,85 +959,628 @@ where
     }
 }
 
-/// PoSt proof variants.
-enum ProofType {
-    #[allow(unused)]
-    Winning,
-    Window,
-}
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use anyhow::anyhow;
+    use fvm_shared::actor::builtin::Type;
+    use fvm_shared::address::Address;
+    use fvm_shared::blockstore::{Blockstore, CborStore};
+    use fvm_shared::crypto::randomness::DomainSeparationTag;
+    use fvm_shared::crypto::signature::Signature;
+    use fvm_shared::econ::TokenAmount;
+    use fvm_shared::encoding::{blake2b_256, to_vec, RawBytes, DAG_CBOR};
+    use fvm_shared::error::{ErrorNumber, ExitCode};
+    use fvm_shared::randomness::Randomness;
+    use fvm_shared::sector::{RegisteredSealProof, SealVerifyInfo, SectorID};
+    use fvm_shared::version::NetworkVersion;
+    use fvm_shared::{ActorID, IPLD_RAW};
+    use multihash::Code;
+    use num_traits::Zero;
+
+    use super::DefaultKernel;
+    use crate::call_manager::CallManager;
+    use crate::externs::Chain;
+    use crate::gas::Gas;
+    use crate::kernel::{
+        ActorOps, BlockOps, CircSupplyOps, CryptoOps, ExecutionError, GasOps, NetworkOps,
+        PrecompileOps, RandomnessOps, SelfOps, SendOps,
+    };
+    use crate::machine::{PrecompileRegistry, ProofsVerifier, SingletonRegistry};
+    use crate::state_tree::ActorState;
+    use crate::testing::{cbor_blake2b_cid, MockCallManager, MockExterns, MockMachine};
+    use crate::trace::{ExecutionEvent, StateChangeTrace};
+    use crate::{Kernel, EMPTY_ARR_CID};
+
+    const ACTOR: ActorID = 100;
+    const BENEFICIARY: ActorID = 101;
+
+    fn kernel(balance: u64) -> DefaultKernel<MockCallManager> {
+        let mut machine = MockMachine::new(NetworkVersion::V15).unwrap();
+        for (id, balance) in [(ACTOR, balance), (BENEFICIARY, 0)] {
+            let actor = ActorState::new(
+                *EMPTY_ARR_CID,
+                *EMPTY_ARR_CID,
+                TokenAmount::from(balance),
+                0,
+            );
+            machine.state_tree.set_actor_id(id, actor).unwrap();
+        }
+        let call_manager =
+            MockCallManager::new(machine, Gas::new(10_000_000_000), Address::new_id(ACTOR), 0);
+        DefaultKernel::new(call_manager, ACTOR, ACTOR, 2, TokenAmount::zero())
+    }
 
-fn prover_id_from_u64(id: u64) -> ProverId {
-    let mut prover_id = ProverId::default();
-    let prover_bytes = Address::new_id(id).payload().to_raw_bytes();
-    prover_id[..prover_bytes.len()].copy_from_slice(&prover_bytes);
-    prover_id
-}
+    fn balance_of(kernel: &DefaultKernel<MockCallManager>, id: ActorID) -> Option<TokenAmount> {
+        kernel
+            .call_manager
+            .state_tree()
+            .get_actor_id(id)
+            .unwrap()
+            .map(|act| act.balance)
+    }
+
+    #[test]
+    fn block_create_stat_read() {
+        let mut kernel = kernel(0);
+        let data = [0x83, 1, 2, 3];
 
-fn get_required_padding(
-    old_length: PaddedPieceSize,
-    new_piece_length: PaddedPieceSize,
-) -> (Vec<PaddedPieceSize>, PaddedPieceSize) {
-    let mut sum = 0;
+        let id = kernel.block_create(DAG_CBOR, &data).unwrap();
+        let stat = kernel.block_stat(id).unwrap();
+        assert_eq!(stat.codec, DAG_CBOR);
+        assert_eq!(stat.size, data.len() as u32);
+        assert_eq!(kernel.block_get(id).unwrap(), (DAG_CBOR, data.to_vec()));
 
-    let mut to_fill = 0u64.wrapping_sub(old_length.0) % new_piece_length.0;
-    let n = to_fill.count_ones();
-    let mut pad_pieces = Vec::with_capacity(n as usize);
-    for _ in 0..n {
-        let next = to_fill.trailing_zeros();
-        let p_size = 1 << next;
-        to_fill ^= p_size;
+        let mut buf = [0u8; 4];
+        assert_eq!(kernel.block_read(id, 0, &mut buf).unwrap(), 4);
+        assert_eq!(buf, data);
+        assert_eq!(kernel.block_read(id, 4, &mut buf).unwrap(), 0);
 
-        let padded = PaddedPieceSize(p_size);
-        pad_pieces.push(padded);
-        sum += padded.0;
+        assert!(kernel.block_stat(id + 1).is_err());
     }
 
-    (pad_pieces, PaddedPieceSize(sum))
-}
+    #[test]
+    fn block_read_partial() {
+        let mut kernel = kernel(0);
+        let data = [0x84, 1, 2, 3, 4];
+        let id = kernel.block_create(DAG_CBOR, &data).unwrap();
 
-fn to_fil_public_replica_infos(
-    src: &[SectorInfo],
-    typ: ProofType,
-) -> Result<BTreeMap<SectorId, PublicReplicaInfo>> {
-    let replicas = src
-        .iter()
-        .map::<core::result::Result<(SectorId, PublicReplicaInfo), String>, _>(
-            |sector_info: &SectorInfo| {
-                let commr = cid_to_replica_commitment_v1(&sector_info.sealed_cid)?;
-                let proof = match typ {
-                    ProofType::Winning => sector_info.proof.registered_winning_post_proof()?,
-                    ProofType::Window => sector_info.proof.registered_window_post_proof()?,
-                };
-                let replica = PublicReplicaInfo::new(proof.try_into()?, commr);
-                Ok((SectorId::from(sector_info.sector_number), replica))
-            },
-        )
-        .collect::<core::result::Result<BTreeMap<SectorId, PublicReplicaInfo>, _>>()
-        .or_illegal_argument()?;
-    Ok(replicas)
-}
+        // Buffers larger than what's left of the block are only partially filled.
+        let mut buf = [0xffu8; 8];
+        assert_eq!(kernel.block_read(id, 2, &mut buf).unwrap(), 3);
+        assert_eq!(buf, [2, 3, 4, 0xff, 0xff, 0xff, 0xff, 0xff]);
 
-fn verify_seal(vi: &SealVerifyInfo) -> Result<bool> {
-    let commr = cid_to_replica_commitment_v1(&vi.sealed_cid).or_illegal_argument()?;
-    let commd = cid_to_data_commitment_v1(&vi.unsealed_cid).or_illegal_argument()?;
-    let prover_id = prover_id_from_u64(vi.sector_id.miner);
+        // Smaller ones are filled from the offset.
+        let mut buf = [0u8; 2];
+        assert_eq!(kernel.block_read(id, 1, &mut buf).unwrap(), 2);
+        assert_eq!(buf, [1, 2]);
 
-    proofs_verify_seal(
-        vi.registered_proof
-            .try_into()
-            .or_illegal_argument()
-            .context(format_args!("invalid proof type {:?}", vi.registered_proof))?,
-        commr,
-        commd,
-        prover_id,
-        SectorId::from(vi.sector_id.number),
-        bytes_32(&vi.randomness.0),
-        bytes_32(&vi.interactive_randomness.0),
-        &vi.proof,
-    )
-    .or_illegal_argument()
-    // TODO: There are probably errors here that should be fatal, but it's hard to tell so I'm
-    // sticking with illegal argument for now.
-    // Worst case, _some_ node falls out of sync. Better than the network halting.
-    .context("failed to verify seal proof")
+        assert_eq!(kernel.block_read(id, u32::MAX, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn block_drop() {
+        let mut kernel = kernel(0);
+        let first = kernel.block_create(DAG_CBOR, &[0x80]).unwrap();
+        let second = kernel.block_create(DAG_CBOR, &[0x81, 1]).unwrap();
+
+        kernel.block_drop(first).unwrap();
+        assert!(kernel.block_stat(first).is_err());
+        assert!(kernel.block_read(first, 0, &mut [0u8; 1]).is_err());
+        assert!(kernel.block_drop(first).is_err());
+        assert!(kernel.block_drop(0).is_err());
+
+        // Other blocks are left alone, and handles aren't reused.
+        assert_eq!(kernel.block_get(second).unwrap(), (DAG_CBOR, vec![0x81, 1]));
+        let third = kernel.block_create(DAG_CBOR, &[0x80]).unwrap();
+        assert!(third != first && third != second);
+    }
+
+    #[test]
+    fn block_link_then_open() {
+        let mut kernel = kernel(0);
+        let data = [0x82, 4, 5];
+
+        let id = kernel.block_create(DAG_CBOR, &data).unwrap();
+        let gas_before = kernel.gas_used();
+        let cid = kernel.block_link(id, Code::Blake2b256.into(), 32).unwrap();
+        assert_eq!(cid, cbor_blake2b_cid(&data));
+        assert!(kernel.gas_used() > gas_before, "linking should charge gas");
+
+        let (opened, stat) = kernel.block_open(&cid).unwrap();
+        assert_ne!(opened, id);
+        assert_eq!(stat.size, data.len() as u32);
+        assert_eq!(kernel.block_get(opened).unwrap(), (DAG_CBOR, data.to_vec()));
+    }
+
+    #[test]
+    fn unreachable_blocks_are_discarded() {
+        let mut kernel = kernel(0);
+        let mut link = |data: &[u8]| {
+            let id = kernel.block_create(DAG_CBOR, data).unwrap();
+            kernel.block_link(id, Code::Blake2b256.into(), 32).unwrap()
+        };
+        let garbage = link(&[0x81, 1]);
+        let leaf = link(&[0x81, 2]);
+        let root = link(&to_vec(&[leaf]).unwrap());
+
+        // The new state is only written once the invocation returns successfully.
+        kernel.set_root(root).unwrap();
+        assert!(!kernel.call_manager.blockstore().has(&root).unwrap());
+        let (id, _) = kernel.block_open(&leaf).unwrap();
+        assert_eq!(kernel.block_get(id).unwrap(), (DAG_CBOR, vec![0x81, 2]));
+
+        kernel.flush_committed().unwrap();
+        let bs = kernel.call_manager.blockstore();
+        assert!(bs.has(&root).unwrap() && bs.has(&leaf).unwrap());
+        assert!(!bs.has(&garbage).unwrap());
+
+        // Unreachable blocks can still be read until the invocation returns.
+        let (id, _) = kernel.block_open(&garbage).unwrap();
+        assert_eq!(kernel.block_get(id).unwrap(), (DAG_CBOR, vec![0x81, 1]));
+    }
+
+    #[test]
+    fn block_open_missing_names_block() {
+        let mut kernel = kernel(0);
+        let cid = cbor_blake2b_cid(b"missing");
+        match kernel.block_open(&cid) {
+            Err(ExecutionError::Fatal(e)) => assert!(format!("{:#}", e).contains(&cid.to_string())),
+            Err(e) => panic!("expected a fatal error, got {:?}", e),
+            Ok(_) => panic!("opened a missing block"),
+        }
+    }
+
+    #[test]
+    fn block_link_rejects_bad_hashes() {
+        let mut kernel = kernel(0);
+        let id = kernel.block_create(DAG_CBOR, &[0x80]).unwrap();
+        assert!(kernel.block_link(id, 0xdead, 32).is_err());
+        assert!(kernel.block_link(id, Code::Blake2b256.into(), 33).is_err());
+    }
+
+    #[test]
+    fn skip_proof_verification() {
+        let info = SealVerifyInfo {
+            registered_proof: RegisteredSealProof::StackedDRG32GiBV1P1,
+            sector_id: SectorID::default(),
+            deal_ids: Vec::new(),
+            randomness: Randomness::default(),
+            interactive_randomness: Randomness::default(),
+            proof: vec![0; 192],
+            sealed_cid: *EMPTY_ARR_CID,
+            unsealed_cid: *EMPTY_ARR_CID,
+        };
+
+        // Bogus proofs are rejected...
+        let mut kernel = kernel(0);
+        assert!(kernel.verify_seal(&info).is_err());
+
+        // ...unless proof verification is disabled, in which case they're still charged for.
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.config.verify_proofs = false;
+        assert!(kernel.verify_seal(&info).unwrap());
+        assert!(kernel.gas_used() > Gas::ZERO);
+        assert_eq!(kernel.batch_verify_seals(&[info]).unwrap(), vec![true]);
+    }
+
+    #[test]
+    fn custom_proofs_verifier() {
+        /// Accepts seal proofs starting with a one, on behalf of the node.
+        struct NodeVerifier;
+
+        impl ProofsVerifier for NodeVerifier {
+            fn verify_seal(&self, vi: &SealVerifyInfo) -> crate::kernel::Result<bool> {
+                Ok(vi.proof.first() == Some(&1))
+            }
+        }
+
+        let mut info = SealVerifyInfo {
+            registered_proof: RegisteredSealProof::StackedDRG32GiBV1P1,
+            sector_id: SectorID::default(),
+            deal_ids: Vec::new(),
+            randomness: Randomness::default(),
+            interactive_randomness: Randomness::default(),
+            proof: vec![0; 192],
+            sealed_cid: *EMPTY_ARR_CID,
+            unsealed_cid: *EMPTY_ARR_CID,
+        };
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.config.proofs_verifier = Arc::new(NodeVerifier);
+        assert!(!kernel.verify_seal(&info).unwrap());
+        info.proof[0] = 1;
+        assert!(kernel.verify_seal(&info).unwrap());
+        assert!(kernel.gas_used() > Gas::ZERO);
+
+        // The verifier isn't consulted when proof verification is disabled.
+        info.proof[0] = 0;
+        kernel.call_manager.machine.config.verify_proofs = false;
+        assert!(kernel.verify_seal(&info).unwrap());
+    }
+
+    #[test]
+    fn resolve_addresses() {
+        let mut kernel = kernel(0);
+        let addrs = [Address::new_id(ACTOR), Address::new_id(BENEFICIARY)];
+        assert_eq!(
+            kernel.resolve_addresses(&addrs).unwrap(),
+            vec![Some(ACTOR), Some(BENEFICIARY)]
+        );
+        assert_eq!(kernel.resolve_addresses(&[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn get_actor_code_cid() {
+        let kernel = kernel(0);
+        assert_eq!(
+            kernel.get_actor_code_cid(ACTOR).unwrap(),
+            Some(*EMPTY_ARR_CID)
+        );
+        assert_eq!(kernel.get_actor_code_cid(BENEFICIARY + 1).unwrap(), None);
+    }
+
+    #[test]
+    fn create_actor_rejects_custom_singletons() {
+        let mut kernel = kernel(0);
+        let code = cbor_blake2b_cid(b"faucet");
+        kernel.call_manager.machine.config.singletons = SingletonRegistry::default()
+            .with_singleton(Address::new_id(10), code, |_| Ok(*EMPTY_ARR_CID))
+            .unwrap();
+        let err = kernel.create_actor(code, BENEFICIARY + 1).unwrap_err();
+        assert!(matches!(err, ExecutionError::Syscall(e) if e.0.contains("singleton")));
+        assert_eq!(balance_of(&kernel, BENEFICIARY + 1), None);
+    }
+
+    #[test]
+    fn set_root() {
+        let mut kernel = kernel(0);
+        assert_eq!(kernel.root().unwrap(), *EMPTY_ARR_CID);
+
+        let id = kernel.block_create(DAG_CBOR, &[0x81, 0]).unwrap();
+        let cid = kernel.block_link(id, Code::Blake2b256.into(), 32).unwrap();
+        kernel.set_root(cid).unwrap();
+        assert_eq!(kernel.root().unwrap(), cid);
+    }
+
+    #[test]
+    fn state_changes_are_traced() {
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.config.trace_calls = true;
+
+        let id = kernel.block_create(DAG_CBOR, &[0x81, 0]).unwrap();
+        let cid = kernel.block_link(id, Code::Blake2b256.into(), 32).unwrap();
+        kernel.set_root(cid).unwrap();
+        kernel.self_destruct(&Address::new_id(BENEFICIARY)).unwrap();
+
+        let changes: Vec<_> = kernel
+            .call_manager
+            .exec_trace
+            .iter()
+            .filter_map(|event| match event {
+                ExecutionEvent::StateChange(change) => Some(change),
+                _ => None,
+            })
+            .collect();
+        assert!(matches!(
+            changes[..],
+            [
+                StateChangeTrace::StateRootSet { id: ACTOR, root },
+                StateChangeTrace::ActorDeleted { id: ACTOR },
+            ] if *root == cid
+        ));
+    }
+
+    #[test]
+    fn current_balance_is_refreshed_after_sends() {
+        let mut kernel = kernel(1000);
+        let beneficiary = Address::new_id(BENEFICIARY);
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::from(1000));
+
+        kernel
+            .send(
+                &beneficiary,
+                0,
+                &RawBytes::default(),
+                &TokenAmount::from(100),
+            )
+            .unwrap();
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::from(900));
+
+        // Pretend a nested call sent funds back to this actor.
+        kernel
+            .call_manager
+            .machine
+            .state_tree
+            .mutate_actor_id(ACTOR, |act| {
+                act.deposit_funds(&TokenAmount::from(50));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::from(900));
+        kernel
+            .send(&beneficiary, 0, &RawBytes::default(), &TokenAmount::zero())
+            .unwrap();
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::from(950));
+    }
+
+    #[test]
+    fn send_params_limit() {
+        let mut kernel = kernel(0);
+        let beneficiary = Address::new_id(BENEFICIARY);
+        kernel.call_manager.machine.config.max_send_params_size = 4;
+        kernel
+            .call_manager
+            .machine
+            .context
+            .price_list
+            .send_params_per_byte = Gas::new(10);
+
+        let gas_before = kernel.gas_used();
+        kernel
+            .send(&beneficiary, 2, &vec![0; 4].into(), &TokenAmount::zero())
+            .unwrap();
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(40));
+
+        let gas_before = kernel.gas_used();
+        let err = kernel
+            .send(&beneficiary, 2, &vec![0; 5].into(), &TokenAmount::zero())
+            .unwrap_err();
+        assert!(matches!(err, ExecutionError::Syscall(e) if e.1 == ErrorNumber::ParamsTooLarge));
+        assert_eq!(kernel.gas_used(), gas_before);
+        assert_eq!(kernel.call_manager.sends.len(), 1);
+    }
+
+    #[test]
+    fn call_precompile() {
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.config.precompiles = PrecompileRegistry::default()
+            .with_precompile(
+                1,
+                NetworkVersion::V15,
+                Gas::new(100),
+                Gas::new(10),
+                |input| Ok(blake2b_256(input).to_vec()),
+            )
+            .unwrap()
+            .with_precompile(2, NetworkVersion::V15, Gas::ZERO, Gas::ZERO, |_| {
+                Err(anyhow!("bad input"))
+            })
+            .unwrap();
+
+        let gas_before = kernel.gas_used();
+        let id = kernel.call_precompile(1, b"abc").unwrap();
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(130));
+        assert_eq!(
+            kernel.block_get(id).unwrap(),
+            (IPLD_RAW, blake2b_256(b"abc").to_vec())
+        );
+
+        let err = kernel.call_precompile(2, b"abc").unwrap_err();
+        assert!(matches!(err, ExecutionError::Syscall(e) if e.1 == ErrorNumber::IllegalArgument));
+        let err = kernel.call_precompile(3, b"abc").unwrap_err();
+        assert!(matches!(err, ExecutionError::Syscall(e) if e.1 == ErrorNumber::NotFound));
+
+        // Precompiles aren't available before the network version they were registered for.
+        kernel.call_manager.machine.context.network_version = NetworkVersion::V14;
+        let err = kernel.call_precompile(1, b"abc").unwrap_err();
+        assert!(matches!(err, ExecutionError::Syscall(e) if e.1 == ErrorNumber::NotFound));
+    }
+
+    #[test]
+    fn circ_supply_from_externs() {
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.context.circ_supply = TokenAmount::from(10);
+        assert_eq!(
+            kernel.total_fil_circ_supply().unwrap(),
+            TokenAmount::from(10)
+        );
+
+        // The node's supply for the epoch takes precedence over the machine's.
+        kernel.call_manager.machine.externs.circ_supply = Some(TokenAmount::from(20));
+        assert_eq!(
+            kernel.total_fil_circ_supply().unwrap(),
+            TokenAmount::from(20)
+        );
+    }
+
+    #[test]
+    fn resolve_to_key_addr_rejects_non_key_account_address() {
+        let mut kernel = kernel(0);
+        let account = cbor_blake2b_cid(b"account");
+        kernel
+            .call_manager
+            .machine
+            .builtin_actors
+            .insert(account, Type::Account);
+        let key = Address::new_secp256k1(&[1; 65]).unwrap();
+        for (id, address) in [(ACTOR, key), (BENEFICIARY, Address::new_id(ACTOR))] {
+            let state = kernel
+                .call_manager
+                .machine
+                .state_tree
+                .store()
+                .put_cbor(&crate::account_actor::State { address }, Code::Blake2b256)
+                .unwrap();
+            let actor = ActorState::new(account, state, TokenAmount::zero(), 0);
+            kernel
+                .call_manager
+                .machine
+                .state_tree
+                .set_actor_id(id, actor)
+                .unwrap();
+        }
+
+        assert_eq!(
+            kernel
+                .resolve_to_key_addr(&Address::new_id(ACTOR), false)
+                .unwrap(),
+            key
+        );
+        let err = kernel
+            .resolve_to_key_addr(&Address::new_id(BENEFICIARY), false)
+            .unwrap_err();
+        assert!(matches!(err, ExecutionError::Fatal(_)));
+    }
+
+    #[test]
+    fn send_reports_callee_gas() {
+        let mut kernel = kernel(0);
+        let beneficiary = Address::new_id(BENEFICIARY);
+        kernel.call_manager.send_gas = Gas::new(123);
+        kernel
+            .call_manager
+            .machine
+            .context
+            .price_list
+            .send_params_per_byte = Gas::new(10);
+
+        let gas_before = kernel.gas_used();
+        let receipt = kernel
+            .send(&beneficiary, 2, &vec![0; 4].into(), &TokenAmount::zero())
+            .unwrap();
+        assert_eq!(receipt.exit_code, ExitCode::Ok);
+        // The caller paid for the parameters, the callee for its execution.
+        assert_eq!(receipt.gas_used, 123);
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(163));
+    }
+
+    #[test]
+    fn self_destruct_transfers_balance() {
+        let mut kernel = kernel(1000);
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::from(1000));
+
+        kernel.self_destruct(&Address::new_id(BENEFICIARY)).unwrap();
+        assert_eq!(balance_of(&kernel, ACTOR), None);
+        assert_eq!(
+            balance_of(&kernel, BENEFICIARY),
+            Some(TokenAmount::from(1000))
+        );
+        assert_eq!(kernel.current_balance().unwrap(), TokenAmount::zero());
+        assert!(kernel.root().is_err());
+    }
+
+    #[test]
+    fn self_destruct_to_self_fails() {
+        let mut kernel = kernel(1000);
+        assert!(kernel.self_destruct(&Address::new_id(ACTOR)).is_err());
+        assert_eq!(balance_of(&kernel, ACTOR), Some(TokenAmount::from(1000)));
+    }
+
+    #[test]
+    fn randomness_charges_for_entropy() {
+        let mut kernel = kernel(0);
+        let price_list = &mut kernel.call_manager.machine.context.price_list;
+        price_list.get_randomness_base = Gas::new(10);
+        price_list.get_randomness_per_byte = Gas::new(2);
+
+        let gas_before = kernel.gas_used();
+        let randomness = kernel
+            .get_randomness_from_tickets(DomainSeparationTag::SealRandomness, 0, &[1; 5])
+            .unwrap();
+        assert_eq!(randomness, [0; 32]);
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(20));
+
+        kernel
+            .get_randomness_from_beacon(DomainSeparationTag::SealRandomness, 0, &[])
+            .unwrap();
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(30));
+    }
+
+    #[test]
+    fn randomness_cached_per_request() {
+        let mut kernel = kernel(0);
+        let price_list = &mut kernel.call_manager.machine.context.price_list;
+        price_list.get_randomness_base = Gas::new(10);
+        price_list.get_randomness_per_byte = Gas::new(0);
+
+        let gas_before = kernel.gas_used();
+        for _ in 0..2 {
+            kernel
+                .get_randomness_from_tickets(DomainSeparationTag::SealRandomness, 5, &[1])
+                .unwrap();
+        }
+        // Served from the cache the second time, but still charged for.
+        assert_eq!(kernel.call_manager.randomness_cache.len(), 1);
+        assert_eq!(kernel.gas_used() - gas_before, Gas::new(20));
+
+        // Any difference in the request (including the source) misses the cache.
+        kernel
+            .get_randomness_from_beacon(DomainSeparationTag::SealRandomness, 5, &[1])
+            .unwrap();
+        kernel
+            .get_randomness_from_tickets(DomainSeparationTag::SealRandomness, 5, &[2])
+            .unwrap();
+        kernel
+            .get_randomness_from_tickets(DomainSeparationTag::SealRandomness, 6, &[1])
+            .unwrap();
+        kernel
+            .get_randomness_from_tickets(DomainSeparationTag::InteractiveSealChallengeSeed, 5, &[1])
+            .unwrap();
+        assert_eq!(kernel.call_manager.randomness_cache.len(), 5);
+    }
+
+    #[test]
+    fn hashing_charges_per_byte() {
+        let mut kernel = kernel(0);
+        let price_list = &mut kernel.call_manager.machine.context.price_list;
+        price_list.hashing_base = Gas::new(100);
+        price_list.hashing_per_byte = Gas::new(3);
+
+        for (len, cost) in [(0, 100), (1, 103), (32, 196), (1024, 3172)] {
+            let gas_before = kernel.gas_used();
+            kernel.hash_blake2b(&vec![0xab; len]).unwrap();
+            assert_eq!(kernel.gas_used() - gas_before, Gas::new(cost));
+        }
+    }
+
+    #[test]
+    fn signature_verification_charges_per_byte() {
+        let mut kernel = kernel(0);
+        let price_list = &mut kernel.call_manager.machine.context.price_list;
+        price_list.secp256k1_sig_cost = Gas::new(1000);
+        price_list.secp256k1_sig_per_byte = Gas::new(2);
+        price_list.bls_sig_cost = Gas::new(5000);
+        price_list.bls_sig_per_byte = Gas::new(7);
+
+        let secp = (
+            Signature::new_secp256k1(vec![0; 65]),
+            Address::new_secp256k1(&[4; 65]).unwrap(),
+        );
+        let bls = (
+            Signature::new_bls(vec![0; 96]),
+            Address::new_bls(&[1; 48]).unwrap(),
+        );
+        for len in [0, 10, 1000] {
+            let plaintext = vec![1; len];
+            for ((signature, signer), cost) in [(&secp, 1000 + 2 * len), (&bls, 5000 + 7 * len)] {
+                let gas_before = kernel.gas_used();
+                // The signatures are bogus: only the charge matters.
+                let valid = kernel
+                    .verify_signature(signature, signer, &plaintext)
+                    .unwrap();
+                assert!(!valid);
+                assert_eq!(kernel.gas_used() - gas_before, Gas::new(cost as i64));
+            }
+        }
+    }
+
+    #[test]
+    fn tipset_cid_lookback() {
+        let mut kernel = kernel(0);
+        kernel.call_manager.machine.context.epoch = 1000;
+
+        for epoch in [999, 100] {
+            assert_eq!(
+                kernel.tipset_cid(epoch).unwrap(),
+                MockExterns::default().get_tipset_cid(epoch).unwrap()
+            );
+        }
+        for epoch in [1000, 1001, 99, -1] {
+            assert!(matches!(
+                kernel.tipset_cid(epoch),
+                Err(ExecutionError::Syscall(_))
+            ));
+        }
+    }
 }
@@ -26,12 +26,26 @@ macro_rules! syscall_error {
     };
 }
 
+/// An error returned by the kernel. How it surfaces to the actor that made the syscall:
+///
+/// | Error       | Returned to the actor         | Actor                                  |
+/// |-------------|-------------------------------|----------------------------------------|
+/// | `Syscall`   | its [`ErrorNumber`]           | carries on, or aborts (its choice)     |
+/// | `OutOfGas`  | nothing: the actor is trapped | exits with `SysErrOutOfGas`            |
+/// | `Fatal`     | nothing: the actor is trapped | the message fails without a receipt    |
+///
+/// Syscalls return their error number as their first return value, and write any other return
+/// values through out-pointers (see `syscalls::bind`). The error message is only recorded in the
+/// backtrace.
 // NOTE: this intentionally does not implemnent error so we can make the context impl work out
 // below.
 #[derive(Display, Debug)]
 pub enum ExecutionError {
+    /// The actor ran out of gas.
     OutOfGas,
+    /// The syscall failed, and the actor may recover.
     Syscall(SyscallError),
+    /// Something went wrong in the FVM, or in the node.
     Fatal(anyhow::Error),
 }
 
@@ -8,7 +8,8 @@ use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::RawBytes;
 use fvm_shared::piece::PieceInfo;
-use fvm_shared::randomness::{Randomness, RANDOMNESS_LENGTH};
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::receipt::Receipt;
 use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
     WindowPoStVerifyInfo,
@@ -17,14 +18,18 @@ use fvm_shared::version::NetworkVersion;
 use fvm_shared::{actor, ActorID, MethodNum};
 
 mod blocks;
+#[doc(hidden)]
 pub mod default;
 
 mod error;
+pub(crate) mod proofs;
 
 pub use error::{ClassifyResult, Context, ExecutionError, Result, SyscallError};
 
-use crate::call_manager::{CallManager, InvocationResult};
+use crate::call_manager::CallManager;
+use crate::gas::{Gas, PriceList};
 use crate::machine::Machine;
+use crate::trace::ExecutionEvent;
 
 /// The "kernel" implements
 pub trait Kernel:
@@ -36,6 +41,7 @@ pub trait Kernel:
     + GasOps
     + MessageOps
     + NetworkOps
+    + PrecompileOps
     + RandomnessOps
     + SelfOps
     + SendOps
@@ -44,11 +50,21 @@ pub trait Kernel:
     /// The [`Kernel`]'s [`CallManager`] is
     type CallManager: CallManager;
 
+    /// Records an event in the call stack's execution trace, through the kernel's call manager
+    /// (see [`CallManager::trace`]).
+    fn trace(&mut self, event: ExecutionEvent);
+
     /// Consume the [`Kernel`] and return the underlying [`CallManager`].
     fn take(self) -> Self::CallManager
     where
         Self: Sized;
 
+    /// Writes the blocks of the state committed by the invocation (see [`SelfOps::set_root`]) to
+    /// the blockstore. The call manager calls this once the invocation has returned successfully;
+    /// otherwise, the invocation's state changes are reverted, and its blocks are discarded along
+    /// with the kernel.
+    fn flush_committed(&mut self) -> Result<()>;
+
     /// Construct a new [`Kernel`] from the given [`CallManager`].
     ///
     /// - `caller` is the ID of the _immediate_ caller.
@@ -76,6 +92,11 @@ pub trait NetworkOps {
 
     /// The current base-fee (constant).
     fn network_base_fee(&self) -> &TokenAmount;
+
+    /// The CID of the tipset at the given epoch, which must be within the last
+    /// [`TIPSET_CID_LOOKBACK`](fvm_shared::clock::TIPSET_CID_LOOKBACK) epochs (excluding the
+    /// current one).
+    fn tipset_cid(&mut self, epoch: ChainEpoch) -> Result<Cid>;
 }
 
 /// Accessors to query attributes of the incoming message.
@@ -91,6 +112,12 @@ pub trait MessageOps {
 
     /// The value received from the caller (constant).
     fn msg_value_received(&self) -> TokenAmount;
+
+    /// The sender of the top-level message that started this call stack (constant).
+    fn msg_origin(&self) -> Address;
+
+    /// The nonce of the top-level message that started this call stack (constant).
+    fn msg_nonce(&self) -> u64;
 }
 
 /// The IPLD subset of the kernel.
@@ -109,12 +136,18 @@ pub trait BlockOps {
 
     /// Computes a CID for a block.
     ///
-    /// This is the only way to add a new block to the "reachable" set.
+    /// This is the only way to add a new block to the "reachable" set. Linked blocks are only
+    /// persisted if they end up reachable from the actor's state root (see
+    /// [`SelfOps::set_root`]).
     ///
     /// This method will fail if the block handle is invalid.
     fn block_link(&mut self, id: BlockId, hash_fun: u64, hash_len: u32) -> Result<Cid>;
 
-    /// Read data from a block.
+    /// Read data from a block, starting at `offset`, into `buf`. Returns the number of bytes read,
+    /// which is less than the length of the buffer if the end of the block is reached.
+    ///
+    /// The syscall layer passes a slice of the actor's memory as the buffer, so blocks are copied
+    /// straight into the actor without intermediate allocations.
     ///
     /// This method will fail if the block handle is invalid.
     fn block_read(&self, id: BlockId, offset: u32, buf: &mut [u8]) -> Result<u32>;
@@ -124,6 +157,14 @@ pub trait BlockOps {
     /// This method will fail if the block handle is invalid.
     fn block_stat(&self, id: BlockId) -> Result<BlockStat>;
 
+    /// Releases a block, e.g. once it has been read, so that long-running invocations don't keep
+    /// every block they ever touched in memory. The block handle can't be used afterwards (handles
+    /// aren't reused). Blocks aren't charged for the time they're kept around, so no gas is
+    /// refunded.
+    ///
+    /// This method will fail if the block handle is invalid (or the block was already dropped).
+    fn block_drop(&mut self, id: BlockId) -> Result<()>;
+
     /// Returns a codec and a block as an owned buffer, given an ID.
     ///
     /// This method will fail if the block handle is invalid.
@@ -145,7 +186,9 @@ pub trait SelfOps: BlockOps {
     /// Get the state root.
     fn root(&self) -> Result<Cid>;
 
-    /// Update the state-root.
+    /// Update the state-root. The linked blocks reachable from it are committed, and written to
+    /// the blockstore when the invocation returns successfully (see [`Kernel::flush_committed`]).
+    /// Scanning the new blocks for links is charged by the byte.
     ///
     /// This method will fail if the new state-root isn't reachable.
     fn set_root(&mut self, root: Cid) -> Result<()>;
@@ -156,6 +199,9 @@ pub trait SelfOps: BlockOps {
     /// Deletes the executing actor from the state tree, transferring any balance to beneficiary.
     /// Aborts if the beneficiary does not exist.
     /// May only be called by the actor itself.
+    ///
+    /// The beneficiary is taken as an address rather than an ID because it is only resolved (and
+    /// only has to exist) if there is a balance to transfer.
     fn self_destruct(&mut self, beneficiary: &Address) -> Result<()>;
 }
 
@@ -167,8 +213,14 @@ pub trait ActorOps {
     /// If the argument is an ID address it is returned directly.
     fn resolve_address(&self, address: &Address) -> Result<Option<ActorID>>;
 
-    /// Look up the code ID at an actor address.
-    fn get_actor_code_cid(&self, addr: &Address) -> Result<Option<Cid>>;
+    /// Resolves a batch of addresses like [`ActorOps::resolve_address`], charging gas per address.
+    fn resolve_addresses(&mut self, addresses: &[Address]) -> Result<Vec<Option<ActorID>>>;
+
+    /// Looks up the code CID of the actor with the given ID. Returns `None` if there is no such
+    /// actor.
+    ///
+    /// Addresses must be resolved with [`ActorOps::resolve_address`] first.
+    fn get_actor_code_cid(&self, id: ActorID) -> Result<Option<Cid>>;
 
     /// Computes an address for a new actor. The returned address is intended to uniquely refer to
     /// the actor even in the event of a chain re-org (whereas an ID-address might refer to a
@@ -189,13 +241,22 @@ pub trait ActorOps {
 
 /// Operations to send messages to other actors.
 pub trait SendOps {
+    /// Sends a message to another actor, within a transaction.
+    ///
+    /// The returned receipt carries the exit code and return value of the receiver, along with the
+    /// gas consumed by the call (the receiver and everything it called in turn). Charges made by
+    /// the caller before the call, e.g. for the parameters, aren't included.
+    ///
+    /// Fails with [`ErrorNumber::ParamsTooLarge`](fvm_shared::error::ErrorNumber::ParamsTooLarge)
+    /// if the parameters exceed the configured
+    /// [`max_send_params_size`](crate::Config::max_send_params_size).
     fn send(
         &mut self,
         recipient: &Address,
         method: u64,
         params: &RawBytes,
         value: &TokenAmount,
-    ) -> Result<InvocationResult>;
+    ) -> Result<Receipt>;
 }
 
 /// Operations to query the circulating supply.
@@ -220,7 +281,16 @@ pub trait CircSupplyOps {
 pub trait GasOps {
     /// ChargeGas charges specified amount of `gas` for execution.
     /// `name` provides information about gas charging point
-    fn charge_gas(&mut self, name: &str, compute: i64) -> Result<()>;
+    fn charge_gas(&mut self, name: &str, compute: Gas) -> Result<()>;
+
+    /// Returns the gas used so far by the current call stack.
+    fn gas_used(&self) -> Gas;
+
+    /// Returns the gas limit of the current call stack.
+    fn gas_available(&self) -> Gas;
+
+    /// Returns the currently active gas price list.
+    fn price_list(&self) -> &PriceList;
 }
 
 /// Cryptographic primitives provided by the kernel.
@@ -284,13 +354,26 @@ pub trait CryptoOps {
     fn verify_replica_update(&mut self, replica: &ReplicaUpdateInfo) -> Result<bool>;
 }
 
+/// Host-side precompiles.
+pub trait PrecompileOps {
+    /// Calls the precompile registered under the given ID in the
+    /// [`PrecompileRegistry`](crate::machine::PrecompileRegistry), charging its gas, and returns
+    /// its output as a raw block.
+    ///
+    /// Fails with [`ErrorNumber::NotFound`](fvm_shared::error::ErrorNumber::NotFound) if no such
+    /// precompile is available at the current network version, and with
+    /// [`ErrorNumber::IllegalArgument`](fvm_shared::error::ErrorNumber::IllegalArgument) if the
+    /// precompile rejects its input.
+    fn call_precompile(&mut self, id: u64, input: &[u8]) -> Result<BlockId>;
+}
+
 /// Randomness queries.
 pub trait RandomnessOps {
     /// Randomness returns a (pseudo)random byte array drawing from the latest
     /// ticket chain from a given epoch and incorporating requisite entropy.
     /// This randomness is fork dependant but also biasable because of this.
     fn get_randomness_from_tickets(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
@@ -300,7 +383,7 @@ pub trait RandomnessOps {
     /// beacon from a given epoch and incorporating requisite entropy.
     /// This randomness is not tied to any fork of the chain, and is unbiasable.
     fn get_randomness_from_beacon(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
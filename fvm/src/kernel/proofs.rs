@@ -0,0 +1,422 @@
+//! Proof verification and sector commitments, backed by `filecoin-proofs-api`.
+//!
+//! The proofs libraries are only linked with the `proofs` feature. Without it, every operation
+//! here fails with a fatal error: the machine can't compute the right result, so it must not pretend
+//! to. Proof verification can still be skipped altogether with
+//! [`Config::verify_proofs`](crate::Config::verify_proofs), but unsealed sector CIDs can't be
+//! computed at all.
+
+pub use imp::*;
+
+#[cfg(feature = "proofs")]
+mod imp {
+    use std::collections::BTreeMap;
+    use std::convert::{TryFrom, TryInto};
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::Context as _;
+    use cid::Cid;
+    use filecoin_proofs_api::seal::{
+        compute_comm_d, verify_aggregate_seal_commit_proofs, verify_seal as proofs_verify_seal,
+    };
+    use filecoin_proofs_api::update::verify_empty_sector_update_proof;
+    use filecoin_proofs_api::{self as proofs, post, seal, ProverId, PublicReplicaInfo, SectorId};
+    use fvm_shared::address::Address;
+    use fvm_shared::commcid::{
+        cid_to_data_commitment_v1, cid_to_replica_commitment_v1, data_commitment_v1_to_cid,
+    };
+    use fvm_shared::piece::{zero_piece_commitment, PaddedPieceSize, PieceInfo};
+    use fvm_shared::randomness::Randomness;
+    use fvm_shared::sector::{
+        AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
+        SectorInfo, WindowPoStVerifyInfo,
+    };
+    use lazy_static::lazy_static;
+    use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
+    use rayon::{ThreadPool, ThreadPoolBuilder};
+
+    use crate::kernel::{ClassifyResult, Context as _, Result};
+
+    lazy_static! {
+        static ref NUM_CPUS: usize = num_cpus::get();
+        static ref VERIFIER_POOLS: Mutex<BTreeMap<usize, Arc<ThreadPool>>> = Default::default();
+    }
+
+    /// Returns the proof verification thread pool with the given number of threads (zero meaning one
+    /// per CPU), creating it on first use. Pools are shared by all machines configured with the same
+    /// number of threads, so that concurrently executing messages don't oversubscribe the CPUs.
+    ///
+    /// Verification isn't offloaded: actors need its outcome to continue, so the calling thread
+    /// blocks until the pool is done. The pool only bounds how many threads verify proofs at once.
+    pub fn verifier_pool(threads: usize) -> Result<Arc<ThreadPool>> {
+        let threads = if threads == 0 { *NUM_CPUS } else { threads };
+        let mut pools = VERIFIER_POOLS.lock().expect("verifier pool lock poisoned");
+        if let Some(pool) = pools.get(&threads) {
+            return Ok(pool.clone());
+        }
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("fvm-verifier-{}", i))
+            .build()
+            .context("failed to create the proof verification thread pool")
+            .or_fatal()?;
+        let pool = Arc::new(pool);
+        pools.insert(threads, pool.clone());
+        Ok(pool)
+    }
+
+    pub fn compute_unsealed_sector_cid(
+        proof_type: RegisteredSealProof,
+        pieces: &[PieceInfo],
+    ) -> Result<Cid> {
+        let ssize = proof_type.sector_size().or_illegal_argument()? as u64;
+
+        let mut all_pieces = Vec::<proofs::PieceInfo>::with_capacity(pieces.len());
+
+        let pssize = PaddedPieceSize(ssize);
+        if pieces.is_empty() {
+            all_pieces.push(proofs::PieceInfo {
+                size: pssize.unpadded().into(),
+                commitment: zero_piece_commitment(pssize),
+            })
+        } else {
+            // pad remaining space with 0 piece commitments
+            let mut sum = PaddedPieceSize(0);
+            let pad_to = |pads: Vec<PaddedPieceSize>,
+                          all_pieces: &mut Vec<proofs::PieceInfo>,
+                          sum: &mut PaddedPieceSize| {
+                for p in pads {
+                    all_pieces.push(proofs::PieceInfo {
+                        size: p.unpadded().into(),
+                        commitment: zero_piece_commitment(p),
+                    });
+
+                    sum.0 += p.0;
+                }
+            };
+            for p in pieces {
+                let (ps, _) = get_required_padding(sum, p.size);
+                pad_to(ps, &mut all_pieces, &mut sum);
+
+                all_pieces.push(proofs::PieceInfo::try_from(p).or_illegal_argument()?);
+                sum.0 += p.size.0;
+            }
+
+            let (ps, _) = get_required_padding(sum, pssize);
+            pad_to(ps, &mut all_pieces, &mut sum);
+        }
+
+        let comm_d = compute_comm_d(proof_type.try_into().or_illegal_argument()?, &all_pieces)
+            .or_illegal_argument()?;
+
+        data_commitment_v1_to_cid(&comm_d).or_illegal_argument()
+    }
+
+    pub fn verify_post(threads: usize, verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        let WindowPoStVerifyInfo {
+            ref proofs,
+            ref challenged_sectors,
+            prover,
+            ..
+        } = verify_info;
+
+        let Randomness(mut randomness) = verify_info.randomness;
+
+        // Necessary to be valid bls12 381 element.
+        randomness[31] &= 0x3f;
+
+        // Convert sector info into public replica
+        let replicas = to_fil_public_replica_infos(challenged_sectors, ProofType::Window)?;
+
+        // Convert PoSt proofs into proofs-api format
+        let proofs: Vec<(proofs::RegisteredPoStProof, _)> = proofs
+            .iter()
+            .map(|p| Ok((p.post_proof.try_into()?, p.proof_bytes.as_ref())))
+            .collect::<core::result::Result<_, String>>()
+            .or_illegal_argument()?;
+
+        // Generate prover bytes from ID
+        let prover_id = prover_id_from_u64(*prover);
+
+        // Verify Proof, waiting for the verifier pool.
+        verifier_pool(threads)?
+            .install(|| post::verify_window_post(&randomness, &proofs, &replicas, prover_id))
+            .or_illegal_argument()
+    }
+
+    pub fn batch_verify_seals(threads: usize, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        log::debug!("batch verify seals start");
+        let pool = verifier_pool(threads)?;
+        let out = pool.install(|| {
+            vis.par_iter()
+                .with_min_len(vis.len() / pool.current_num_threads())
+                .map(|seal| {
+                    let verify_seal_result = std::panic::catch_unwind(|| verify_seal(seal));
+                    match verify_seal_result {
+                        Ok(res) => {
+                            match res {
+                                Ok(correct) => {
+                                    if !correct {
+                                        log::debug!(
+                                            "seal verify in batch failed (miner: {}) (err: Invalid Seal proof)",
+                                            seal.sector_id.miner
+                                        );
+                                    }
+                                    correct // all ok
+                                }
+                                Err(err) => {
+                                    log::debug!(
+                                        "seal verify in batch failed (miner: {}) (err: {})",
+                                        seal.sector_id.miner,
+                                        err
+                                    );
+                                    false
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            log::error!("seal verify internal fail (miner: {}) (err: {:?})", seal.sector_id.miner, e);
+                            false
+                        }
+                    }
+                })
+                .collect()
+        });
+        log::debug!("batch verify seals end");
+        Ok(out)
+    }
+
+    pub fn verify_aggregate_seals(aggregate: &AggregateSealVerifyProofAndInfos) -> Result<bool> {
+        let spt: proofs::RegisteredSealProof =
+            aggregate.seal_proof.try_into().or_illegal_argument()?;
+        let prover_id = prover_id_from_u64(aggregate.miner);
+        struct AggregationInputs {
+            // replica
+            commr: [u8; 32],
+            // data
+            commd: [u8; 32],
+            sector_id: SectorId,
+            ticket: [u8; 32],
+            seed: [u8; 32],
+        }
+        let inputs: Vec<AggregationInputs> = aggregate
+            .infos
+            .iter()
+            .map(|info| {
+                let commr = cid_to_replica_commitment_v1(&info.sealed_cid)?;
+                let commd = cid_to_data_commitment_v1(&info.unsealed_cid)?;
+                Ok(AggregationInputs {
+                    commr,
+                    commd,
+                    ticket: info.randomness.0,
+                    seed: info.interactive_randomness.0,
+                    sector_id: SectorId::from(info.sector_number),
+                })
+            })
+            .collect::<core::result::Result<Vec<_>, &'static str>>()
+            .or_illegal_argument()?;
+
+        let inp: Vec<Vec<_>> = inputs
+            .par_iter()
+            .map(|input| {
+                seal::get_seal_inputs(
+                    spt,
+                    input.commr,
+                    input.commd,
+                    prover_id,
+                    input.sector_id,
+                    input.ticket,
+                    input.seed,
+                )
+            })
+            .try_reduce(Vec::new, |mut acc, current| {
+                acc.extend(current);
+                Ok(acc)
+            })
+            .or_illegal_argument()?;
+
+        let commrs: Vec<[u8; 32]> = inputs.iter().map(|input| input.commr).collect();
+        let seeds: Vec<[u8; 32]> = inputs.iter().map(|input| input.seed).collect();
+
+        verify_aggregate_seal_commit_proofs(
+            spt,
+            aggregate.aggregate_proof.try_into().or_illegal_argument()?,
+            aggregate.proof.clone(),
+            &commrs,
+            &seeds,
+            inp,
+        )
+        .or_illegal_argument()
+    }
+
+    pub fn verify_replica_update(replica: &ReplicaUpdateInfo) -> Result<bool> {
+        let up: proofs::RegisteredUpdateProof =
+            replica.update_proof_type.try_into().or_illegal_argument()?;
+
+        let commr_old =
+            cid_to_replica_commitment_v1(&replica.old_sealed_cid).or_illegal_argument()?;
+        let commr_new =
+            cid_to_replica_commitment_v1(&replica.new_sealed_cid).or_illegal_argument()?;
+        let commd = cid_to_data_commitment_v1(&replica.new_unsealed_cid).or_illegal_argument()?;
+
+        verify_empty_sector_update_proof(up, &replica.proof, commr_old, commr_new, commd)
+            .or_illegal_argument()
+    }
+
+    /// PoSt proof variants.
+    enum ProofType {
+        #[allow(unused)]
+        Winning,
+        Window,
+    }
+
+    fn prover_id_from_u64(id: u64) -> ProverId {
+        let mut prover_id = ProverId::default();
+        let prover_bytes = Address::new_id(id).payload().to_raw_bytes();
+        prover_id[..prover_bytes.len()].copy_from_slice(&prover_bytes);
+        prover_id
+    }
+
+    fn get_required_padding(
+        old_length: PaddedPieceSize,
+        new_piece_length: PaddedPieceSize,
+    ) -> (Vec<PaddedPieceSize>, PaddedPieceSize) {
+        let mut sum = 0;
+
+        let mut to_fill = 0u64.wrapping_sub(old_length.0) % new_piece_length.0;
+        let n = to_fill.count_ones();
+        let mut pad_pieces = Vec::with_capacity(n as usize);
+        for _ in 0..n {
+            let next = to_fill.trailing_zeros();
+            let p_size = 1 << next;
+            to_fill ^= p_size;
+
+            let padded = PaddedPieceSize(p_size);
+            pad_pieces.push(padded);
+            sum += padded.0;
+        }
+
+        (pad_pieces, PaddedPieceSize(sum))
+    }
+
+    fn to_fil_public_replica_infos(
+        src: &[SectorInfo],
+        typ: ProofType,
+    ) -> Result<BTreeMap<SectorId, PublicReplicaInfo>> {
+        let replicas = src
+            .iter()
+            .map::<core::result::Result<(SectorId, PublicReplicaInfo), String>, _>(
+                |sector_info: &SectorInfo| {
+                    let commr = cid_to_replica_commitment_v1(&sector_info.sealed_cid)?;
+                    let proof = match typ {
+                        ProofType::Winning => sector_info.proof.registered_winning_post_proof()?,
+                        ProofType::Window => sector_info.proof.registered_window_post_proof()?,
+                    };
+                    let replica = PublicReplicaInfo::new(proof.try_into()?, commr);
+                    Ok((SectorId::from(sector_info.sector_number), replica))
+                },
+            )
+            .collect::<core::result::Result<BTreeMap<SectorId, PublicReplicaInfo>, _>>()
+            .or_illegal_argument()?;
+        Ok(replicas)
+    }
+
+    pub fn verify_seal(vi: &SealVerifyInfo) -> Result<bool> {
+        let commr = cid_to_replica_commitment_v1(&vi.sealed_cid).or_illegal_argument()?;
+        let commd = cid_to_data_commitment_v1(&vi.unsealed_cid).or_illegal_argument()?;
+        let prover_id = prover_id_from_u64(vi.sector_id.miner);
+
+        proofs_verify_seal(
+            vi.registered_proof
+                .try_into()
+                .or_illegal_argument()
+                .context(format_args!("invalid proof type {:?}", vi.registered_proof))?,
+            commr,
+            commd,
+            prover_id,
+            SectorId::from(vi.sector_id.number),
+            vi.randomness.0,
+            vi.interactive_randomness.0,
+            &vi.proof,
+        )
+        .or_illegal_argument()
+        // TODO: There are probably errors here that should be fatal, but it's hard to tell so I'm
+        // sticking with illegal argument for now.
+        // Worst case, _some_ node falls out of sync. Better than the network halting.
+        .context("failed to verify seal proof")
+    }
+}
+
+#[cfg(not(feature = "proofs"))]
+mod imp {
+    use anyhow::anyhow;
+    use cid::Cid;
+    use fvm_shared::piece::PieceInfo;
+    use fvm_shared::sector::{
+        AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
+        WindowPoStVerifyInfo,
+    };
+
+    use crate::kernel::{ExecutionError, Result};
+
+    fn unsupported() -> ExecutionError {
+        ExecutionError::Fatal(anyhow!(
+            "proofs are not supported: the FVM was built without the `proofs` feature"
+        ))
+    }
+
+    pub fn compute_unsealed_sector_cid(
+        _proof_type: RegisteredSealProof,
+        _pieces: &[PieceInfo],
+    ) -> Result<Cid> {
+        Err(unsupported())
+    }
+
+    pub fn verify_seal(_vi: &SealVerifyInfo) -> Result<bool> {
+        Err(unsupported())
+    }
+
+    pub fn verify_post(_threads: usize, _verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        Err(unsupported())
+    }
+
+    pub fn batch_verify_seals(_threads: usize, _vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        Err(unsupported())
+    }
+
+    pub fn verify_aggregate_seals(_aggregate: &AggregateSealVerifyProofAndInfos) -> Result<bool> {
+        Err(unsupported())
+    }
+
+    pub fn verify_replica_update(_replica: &ReplicaUpdateInfo) -> Result<bool> {
+        Err(unsupported())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "proofs")]
+    #[test]
+    fn verifier_pools_are_shared() {
+        use std::sync::Arc;
+
+        use super::imp::verifier_pool;
+
+        let pool = verifier_pool(2).unwrap();
+        assert_eq!(pool.current_num_threads(), 2);
+        assert!(Arc::ptr_eq(&pool, &verifier_pool(2).unwrap()));
+        assert!(!Arc::ptr_eq(&pool, &verifier_pool(3).unwrap()));
+    }
+
+    #[cfg(not(feature = "proofs"))]
+    #[test]
+    fn proofs_are_unsupported() {
+        use fvm_shared::sector::RegisteredSealProof;
+
+        use crate::kernel::ExecutionError;
+
+        let err = super::compute_unsealed_sector_cid(RegisteredSealProof::StackedDRG2KiBV1P1, &[])
+            .unwrap_err();
+        assert!(matches!(err, ExecutionError::Fatal(_)));
+    }
+}
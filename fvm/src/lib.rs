@@ -1,10 +1,16 @@
 //! (Proper package docs coming shortly; for now this is a holding pen for items
 //! we must mention).
 //!
+//! ## Embedding
+//!
+//! Nodes embedding the FVM should import what they need from [`prelude`], whose paths are stable.
+//! The layout of the other modules may change between releases.
+//!
 //! ## Logging
 //!
 //! This package emits logs using the log façade. Configure the logging backend
 //! of your choice during the initialization of the consuming application.
+pub use fvm_shared::constants;
 pub use kernel::default::DefaultKernel;
 pub use kernel::{BlockError, Kernel};
 
@@ -13,14 +19,26 @@ pub mod executor;
 pub mod externs;
 pub mod kernel;
 pub mod machine;
+pub mod prelude;
+#[cfg(all(unix, feature = "remote-blockstore"))]
+pub mod remote_blockstore;
+#[doc(hidden)]
 pub mod syscalls;
+pub mod trace;
+
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 
 // TODO Public only for conformance tests.
 //  Consider exporting only behind a feature.
 pub mod gas;
 pub mod state_tree;
 
-mod blockstore;
+/// Blockstore wrappers used by the FVM, which now live in [`fvm_shared::blockstore`].
+#[deprecated(note = "import the blockstore wrappers from `fvm_shared::blockstore` instead")]
+pub mod blockstore {
+    pub use fvm_shared::blockstore::{BufferedBlockstore, ReadOnlyBlockstore};
+}
 
 mod account_actor;
 mod init_actor;
@@ -29,9 +47,14 @@ mod power_actor;
 mod reward_actor;
 mod system_actor;
 
+use std::sync::Arc;
+
 use cid::multihash::{Code, MultihashDigest};
 use cid::Cid;
 use fvm_shared::encoding::{to_vec, DAG_CBOR};
+use wasmtime::OptLevel;
+
+use crate::gas::PriceListRegistry;
 
 lazy_static::lazy_static! {
     /// Cid of the empty array Cbor bytes (`EMPTY_ARR_BYTES`).
@@ -50,8 +73,56 @@ pub struct Config {
     /// Maximum number of memory pages an invocation container's memory
     /// can expand to.
     pub max_pages: usize,
+    /// Maximum number of elements an invocation container's tables can grow to. Like the page
+    /// limit, it bounds the tables declared by the module as well as `table.grow`.
+    pub max_table_elements: u32,
     /// Whether debug mode is enabled or not.
     pub debug: bool,
+    /// Whether to record every syscall made by actors (with its arguments and the gas it charged)
+    /// in the execution trace.
+    pub trace_syscalls: bool,
+    /// Whether to record every send, gas charge and state change in the execution trace.
+    pub trace_calls: bool,
+    /// Custom price lists, overriding the compiled-in price list of their network version.
+    pub price_lists: PriceListRegistry,
+    /// The number of threads used to verify proofs (zero for one per CPU). Machines configured with
+    /// the same number of threads share a thread pool, bounding how many threads verify proofs at
+    /// once. Verification is synchronous: execution waits for each proof to be verified.
+    pub verifier_threads: usize,
+    /// Whether to verify seal, PoSt, aggregate seal and replica update proofs. When disabled, all
+    /// such proofs are accepted (but still charged for), which lets devnets run with fake proofs.
+    pub verify_proofs: bool,
+    /// How proofs are verified (see [`ProofsVerifier`](machine::ProofsVerifier)). In process by
+    /// default.
+    pub proofs_verifier: Arc<dyn machine::ProofsVerifier>,
+    /// Custom singleton actors, created at genesis with
+    /// [`DefaultMachine::install_singletons`](machine::DefaultMachine::install_singletons) (see
+    /// [`SingletonRegistry`](machine::SingletonRegistry)).
+    pub singletons: machine::SingletonRegistry,
+    /// Host-side precompiles actors can call (see
+    /// [`PrecompileRegistry`](machine::PrecompileRegistry)). None by default.
+    pub precompiles: machine::PrecompileRegistry,
+    /// The maximum size (in bytes) of the parameters an actor can pass when sending a message to
+    /// another actor. Top-level messages aren't subject to this limit.
+    pub max_send_params_size: usize,
+    /// The gas metering policy. Anything but the default
+    /// [`StandardGasMeter`](gas::StandardGasMeter) diverges from the network, and is only meant
+    /// for experiments.
+    pub gas_meter: Arc<dyn gas::GasMeter>,
+    /// Whether to compile the code of the builtin actors when constructing a machine. Otherwise,
+    /// each actor's code is compiled when it's first called, unless loaded beforehand (see
+    /// [`Machine::load_builtin_actors_modules`](machine::Machine::load_builtin_actors_modules)).
+    pub preload_actors: bool,
+    /// Whether to report the work done by the state tree (node loads, cache hits and misses) while
+    /// applying each message, in [`ApplyRet::state_tree_stats`](executor::ApplyRet).
+    pub state_tree_stats: bool,
+    /// Builtin actors bundles to load the manifest and actor code from, before the blockstore (see
+    /// [`BundleRegistry`](machine::BundleRegistry)). None by default.
+    pub bundles: machine::BundleRegistry,
+    /// Where to aggregate the wall time and gas spent in each actor method, if anywhere (see
+    /// [`MethodStats`](executor::MethodStats)).
+    #[cfg(feature = "method-stats")]
+    pub method_stats: Option<executor::MethodStats>,
 }
 
 impl Default for Config {
@@ -59,23 +130,92 @@ impl Default for Config {
         Self {
             initial_pages: 0,
             max_pages: 1024,
+            max_table_elements: 65536,
             max_call_depth: 4096,
             debug: false,
+            trace_syscalls: false,
+            trace_calls: false,
+            price_lists: PriceListRegistry::default(),
+            verifier_threads: 0,
+            verify_proofs: true,
+            proofs_verifier: Arc::new(machine::InProcessVerifier),
+            singletons: Default::default(),
+            precompiles: Default::default(),
+            max_send_params_size: 1 << 20,
+            gas_meter: Arc::new(gas::StandardGasMeter),
+            preload_actors: true,
+            state_tree_stats: false,
+            bundles: Default::default(),
+            #[cfg(feature = "method-stats")]
+            method_stats: None,
         }
     }
 }
 
+/// The common ways of running the FVM, each with its own trade-offs between speed, memory and
+/// debuggability. Use [`Config::preset`] and [`Profile::engine_config`] as starting points rather
+/// than tuning every setting.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Profile {
+    /// Validating blocks at the head of the chain, where latency matters. Nothing is traced or
+    /// logged, proofs are verified on every CPU, and actors are compiled with optimizations (once,
+    /// as the same actors run over and over).
+    Validator,
+    /// Replaying past messages, e.g. to re-execute the chain on an archival node or to investigate
+    /// a divergence. Like [`Profile::Validator`], except that calls and gas charges are traced, at
+    /// the cost of some speed and memory per message.
+    Replay,
+    /// Developer networks and tests. Debug logging is enabled, proofs are accepted without being
+    /// verified (so that fake proofs can be used), and actors are compiled without optimizations,
+    /// which makes compiling frequently changing actors faster at the expense of running them.
+    Devnet,
+}
+
+impl Config {
+    /// Returns the machine configuration for the given profile.
+    pub fn preset(profile: Profile) -> Self {
+        let default = Self::default();
+        match profile {
+            Profile::Validator => default,
+            Profile::Replay => Self {
+                trace_calls: true,
+                ..default
+            },
+            Profile::Devnet => Self {
+                debug: true,
+                verify_proofs: false,
+                ..default
+            },
+        }
+    }
+}
+
+impl Profile {
+    /// Returns the wasm engine configuration for the profile, to construct an
+    /// [`Engine`](machine::Engine) with.
+    pub fn engine_config(self) -> wasmtime::Config {
+        let mut config = wasmtime::Config::default();
+        match self {
+            Profile::Validator | Profile::Replay => config.cranelift_opt_level(OptLevel::Speed),
+            Profile::Devnet => config.cranelift_opt_level(OptLevel::None),
+        };
+        config
+    }
+}
+
 #[cfg(test)]
 mod test {
+    use std::sync::Arc;
+
     use fvm_shared::actor::builtin::Manifest;
-    use fvm_shared::blockstore::{CborStore, MemoryBlockstore};
+    use fvm_shared::blockstore::{Blockstore, CborStore, MemoryBlockstore};
     use fvm_shared::state::StateTreeVersion;
     use multihash::Code;
     use num_traits::Zero;
 
     use crate::call_manager::DefaultCallManager;
-    use crate::externs::{Consensus, Externs, Rand};
-    use crate::machine::{DefaultMachine, Engine};
+    use crate::externs::{Chain, CircSupply, CodePolicy, Consensus, Externs, Rand};
+    use crate::machine::{DefaultMachine, Engine, Machine};
     use crate::state_tree::StateTree;
     use crate::{executor, Config, DefaultKernel};
 
@@ -83,6 +223,19 @@ mod test {
 
     impl Externs for DummyExterns {}
 
+    impl CodePolicy for DummyExterns {}
+
+    impl CircSupply for DummyExterns {}
+
+    impl Chain for DummyExterns {
+        fn get_tipset_cid(
+            &self,
+            _epoch: fvm_shared::clock::ChainEpoch,
+        ) -> anyhow::Result<cid::Cid> {
+            todo!()
+        }
+    }
+
     impl Rand for DummyExterns {
         fn get_chain_randomness(
             &self,
@@ -114,12 +267,10 @@ mod test {
         }
     }
 
-    #[test]
-    fn test_constructor() {
-        let mut bs = MemoryBlockstore::default();
+    fn new_machine<B: Blockstore + 'static>(bs: B) -> DefaultMachine<B, DummyExterns> {
         let mut st = StateTree::new(bs, StateTreeVersion::V4).unwrap();
         let root = st.flush().unwrap();
-        bs = st.consume();
+        let bs = st.consume();
 
         // An empty built-in actors manifest.
         let manifest_cid = {
@@ -127,7 +278,7 @@ mod test {
             bs.put_cbor(&manifest, Code::Blake2b256).unwrap()
         };
 
-        let machine = DefaultMachine::new(
+        DefaultMachine::new(
             Config::default(),
             Engine::default(),
             0,
@@ -139,9 +290,28 @@ mod test {
             bs,
             DummyExterns,
         )
-        .unwrap();
+        .unwrap()
+    }
+
+    #[test]
+    fn test_constructor() {
+        let machine = new_machine(MemoryBlockstore::default());
         let _ = executor::DefaultExecutor::<DefaultKernel<DefaultCallManager<_>>>::new(Box::new(
             machine,
         ));
     }
+
+    #[test]
+    fn shared_blockstore() {
+        let bs = Arc::new(MemoryBlockstore::default());
+        let machine = new_machine(bs.clone());
+        let unflushed = machine
+            .blockstore()
+            .put_cbor(&"unflushed", Code::Blake2b256)
+            .unwrap();
+
+        let store = machine.into_store();
+        assert!(Arc::ptr_eq(&store, &bs));
+        assert!(!bs.has(&unflushed).unwrap());
+    }
 }
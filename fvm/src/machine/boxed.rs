@@ -1,6 +1,7 @@
 use cid::Cid;
 use fvm_shared::actor::builtin::Manifest;
 use fvm_shared::address::Address;
+use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::ActorID;
 
@@ -72,4 +73,9 @@ impl<M: Machine> Machine for Box<M> {
     fn flush(&mut self) -> Result<Cid> {
         (**self).flush()
     }
+
+    #[inline(always)]
+    fn reset_to(&mut self, state_root: Cid, epoch: ChainEpoch) -> Result<()> {
+        (**self).reset_to(state_root, epoch)
+    }
 }
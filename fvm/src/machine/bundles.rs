@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm_shared::blockstore::{Blockstore, Collectable, MemoryBlockstore};
+
+/// Builtin actors bundles (CAR files holding the actors' wasm bytecode, rooted at their
+/// manifest), registered in the machine's [`Config`](crate::Config) so that the actors' code
+/// doesn't have to be in the node's blockstore.
+///
+/// The machine looks up the builtin actors manifest and the code of every actor in the registered
+/// bundles first, then in its blockstore (see [`CodeLoader`]).
+#[derive(Clone, Default)]
+pub struct BundleRegistry {
+    blocks: Arc<HashMap<Cid, Vec<u8>>>,
+    manifests: Vec<Cid>,
+}
+
+impl fmt::Debug for BundleRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BundleRegistry")
+            .field("manifests", &self.manifests)
+            .field("blocks", &self.blocks.len())
+            .finish()
+    }
+}
+
+impl BundleRegistry {
+    /// Registers the bundle in the given CAR file, whose root is the bundle's manifest.
+    pub fn with_bundle(mut self, car: &[u8]) -> anyhow::Result<Self> {
+        let bs = MemoryBlockstore::default();
+        let roots = futures::executor::block_on(fvm_ipld_car::load_car(&bs, car))
+            .map_err(|e| anyhow!("failed to load actors bundle: {}", e))?;
+        let manifest = match roots.as_slice() {
+            [manifest] => *manifest,
+            _ => return Err(anyhow!("actors bundle must have a single root")),
+        };
+        if !bs.has(&manifest)? {
+            return Err(anyhow!("bundle is missing its manifest {}", manifest));
+        }
+
+        let blocks = Arc::make_mut(&mut self.blocks);
+        for k in bs.keys()? {
+            if let Some(block) = bs.get(&k)? {
+                blocks.insert(k, block);
+            }
+        }
+        self.manifests.push(manifest);
+        Ok(self)
+    }
+
+    /// Returns the manifests of the registered bundles, in registration order.
+    pub fn manifests(&self) -> &[Cid] {
+        &self.manifests
+    }
+
+    /// Returns a blockstore reading actor code from the registered bundles, and from the given
+    /// blockstore otherwise.
+    pub fn code_loader<B: Blockstore>(&self, blockstore: B) -> CodeLoader<'_, B> {
+        CodeLoader {
+            bundles: self,
+            blockstore,
+        }
+    }
+}
+
+/// Where a machine loads the builtin actors manifest and the actors' wasm bytecode from: the
+/// registered bundles, then the machine's blockstore. Writes aren't supported.
+pub struct CodeLoader<'a, B> {
+    bundles: &'a BundleRegistry,
+    blockstore: B,
+}
+
+impl<B: Blockstore> Blockstore for CodeLoader<'_, B> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        match self.bundles.blocks.get(k) {
+            Some(block) => Ok(Some(block.clone())),
+            None => self.blockstore.get(k),
+        }
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        Ok(self.bundles.blocks.contains_key(k) || self.blockstore.has(k)?)
+    }
+
+    fn put_keyed(&self, k: &Cid, _block: &[u8]) -> anyhow::Result<()> {
+        Err(anyhow!("cannot write block {} through the code loader", k))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_ipld_car::CarHeader;
+    use fvm_shared::blockstore::CborStore;
+    use fvm_shared::IPLD_RAW;
+
+    use super::*;
+
+    #[test]
+    fn code_from_bundle_then_blockstore() {
+        let code = b"\0asm\x01\0\0\0".to_vec();
+        let code_cid = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(&code));
+        let manifest_bs = MemoryBlockstore::default();
+        let manifest = manifest_bs
+            .put_cbor(&vec![("account", code_cid)], Code::Blake2b256)
+            .unwrap();
+        let manifest_block = manifest_bs.get(&manifest).unwrap().unwrap();
+
+        let mut car = Vec::new();
+        let mut blocks = futures::stream::iter(vec![(manifest, manifest_block), (code_cid, code)]);
+        futures::executor::block_on(
+            CarHeader::from(vec![manifest]).write_stream_async(&mut car, &mut blocks),
+        )
+        .unwrap();
+
+        let registry = BundleRegistry::default().with_bundle(&car).unwrap();
+        assert_eq!(registry.manifests(), [manifest]);
+
+        let bs = MemoryBlockstore::default();
+        let other = bs.put_cbor(&"state", Code::Blake2b256).unwrap();
+        let loader = registry.code_loader(&bs);
+        assert!(loader.has(&code_cid).unwrap());
+        assert!(loader.has(&other).unwrap());
+        assert!(!bs.has(&code_cid).unwrap());
+        assert!(loader.put_keyed(&other, b"").is_err());
+
+        assert!(BundleRegistry::default().with_bundle(&car[1..]).is_err());
+    }
+}
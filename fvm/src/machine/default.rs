@@ -4,7 +4,7 @@ use anyhow::{anyhow, Context as _};
 use cid::Cid;
 use fvm_shared::actor::builtin::{load_manifest, Manifest};
 use fvm_shared::address::Address;
-use fvm_shared::blockstore::{Blockstore, Buffered};
+use fvm_shared::blockstore::{Blockstore, Buffered, BufferedBlockstore, ReadOnlyBlockstore};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::error::ErrorNumber;
@@ -14,10 +14,8 @@ use log::debug;
 use num_traits::{Signed, Zero};
 
 use super::{Engine, Machine, MachineContext};
-use crate::blockstore::BufferedBlockstore;
 use crate::externs::Externs;
-use crate::gas::price_list_by_network_version;
-use crate::kernel::{ClassifyResult, Context as _, Result};
+use crate::kernel::{ClassifyResult, Context as _, ExecutionError, Result};
 use crate::state_tree::{ActorState, StateTree};
 use crate::system_actor::State as SystemActorState;
 use crate::{syscall_error, Config};
@@ -42,6 +40,12 @@ pub struct DefaultMachine<B, E> {
     builtin_actors: Manifest,
 }
 
+/// A [`DefaultMachine`] that never writes to the node's blockstore, for executing messages against
+/// historical state (e.g., to serve `StateCall` queries). Construct it by passing the blockstore
+/// wrapped in a [`ReadOnlyBlockstore`]. Flushing works as usual, but the new state is discarded
+/// along with the machine.
+pub type ReadOnlyMachine<B, E> = DefaultMachine<ReadOnlyBlockstore<B>, E>;
+
 impl<B, E> DefaultMachine<B, E>
 where
     B: Blockstore + 'static,
@@ -61,6 +65,7 @@ where
         blockstore: B,
         externs: E,
     ) -> anyhow::Result<Self> {
+        // Network version 16 already has prices, but no actors bundle to run with yet.
         const SUPPORTED_VERSIONS: RangeInclusive<NetworkVersion> =
             NetworkVersion::V14..=NetworkVersion::V15;
 
@@ -79,7 +84,7 @@ where
             circ_supply,
             network_version,
             initial_state_root: state_root,
-            price_list: price_list_by_network_version(network_version),
+            price_list: config.price_lists.price_list(network_version),
             debug: config.debug,
         };
 
@@ -109,15 +114,52 @@ where
                 state.builtin_actors
             }
         };
-        let builtin_actors =
-            load_manifest(state_tree.store(), &builtin_actors_cid, builtin_actors.0)?;
+        let builtin_actors = load_manifest(
+            &config.bundles.code_loader(state_tree.store()),
+            &builtin_actors_cid,
+            builtin_actors.0,
+        )?;
+
+        // Custom singletons are only checked here: they're created explicitly, at genesis (see
+        // `install_singletons`).
+        config
+            .singletons
+            .validate(&builtin_actors)
+            .context("invalid custom singleton actors")?;
+
+        let machine = DefaultMachine {
+            config,
+            context,
+            engine,
+            externs,
+            state_tree,
+            builtin_actors,
+        };
 
         // Preload any uncached modules.
         // This interface works for now because we know all actor CIDs
         // ahead of time, but with user-supplied code, we won't have that
         // guarantee.
-        engine.preload(state_tree.store(), builtin_actors.left_values())?;
+        if machine.config.preload_actors {
+            machine.load_builtin_actors_modules()?;
+        }
+
+        Ok(machine)
+    }
 
+    /// Creates a machine over the given state, reusing an already loaded builtin actors manifest.
+    /// Modules are assumed to have already been compiled by the engine.
+    pub(crate) fn from_parts(
+        config: Config,
+        engine: Engine,
+        context: MachineContext,
+        builtin_actors: Manifest,
+        blockstore: B,
+        externs: E,
+    ) -> Result<Self> {
+        let bstore = BufferedBlockstore::new(blockstore);
+        let state_tree = StateTree::new_from_root(bstore, &context.initial_state_root)?;
+        config.singletons.validate(&builtin_actors).or_fatal()?;
         Ok(DefaultMachine {
             config,
             context,
@@ -127,6 +169,41 @@ where
             builtin_actors,
         })
     }
+
+    /// Consumes the machine and returns the blockstore it was created with. Unlike
+    /// [`Machine::consume`], which returns the machine's write buffer, blocks written since the
+    /// last [`flush`](Machine::flush) are discarded.
+    ///
+    /// Shared handles (e.g. an `Arc` of the node's blockstore) can be passed to the machine to
+    /// keep using the blockstore while it runs.
+    pub fn into_store(self) -> B {
+        self.state_tree.consume().consume()
+    }
+
+    /// Creates the custom singletons (see [`Config::singletons`]) missing from the machine's state
+    /// tree, e.g. when building the genesis state of a devnet. Machines never do this on their
+    /// own, as it changes the state they were given: callers opt in, then
+    /// [`flush`](Machine::flush) the new state.
+    pub fn install_singletons(&mut self) -> Result<()> {
+        self.config
+            .singletons
+            .install(&mut self.state_tree, &self.builtin_actors)
+            .context("failed to install custom singleton actors")
+            .or_fatal()
+    }
+
+    /// Takes the machine apart, discarding the state tree and any unflushed writes. The parts can
+    /// be put back together (possibly several times) with [`DefaultMachine::from_parts`].
+    pub(crate) fn into_parts(self) -> (Config, Engine, MachineContext, Manifest, B, E) {
+        (
+            self.config,
+            self.engine,
+            self.context,
+            self.builtin_actors,
+            self.state_tree.consume().consume(),
+            self.externs,
+        )
+    }
 }
 
 impl<B, E> Machine for DefaultMachine<B, E>
@@ -180,6 +257,28 @@ where
         Ok(root)
     }
 
+    fn reset_to(&mut self, state_root: Cid, epoch: ChainEpoch) -> Result<()> {
+        debug!("resetting machine, epoch={}, root={}", epoch, state_root);
+
+        if epoch < 0 {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "cannot reset to negative epoch {}",
+                epoch
+            )));
+        }
+
+        // Fails if the blockstore doesn't have the state root, leaving the machine as it was.
+        self.state_tree.reset_to_root(&state_root)?;
+        // The blocks written since the last flush belong to the discarded state, unless the new
+        // state links to them.
+        self.blockstore()
+            .discard_unreachable(&state_root)
+            .or_fatal()?;
+        self.context.initial_state_root = state_root;
+        self.context.epoch = epoch;
+        Ok(())
+    }
+
     /// Creates an uninitialized actor.
     // TODO: Remove
     fn create_actor(&mut self, addr: &Address, act: ActorState) -> Result<ActorID> {
@@ -284,3 +383,156 @@ where
         self.state_tree.consume()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::blockstore::MemoryBlockstore;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::gas::price_list_by_network_version;
+    use crate::machine::SingletonRegistry;
+    use crate::testing::{cbor_blake2b_cid, MockExterns};
+    use crate::EMPTY_ARR_CID;
+
+    const ALICE: ActorID = 100;
+    const BOB: ActorID = 101;
+
+    /// Returns a machine at the given network version, over a state where Alice holds 10 attoFIL
+    /// and Bob doesn't exist.
+    fn machine(nv: NetworkVersion) -> DefaultMachine<MemoryBlockstore, MockExterns> {
+        let mut state_tree =
+            StateTree::new(MemoryBlockstore::default(), StateTreeVersion::V4).unwrap();
+        let alice = ActorState::new(
+            cbor_blake2b_cid(b"account"),
+            *EMPTY_ARR_CID,
+            TokenAmount::from(10),
+            0,
+        );
+        state_tree.set_actor_id(ALICE, alice).unwrap();
+        let initial_state_root = state_tree.flush().unwrap();
+
+        let context = MachineContext {
+            epoch: 0,
+            base_fee: TokenAmount::zero(),
+            circ_supply: TokenAmount::zero(),
+            initial_state_root,
+            price_list: price_list_by_network_version(nv),
+            network_version: nv,
+            debug: false,
+        };
+        DefaultMachine::from_parts(
+            Config::default(),
+            Engine::default(),
+            context,
+            Manifest::default(),
+            state_tree.consume(),
+            MockExterns::default(),
+        )
+        .unwrap()
+    }
+
+    fn balance(
+        machine: &DefaultMachine<MemoryBlockstore, MockExterns>,
+        id: ActorID,
+    ) -> TokenAmount {
+        machine
+            .state_tree
+            .get_actor_id(id)
+            .unwrap()
+            .unwrap()
+            .balance
+    }
+
+    fn transfer(nv: NetworkVersion, from: ActorID, to: ActorID, value: i64) -> Result<()> {
+        machine(nv).transfer(from, to, &TokenAmount::from(value))
+    }
+
+    fn error_number(res: Result<()>) -> ErrorNumber {
+        match res {
+            Err(ExecutionError::Syscall(e)) => e.1,
+            _ => panic!("expected a syscall error"),
+        }
+    }
+
+    #[test]
+    fn reset_rejects_negative_epochs() {
+        let mut machine = machine(NetworkVersion::V15);
+        let root = machine.context.initial_state_root;
+        let err = machine.reset_to(root, -1).unwrap_err();
+        assert!(err.is_fatal());
+        assert_eq!(machine.context.epoch, 0);
+    }
+
+    #[test]
+    fn singletons_are_only_installed_on_request() {
+        const FAUCET: ActorID = 10;
+        let (config, engine, context, builtin_actors, blockstore, externs) =
+            machine(NetworkVersion::V15).into_parts();
+        let singletons = SingletonRegistry::default()
+            .with_singleton(Address::new_id(FAUCET), cbor_blake2b_cid(b"faucet"), |_| {
+                Ok(*EMPTY_ARR_CID)
+            })
+            .unwrap();
+        let config = Config {
+            singletons,
+            ..config
+        };
+        let mut machine = DefaultMachine::from_parts(
+            config,
+            engine,
+            context,
+            builtin_actors,
+            blockstore,
+            externs,
+        )
+        .unwrap();
+        assert!(machine.state_tree.get_actor_id(FAUCET).unwrap().is_none());
+        let root = machine.context.initial_state_root;
+        machine.reset_to(root, 1).unwrap();
+        assert!(machine.state_tree.get_actor_id(FAUCET).unwrap().is_none());
+
+        machine.install_singletons().unwrap();
+        assert!(machine.state_tree.get_actor_id(FAUCET).unwrap().is_some());
+    }
+
+    #[test]
+    fn transfers_move_funds() {
+        for nv in [NetworkVersion::V14, NetworkVersion::V15] {
+            let mut machine = machine(nv);
+            let bob = ActorState::new(*EMPTY_ARR_CID, *EMPTY_ARR_CID, TokenAmount::zero(), 0);
+            machine.state_tree.set_actor_id(BOB, bob).unwrap();
+
+            machine.transfer(ALICE, BOB, &TokenAmount::from(4)).unwrap();
+            assert_eq!(balance(&machine, ALICE), TokenAmount::from(6));
+            assert_eq!(balance(&machine, BOB), TokenAmount::from(4));
+        }
+    }
+
+    #[test]
+    fn negative_transfers_are_illegal() {
+        for nv in [NetworkVersion::V14, NetworkVersion::V15] {
+            let err = error_number(transfer(nv, ALICE, BOB, -1));
+            assert_eq!(err, ErrorNumber::IllegalArgument);
+        }
+    }
+
+    #[test]
+    fn transfer_checks_follow_network_version() {
+        // Before v15, self and zero transfers aren't checked at all.
+        transfer(NetworkVersion::V14, ALICE, ALICE, 20).unwrap();
+        transfer(NetworkVersion::V14, BOB, ALICE, 0).unwrap();
+        let err = error_number(transfer(NetworkVersion::V15, ALICE, ALICE, 20));
+        assert_eq!(err, ErrorNumber::InsufficientFunds);
+        let err = error_number(transfer(NetworkVersion::V15, BOB, ALICE, 0));
+        assert_eq!(err, ErrorNumber::InsufficientFunds);
+
+        // From v15, the sender's balance is checked before the receiver's existence.
+        let err = error_number(transfer(NetworkVersion::V14, ALICE, BOB, 20));
+        assert_eq!(err, ErrorNumber::NotFound);
+        let err = error_number(transfer(NetworkVersion::V15, ALICE, BOB, 20));
+        assert_eq!(err, ErrorNumber::InsufficientFunds);
+        let err = error_number(transfer(NetworkVersion::V15, ALICE, BOB, 5));
+        assert_eq!(err, ErrorNumber::NotFound);
+    }
+}
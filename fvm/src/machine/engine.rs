@@ -2,16 +2,22 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::ops::Deref;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use anyhow::anyhow;
 use cid::Cid;
+use fvm_shared::actor::builtin::Manifest;
 use fvm_shared::blockstore::Blockstore;
 use wasmtime::{Linker, Module};
 
 use crate::syscalls::{bind_syscalls, InvocationData};
-use crate::Kernel;
+use crate::{Config, Kernel};
 
 /// A caching wasmtime engine.
+///
+/// Compiled modules are cached by code CID, and clones share the cache: construct one engine per
+/// process and pass clones of it to every machine, so that each actor's code is only compiled once
+/// (see [`Engine::preload_manifest`] to compile the builtin actors up front).
 #[derive(Clone)]
 pub struct Engine(Arc<EngineInner>);
 
@@ -24,6 +30,8 @@ impl Default for Engine {
 struct EngineInner {
     engine: wasmtime::Engine,
     module_cache: Mutex<HashMap<Cid, Module>>,
+    /// The size of the wasm bytecode of the cached modules, when known.
+    code_sizes: Mutex<HashMap<Cid, usize>>,
     instance_cache: Mutex<anymap::Map<dyn anymap::any::Any + Send>>,
 }
 
@@ -36,9 +44,16 @@ impl Deref for Engine {
 }
 
 impl Engine {
-    /// Create a new Engine from a wasmtime config.
+    /// Create a new Engine from a wasmtime config. Fuel consumption is always enabled, so that
+    /// wasm execution can be charged for (see
+    /// [`PriceList::on_wasm_exec`](crate::gas::PriceList::on_wasm_exec)).
+    ///
+    /// Engines created from a [`wasmtime::Engine`] directly don't consume fuel unless their
+    /// config enabled it, and can't execute actors on networks pricing wasm execution.
     pub fn new(c: &wasmtime::Config) -> anyhow::Result<Self> {
-        Ok(wasmtime::Engine::new(c)?.into())
+        let mut c = c.clone();
+        c.consume_fuel(true);
+        Ok(wasmtime::Engine::new(&c)?.into())
     }
 }
 
@@ -47,6 +62,7 @@ impl From<wasmtime::Engine> for Engine {
         Engine(Arc::new(EngineInner {
             engine,
             module_cache: Default::default(),
+            code_sizes: Default::default(),
             instance_cache: Mutex::new(anymap::Map::new()),
         }))
     }
@@ -67,9 +83,28 @@ impl Engine {
         BS: Blockstore,
         I: IntoIterator<Item = &'a Cid>,
     {
-        let mut cache = self.0.module_cache.lock().expect("module_cache poisoned");
+        self.preload_with_progress(blockstore, cids, |_, _| {})
+    }
+
+    /// Like [`Engine::preload`], calling `on_compiled` with the time it took to compile each module
+    /// that wasn't cached yet.
+    ///
+    /// Modules are compiled without holding the cache, so actors can keep running (and compiling
+    /// the modules they need on their own) while this is called from another thread on a clone of
+    /// the engine.
+    pub fn preload_with_progress<'a, BS, I, F>(
+        &self,
+        blockstore: BS,
+        cids: I,
+        mut on_compiled: F,
+    ) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+        I: IntoIterator<Item = &'a Cid>,
+        F: FnMut(&Cid, Duration),
+    {
         for cid in cids {
-            if cache.contains_key(cid) {
+            if self.code_size(cid).is_some() {
                 continue;
             }
             let wasm = blockstore.get(cid)?.ok_or_else(|| {
@@ -78,14 +113,38 @@ impl Engine {
                     &cid.to_string()
                 )
             })?;
+            self.record_code_size(cid, wasm.len());
+            // Modules loaded precompiled are only missing their size.
+            if self.get_module(cid).is_some() {
+                continue;
+            }
+            let start = Instant::now();
             let module = Module::from_binary(&self.0.engine, wasm.as_slice())?;
-            cache.insert(*cid, module);
+            let elapsed = start.elapsed();
+            log::debug!("compiled actor code {} in {:?}", cid, elapsed);
+            self.0
+                .module_cache
+                .lock()
+                .expect("module_cache poisoned")
+                .entry(*cid)
+                .or_insert(module);
+            on_compiled(cid, elapsed);
         }
         Ok(())
     }
 
+    /// Compiles and caches the code of every actor of a builtin actors bundle, given its manifest,
+    /// before any machine is constructed. The code must be in the blockstore.
+    pub fn preload_manifest<BS>(&self, blockstore: BS, manifest: &Manifest) -> anyhow::Result<()>
+    where
+        BS: Blockstore,
+    {
+        self.preload(blockstore, manifest.left_values())
+    }
+
     /// Load some wasm code into the engine.
     pub fn load_bytecode(&self, k: &Cid, wasm: &[u8]) -> anyhow::Result<Module> {
+        self.record_code_size(k, wasm.len());
         let mut cache = self.0.module_cache.lock().expect("module_cache poisoned");
         let module = match cache.get(k) {
             Some(module) => module.clone(),
@@ -98,7 +157,8 @@ impl Engine {
         Ok(module)
     }
 
-    /// Load compiled wasm code into the engine.
+    /// Load compiled wasm code into the engine. The size of the module's bytecode isn't known
+    /// until it's [preloaded](Engine::preload), which actors can't be invoked without.
     ///
     /// # Safety
     ///
@@ -126,8 +186,27 @@ impl Engine {
             .cloned()
     }
 
+    /// Returns the size of the wasm bytecode of a loaded module, which invocations are charged
+    /// for (see [`PriceList::on_code_load`](crate::gas::PriceList::on_code_load)).
+    pub fn code_size(&self, k: &Cid) -> Option<usize> {
+        self.0
+            .code_sizes
+            .lock()
+            .expect("code_sizes poisoned")
+            .get(k)
+            .copied()
+    }
+
+    fn record_code_size(&self, k: &Cid, size: usize) {
+        self.0
+            .code_sizes
+            .lock()
+            .expect("code_sizes poisoned")
+            .insert(*k, size);
+    }
+
     /// Lookup and instantiate a loaded wasmtime module with the given store. This will cache the
-    /// linker, syscalls, "pre" isntance, etc.
+    /// linker, syscalls, "pre" instance, etc.
     pub fn get_instance<K: Kernel>(
         &self,
         store: &mut wasmtime::Store<InvocationData<K>>,
@@ -163,8 +242,66 @@ impl Engine {
         Ok(Some(instance))
     }
 
-    /// Construct a new wasmtime "store" from the given kernel.
-    pub fn new_store<K: Kernel>(&self, kernel: K) -> wasmtime::Store<InvocationData<K>> {
-        wasmtime::Store::new(&self.0.engine, InvocationData::new(kernel))
+    /// Construct a new wasmtime "store" from the given kernel. The actor's memory may grow up to
+    /// the configured maximum number of pages, and every page grown is charged to the kernel.
+    pub fn new_store<K: Kernel>(
+        &self,
+        kernel: K,
+        config: &Config,
+    ) -> wasmtime::Store<InvocationData<K>> {
+        let mut store = wasmtime::Store::new(&self.0.engine, InvocationData::new(kernel, config));
+        store.limiter(|data| data as &mut dyn wasmtime::ResourceLimiter);
+        store
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+    use fvm_shared::actor::builtin::Type;
+    use fvm_shared::blockstore::MemoryBlockstore;
+    use fvm_shared::IPLD_RAW;
+
+    use super::*;
+
+    /// The smallest valid wasm module: just the header.
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    #[test]
+    fn preload_compiles_once() {
+        let bs = MemoryBlockstore::default();
+        let code = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(EMPTY_MODULE));
+        bs.put_keyed(&code, EMPTY_MODULE).unwrap();
+        let engine = Engine::default();
+
+        let mut compiled = Vec::new();
+        engine
+            .preload_with_progress(&bs, [&code], |cid, _| compiled.push(*cid))
+            .unwrap();
+        assert_eq!(compiled, [code]);
+        assert!(engine.get_module(&code).is_some());
+        assert_eq!(engine.code_size(&code), Some(EMPTY_MODULE.len()));
+
+        engine
+            .preload_with_progress(&bs, [&code], |_, _| panic!("compiled twice"))
+            .unwrap();
+
+        let missing = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(b"missing"));
+        assert!(engine.preload(&bs, [&missing]).is_err());
+    }
+
+    #[test]
+    fn clones_share_modules() {
+        let bs = MemoryBlockstore::default();
+        let code = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(EMPTY_MODULE));
+        bs.put_keyed(&code, EMPTY_MODULE).unwrap();
+        let mut manifest = Manifest::new();
+        manifest.insert(code, Type::Account);
+
+        let engine = Engine::default();
+        let shared = engine.clone();
+        engine.preload_manifest(&bs, &manifest).unwrap();
+        assert!(shared.get_module(&code).is_some());
+        assert!(Engine::default().get_module(&code).is_none());
     }
 }
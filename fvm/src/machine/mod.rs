@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use cid::Cid;
 use fvm_shared::actor::builtin::Manifest;
 use fvm_shared::address::Address;
@@ -15,7 +17,7 @@ use crate::Config;
 
 mod default;
 
-pub use default::DefaultMachine;
+pub use default::{DefaultMachine, ReadOnlyMachine};
 
 mod engine;
 
@@ -23,10 +25,21 @@ pub use engine::Engine;
 
 mod boxed;
 
-pub const REWARD_ACTOR_ADDR: Address = Address::new_id(2);
+mod singletons;
+
+pub use singletons::{SingletonRegistry, StateProducer, StateStore};
+
+mod precompiles;
+
+pub use precompiles::{Precompile, PrecompileFn, PrecompileRegistry};
+
+mod bundles;
+
+pub use bundles::{BundleRegistry, CodeLoader};
+
+mod verifier;
 
-/// Distinguished Account actor that is the destination of all burnt funds.
-pub const BURNT_FUNDS_ACTOR_ADDR: Address = Address::new_id(99);
+pub use verifier::{InProcessVerifier, ProofsVerifier};
 
 /// The Machine is the top-level object of the FVM.
 ///
@@ -63,6 +76,25 @@ pub trait Machine: 'static {
     /// Returns the builtin actor index.
     fn builtin_actors(&self) -> &Manifest;
 
+    /// Compiles and caches the code of the builtin actors (and of the custom singletons) that the
+    /// engine hasn't compiled yet, returning how long each of them took. Calling it again is cheap,
+    /// and returns nothing.
+    ///
+    /// Code that isn't loaded is compiled when an actor running it is first called, so this only
+    /// moves the cost up front (see [`Config::preload_actors`]). To load the code in the background
+    /// while messages execute, call [`Engine::preload_with_progress`] from another thread instead.
+    fn load_builtin_actors_modules(&self) -> anyhow::Result<Vec<(Cid, Duration)>> {
+        let mut compiled = Vec::new();
+        self.engine().preload_with_progress(
+            self.config().bundles.code_loader(self.blockstore()),
+            self.builtin_actors()
+                .left_values()
+                .chain(self.config().singletons.codes()),
+            |cid, elapsed| compiled.push((*cid, elapsed)),
+        )?;
+        Ok(compiled)
+    }
+
     /// Returns an immutable reference to the state tree.
     fn state_tree(&self) -> &StateTree<Self::Blockstore>;
 
@@ -80,10 +112,28 @@ pub trait Machine: 'static {
     fn transfer(&mut self, from: ActorID, to: ActorID, value: &TokenAmount) -> Result<()>;
 
     /// Flushes the state-tree and returns the new root CID.
+    ///
+    /// This is how the post-state of a message (or of a block of messages) is obtained: once this
+    /// returns, the whole state under the returned root must be readable from the blockstore the
+    /// machine was constructed with. Machines buffering writes must flush their buffer here, as
+    /// the [`DefaultMachine`] does; the default implementation only flushes the state-tree.
     fn flush(&mut self) -> Result<Cid> {
         self.state_tree_mut().flush()
     }
 
+    /// Resets the machine to execute on top of the given state root, at the given epoch, so it can
+    /// be reused instead of constructing a new machine for every message or tipset.
+    ///
+    /// All cached actor states and resolved addresses are dropped, while the engine (and the
+    /// modules it compiled) is kept. The network version, base fee, circulating supply and builtin
+    /// actors are also kept: a machine can't be reset across network upgrades. The machine doesn't
+    /// know the network's upgrade schedule, so callers must make sure its network version is still
+    /// the one in effect at the new epoch.
+    ///
+    /// Unflushed state is discarded. Fails if called in the middle of a message execution, or with
+    /// a negative epoch.
+    fn reset_to(&mut self, state_root: Cid, epoch: ChainEpoch) -> Result<()>;
+
     /// Consumes the machine and returns the owned blockstore.
     fn consume(self) -> Self::Blockstore;
 }
@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use fvm_shared::version::NetworkVersion;
+
+use crate::gas::{Gas, GasCharge};
+
+/// The host implementation of a precompile: a pure function of its input.
+pub type PrecompileFn = Arc<dyn Fn(&[u8]) -> anyhow::Result<Vec<u8>> + Send + Sync>;
+
+/// A function implemented by the host, callable by actors through the `call_precompile` syscall.
+#[derive(Clone)]
+pub struct Precompile {
+    /// The first network version the precompile is available at.
+    pub since: NetworkVersion,
+    /// The gas charged for every call.
+    pub base_gas: Gas,
+    /// The gas charged per byte of input.
+    pub per_byte_gas: Gas,
+    func: PrecompileFn,
+}
+
+impl Precompile {
+    /// Returns the gas charged for calling the precompile on `input_len` bytes.
+    pub fn gas(&self, input_len: usize) -> GasCharge<'static> {
+        GasCharge::new(
+            "OnCallPrecompile",
+            self.base_gas + self.per_byte_gas * input_len as i64,
+            Gas::ZERO,
+        )
+    }
+
+    /// Runs the precompile on the given input.
+    pub fn call(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        (self.func)(input)
+    }
+}
+
+/// Expensive pure functions (e.g., a hash function or a pairing check a FIP proposes to add)
+/// implemented by the host instead of in wasm, registered in the machine's
+/// [`Config`](crate::Config) under well-known IDs.
+///
+/// Each precompile has a fixed gas schedule (a base price plus a price per byte of input), and is
+/// only available from a given network version on. The registry is empty by default: precompiles
+/// aren't part of any network, so registering one makes the FVM diverge from the network wherever
+/// it's called. They're meant for prototyping.
+#[derive(Clone, Default)]
+pub struct PrecompileRegistry {
+    precompiles: Arc<BTreeMap<u64, Precompile>>,
+}
+
+impl fmt::Debug for PrecompileRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.precompiles.iter().map(|(id, p)| (id, p.since)))
+            .finish()
+    }
+}
+
+impl PrecompileRegistry {
+    /// Registers the precompile `func` under the given ID, available from network version
+    /// `since` on, and charging `base_gas` plus `per_byte_gas` per byte of input.
+    pub fn with_precompile<F>(
+        mut self,
+        id: u64,
+        since: NetworkVersion,
+        base_gas: Gas,
+        per_byte_gas: Gas,
+        func: F,
+    ) -> anyhow::Result<Self>
+    where
+        F: Fn(&[u8]) -> anyhow::Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        if base_gas.is_negative() || per_byte_gas.is_negative() {
+            return Err(anyhow!("precompile {} has a negative price", id));
+        }
+        if self.precompiles.contains_key(&id) {
+            return Err(anyhow!("precompile {} already registered", id));
+        }
+        let precompile = Precompile {
+            since,
+            base_gas,
+            per_byte_gas,
+            func: Arc::new(func),
+        };
+        Arc::make_mut(&mut self.precompiles).insert(id, precompile);
+        Ok(self)
+    }
+
+    /// Returns true if no precompiles have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.precompiles.is_empty()
+    }
+
+    /// Returns the precompile registered under the given ID, if it's available at the given
+    /// network version.
+    pub fn get(&self, id: u64, nv: NetworkVersion) -> Option<&Precompile> {
+        self.precompiles.get(&id).filter(|p| nv >= p.since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty(_: &[u8]) -> anyhow::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+
+    #[test]
+    fn gated_by_network_version() {
+        let registry = PrecompileRegistry::default()
+            .with_precompile(1, NetworkVersion::V15, Gas::new(10), Gas::new(2), |input| {
+                Ok(input.iter().rev().copied().collect())
+            })
+            .unwrap();
+        assert!(registry.get(1, NetworkVersion::V14).is_none());
+        assert!(registry.get(2, NetworkVersion::V15).is_none());
+
+        let precompile = registry.get(1, NetworkVersion::V15).unwrap();
+        assert_eq!(precompile.call(&[1, 2, 3]).unwrap(), [3, 2, 1]);
+        assert_eq!(precompile.gas(3).total(), Gas::new(16));
+
+        assert!(registry
+            .clone()
+            .with_precompile(1, NetworkVersion::V15, Gas::ZERO, Gas::ZERO, empty)
+            .is_err());
+        assert!(registry
+            .with_precompile(2, NetworkVersion::V15, Gas::new(-1), Gas::ZERO, empty)
+            .is_err());
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm_shared::actor::builtin::Manifest;
+use fvm_shared::address::{Address, Payload};
+use fvm_shared::blockstore::Blockstore;
+use fvm_shared::constants::{
+    BURNT_FUNDS_ACTOR_ADDR, CHAOS_ACTOR_ADDR, CRON_ACTOR_ADDR, FIRST_NON_SINGLETON_ADDR,
+    INIT_ACTOR_ADDR, REWARD_ACTOR_ADDR, STORAGE_MARKET_ACTOR_ADDR, STORAGE_POWER_ACTOR_ADDR,
+    SYSTEM_ACTOR_ADDR, VERIFIED_REGISTRY_ACTOR_ADDR,
+};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::ActorID;
+use num_traits::Zero;
+
+use crate::state_tree::{ActorState, StateTree};
+
+/// The addresses reserved to the builtin singleton actors.
+const BUILTIN_SINGLETON_ADDRS: &[Address] = &[
+    SYSTEM_ACTOR_ADDR,
+    INIT_ACTOR_ADDR,
+    REWARD_ACTOR_ADDR,
+    CRON_ACTOR_ADDR,
+    STORAGE_POWER_ACTOR_ADDR,
+    STORAGE_MARKET_ACTOR_ADDR,
+    VERIFIED_REGISTRY_ACTOR_ADDR,
+    CHAOS_ACTOR_ADDR,
+    BURNT_FUNDS_ACTOR_ADDR,
+];
+
+/// The blockstore the initial state of a custom singleton actor is written to.
+pub struct StateStore<'a>(&'a dyn Blockstore);
+
+impl Blockstore for StateStore<'_> {
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        self.0.get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.0.put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        self.0.has(k)
+    }
+}
+
+/// Writes the initial state of a custom singleton actor to the given store, returning its root.
+pub type StateProducer = Arc<dyn Fn(&StateStore) -> anyhow::Result<Cid> + Send + Sync>;
+
+#[derive(Clone)]
+struct Singleton {
+    code: Cid,
+    state: StateProducer,
+}
+
+/// Custom singleton actors (e.g., a faucet or a governance actor on a devnet), registered in the
+/// machine's [`Config`](crate::Config).
+///
+/// Like the builtin singletons, they live at reserved ID addresses (below
+/// [`FIRST_NON_SINGLETON_ADDR`]) and can't be instantiated again through the init actor. Machines
+/// never create them on their own: whoever builds the genesis state of a devnet creates them
+/// explicitly, with [`SingletonRegistry::install`] (or
+/// [`DefaultMachine::install_singletons`](super::DefaultMachine::install_singletons)). Their code
+/// must be in the blockstore.
+#[derive(Clone, Default)]
+pub struct SingletonRegistry {
+    singletons: Arc<BTreeMap<ActorID, Singleton>>,
+}
+
+impl fmt::Debug for SingletonRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.singletons.iter().map(|(id, s)| (id, s.code)))
+            .finish()
+    }
+}
+
+impl SingletonRegistry {
+    /// Registers a singleton actor running the given code at the given address, which must be a
+    /// reserved ID address not used by a builtin singleton. `state` produces its initial state.
+    pub fn with_singleton<F>(mut self, addr: Address, code: Cid, state: F) -> anyhow::Result<Self>
+    where
+        F: Fn(&StateStore) -> anyhow::Result<Cid> + Send + Sync + 'static,
+    {
+        let id = match addr.payload() {
+            Payload::ID(id) => *id,
+            _ => return Err(anyhow!("singleton address must be an ID address: {}", addr)),
+        };
+        if id >= FIRST_NON_SINGLETON_ADDR {
+            return Err(anyhow!(
+                "singleton address must be below {}: {}",
+                FIRST_NON_SINGLETON_ADDR,
+                addr
+            ));
+        }
+        if BUILTIN_SINGLETON_ADDRS.contains(&addr) {
+            return Err(anyhow!("address reserved to a builtin actor: {}", addr));
+        }
+        if self.singletons.contains_key(&id) {
+            return Err(anyhow!("singleton already registered at {}", addr));
+        }
+        let state: StateProducer = Arc::new(state);
+        Arc::make_mut(&mut self.singletons).insert(id, Singleton { code, state });
+        Ok(self)
+    }
+
+    /// Returns true if no custom singletons have been registered.
+    pub fn is_empty(&self) -> bool {
+        self.singletons.is_empty()
+    }
+
+    /// Returns the code CIDs of the custom singletons.
+    pub fn codes(&self) -> impl Iterator<Item = &Cid> + '_ {
+        self.singletons.values().map(|s| &s.code)
+    }
+
+    /// Returns true if the code belongs to a custom singleton.
+    pub fn is_singleton_code(&self, code: &Cid) -> bool {
+        self.codes().any(|c| c == code)
+    }
+
+    /// Checks that no singleton shares its code with a builtin actor.
+    pub(crate) fn validate(&self, builtin_actors: &Manifest) -> anyhow::Result<()> {
+        for (&id, singleton) in self.singletons.iter() {
+            if let Some(typ) = builtin_actors.get_by_left(&singleton.code) {
+                return Err(anyhow!(
+                    "singleton {} uses the code of the builtin {:?} actor",
+                    id,
+                    typ
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the singletons missing from the state tree, e.g. when building the genesis state of
+    /// a devnet. Singletons already in the state tree are left alone. Fails if a singleton shares
+    /// its code with a builtin actor.
+    ///
+    /// This changes the state: it must only be used on state that isn't (yet) shared with a chain.
+    pub fn install<B>(
+        &self,
+        state_tree: &mut StateTree<B>,
+        builtin_actors: &Manifest,
+    ) -> anyhow::Result<()>
+    where
+        B: Blockstore + 'static,
+    {
+        self.validate(builtin_actors)?;
+        for (&id, singleton) in self.singletons.iter() {
+            if state_tree.get_actor_id(id)?.is_some() {
+                continue;
+            }
+            let state = (singleton.state)(&StateStore(state_tree.store()))
+                .with_context(|| format!("failed to produce the state of singleton {}", id))?;
+            state_tree.set_actor_id(
+                id,
+                ActorState::new(singleton.code, state, TokenAmount::zero(), 0),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Code;
+    use fvm_shared::blockstore::{CborStore, MemoryBlockstore};
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::testing::cbor_blake2b_cid;
+
+    const FAUCET: Address = Address::new_id(10);
+
+    fn registry() -> SingletonRegistry {
+        SingletonRegistry::default()
+            .with_singleton(FAUCET, cbor_blake2b_cid(b"faucet"), |store| {
+                store.put_cbor(&(1u64, "faucet"), Code::Blake2b256)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn rejects_reserved_addresses() {
+        let code = cbor_blake2b_cid(b"code");
+        for addr in [
+            INIT_ACTOR_ADDR,
+            BURNT_FUNDS_ACTOR_ADDR,
+            Address::new_id(FIRST_NON_SINGLETON_ADDR),
+            Address::new_secp256k1(&[0; 65]).unwrap(),
+        ] {
+            assert!(SingletonRegistry::default()
+                .with_singleton(addr, code, |_| Ok(code))
+                .is_err());
+        }
+        assert!(registry()
+            .with_singleton(FAUCET, code, |_| Ok(code))
+            .is_err());
+    }
+
+    #[test]
+    fn installs_missing_singletons() {
+        let registry = registry();
+        let mut state_tree =
+            StateTree::new(MemoryBlockstore::default(), StateTreeVersion::V4).unwrap();
+        registry.install(&mut state_tree, &Manifest::new()).unwrap();
+
+        let actor = state_tree.get_actor(&FAUCET).unwrap().unwrap();
+        assert_eq!(actor.code, cbor_blake2b_cid(b"faucet"));
+        assert_eq!(
+            state_tree
+                .store()
+                .get_cbor::<(u64, String)>(&actor.state)
+                .unwrap(),
+            Some((1, "faucet".into()))
+        );
+
+        // Existing singletons are left alone.
+        let mut updated = actor;
+        updated.sequence = 1;
+        state_tree.set_actor_id(10, updated.clone()).unwrap();
+        registry.install(&mut state_tree, &Manifest::new()).unwrap();
+        assert_eq!(state_tree.get_actor_id(10).unwrap(), Some(updated));
+    }
+
+    #[test]
+    fn rejects_builtin_code() {
+        let registry = registry();
+        let mut manifest = Manifest::new();
+        manifest.insert(
+            cbor_blake2b_cid(b"faucet"),
+            fvm_shared::actor::builtin::Type::Account,
+        );
+        assert!(registry.validate(&manifest).is_err());
+        let mut state_tree =
+            StateTree::new(MemoryBlockstore::default(), StateTreeVersion::V4).unwrap();
+        assert!(registry.install(&mut state_tree, &manifest).is_err());
+        assert!(state_tree.get_actor(&FAUCET).unwrap().is_none());
+    }
+}
@@ -0,0 +1,52 @@
+use fvm_shared::sector::{
+    AggregateSealVerifyProofAndInfos, ReplicaUpdateInfo, SealVerifyInfo, WindowPoStVerifyInfo,
+};
+
+use crate::kernel::{proofs, Result};
+
+/// Verifies the seal, PoSt, aggregate seal and replica update proofs actors submit, for the
+/// `crypto` syscalls.
+///
+/// The defaults verify proofs in process, with `filecoin-proofs` ([`InProcessVerifier`]). Nodes
+/// that already verify proofs (and cache the results) can install a verifier delegating to them
+/// instead, in the [`Config`](crate::Config::proofs_verifier) of their machines; their externs
+/// are a natural place to implement it.
+///
+/// Whichever verifier is installed must accept exactly the proofs `filecoin-proofs` accepts.
+/// Invalid proofs should be reported as `Ok(false)`; malformed inputs as illegal arguments.
+/// Proofs aren't verified at all when [`Config::verify_proofs`](crate::Config::verify_proofs) is
+/// disabled.
+pub trait ProofsVerifier: Send + Sync {
+    /// Verifies a seal proof.
+    fn verify_seal(&self, vi: &SealVerifyInfo) -> Result<bool> {
+        proofs::verify_seal(vi)
+    }
+
+    /// Verifies a window PoSt proof, on up to `threads` threads (zero for one per CPU).
+    fn verify_post(&self, threads: usize, verify_info: &WindowPoStVerifyInfo) -> Result<bool> {
+        proofs::verify_post(threads, verify_info)
+    }
+
+    /// Verifies a batch of seal proofs, on up to `threads` threads (zero for one per CPU).
+    /// Returns whether each proof is valid, in order.
+    fn batch_verify_seals(&self, threads: usize, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
+        proofs::batch_verify_seals(threads, vis)
+    }
+
+    /// Verifies an aggregate seal proof.
+    fn verify_aggregate_seals(&self, aggregate: &AggregateSealVerifyProofAndInfos) -> Result<bool> {
+        proofs::verify_aggregate_seals(aggregate)
+    }
+
+    /// Verifies a replica update proof.
+    fn verify_replica_update(&self, replica: &ReplicaUpdateInfo) -> Result<bool> {
+        proofs::verify_replica_update(replica)
+    }
+}
+
+/// Verifies proofs in process, with `filecoin-proofs`. Without the `proofs` feature, every
+/// verification fails with a fatal error.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InProcessVerifier;
+
+impl ProofsVerifier for InProcessVerifier {}
@@ -9,6 +9,7 @@ use fvm_shared::address::Address;
 use fvm_shared::bigint::bigint_ser;
 use fvm_shared::blockstore::{Blockstore, CborStore};
 use fvm_shared::clock::ChainEpoch;
+use fvm_shared::constants::STORAGE_MARKET_ACTOR_ADDR;
 use fvm_shared::deal::DealID;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::tuple::*;
@@ -17,8 +18,6 @@ use fvm_shared::encoding::Cbor;
 use crate::kernel::{ClassifyResult, Result};
 use crate::state_tree::{ActorState, StateTree};
 
-pub const MARKET_ACTOR_ADDR: Address = Address::new_id(5);
-
 /// Market power actor state
 
 impl Cbor for State {}
@@ -72,7 +71,7 @@ impl State {
         B: Blockstore,
     {
         let market_act = state_tree
-            .get_actor(&MARKET_ACTOR_ADDR)?
+            .get_actor(&STORAGE_MARKET_ACTOR_ADDR)?
             .context("Market actor address could not be resolved")
             .or_fatal()?;
 
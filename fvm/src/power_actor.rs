@@ -5,10 +5,10 @@
 
 use anyhow::Context;
 use cid::Cid;
-use fvm_shared::address::Address;
 use fvm_shared::bigint::bigint_ser;
 use fvm_shared::blockstore::{Blockstore, CborStore};
 use fvm_shared::clock::ChainEpoch;
+use fvm_shared::constants::STORAGE_POWER_ACTOR_ADDR;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::tuple::*;
 use fvm_shared::encoding::Cbor;
@@ -18,8 +18,6 @@ use fvm_shared::smooth::FilterEstimate;
 use crate::kernel::{ClassifyResult, Result};
 use crate::state_tree::{ActorState, StateTree};
 
-pub const POWER_ACTOR_ADDR: Address = Address::new_id(4);
-
 /// Storage power actor state
 #[derive(Default, Serialize_tuple, Deserialize_tuple)]
 pub struct State {
@@ -68,7 +66,7 @@ impl State {
         B: Blockstore,
     {
         let power_act = state_tree
-            .get_actor(&POWER_ACTOR_ADDR)?
+            .get_actor(&STORAGE_POWER_ACTOR_ADDR)?
             .context("Power actor address could not be resolved")
             .or_fatal()?;
 
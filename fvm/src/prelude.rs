@@ -0,0 +1,23 @@
+//! The types and traits needed to embed the FVM in a node: constructing a [`Machine`], applying
+//! messages to it through an [`Executor`], and plugging in the node's [`Externs`].
+//!
+//! ```ignore
+//! use fvm::prelude::*;
+//! ```
+//!
+//! Everything exported here is stable: the modules it comes from may be reorganized, but these
+//! paths will keep working.
+
+pub use crate::call_manager::{Backtrace, DefaultCallManager};
+pub use crate::executor::{
+    ApplyFailure, ApplyKind, ApplyRet, BatchExecutor, DefaultExecutor, Executor,
+    SpeculativeExecutor, StateOverrides,
+};
+pub use crate::externs::{Chain, CircSupply, CodePolicy, Consensus, Externs, Rand};
+pub use crate::gas::{Gas, GasMeter, PriceList, PriceListRegistry, StandardGasMeter};
+pub use crate::kernel::{ExecutionError, Kernel};
+pub use crate::machine::{
+    DefaultMachine, Engine, Machine, MachineContext, ReadOnlyMachine, SingletonRegistry,
+};
+pub use crate::trace::{ExecutionEvent, ExecutionTrace};
+pub use crate::{Config, DefaultKernel, Profile};
@@ -0,0 +1,429 @@
+//! A blockstore kept by another process (e.g., the node's chain store), accessed over a unix
+//! socket. This lets the FVM run out-of-process from the chain store.
+//!
+//! ## Protocol
+//!
+//! Requests and responses are frames: a big-endian `u32` length followed by that many bytes. The
+//! client sends a request frame and waits for the response frame before sending the next one.
+//!
+//! Within frames, integers are big-endian `u32`s, and CIDs and blocks are written as their length
+//! followed by their bytes. A request is an op byte followed by a count of entries:
+//!
+//! | Op        | Request entries | Response entries                                    |
+//! |-----------|-----------------|-----------------------------------------------------|
+//! | `1` (get) | CIDs            | for each CID, `0` if missing, or `1` and its block  |
+//! | `2` (put) | CID, block      | none                                                |
+//! | `3` (has) | CIDs            | for each CID, `0` if missing, `1` otherwise         |
+//!
+//! A response starts with a status byte: `0` if the request succeeded (followed by the response
+//! entries), or `1` followed by a utf8 error message.
+//!
+//! [`serve`] implements the server side of the protocol over any [`Blockstore`].
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{anyhow, Context as _, Result};
+use cid::Cid;
+use fvm_shared::blockstore::Blockstore;
+use lru::LruCache;
+
+const OP_GET: u8 = 1;
+const OP_PUT: u8 = 2;
+const OP_HAS: u8 = 3;
+
+const STATUS_OK: u8 = 0;
+const STATUS_ERR: u8 = 1;
+
+/// The maximum length of a frame. Longer frames are rejected as corrupt.
+pub const MAX_FRAME_LEN: u32 = 256 << 20;
+
+/// Tunables of a [`RemoteBlockstore`].
+#[derive(Clone, Debug)]
+pub struct RemoteBlockstoreConfig {
+    /// The number of blocks kept in the read cache.
+    pub cache_size: usize,
+    /// Written blocks are buffered and sent in a single request once this many are pending.
+    pub max_batch_len: usize,
+    /// Written blocks are buffered and sent in a single request once they add up to this many
+    /// bytes.
+    pub max_batch_bytes: usize,
+}
+
+impl Default for RemoteBlockstoreConfig {
+    fn default() -> Self {
+        Self {
+            cache_size: 4096,
+            max_batch_len: 256,
+            max_batch_bytes: 4 << 20,
+        }
+    }
+}
+
+/// A [`Blockstore`] proxying gets and puts to another process over a unix socket (see the
+/// [module documentation](self) for the protocol).
+///
+/// Blocks read or written are kept in an LRU cache. Writes are buffered and sent in batches: call
+/// [`flush`](RemoteBlockstore::flush) to make sure they've reached the remote store (this also
+/// happens, on a best-effort basis, when the blockstore is dropped).
+pub struct RemoteBlockstore {
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    config: RemoteBlockstoreConfig,
+    reader: BufReader<UnixStream>,
+    writer: BufWriter<UnixStream>,
+    cache: LruCache<Cid, Vec<u8>>,
+    pending: HashMap<Cid, Vec<u8>>,
+    pending_bytes: usize,
+}
+
+impl RemoteBlockstore {
+    /// Connects to the blockstore served at the given socket path.
+    pub fn connect(path: impl AsRef<Path>, config: RemoteBlockstoreConfig) -> Result<Self> {
+        let path = path.as_ref();
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("failed to connect to blockstore at {}", path.display()))?;
+        Self::new(stream, config)
+    }
+
+    /// Uses a blockstore served over an already connected socket.
+    pub fn new(stream: UnixStream, config: RemoteBlockstoreConfig) -> Result<Self> {
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                cache: LruCache::new(config.cache_size),
+                config,
+                reader,
+                writer: BufWriter::new(stream),
+                pending: HashMap::new(),
+                pending_bytes: 0,
+            }),
+        })
+    }
+
+    /// Sends the buffered writes to the remote store.
+    pub fn flush(&self) -> Result<()> {
+        self.lock()?.flush()
+    }
+
+    /// Gets several blocks at once, fetching all those that aren't cached in a single request.
+    pub fn get_many(&self, keys: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        self.lock()?.get_many(keys)
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, Inner>> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow!("remote blockstore lock poisoned"))
+    }
+}
+
+impl Drop for RemoteBlockstore {
+    fn drop(&mut self) {
+        if let Ok(inner) = self.inner.get_mut() {
+            if let Err(e) = inner.flush() {
+                log::error!("failed to flush remote blockstore: {:#}", e);
+            }
+        }
+    }
+}
+
+impl Blockstore for RemoteBlockstore {
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        let mut blocks = self.get_many(std::slice::from_ref(k))?;
+        Ok(blocks.pop().flatten())
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.lock()?.put(*k, block.to_vec())
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.lock()?.has(k)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        let mut inner = self.lock()?;
+        for (k, block) in blocks {
+            inner.put(k, block.as_ref().to_vec())?;
+        }
+        Ok(())
+    }
+}
+
+impl Inner {
+    fn get_many(&mut self, keys: &[Cid]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut blocks = Vec::with_capacity(keys.len());
+        let mut missing = Vec::new();
+        for k in keys {
+            let block = match self.pending.get(k) {
+                Some(block) => Some(block.clone()),
+                None => self.cache.get(k).cloned(),
+            };
+            if block.is_none() {
+                missing.push(*k);
+            }
+            blocks.push(block);
+        }
+        if missing.is_empty() {
+            return Ok(blocks);
+        }
+
+        let mut req = request(OP_GET, missing.len());
+        for k in &missing {
+            write_bytes(&mut req, &k.to_bytes());
+        }
+        let resp = self.call(&req)?;
+        let mut resp = &resp[..];
+        let mut fetched = HashMap::with_capacity(missing.len());
+        for k in missing {
+            if read_u8(&mut resp)? != 0 {
+                let block = read_bytes(&mut resp)?.to_vec();
+                self.cache.put(k, block.clone());
+                fetched.insert(k, block);
+            }
+        }
+        for (k, block) in keys.iter().zip(&mut blocks) {
+            if block.is_none() {
+                *block = fetched.get(k).cloned();
+            }
+        }
+        Ok(blocks)
+    }
+
+    fn has(&mut self, k: &Cid) -> Result<bool> {
+        if self.pending.contains_key(k) || self.cache.contains(k) {
+            return Ok(true);
+        }
+        let mut req = request(OP_HAS, 1);
+        write_bytes(&mut req, &k.to_bytes());
+        let resp = self.call(&req)?;
+        Ok(read_u8(&mut &resp[..])? != 0)
+    }
+
+    fn put(&mut self, k: Cid, block: Vec<u8>) -> Result<()> {
+        self.pending_bytes += block.len();
+        if let Some(old) = self.pending.insert(k, block.clone()) {
+            self.pending_bytes -= old.len();
+        }
+        self.cache.put(k, block);
+        if self.pending.len() >= self.config.max_batch_len
+            || self.pending_bytes >= self.config.max_batch_bytes
+        {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let mut req = request(OP_PUT, self.pending.len());
+        for (k, block) in &self.pending {
+            write_bytes(&mut req, &k.to_bytes());
+            write_bytes(&mut req, block);
+        }
+        self.call(&req)?;
+        self.pending.clear();
+        self.pending_bytes = 0;
+        Ok(())
+    }
+
+    /// Sends a request and returns the entries of the response.
+    fn call(&mut self, req: &[u8]) -> Result<Vec<u8>> {
+        write_frame(&mut self.writer, req)?;
+        self.writer.flush()?;
+        let mut resp = read_frame(&mut self.reader)?
+            .ok_or_else(|| anyhow!("remote blockstore closed the connection"))?;
+        match resp.first() {
+            Some(&STATUS_OK) => {
+                resp.remove(0);
+                Ok(resp)
+            }
+            Some(&STATUS_ERR) => Err(anyhow!(
+                "remote blockstore error: {}",
+                String::from_utf8_lossy(&resp[1..])
+            )),
+            _ => Err(anyhow!("invalid remote blockstore response")),
+        }
+    }
+}
+
+/// Serves the given blockstore over a connected socket until the client disconnects. Errors
+/// returned by the blockstore are sent to the client; this only fails if the connection breaks or
+/// the client sends a malformed request.
+pub fn serve<BS: Blockstore>(bs: &BS, stream: UnixStream) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    while let Some(req) = read_frame(&mut reader)? {
+        let resp = match handle(bs, &req) {
+            Ok(resp) => resp,
+            Err(e) => {
+                let mut resp = vec![STATUS_ERR];
+                resp.extend_from_slice(format!("{:#}", e).as_bytes());
+                resp
+            }
+        };
+        write_frame(&mut writer, &resp)?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+fn handle<BS: Blockstore>(bs: &BS, mut req: &[u8]) -> Result<Vec<u8>> {
+    let op = read_u8(&mut req)?;
+    let count = read_u32(&mut req)?;
+    let mut resp = vec![STATUS_OK];
+    for _ in 0..count {
+        let k = Cid::try_from(read_bytes(&mut req)?)?;
+        match op {
+            OP_GET => match bs.get(&k)? {
+                Some(block) => {
+                    resp.push(1);
+                    write_bytes(&mut resp, &block);
+                }
+                None => resp.push(0),
+            },
+            OP_PUT => bs.put_keyed(&k, read_bytes(&mut req)?)?,
+            OP_HAS => resp.push(bs.has(&k)? as u8),
+            _ => return Err(anyhow!("unknown op {}", op)),
+        }
+    }
+    Ok(resp)
+}
+
+fn request(op: u8, count: usize) -> Vec<u8> {
+    let mut req = vec![op];
+    req.extend_from_slice(&(count as u32).to_be_bytes());
+    req
+}
+
+fn write_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn read_u8(buf: &mut &[u8]) -> Result<u8> {
+    let (&b, rest) = buf
+        .split_first()
+        .ok_or_else(|| anyhow!("truncated frame"))?;
+    *buf = rest;
+    Ok(b)
+}
+
+fn read_u32(buf: &mut &[u8]) -> Result<u32> {
+    if buf.len() < 4 {
+        return Err(anyhow!("truncated frame"));
+    }
+    let (n, rest) = buf.split_at(4);
+    *buf = rest;
+    Ok(u32::from_be_bytes(n.try_into().unwrap()))
+}
+
+fn read_bytes<'a>(buf: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let len = read_u32(buf)? as usize;
+    if buf.len() < len {
+        return Err(anyhow!("truncated frame"));
+    }
+    let (bytes, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(bytes)
+}
+
+fn write_frame(w: &mut impl Write, frame: &[u8]) -> Result<()> {
+    if frame.len() > MAX_FRAME_LEN as usize {
+        return Err(anyhow!("frame too large: {} bytes", frame.len()));
+    }
+    w.write_all(&(frame.len() as u32).to_be_bytes())?;
+    w.write_all(frame)?;
+    Ok(())
+}
+
+/// Reads a frame, returning `None` if the stream ended before it started.
+fn read_frame(r: &mut impl Read) -> Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(anyhow!("frame too large: {} bytes", len));
+    }
+    let mut frame = vec![0; len as usize];
+    r.read_exact(&mut frame)?;
+    Ok(Some(frame))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use fvm_shared::blockstore::MemoryBlockstore;
+    use fvm_shared::IPLD_RAW;
+    use multihash::{Code, MultihashDigest};
+
+    use super::*;
+
+    fn block(i: u8) -> (Cid, Vec<u8>) {
+        let data = vec![i; i as usize + 1];
+        (Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(&data)), data)
+    }
+
+    #[test]
+    fn remote_blockstore() {
+        let (client, server) = UnixStream::pair().unwrap();
+        let (k0, b0) = block(0);
+        let server = thread::spawn(move || {
+            let bs = MemoryBlockstore::new();
+            bs.put_keyed(&k0, &b0).unwrap();
+            serve(&bs, server).unwrap();
+            bs
+        });
+
+        let config = RemoteBlockstoreConfig {
+            cache_size: 2,
+            max_batch_len: 3,
+            ..Default::default()
+        };
+        let bs = RemoteBlockstore::new(client, config).unwrap();
+        let (k0, b0) = block(0);
+        assert_eq!(bs.get(&k0).unwrap(), Some(b0.clone()));
+        assert!(bs.has(&k0).unwrap());
+
+        // Buffered writes are visible before being flushed.
+        let blocks: Vec<_> = (1..=4).map(block).collect();
+        for (k, b) in &blocks {
+            bs.put_keyed(k, b).unwrap();
+        }
+        for (k, b) in &blocks {
+            assert_eq!(bs.get(k).unwrap().as_ref(), Some(b));
+        }
+
+        let (missing, _) = block(5);
+        assert!(!bs.has(&missing).unwrap());
+        assert_eq!(
+            bs.get_many(&[k0, missing, blocks[3].0]).unwrap(),
+            vec![Some(b0), None, Some(blocks[3].1.clone())]
+        );
+
+        // The last block is only sent when the blockstore is dropped.
+        drop(bs);
+        let remote = server.join().unwrap();
+        for (k, b) in &blocks {
+            assert_eq!(remote.get(k).unwrap().as_ref(), Some(b));
+        }
+    }
+}
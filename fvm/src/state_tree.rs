@@ -1,8 +1,8 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use std::cell::RefCell;
-use std::collections::HashMap;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
 
 use anyhow::{anyhow, Context as _};
 use cid::{multihash, Cid};
@@ -10,6 +10,8 @@ use fvm_ipld_hamt::Hamt;
 use fvm_shared::address::{Address, Payload};
 use fvm_shared::bigint::bigint_ser;
 use fvm_shared::blockstore::{Blockstore, CborStore};
+use fvm_shared::collections::FixedHashMap;
+use fvm_shared::constants::INIT_ACTOR_ADDR;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::tuple::*;
 use fvm_shared::state::{StateInfo0, StateRoot, StateTreeVersion};
@@ -22,13 +24,88 @@ use crate::syscall_error;
 /// State tree implementation using hamt. This structure is not threadsafe and should only be used
 /// in sync contexts.
 pub struct StateTree<S> {
-    hamt: Hamt<S, ActorState>,
+    hamt: Hamt<CountingStore<S>, ActorState>,
 
     version: StateTreeVersion,
     info: Option<Cid>,
 
     /// State cache
     snaps: StateSnapshots,
+
+    /// Cache statistics, except for node loads, which the store counts.
+    stats: Cell<StateTreeStats>,
+}
+
+/// Counters of the work done by a [`StateTree`] since it was constructed. Use
+/// [`StateTreeStats::since`] to get the work done by a single operation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StateTreeStats {
+    /// Nodes loaded from the store by the actors HAMT and the init actor's address map.
+    pub hamt_node_loads: u64,
+    /// Actor lookups served by the cache.
+    pub actor_cache_hits: u64,
+    /// Actor lookups that went to the actors HAMT.
+    pub actor_cache_misses: u64,
+    /// Address resolutions served by the cache (ID addresses aren't counted).
+    pub address_cache_hits: u64,
+    /// Address resolutions that went to the init actor's address map.
+    pub address_cache_misses: u64,
+    /// Number of times the state tree was flushed.
+    pub flushes: u64,
+}
+
+impl StateTreeStats {
+    /// Returns the work done since the `earlier` stats were taken.
+    pub fn since(&self, earlier: &StateTreeStats) -> StateTreeStats {
+        StateTreeStats {
+            hamt_node_loads: self.hamt_node_loads - earlier.hamt_node_loads,
+            actor_cache_hits: self.actor_cache_hits - earlier.actor_cache_hits,
+            actor_cache_misses: self.actor_cache_misses - earlier.actor_cache_misses,
+            address_cache_hits: self.address_cache_hits - earlier.address_cache_hits,
+            address_cache_misses: self.address_cache_misses - earlier.address_cache_misses,
+            flushes: self.flushes - earlier.flushes,
+        }
+    }
+}
+
+/// A blockstore counting the blocks loaded through it. Its errors name the block and operation
+/// that failed, as the HAMT doesn't.
+struct CountingStore<S> {
+    inner: S,
+    loads: Cell<u64>,
+}
+
+impl<S> CountingStore<S> {
+    fn new(inner: S) -> Self {
+        CountingStore {
+            inner,
+            loads: Cell::new(0),
+        }
+    }
+}
+
+impl<S> Blockstore for CountingStore<S>
+where
+    S: Blockstore,
+{
+    fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+        self.loads.set(self.loads.get() + 1);
+        self.inner
+            .get(k)
+            .with_context(|| format!("failed to load state tree node {}", k))
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+        self.inner
+            .put_keyed(k, block)
+            .with_context(|| format!("failed to store state tree node {}", k))
+    }
+
+    fn has(&self, k: &Cid) -> anyhow::Result<bool> {
+        self.inner
+            .has(k)
+            .with_context(|| format!("failed to look up state tree node {}", k))
+    }
 }
 
 /// Collection of state snapshots
@@ -39,8 +116,9 @@ struct StateSnapshots {
 /// State snap shot layer
 #[derive(Debug, Default)]
 struct StateSnapLayer {
-    actors: RefCell<HashMap<ActorID, Option<ActorState>>>,
-    resolve_cache: RefCell<HashMap<Address, ActorID>>,
+    /// Ordered, so that the state tree is flushed in the same order on every node.
+    actors: RefCell<BTreeMap<ActorID, Option<ActorState>>>,
+    resolve_cache: RefCell<FixedHashMap<Address, ActorID>>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -88,12 +166,9 @@ impl StateSnapshots {
             .or_fatal()?
             .actors
             .borrow_mut()
-            .extend(
-                self.layers[&self.layers.len() - 1]
-                    .actors
-                    .borrow_mut()
-                    .drain(),
-            );
+            .extend(std::mem::take(
+                &mut *self.layers[&self.layers.len() - 1].actors.borrow_mut(),
+            ));
 
         self.layers
             .get(&self.layers.len() - 2)
@@ -216,64 +291,73 @@ where
         };
 
         // Both V3 and V4 use bitwidt=5.
-        let hamt = Hamt::new_with_bit_width(store, HAMT_BIT_WIDTH);
+        let hamt = Hamt::new_with_bit_width(CountingStore::new(store), HAMT_BIT_WIDTH);
         Ok(Self {
             hamt,
             version,
             info,
             snaps: StateSnapshots::new(),
+            stats: Cell::default(),
         })
     }
 
     /// Constructor for a hamt state tree given an IPLD store
     pub fn new_from_root(store: S, c: &Cid) -> Result<Self> {
-        // Try to load state root, if versioned
-        let (version, info, actors) = match store.get_cbor(c) {
-            Ok(Some(StateRoot {
-                version,
-                info,
-                actors,
-            })) => (version, Some(info), actors),
-            Ok(None) => {
-                return Err(ExecutionError::Fatal(anyhow!(
-                    "failed to find state tree {}",
-                    c
-                )))
-            }
-            Err(e) => {
-                return Err(ExecutionError::Fatal(anyhow!(
-                    "failed to load state tree {}: {}",
-                    c,
-                    e
-                )))
-            }
-        };
+        let (version, info, actors) = load_state_root(&store, c)?;
+        let hamt = Hamt::load_with_bit_width(&actors, CountingStore::new(store), HAMT_BIT_WIDTH)
+            .context("failed to load state tree")
+            .or_fatal()?;
 
-        match version {
-            StateTreeVersion::V0 | StateTreeVersion::V1 | StateTreeVersion::V2 => {
-                return Err(ExecutionError::Fatal(anyhow!(
-                    "unsupported state tree version: {:?}",
-                    version
-                )))
-            }
-            StateTreeVersion::V3 | StateTreeVersion::V4 => {
-                let hamt = Hamt::load_with_bit_width(&actors, store, HAMT_BIT_WIDTH)
-                    .context("failed to load state tree")
-                    .or_fatal()?;
+        Ok(Self {
+            hamt,
+            version,
+            info: Some(info),
+            snaps: StateSnapshots::new(),
+            stats: Cell::default(),
+        })
+    }
 
-                Ok(Self {
-                    hamt,
-                    version,
-                    info,
-                    snaps: StateSnapshots::new(),
-                })
-            }
+    /// Points the state tree at a different root, discarding all cached actors and resolved
+    /// addresses. Must not be called with snapshots on the stack.
+    ///
+    /// The store is kept as is, so blocks written (but not yet flushed) by earlier executions remain
+    /// available.
+    pub fn reset_to_root(&mut self, c: &Cid) -> Result<()> {
+        if self.snaps.layers.len() != 1 {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "tried to reset state tree with snapshots on the stack: {:?}",
+                self.snaps.layers.len()
+            )));
         }
+
+        let (version, info, actors) = load_state_root(self.store(), c)?;
+        self.hamt
+            .set_root(&actors)
+            .context("failed to load state tree")
+            .or_fatal()?;
+        self.version = version;
+        self.info = Some(info);
+        self.snaps = StateSnapshots::new();
+        Ok(())
     }
 
     /// Retrieve store reference to modify db.
     pub fn store(&self) -> &S {
-        self.hamt.store()
+        &self.hamt.store().inner
+    }
+
+    /// Returns the work done by the state tree so far.
+    pub fn stats(&self) -> StateTreeStats {
+        StateTreeStats {
+            hamt_node_loads: self.hamt.store().loads.get(),
+            ..self.stats.get()
+        }
+    }
+
+    fn count(&self, f: impl FnOnce(&mut StateTreeStats)) {
+        let mut stats = self.stats.get();
+        f(&mut stats);
+        self.stats.set(stats);
     }
 
     /// Get actor state from an address. Will be resolved to ID address.
@@ -288,7 +372,12 @@ where
     /// Get actor state from an actor ID.
     pub fn get_actor_id(&self, id: ActorID) -> Result<Option<ActorState>> {
         // Check cache for actor state
-        Ok(match self.snaps.get_actor(id) {
+        let cached = self.snaps.get_actor(id);
+        match cached {
+            StateCacheResult::Uncached => self.count(|s| s.actor_cache_misses += 1),
+            _ => self.count(|s| s.actor_cache_hits += 1),
+        }
+        Ok(match cached {
             StateCacheResult::Exists(state) => Some(state),
             StateCacheResult::Deleted => None,
             StateCacheResult::Uncached => {
@@ -328,18 +417,21 @@ where
 
     /// Get an ID address from any Address
     pub fn lookup_id(&self, addr: &Address) -> Result<Option<ActorID>> {
-        if let &Payload::ID(id) = addr.payload() {
-            return Ok(Some(id));
-        }
+        resolve_chain(addr, |addr| self.resolve_step(addr))
+    }
 
+    /// Resolves a non-ID address one step, through the cache or the init actor's address map.
+    fn resolve_step(&self, addr: &Address) -> Result<Option<Address>> {
         if let Some(res_address) = self.snaps.resolve_address(addr) {
-            return Ok(Some(res_address));
+            self.count(|s| s.address_cache_hits += 1);
+            return Ok(Some(Address::new_id(res_address)));
         }
+        self.count(|s| s.address_cache_misses += 1);
 
         let (state, _) = InitActorState::load(self)?;
 
         let a = match state
-            .resolve_address(self.store(), addr)
+            .resolve_address(self.hamt.store(), addr)
             .context("Could not resolve address")
             .or_fatal()?
         {
@@ -349,7 +441,7 @@ where
 
         self.snaps.cache_resolve_address(*addr, a)?;
 
-        Ok(Some(a))
+        Ok(Some(Address::new_id(a)))
     }
 
     /// Delete actor for an address. Will resolve to ID address to delete.
@@ -430,7 +522,7 @@ where
             .put_cbor(&state, multihash::Code::Blake2b256)
             .or_fatal()?;
 
-        self.set_actor(&crate::init_actor::INIT_ACTOR_ADDR, actor)?;
+        self.set_actor(&INIT_ACTOR_ADDR, actor)?;
 
         Ok(new_addr)
     }
@@ -473,6 +565,7 @@ where
         }
 
         let root = self.hamt.flush().or_fatal()?;
+        self.count(|s| s.flushes += 1);
 
         match self.version {
             StateTreeVersion::V0 => Ok(root),
@@ -494,9 +587,39 @@ where
         }
     }
 
+    /// Lists the actors modified since the state tree was last flushed, across all open
+    /// transactions, ordered by actor ID. Actors that were merely loaded are not reported.
+    pub fn changes(&self) -> Result<Vec<ActorChange>> {
+        // Later layers take precedence.
+        let mut pending = BTreeMap::new();
+        for layer in self.snaps.layers.iter() {
+            pending.extend(
+                layer
+                    .actors
+                    .borrow()
+                    .iter()
+                    .map(|(&id, act)| (id, act.clone())),
+            );
+        }
+
+        let mut changes = Vec::new();
+        for (id, new) in pending {
+            let old = self
+                .hamt
+                .get(&Address::new_id(id).to_bytes())
+                .with_context(|| format!("failed to lookup actor {}", id))
+                .or_fatal()?
+                .cloned();
+            if old != new {
+                changes.push(ActorChange { id, old, new });
+            }
+        }
+        Ok(changes)
+    }
+
     /// Consumes this StateTree and returns the Blockstore it owns via the HAMT.
     pub fn consume(self) -> S {
-        self.hamt.consume()
+        self.hamt.consume().inner
     }
 
     pub fn for_each<F>(&self, mut f: F) -> anyhow::Result<()>
@@ -511,6 +634,50 @@ where
     }
 }
 
+/// An uncommitted change to an actor, as reported by [`StateTree::changes`].
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct ActorChange {
+    /// The ID of the changed actor.
+    pub id: ActorID,
+    /// The actor as of the last flush, if it existed.
+    pub old: Option<ActorState>,
+    /// The actor's current state, if it hasn't been deleted.
+    pub new: Option<ActorState>,
+}
+
+impl ActorChange {
+    /// The ID address of the changed actor.
+    pub fn address(&self) -> Address {
+        Address::new_id(self.id)
+    }
+
+    /// The actor's state root as of the last flush.
+    pub fn old_head(&self) -> Option<&Cid> {
+        self.old.as_ref().map(|a| &a.state)
+    }
+
+    /// The actor's current state root.
+    pub fn new_head(&self) -> Option<&Cid> {
+        self.new.as_ref().map(|a| &a.state)
+    }
+
+    /// The change in the actor's balance. Created and deleted actors are treated as having had
+    /// (respectively) a zero balance before and after the change.
+    pub fn balance_delta(&self) -> TokenAmount {
+        let old = self
+            .old
+            .as_ref()
+            .map(|a| a.balance.clone())
+            .unwrap_or_default();
+        let new = self
+            .new
+            .as_ref()
+            .map(|a| a.balance.clone())
+            .unwrap_or_default();
+        new - old
+    }
+}
+
 /// State of all actor implementations.
 #[derive(PartialEq, Eq, Clone, Debug, Serialize_tuple, Deserialize_tuple)]
 pub struct ActorState {
@@ -630,6 +797,77 @@ pub mod json {
     }
 }
 
+/// Loads a versioned state root, returning its version, info and actors HAMT root.
+/// The maximum number of steps resolving an address to an actor ID may take.
+const MAX_RESOLUTION_STEPS: usize = 16;
+
+/// Resolves an address to an actor ID by following the addresses `step` maps it to, until it
+/// reaches an ID address. Returns `None` if some address along the way isn't mapped.
+///
+/// A cycle, or a chain longer than [`MAX_RESOLUTION_STEPS`], can only come from corrupted state:
+/// it's reported as a fatal error instead of being followed forever.
+fn resolve_chain<F>(addr: &Address, mut step: F) -> Result<Option<ActorID>>
+where
+    F: FnMut(&Address) -> Result<Option<Address>>,
+{
+    let mut visited = Vec::new();
+    let mut current = *addr;
+    loop {
+        if let &Payload::ID(id) = current.payload() {
+            return Ok(Some(id));
+        }
+        if visited.contains(&current) {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "state corruption: resolving address {} loops back to {}",
+                addr,
+                current
+            )));
+        }
+        if visited.len() == MAX_RESOLUTION_STEPS {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "state corruption: resolving address {} takes more than {} steps",
+                addr,
+                MAX_RESOLUTION_STEPS
+            )));
+        }
+        visited.push(current);
+        current = match step(&current)? {
+            Some(next) => next,
+            None => return Ok(None),
+        };
+    }
+}
+
+fn load_state_root<S: Blockstore>(store: &S, c: &Cid) -> Result<(StateTreeVersion, Cid, Cid)> {
+    let (version, info, actors) = match store.get_cbor(c) {
+        Ok(Some(StateRoot {
+            version,
+            info,
+            actors,
+        })) => (version, info, actors),
+        Ok(None) => {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "failed to find state tree {}",
+                c
+            )))
+        }
+        Err(e) => {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "failed to load state tree {}: {}",
+                c,
+                e
+            )))
+        }
+    };
+
+    match version {
+        StateTreeVersion::V0 | StateTreeVersion::V1 | StateTreeVersion::V2 => Err(
+            ExecutionError::Fatal(anyhow!("unsupported state tree version: {:?}", version)),
+        ),
+        StateTreeVersion::V3 | StateTreeVersion::V4 => Ok((version, info, actors)),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use cid::multihash::Code::Blake2b256;
@@ -639,13 +877,15 @@ mod tests {
     use fvm_shared::address::{Address, SECP_PUB_LEN};
     use fvm_shared::bigint::BigInt;
     use fvm_shared::blockstore::{CborStore, MemoryBlockstore};
+    use fvm_shared::collections::FixedHashMap;
+    use fvm_shared::constants::INIT_ACTOR_ADDR;
     use fvm_shared::encoding::DAG_CBOR;
     use fvm_shared::state::StateTreeVersion;
     use fvm_shared::{IDENTITY_HASH, IPLD_RAW};
     use lazy_static::lazy_static;
 
+    use super::{resolve_chain, MAX_RESOLUTION_STEPS};
     use crate::init_actor;
-    use crate::init_actor::INIT_ACTOR_ADDR;
     use crate::state_tree::{ActorState, StateTree};
 
     lazy_static! {
@@ -696,6 +936,73 @@ mod tests {
         assert_eq!(tree.get_actor(&addr).unwrap(), None);
     }
 
+    #[test]
+    fn changes() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V3).unwrap();
+
+        let loaded = ActorState::new(empty_cid(), empty_cid(), BigInt::from(5), 1);
+        let deleted = ActorState::new(empty_cid(), empty_cid(), BigInt::from(7), 1);
+        tree.set_actor_id(1, loaded.clone()).unwrap();
+        tree.set_actor_id(2, deleted.clone()).unwrap();
+        tree.flush().unwrap();
+        assert!(tree.changes().unwrap().is_empty());
+
+        // Loading an actor doesn't change it.
+        assert_eq!(tree.get_actor_id(1).unwrap(), Some(loaded));
+
+        tree.begin_transaction();
+        let created = ActorState::new(empty_cid(), empty_cid(), BigInt::from(3), 0);
+        tree.set_actor_id(3, created.clone()).unwrap();
+        tree.delete_actor_id(2).unwrap();
+
+        let changes = tree.changes().unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].id, 2);
+        assert_eq!(changes[0].old, Some(deleted));
+        assert_eq!(changes[0].new, None);
+        assert_eq!(changes[0].balance_delta(), BigInt::from(-7));
+        assert_eq!(changes[1].id, 3);
+        assert_eq!(changes[1].old, None);
+        assert_eq!(changes[1].new, Some(created));
+        assert_eq!(changes[1].balance_delta(), BigInt::from(3));
+
+        // Reverting the transaction drops its changes.
+        tree.end_transaction(true).unwrap();
+        assert!(tree.changes().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reset_to_root() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V3).unwrap();
+
+        let flushed = ActorState::new(empty_cid(), empty_cid(), BigInt::from(5), 1);
+        tree.set_actor_id(1, flushed.clone()).unwrap();
+        let root = tree.flush().unwrap();
+
+        // Modify the cached balance, and add an actor that is never flushed.
+        tree.mutate_actor_id(1, |act| {
+            act.balance = BigInt::from(9);
+            Ok(())
+        })
+        .unwrap();
+        tree.set_actor_id(2, flushed.clone()).unwrap();
+
+        tree.reset_to_root(&root).unwrap();
+        assert_eq!(tree.get_actor_id(1).unwrap(), Some(flushed));
+        assert_eq!(tree.get_actor_id(2).unwrap(), None);
+
+        // Resetting in the middle of a transaction is not allowed.
+        tree.begin_transaction();
+        assert!(tree.reset_to_root(&root).is_err());
+        tree.end_transaction(true).unwrap();
+
+        // Neither is resetting to a root the store doesn't have.
+        assert!(tree.reset_to_root(&empty_cid()).is_err());
+        assert!(tree.changes().unwrap().is_empty());
+    }
+
     #[test]
     fn get_set_non_id() {
         let store = MemoryBlockstore::default();
@@ -849,6 +1156,70 @@ mod tests {
         assert_eq!(tree.get_actor(&addr).unwrap(), None);
     }
 
+    #[test]
+    fn stats_show_cached_lookups_load_nothing() {
+        let store = MemoryBlockstore::default();
+        let mut tree = StateTree::new(&store, StateTreeVersion::V4).unwrap();
+        let act_s = ActorState::new(empty_cid(), empty_cid(), Default::default(), 1);
+        // Enough actors for every bucket of the root node to overflow into a child node.
+        for id in 100..1100 {
+            tree.set_actor_id(id, act_s.clone()).unwrap();
+        }
+        let root = tree.flush().unwrap();
+        assert_eq!(tree.stats().flushes, 1);
+
+        let tree = StateTree::new_from_root(&store, &root).unwrap();
+        let before = tree.stats();
+        assert_eq!(tree.get_actor_id(500).unwrap(), Some(act_s));
+        let first = tree.stats().since(&before);
+        assert_eq!(first.actor_cache_misses, 1);
+        assert!(first.hamt_node_loads > 0);
+
+        // Missing actors aren't cached, but the HAMT keeps the nodes it loaded looking for them.
+        assert_eq!(tree.get_actor_id(5000).unwrap(), None);
+
+        // From then on, neither lookup loads anything.
+        let before = tree.stats();
+        for _ in 0..10 {
+            tree.get_actor_id(500).unwrap();
+            assert_eq!(tree.get_actor_id(5000).unwrap(), None);
+        }
+        let again = tree.stats().since(&before);
+        assert_eq!(again.actor_cache_hits, 10);
+        assert_eq!(again.actor_cache_misses, 10);
+        assert_eq!(again.hamt_node_loads, 0);
+    }
+
+    #[test]
+    fn resolution_follows_chains_and_detects_cycles() {
+        let a = Address::new_actor(b"a");
+        let b = Address::new_actor(b"b");
+        let c = Address::new_actor(b"c");
+        let resolve = |map: &FixedHashMap<Address, Address>, addr: &Address| {
+            resolve_chain(addr, |addr| Ok(map.get(addr).copied()))
+        };
+
+        let chain = [(a, b), (b, Address::new_id(5))].into_iter().collect();
+        assert_eq!(resolve(&chain, &a).unwrap(), Some(5));
+        assert_eq!(resolve(&chain, &Address::new_id(7)).unwrap(), Some(7));
+        assert_eq!(resolve(&chain, &c).unwrap(), None);
+
+        let cycle = [(a, b), (b, c), (c, a)].into_iter().collect();
+        for addr in [a, b, c] {
+            assert!(resolve(&cycle, &addr).unwrap_err().is_fatal());
+        }
+
+        // An unbounded chain: every step leads to a new address.
+        let mut steps = 0u64;
+        let err = resolve_chain(&a, |_| {
+            steps += 1;
+            Ok(Some(Address::new_actor(&steps.to_be_bytes())))
+        })
+        .unwrap_err();
+        assert!(err.is_fatal());
+        assert_eq!(steps, MAX_RESOLUTION_STEPS as u64);
+    }
+
     #[test]
     fn unsupported_versions() {
         let unsupported = vec![
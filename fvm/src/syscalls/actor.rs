@@ -1,9 +1,12 @@
 use fvm_shared::actor::builtin::Type;
+use fvm_shared::address::Address;
+use fvm_shared::encoding::{from_slice_limited, DecodeLimits};
 use fvm_shared::sys;
+use fvm_shared::sys::out::actor::RESOLVED_ADDRESS_LEN;
 use num_traits::FromPrimitive;
 
 use super::Context;
-use crate::kernel::{ClassifyResult, Result};
+use crate::kernel::{ClassifyResult, Context as _, Result};
 use crate::{syscall_error, Kernel};
 
 pub fn resolve_address(
@@ -19,6 +22,38 @@ pub fn resolve_address(
     Ok(sys::out::actor::ResolveAddress { resolved, value })
 }
 
+/// Resolves a CBOR encoded list of addresses, writing a [`RESOLVED_ADDRESS_LEN`] byte entry per
+/// address into the output buffer. Returns the number of addresses.
+pub fn resolve_addresses(
+    context: Context<'_, impl Kernel>,
+    addrs_off: u32, // Vec<Address>
+    addrs_len: u32,
+    obuf_off: u32,
+    obuf_len: u32,
+) -> Result<u32> {
+    let addrs_bytes = context.memory.try_slice(addrs_off, addrs_len)?;
+    let addrs: Vec<Address> = from_slice_limited(addrs_bytes, &DecodeLimits::default())
+        .or_illegal_argument()
+        .context("failed to decode addresses")?;
+
+    let len = addrs.len() * RESOLVED_ADDRESS_LEN;
+    if len > obuf_len as usize {
+        return Err(syscall_error!(IllegalArgument;
+            "insufficient output buffer capacity; {} (resolved addresses) > {} (buffer capacity)",
+            len, obuf_len
+        )
+        .into());
+    }
+
+    let resolved = context.kernel.resolve_addresses(&addrs)?;
+    let obuf = context.memory.try_slice_mut(obuf_off, obuf_len)?;
+    for (entry, id) in obuf.chunks_exact_mut(RESOLVED_ADDRESS_LEN).zip(resolved) {
+        entry[0] = id.is_some() as u8;
+        entry[1..].copy_from_slice(&id.unwrap_or_default().to_le_bytes());
+    }
+    Ok(addrs.len() as u32)
+}
+
 pub fn get_actor_code_cid(
     context: Context<'_, impl Kernel>,
     addr_off: u32, // Address
@@ -27,7 +62,11 @@ pub fn get_actor_code_cid(
     obuf_len: u32,
 ) -> Result<i32> {
     let addr = context.memory.read_address(addr_off, addr_len)?;
-    match context.kernel.get_actor_code_cid(&addr)? {
+    let id = match context.kernel.resolve_address(&addr)? {
+        Some(id) => id,
+        None => return Ok(-1),
+    };
+    match context.kernel.get_actor_code_cid(id)? {
         Some(typ) => {
             let obuf = context.memory.try_slice_mut(obuf_off, obuf_len)?;
             // TODO: This isn't always an illegal argument error, only when the buffer is too small.
@@ -1,13 +1,16 @@
+use std::fmt::{Debug, Write};
 use std::mem;
 
-use fvm_shared::error::ErrorNumber;
+use fvm_shared::error::{ErrorNumber, ExitCode};
 use wasmtime::{Caller, Linker, Trap, WasmTy};
 
 use super::context::Memory;
 use super::error::Abort;
-use super::{Context, InvocationData};
+use super::{charge_for_exec, refuel, Context, InvocationData};
 use crate::call_manager::backtrace;
+use crate::gas::Gas;
 use crate::kernel::{self, ExecutionError, Kernel, SyscallError};
+use crate::trace::{ExecutionEvent, SyscallTrace, MAX_SYSCALL_ARGS_LEN};
 
 // TODO: we should consider implementing a proc macro attribute for syscall functions instead of
 // this type nonsense. But this was faster and will "work" for now.
@@ -83,6 +86,12 @@ where
     }
 }
 
+/// Aborts the actor when charging for its execution fails, which is either because it ran out of
+/// gas, or because of a fatal error.
+fn exec_abort(e: ExecutionError) -> Abort {
+    Abort::from_error(ExitCode::SysErrActorPanic, e)
+}
+
 fn memory_and_data<'a, K: Kernel>(
     caller: &'a mut Caller<'_, InvocationData<K>>,
 ) -> Result<(&'a mut Memory, &'a mut InvocationData<K>), Trap> {
@@ -94,6 +103,57 @@ fn memory_and_data<'a, K: Kernel>(
     Ok((Memory::new(mem), data))
 }
 
+/// Formats syscall arguments for the syscall trace, truncating them to [`MAX_SYSCALL_ARGS_LEN`].
+fn format_syscall_args(args: &[&dyn Debug]) -> String {
+    let mut out = String::new();
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        let _ = write!(out, "{:?}", arg);
+        if out.len() > MAX_SYSCALL_ARGS_LEN {
+            let mut end = MAX_SYSCALL_ARGS_LEN;
+            while !out.is_char_boundary(end) {
+                end -= 1;
+            }
+            out.truncate(end);
+            break;
+        }
+    }
+    out
+}
+
+impl<K: Kernel> InvocationData<K> {
+    /// Starts tracing a syscall, if syscall tracing is enabled. Returns the formatted arguments and
+    /// the gas used before the syscall.
+    fn begin_syscall_trace(&self, args: &[&dyn Debug]) -> Option<(String, Gas)> {
+        self.trace_syscalls
+            .then(|| (format_syscall_args(args), self.kernel.gas_used()))
+    }
+
+    /// Records a syscall that returned the given code to the actor, in the call stack's execution
+    /// trace: after the calls it made and the gas it charged.
+    fn end_syscall_trace(
+        &mut self,
+        module: &'static str,
+        name: &'static str,
+        started: Option<(String, Gas)>,
+        code: u32,
+    ) {
+        if let Some((args, gas_used_before)) = started {
+            let gas_used_after = self.kernel.gas_used();
+            self.kernel.trace(ExecutionEvent::Syscall(SyscallTrace {
+                module,
+                name,
+                args,
+                code,
+                gas_used_before,
+                gas_used_after,
+            }));
+        }
+    }
+}
+
 // Unfortunately, we can't implement this for _all_ functions. So we implement it for functions of up to 6 arguments.
 macro_rules! impl_bind_syscalls {
     ($($t:ident)*) => {
@@ -103,7 +163,7 @@ macro_rules! impl_bind_syscalls {
             K: Kernel,
             Func: Fn(Context<'_, K> $(, $t)*) -> Ret + Send + Sync + 'static,
             Ret: IntoSyscallResult,
-           $($t: WasmTy,)*
+           $($t: WasmTy + Debug,)*
         {
             fn bind(
                 &mut self,
@@ -114,9 +174,11 @@ macro_rules! impl_bind_syscalls {
                 if mem::size_of::<Ret::Value>() == 0 {
                     // If we're returning a zero-sized "value", we return no value therefore and expect no out pointer.
                     self.func_wrap(module, name, move |mut caller: Caller<'_, InvocationData<K>> $(, $t: $t)*| {
+                        charge_for_exec(&mut caller).map_err(exec_abort)?;
                         let (mut memory, mut data) = memory_and_data(&mut caller)?;
+                        let trace = data.begin_syscall_trace(&[$(&$t),*]);
                         let ctx = Context{kernel: &mut data.kernel, memory: &mut memory};
-                        Ok(match syscall(ctx $(, $t)*).into()? {
+                        let code = match syscall(ctx $(, $t)*).into()? {
                             Ok(_) => {
                                 log::trace!("syscall {}::{}: ok", module, name);
                                 data.last_error = None;
@@ -128,11 +190,15 @@ macro_rules! impl_bind_syscalls {
                                 data.last_error = Some(backtrace::Cause::new(module, name, err));
                                 code as u32
                             },
-                        })
+                        };
+                        data.end_syscall_trace(module, name, trace, code);
+                        refuel(&mut caller).map_err(exec_abort)?;
+                        Ok(code)
                     })
                 } else {
                     // If we're returning an actual value, we need to write it back into the wasm module's memory.
                     self.func_wrap(module, name, move |mut caller: Caller<'_, InvocationData<K>>, ret: u32 $(, $t: $t)*| {
+                        charge_for_exec(&mut caller).map_err(exec_abort)?;
                         let (mut memory, mut data) = memory_and_data(&mut caller)?;
 
                         // We need to check to make sure we can store the return value _before_ we do anything.
@@ -143,8 +209,9 @@ macro_rules! impl_bind_syscalls {
                             return Ok(code as u32);
                         }
 
+                        let trace = data.begin_syscall_trace(&[$(&$t),*]);
                         let ctx = Context{kernel: &mut data.kernel, memory: &mut memory};
-                        Ok(match syscall(ctx $(, $t)*).into()? {
+                        let code = match syscall(ctx $(, $t)*).into()? {
                             Ok(value) => {
                                 log::trace!("syscall {}::{}: ok", module, name);
                                 unsafe { *(memory.as_mut_ptr().offset(ret as isize) as *mut Ret::Value) = value };
@@ -157,7 +224,10 @@ macro_rules! impl_bind_syscalls {
                                 data.last_error = Some(backtrace::Cause::new(module, name, err));
                                 code as u32
                             },
-                        })
+                        };
+                        data.end_syscall_trace(module, name, trace, code);
+                        refuel(&mut caller).map_err(exec_abort)?;
+                        Ok(code)
                     })
                 }
             }
@@ -172,3 +242,19 @@ impl_bind_syscalls!(A B C);
 impl_bind_syscalls!(A B C D);
 impl_bind_syscalls!(A B C D E);
 impl_bind_syscalls!(A B C D E F);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn syscall_args_are_truncated() {
+        assert_eq!(format_syscall_args(&[]), "");
+        assert_eq!(format_syscall_args(&[&1u32, &-2i64]), "1, -2");
+
+        let long = "é".repeat(MAX_SYSCALL_ARGS_LEN);
+        let formatted = format_syscall_args(&[&long]);
+        assert!(formatted.len() <= MAX_SYSCALL_ARGS_LEN);
+        assert!(formatted.starts_with("\"é"));
+    }
+}
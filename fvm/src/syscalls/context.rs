@@ -2,7 +2,7 @@ use std::ops::{Deref, DerefMut};
 
 use cid::Cid;
 use fvm_shared::address::Address;
-use fvm_shared::encoding::{from_slice, Cbor};
+use fvm_shared::encoding::{from_slice_limited, Cbor, DecodeLimits};
 use fvm_shared::error::ErrorNumber;
 
 use crate::kernel::{ClassifyResult, Context as _, Result};
@@ -62,8 +62,9 @@ impl Memory {
         Address::from_bytes(bytes).or_error(ErrorNumber::IllegalArgument)
     }
 
+    /// Decodes a CBOR value from actor memory, within the default [`DecodeLimits`].
     pub fn read_cbor<T: Cbor>(&self, offset: u32, len: u32) -> Result<T> {
         let bytes = self.try_slice(offset, len)?;
-        from_slice(bytes).or_error(ErrorNumber::IllegalArgument)
+        from_slice_limited(bytes, &DecodeLimits::default()).or_error(ErrorNumber::IllegalArgument)
     }
 }
@@ -1,7 +1,6 @@
 // TODO: remove this when we hookup these syscalls.
 #![allow(unused)]
 
-use std::collections::HashMap;
 use std::iter;
 
 use anyhow::Context as _;
@@ -1,16 +1,75 @@
 use std::str;
 
+use fvm_shared::sys::{MAX_GAS_CHARGE, MAX_GAS_CHARGE_NAME_LEN};
+
 use super::Context;
+use crate::gas::Gas;
 use crate::kernel::{ClassifyResult, Result};
-use crate::Kernel;
+use crate::{syscall_error, Kernel};
 
+/// Charges gas on behalf of the actor. The charge is recorded in the execution trace (when calls
+/// are traced) under the given name, like the charges made by the FVM itself.
+///
+/// The name may be at most [`MAX_GAS_CHARGE_NAME_LEN`] bytes long, and a single charge may be at
+/// most [`MAX_GAS_CHARGE`].
 pub fn charge_gas(
     context: Context<'_, impl Kernel>,
     name_off: u32,
     name_len: u32,
-    compute: i64,
+    compute: u64,
 ) -> Result<()> {
+    check_charge(name_len, compute)?;
     let name =
         str::from_utf8(context.memory.try_slice(name_off, name_len)?).or_illegal_argument()?;
-    context.kernel.charge_gas(name, compute)
+    context.kernel.charge_gas(name, Gas::new(compute as i64))
+}
+
+fn check_charge(name_len: u32, compute: u64) -> Result<()> {
+    if name_len > MAX_GAS_CHARGE_NAME_LEN {
+        return Err(syscall_error!(IllegalArgument;
+            "gas charge name too long: {} > {}",
+            name_len, MAX_GAS_CHARGE_NAME_LEN
+        )
+        .into());
+    }
+    if compute > MAX_GAS_CHARGE {
+        return Err(syscall_error!(IllegalArgument;
+            "gas charge too large: {} > {}",
+            compute, MAX_GAS_CHARGE
+        )
+        .into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ErrorNumber;
+
+    use super::*;
+    use crate::kernel::ExecutionError;
+
+    fn check(name_len: u32, compute: u64) -> Option<ErrorNumber> {
+        match check_charge(name_len, compute) {
+            Ok(()) => None,
+            Err(ExecutionError::Syscall(e)) => Some(e.1),
+            Err(e) => panic!("unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn charge_limits() {
+        assert_eq!(check(0, 0), None);
+        assert_eq!(check(MAX_GAS_CHARGE_NAME_LEN, MAX_GAS_CHARGE), None);
+        assert_eq!(
+            check(MAX_GAS_CHARGE_NAME_LEN + 1, 1),
+            Some(ErrorNumber::IllegalArgument)
+        );
+        assert_eq!(
+            check(1, MAX_GAS_CHARGE + 1),
+            Some(ErrorNumber::IllegalArgument)
+        );
+        // Used to be a negative charge, i.e., a refund.
+        assert_eq!(check(1, -1i64 as u64), Some(ErrorNumber::IllegalArgument));
+    }
 }
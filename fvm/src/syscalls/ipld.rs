@@ -68,3 +68,7 @@ pub fn stat(context: Context<'_, impl Kernel>, id: u32) -> Result<sys::out::ipld
             size: stat.size,
         })
 }
+
+pub fn drop(context: Context<'_, impl Kernel>, id: u32) -> Result<()> {
+    context.kernel.block_drop(id)
+}
@@ -1,8 +1,13 @@
+use std::sync::Arc;
+
+use anyhow::anyhow;
 use cid::Cid;
-use wasmtime::Linker;
+use wasmtime::{AsContextMut, Linker, ResourceLimiter};
 
 use crate::call_manager::backtrace;
-use crate::Kernel;
+use crate::gas::{Gas, GasMeter};
+use crate::kernel::{self, ClassifyResult, ExecutionError};
+use crate::{Config, Kernel};
 
 pub(crate) mod error;
 
@@ -15,6 +20,7 @@ mod gas;
 mod ipld;
 mod message;
 mod network;
+mod precompile;
 mod rand;
 mod send;
 mod sself;
@@ -29,15 +35,132 @@ pub struct InvocationData<K> {
     /// The last-seen syscall error. This error is considered the abort "cause" if an actor aborts
     /// after receiving this error without calling any other syscalls.
     pub last_error: Option<backtrace::Cause>,
+    /// The maximum size (in bytes) the actor's memory may grow to.
+    pub memory_limit: usize,
+    /// Whether the actor's memory has been allocated. Wasmtime allocates it (through the
+    /// [`ResourceLimiter`]) when instantiating the module, before any code runs.
+    pub memory_allocated: bool,
+    /// The maximum number of elements the actor's tables may grow to.
+    pub table_limit: u32,
+    /// The error encountered while charging for memory growth, if any. Wasm can't observe this
+    /// error (the failed `memory.grow` simply returns -1), so we record it here and report it
+    /// when the invocation aborts.
+    pub memory_grow_error: Option<ExecutionError>,
+    /// Whether to record the syscalls made by the actor in the call stack's execution trace.
+    pub trace_syscalls: bool,
+    /// The metering policy, pricing memory growth.
+    pub gas_meter: Arc<dyn GasMeter>,
+    /// The fuel consumed by the actor that has already been charged for.
+    pub fuel_charged: u64,
 }
 
 impl<K> InvocationData<K> {
-    pub(crate) fn new(kernel: K) -> Self {
+    pub(crate) fn new(kernel: K, config: &Config) -> Self {
         Self {
             kernel,
             last_error: None,
+            memory_limit: config.max_pages * WASM_PAGE_SIZE,
+            memory_allocated: false,
+            table_limit: config.max_table_elements,
+            memory_grow_error: None,
+            trace_syscalls: config.trace_syscalls,
+            gas_meter: config.gas_meter.clone(),
+            fuel_charged: 0,
+        }
+    }
+}
+
+/// Charges the gas for the wasm code the actor executed since the last charge, as measured by the
+/// fuel it consumed. This must be done before every syscall, and when the actor returns (or
+/// traps), so that the gas used is always up to date when it's observed.
+pub(crate) fn charge_for_exec<K: Kernel>(
+    mut store: impl AsContextMut<Data = InvocationData<K>>,
+) -> kernel::Result<()> {
+    let mut ctx = store.as_context_mut();
+    let consumed = match ctx.fuel_consumed() {
+        Some(consumed) => consumed,
+        None if ctx.data().kernel.price_list().wasm_exec_per_fuel == Gas::ZERO => return Ok(()),
+        None => {
+            return Err(ExecutionError::Fatal(anyhow!(
+                "wasm execution is priced, but the engine doesn't consume fuel"
+            )))
+        }
+    };
+    let data = ctx.data_mut();
+    let fuel = consumed - data.fuel_charged;
+    data.fuel_charged = consumed;
+    let charge = data.kernel.price_list().on_wasm_exec(fuel);
+    data.kernel.charge_gas(charge.name, charge.total())
+}
+
+/// Limits the fuel the actor may consume to the gas left in the call stack, so that it traps as
+/// soon as it executes more code than it can pay for. This must be done before the actor starts
+/// running, and after every syscall (which may have used gas, e.g., by calling other actors).
+///
+/// The actor is given just enough fuel to run out of gas: running out of fuel always fails the
+/// next [`charge_for_exec`].
+pub(crate) fn refuel<K: Kernel>(
+    mut store: impl AsContextMut<Data = InvocationData<K>>,
+) -> kernel::Result<()> {
+    let mut ctx = store.as_context_mut();
+    if ctx.fuel_consumed().is_none() {
+        return Ok(());
+    }
+    let kernel = &ctx.data().kernel;
+    let price = kernel.price_list().wasm_exec_per_fuel.as_i64();
+    let fuel = if price > 0 {
+        let gas_left = (kernel.gas_available() - kernel.gas_used()).as_i64().max(0);
+        (gas_left / price) as u64 + 1
+    } else {
+        i64::MAX as u64
+    };
+    let remaining = ctx.consume_fuel(0).or_fatal()?;
+    if fuel > remaining {
+        ctx.add_fuel(fuel - remaining).or_fatal()?;
+    } else {
+        // Consuming fuel here counts as consumed by the actor: mark it as charged already, so
+        // that the gas it stands for (used by syscalls) isn't billed again as execution.
+        ctx.consume_fuel(remaining - fuel).or_fatal()?;
+        ctx.data_mut().fuel_charged += remaining - fuel;
+    }
+    Ok(())
+}
+
+/// The size of a wasm memory page.
+pub const WASM_PAGE_SIZE: usize = 65536;
+
+/// Intercepts `memory.grow`, enforcing the memory limit and charging gas for every page grown, and
+/// `table.grow`, enforcing the table limit.
+impl<K: Kernel> ResourceLimiter for InvocationData<K> {
+    fn memory_growing(&mut self, current: usize, desired: usize, _maximum: Option<usize>) -> bool {
+        if desired > self.memory_limit {
+            return false;
+        }
+        // The initial memory is allocated on instantiation and isn't charged for. Every later
+        // growth is, even from an empty memory.
+        if !self.memory_allocated {
+            self.memory_allocated = true;
+            return true;
+        }
+        // A previous charge already failed; nothing more can succeed.
+        if self.memory_grow_error.is_some() {
+            return false;
+        }
+        let charge = self
+            .gas_meter
+            .on_memory_grow(self.kernel.price_list(), current, desired);
+        match self.kernel.charge_gas(charge.name, charge.total()) {
+            Ok(()) => true,
+            Err(e) => {
+                self.memory_grow_error = Some(e);
+                false
+            }
         }
     }
+
+    fn table_growing(&mut self, _current: u32, desired: u32, _maximum: Option<u32>) -> bool {
+        desired <= self.table_limit
+    }
 }
 
 use self::bind::BindSyscall;
@@ -60,6 +183,7 @@ pub fn bind_syscalls(
     linker.bind("ipld", "read", ipld::read)?;
     linker.bind("ipld", "stat", ipld::stat)?;
     linker.bind("ipld", "cid", ipld::cid)?;
+    linker.bind("ipld", "drop", ipld::drop)?;
 
     linker.bind("self", "root", sself::root)?;
     linker.bind("self", "set_root", sself::set_root)?;
@@ -79,8 +203,10 @@ pub fn bind_syscalls(
     )?;
     linker.bind("network", "version", network::version)?;
     linker.bind("network", "curr_epoch", network::curr_epoch)?;
+    linker.bind("network", "tipset_cid", network::tipset_cid)?;
 
     linker.bind("actor", "resolve_address", actor::resolve_address)?;
+    linker.bind("actor", "resolve_addresses", actor::resolve_addresses)?;
     linker.bind("actor", "get_actor_code_cid", actor::get_actor_code_cid)?;
     linker.bind("actor", "new_actor_address", actor::new_actor_address)?;
     linker.bind("actor", "create_actor", actor::create_actor)?;
@@ -121,6 +247,8 @@ pub fn bind_syscalls(
     )?;
     linker.bind("crypto", "batch_verify_seals", crypto::batch_verify_seals)?;
 
+    linker.bind("precompile", "call_precompile", precompile::call_precompile)?;
+
     linker.bind("rand", "get_chain_randomness", rand::get_chain_randomness)?;
     linker.bind("rand", "get_beacon_randomness", rand::get_beacon_randomness)?;
 
@@ -22,6 +22,32 @@ pub fn base_fee(context: Context<'_, impl Kernel>) -> Result<sys::TokenAmount> {
         .or_fatal()
 }
 
+/// Returns the CID of the tipset at the given epoch by writing it in the specified buffer.
+///
+/// The returned u32 represents the _actual_ length of the CID. If the supplied
+/// buffer is smaller, no value will have been written. The caller must retry
+/// with a larger buffer.
+pub fn tipset_cid(
+    context: Context<'_, impl Kernel>,
+    epoch: i64,
+    obuf_off: u32,
+    obuf_len: u32,
+) -> Result<u32> {
+    let cid = context.kernel.tipset_cid(epoch)?;
+    let size = super::encoded_cid_size(&cid);
+
+    if size <= obuf_len {
+        // Only write the CID if there's sufficient capacity.
+        let mut obuf = context.memory.try_slice_mut(obuf_off, size)?;
+
+        cid.write_bytes(&mut obuf)
+            .context("failed to write tipset cid")
+            .or_fatal()?;
+    }
+
+    Ok(size)
+}
+
 /// Returns the network circ supply split as two u64 ordered in little endian.
 pub fn total_fil_circ_supply(context: Context<'_, impl Kernel>) -> Result<sys::TokenAmount> {
     context
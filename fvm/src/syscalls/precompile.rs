@@ -0,0 +1,15 @@
+use super::Context;
+use crate::kernel::{BlockId, Result};
+use crate::Kernel;
+
+/// Calls the host-side precompile registered under the given ID on the input, and returns its
+/// output as a raw block, to be read with `ipld::read`.
+pub fn call_precompile(
+    context: Context<'_, impl Kernel>,
+    id: u64,
+    input_off: u32,
+    input_len: u32,
+) -> Result<BlockId> {
+    let input = context.memory.try_slice(input_off, input_len)?;
+    context.kernel.call_precompile(id, input)
+}
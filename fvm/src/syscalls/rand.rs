@@ -1,11 +1,59 @@
 use anyhow::Context as _;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
 use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::sys::MAX_RANDOMNESS_ENTROPY_LEN;
+use fvm_shared::version::NetworkVersion;
 use num_traits::FromPrimitive;
 
+use super::context::Memory;
 use super::Context;
 use crate::kernel::{ClassifyResult, Result};
-use crate::Kernel;
+use crate::{syscall_error, Kernel};
+
+/// Validates a domain separation tag passed by an actor. Unknown tags are the actor's fault, so
+/// they're reported to it instead of aborting.
+///
+/// Only the tags defined by [`DomainSeparationTag`] are accepted: anything else (including
+/// negative values) fails with `IllegalArgument`.
+fn domain_separation_tag(pers: i64) -> Result<DomainSeparationTag> {
+    DomainSeparationTag::from_i64(pers)
+        .with_context(|| format!("invalid domain separation tag {}", pers))
+        .or_illegal_argument()
+}
+
+/// Checks the length of the entropy passed by an actor, before reading it.
+fn check_entropy_len(entropy_len: u32) -> Result<()> {
+    if entropy_len > MAX_RANDOMNESS_ENTROPY_LEN {
+        return Err(syscall_error!(IllegalArgument;
+            "randomness entropy too long: {} > {}",
+            entropy_len, MAX_RANDOMNESS_ENTROPY_LEN
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Validates the domain separation tag and entropy passed by an actor, and reads the entropy.
+///
+/// From network version 16 on, the tag is validated and the entropy length bounded (by
+/// [`MAX_RANDOMNESS_ENTROPY_LEN`]) before anything is read, so that all implementations agree on
+/// the outcome. Earlier versions keep their original behavior: the entropy is read first, whatever
+/// its length, then the tag is validated.
+fn randomness_args(
+    nv: NetworkVersion,
+    memory: &Memory,
+    pers: i64,
+    entropy_off: u32,
+    entropy_len: u32,
+) -> Result<(DomainSeparationTag, &[u8])> {
+    if nv < NetworkVersion::V16 {
+        let entropy = memory.try_slice(entropy_off, entropy_len)?;
+        return Ok((domain_separation_tag(pers)?, entropy));
+    }
+    let pers = domain_separation_tag(pers)?;
+    check_entropy_len(entropy_len)?;
+    Ok((pers, memory.try_slice(entropy_off, entropy_len)?))
+}
 
 /// Gets 32 bytes of randomness from the ticket chain.
 /// The supplied output buffer must have at least 32 bytes of capacity.
@@ -18,11 +66,8 @@ pub fn get_chain_randomness(
     entropy_off: u32,
     entropy_len: u32,
 ) -> Result<[u8; RANDOMNESS_LENGTH]> {
-    let entropy = context.memory.try_slice(entropy_off, entropy_len)?;
-    // TODO determine if this error should lead to an abort.
-    let pers = DomainSeparationTag::from_i64(pers)
-        .context("invalid domain separation tag")
-        .or_illegal_argument()?;
+    let nv = context.kernel.network_version();
+    let (pers, entropy) = randomness_args(nv, context.memory, pers, entropy_off, entropy_len)?;
     context
         .kernel
         .get_randomness_from_tickets(pers, round, entropy)
@@ -39,12 +84,105 @@ pub fn get_beacon_randomness(
     entropy_off: u32,
     entropy_len: u32,
 ) -> Result<[u8; RANDOMNESS_LENGTH]> {
-    let entropy = context.memory.try_slice(entropy_off, entropy_len)?;
-    // TODO determine if this error should lead to an abort.
-    let pers = DomainSeparationTag::from_i64(pers)
-        .context("invalid domain separation tag")
-        .or_illegal_argument()?;
+    let nv = context.kernel.network_version();
+    let (pers, entropy) = randomness_args(nv, context.memory, pers, entropy_off, entropy_len)?;
     context
         .kernel
         .get_randomness_from_beacon(pers, round, entropy)
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ErrorNumber;
+    use fvm_shared::error::ErrorNumber::IllegalArgument;
+    use fvm_shared::version::NetworkVersion::{V15, V16};
+
+    use super::*;
+    use crate::kernel::ExecutionError;
+
+    #[test]
+    fn domain_separation_tags_are_validated() {
+        assert_eq!(
+            domain_separation_tag(DomainSeparationTag::SealRandomness as i64).unwrap(),
+            DomainSeparationTag::SealRandomness
+        );
+        assert_eq!(
+            domain_separation_tag(DomainSeparationTag::PoStChainCommit as i64).unwrap(),
+            DomainSeparationTag::PoStChainCommit
+        );
+        for pers in [
+            0,
+            10,
+            -1,
+            -(DomainSeparationTag::SealRandomness as i64),
+            (1 << 32) | DomainSeparationTag::SealRandomness as i64,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            match domain_separation_tag(pers) {
+                Err(ExecutionError::Syscall(e)) => assert_eq!(e.1, ErrorNumber::IllegalArgument),
+                _ => panic!("expected domain separation tag {} to be rejected", pers),
+            }
+        }
+    }
+
+    #[test]
+    fn entropy_length_is_bounded() {
+        assert!(check_entropy_len(0).is_ok());
+        assert!(check_entropy_len(MAX_RANDOMNESS_ENTROPY_LEN).is_ok());
+        for len in [MAX_RANDOMNESS_ENTROPY_LEN + 1, u32::MAX] {
+            match check_entropy_len(len) {
+                Err(ExecutionError::Syscall(e)) => assert_eq!(e.1, ErrorNumber::IllegalArgument),
+                _ => panic!("expected entropy of {} bytes to be rejected", len),
+            }
+        }
+    }
+
+    /// How the randomness syscalls treat unusual arguments, by network version: `None` if they're
+    /// accepted, or the error number they fail with. Any change here is a consensus change.
+    const FIXTURES: &[(NetworkVersion, i64, u32, Option<ErrorNumber>)] = &[
+        (V15, SEAL, 0, None),
+        (V15, SEAL, MAX, None),
+        (V15, SEAL, MAX + 1, None),
+        (V15, SEAL, MEMORY_LEN + 1, Some(IllegalArgument)),
+        (V15, 0, 0, Some(IllegalArgument)),
+        (V15, -SEAL, 0, Some(IllegalArgument)),
+        (V15, (1 << 32) | SEAL, 0, Some(IllegalArgument)),
+        (V15, i64::MAX, 0, Some(IllegalArgument)),
+        (V16, SEAL, 0, None),
+        (V16, SEAL, MAX, None),
+        (V16, SEAL, MAX + 1, Some(IllegalArgument)),
+        (V16, SEAL, MEMORY_LEN + 1, Some(IllegalArgument)),
+        (V16, 0, 0, Some(IllegalArgument)),
+        (V16, -SEAL, 0, Some(IllegalArgument)),
+        (V16, (1 << 32) | SEAL, 0, Some(IllegalArgument)),
+        (V16, i64::MAX, 0, Some(IllegalArgument)),
+    ];
+
+    const SEAL: i64 = DomainSeparationTag::SealRandomness as i64;
+    const MAX: u32 = MAX_RANDOMNESS_ENTROPY_LEN;
+    const MEMORY_LEN: u32 = 2 * MAX_RANDOMNESS_ENTROPY_LEN;
+
+    #[test]
+    fn unusual_arguments_by_network_version() {
+        let mut buf = vec![0u8; MEMORY_LEN as usize];
+        let memory = Memory::new(&mut buf);
+        for &(nv, pers, entropy_len, expected) in FIXTURES {
+            let res = randomness_args(nv, memory, pers, 0, entropy_len);
+            let actual = match res {
+                Ok((tag, entropy)) => {
+                    assert_eq!(tag as i64, pers);
+                    assert_eq!(entropy.len(), entropy_len as usize);
+                    None
+                }
+                Err(ExecutionError::Syscall(e)) => Some(e.1),
+                Err(e) => panic!("unexpected error: {}", e),
+            };
+            assert_eq!(
+                actual, expected,
+                "nv {}, tag {}, {} bytes of entropy",
+                nv, pers, entropy_len
+            );
+        }
+    }
+}
@@ -1,20 +1,16 @@
 use fvm_shared::address::Address;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::encoding::DAG_CBOR;
-use fvm_shared::error::ExitCode;
 use fvm_shared::sys;
 
 use super::Context;
-use crate::call_manager::{InvocationResult, NO_DATA_BLOCK_ID};
+use crate::call_manager::NO_DATA_BLOCK_ID;
 use crate::kernel::Result;
-use crate::Kernel;
+use crate::{syscall_error, Kernel};
 
-/// Send a message to another actor. The result is placed as a CBOR-encoded
-/// receipt in the block registry, and can be retrieved by the returned BlockId.
-///
-/// TODO result is a Receipt, but messages within a call stack don't
-///  actually produce receipts.
-///  See https://github.com/filecoin-project/fvm/issues/168.
+/// Send a message to another actor. The return value is placed in the block
+/// registry, and can be retrieved by the returned BlockId. The exit code and the
+/// gas consumed by the receiver are returned alongside it.
 pub fn send(
     context: Context<'_, impl Kernel>,
     recipient_off: u32,
@@ -32,22 +28,28 @@ pub fn send(
     } else {
         (DAG_CBOR, Vec::new())
     };
-    debug_assert_eq!(code, DAG_CBOR);
+    // The receiver gets the parameters as a DAG-CBOR block.
+    if code != DAG_CBOR {
+        return Err(
+            syscall_error!(IllegalCodec; "send parameters must be DAG-CBOR, got codec {}", code)
+                .into(),
+        );
+    }
     // An execution error here means that something went wrong in the FVM.
     // Actor errors are communicated in the receipt.
-    let (exit_code, return_id) =
-        match context
+    let receipt = context
+        .kernel
+        .send(&recipient, method, &params.into(), &value)?;
+    let return_id = if receipt.exit_code.is_success() {
+        context
             .kernel
-            .send(&recipient, method, &params.into(), &value)?
-        {
-            InvocationResult::Return(value) => (
-                ExitCode::Ok as u32,
-                context.kernel.block_create(DAG_CBOR, value.bytes())?,
-            ),
-            InvocationResult::Failure(code) => (code as u32, 0),
-        };
+            .block_create(DAG_CBOR, receipt.return_data.bytes())?
+    } else {
+        NO_DATA_BLOCK_ID
+    };
     Ok(sys::out::send::Send {
-        exit_code,
+        exit_code: receipt.exit_code.into(),
         return_id,
+        gas_used: receipt.gas_used,
     })
 }
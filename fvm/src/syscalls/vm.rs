@@ -1,5 +1,5 @@
 use fvm_shared::error::ExitCode;
-use num_traits::FromPrimitive;
+use fvm_shared::sys;
 
 use super::error::Abort;
 use super::Context;
@@ -11,18 +11,29 @@ use crate::Kernel;
 #[derive(Copy, Clone)]
 pub enum Never {}
 
+/// Converts the exit code an actor aborted with into an [`ExitCode`], mapping invalid codes to
+/// `SysErrIllegalActor`.
+///
+/// Actors signal that they don't handle the invoked method number by aborting with
+/// `SysErrInvalidMethod`. This is the one system exit code actors are expected to use, and it's
+/// always passed through as-is.
+pub(crate) fn abort_exit_code(code: sys::ExitCode) -> ExitCode {
+    match ExitCode::try_from(code) {
+        // BUG: https://github.com/filecoin-project/fvm/issues/253
+        // All other system exit codes should be rejected as well.
+        Ok(code) => code,
+        Err(_) => ExitCode::SysErrIllegalActor, // TODO: will become "illegal exit"
+    }
+}
+
 // NOTE: this won't clobber the last syscall error because it directly returns a "trap".
 pub fn abort(
     context: Context<'_, impl Kernel>,
-    code: u32,
+    code: sys::ExitCode,
     message_off: u32,
     message_len: u32,
 ) -> Result<Never, Abort> {
-    // Get the error and convert it into a "system illegal argument error" if it's invalid.
-    // BUG: https://github.com/filecoin-project/fvm/issues/253
-    let code = ExitCode::from_u32(code)
-        //.filter(|c| !c.is_system_error())
-        .unwrap_or(ExitCode::SysErrIllegalActor); // TODO: will become "illegal exit"
+    let code = abort_exit_code(code);
 
     let message = if message_len == 0 {
         "actor aborted".to_owned()
@@ -40,3 +51,28 @@ pub fn abort(
     };
     Err(Abort::Exit(code, message))
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm_shared::error::ExitCode;
+
+    use super::abort_exit_code;
+
+    #[test]
+    fn abort_exit_codes() {
+        assert_eq!(
+            abort_exit_code(ExitCode::SysErrInvalidMethod as u32),
+            ExitCode::SysErrInvalidMethod
+        );
+        assert_eq!(
+            abort_exit_code(ExitCode::ErrIllegalArgument as u32),
+            ExitCode::ErrIllegalArgument
+        );
+        assert_eq!(abort_exit_code(999), ExitCode::SysErrIllegalActor);
+        assert_eq!(abort_exit_code(u32::MAX), ExitCode::SysErrIllegalActor);
+        assert_eq!(
+            abort_exit_code(ExitCode::ErrPlaceholder as u32),
+            ExitCode::ErrPlaceholder
+        );
+    }
+}
@@ -2,15 +2,13 @@ use anyhow::Context;
 use cid::Cid;
 use serde::{Deserialize, Serialize};
 
-use fvm_shared::address::Address;
 use fvm_shared::blockstore::{Blockstore, CborStore};
+use fvm_shared::constants::SYSTEM_ACTOR_ADDR;
 use fvm_shared::encoding::Cbor;
 
 use crate::kernel::{ClassifyResult, Result};
 use crate::state_tree::{ActorState, StateTree};
 
-pub const SYSTEM_ACTOR_ADDR: Address = Address::new_id(0);
-
 #[derive(Default, Deserialize, Serialize)]
 pub struct State {
     // builtin actor registry: Vec<(String, Cid)>
@@ -0,0 +1,371 @@
+//! Test doubles for unit-testing [`Kernel`] implementations in isolation: a [`MockMachine`] over an
+//! in-memory state tree, and a [`MockCallManager`] that records sends instead of executing actor
+//! code.
+//!
+//! Available in this crate's tests, and to other crates with the `testing` feature.
+
+use anyhow::Context as _;
+use cid::Cid;
+use fvm_shared::actor::builtin::Manifest;
+use fvm_shared::address::{Address, Payload};
+use fvm_shared::blockstore::MemoryBlockstore;
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::consensus::ConsensusFault;
+use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::encoding::RawBytes;
+use fvm_shared::error::ErrorNumber;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::state::StateTreeVersion;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, MethodNum};
+use num_traits::Zero;
+
+use crate::call_manager::{Backtrace, CallManager, FinishRet, InvocationResult, RandomnessCache};
+use crate::externs::{Chain, CircSupply, CodePolicy, Consensus, Externs, Rand};
+use crate::gas::{price_list_by_network_version, Gas, GasCharge, GasTracker};
+use crate::kernel::{ClassifyResult, Kernel, Result};
+use crate::machine::{Engine, Machine, MachineContext};
+use crate::state_tree::{ActorState, StateTree};
+use crate::trace::{ExecutionEvent, ExecutionTrace};
+use crate::{syscall_error, Config};
+
+/// Externs returning all-zero randomness, a CID derived from the epoch for tipsets, and never
+/// reporting a consensus fault.
+#[derive(Default)]
+pub struct MockExterns {
+    /// The circulating supply to report, if any.
+    pub circ_supply: Option<TokenAmount>,
+}
+
+impl Externs for MockExterns {}
+
+impl CodePolicy for MockExterns {}
+
+impl CircSupply for MockExterns {
+    fn total_fil_circ_supply(&self, _epoch: ChainEpoch) -> anyhow::Result<Option<TokenAmount>> {
+        Ok(self.circ_supply.clone())
+    }
+}
+
+impl Chain for MockExterns {
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        Ok(cbor_blake2b_cid(&epoch.to_be_bytes()))
+    }
+}
+
+impl Rand for MockExterns {
+    fn get_chain_randomness(
+        &self,
+        _pers: DomainSeparationTag,
+        _round: ChainEpoch,
+        _entropy: &[u8],
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
+        Ok([0; RANDOMNESS_LENGTH])
+    }
+
+    fn get_beacon_randomness(
+        &self,
+        _pers: DomainSeparationTag,
+        _round: ChainEpoch,
+        _entropy: &[u8],
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
+        Ok([0; RANDOMNESS_LENGTH])
+    }
+}
+
+impl Consensus for MockExterns {
+    fn verify_consensus_fault(
+        &self,
+        _h1: &[u8],
+        _h2: &[u8],
+        _extra: &[u8],
+    ) -> anyhow::Result<(Option<ConsensusFault>, i64)> {
+        Ok((None, 0))
+    }
+}
+
+/// A [`Machine`] over an empty, in-memory state tree.
+///
+/// There are no builtin actors (not even the init actor), so actors can only be created at, and
+/// looked up by, ID addresses. Tests are expected to populate `state_tree` directly.
+pub struct MockMachine {
+    pub config: Config,
+    pub context: MachineContext,
+    pub engine: Engine,
+    pub externs: MockExterns,
+    pub builtin_actors: Manifest,
+    pub state_tree: StateTree<MemoryBlockstore>,
+}
+
+impl MockMachine {
+    /// Creates a machine at epoch 0 with an empty state tree, using the price list of the given
+    /// network version.
+    pub fn new(network_version: NetworkVersion) -> anyhow::Result<Self> {
+        let mut state_tree = StateTree::new(MemoryBlockstore::default(), StateTreeVersion::V4)?;
+        let initial_state_root = state_tree.flush()?;
+        Ok(MockMachine {
+            config: Config::default(),
+            context: MachineContext {
+                epoch: 0,
+                base_fee: TokenAmount::zero(),
+                circ_supply: TokenAmount::zero(),
+                initial_state_root,
+                price_list: price_list_by_network_version(network_version),
+                network_version,
+                debug: false,
+            },
+            engine: Engine::default(),
+            externs: MockExterns::default(),
+            builtin_actors: Manifest::default(),
+            state_tree,
+        })
+    }
+}
+
+impl Machine for MockMachine {
+    type Blockstore = MemoryBlockstore;
+    type Externs = MockExterns;
+
+    fn engine(&self) -> &Engine {
+        &self.engine
+    }
+
+    fn config(&self) -> &Config {
+        &self.config
+    }
+
+    fn blockstore(&self) -> &Self::Blockstore {
+        self.state_tree.store()
+    }
+
+    fn context(&self) -> &MachineContext {
+        &self.context
+    }
+
+    fn externs(&self) -> &Self::Externs {
+        &self.externs
+    }
+
+    fn builtin_actors(&self) -> &Manifest {
+        &self.builtin_actors
+    }
+
+    fn state_tree(&self) -> &StateTree<Self::Blockstore> {
+        &self.state_tree
+    }
+
+    fn state_tree_mut(&mut self) -> &mut StateTree<Self::Blockstore> {
+        &mut self.state_tree
+    }
+
+    fn create_actor(&mut self, addr: &Address, act: ActorState) -> Result<ActorID> {
+        let id = match addr.payload() {
+            Payload::ID(id) => *id,
+            _ => {
+                return Err(syscall_error!(IllegalArgument;
+                    "mock machine can only create actors at ID addresses, got {}", addr)
+                .into())
+            }
+        };
+        self.state_tree.set_actor_id(id, act)?;
+        Ok(id)
+    }
+
+    fn transfer(&mut self, from: ActorID, to: ActorID, value: &TokenAmount) -> Result<()> {
+        if from == to {
+            return Ok(());
+        }
+
+        let mut from_actor = self
+            .state_tree
+            .get_actor_id(from)?
+            .context("cannot transfer from non-existent sender")
+            .or_error(ErrorNumber::InsufficientFunds)?;
+        let mut to_actor = self
+            .state_tree
+            .get_actor_id(to)?
+            .context("cannot transfer to non-existent receiver")
+            .or_error(ErrorNumber::NotFound)?;
+
+        from_actor.deduct_funds(value)?;
+        to_actor.deposit_funds(value);
+
+        self.state_tree.set_actor_id(from, from_actor)?;
+        self.state_tree.set_actor_id(to, to_actor)?;
+        Ok(())
+    }
+
+    fn reset_to(&mut self, state_root: Cid, epoch: ChainEpoch) -> Result<()> {
+        self.state_tree.reset_to_root(&state_root)?;
+        self.context.initial_state_root = state_root;
+        self.context.epoch = epoch;
+        Ok(())
+    }
+
+    fn consume(self) -> Self::Blockstore {
+        self.state_tree.consume()
+    }
+}
+
+/// A send made through a [`MockCallManager`].
+#[derive(Clone, Debug)]
+pub struct MockSend {
+    pub from: ActorID,
+    pub to: Address,
+    pub method: MethodNum,
+    pub params: RawBytes,
+    pub value: TokenAmount,
+}
+
+/// A [`CallManager`] that never invokes actor code.
+///
+/// Sends are recorded in `sends`, transfer their value, consume `send_gas`, and return an empty
+/// value without invoking the receiver. Everything else (gas, transactions, actor creation indices) behaves like the
+/// default call manager.
+pub struct MockCallManager {
+    pub machine: MockMachine,
+    pub gas_tracker: GasTracker,
+    pub origin: Address,
+    pub nonce: u64,
+    pub num_actors_created: u64,
+    pub num_invocations: u32,
+    pub sends: Vec<MockSend>,
+    /// The gas each send consumes, standing in for the execution of the receiver.
+    pub send_gas: Gas,
+    pub randomness_cache: RandomnessCache,
+    /// The events traced so far, if the machine is configured to trace calls.
+    pub exec_trace: ExecutionTrace,
+}
+
+impl CallManager for MockCallManager {
+    type Machine = MockMachine;
+
+    fn new(machine: MockMachine, gas_limit: Gas, origin: Address, nonce: u64) -> Self {
+        let gas_meter = machine.config.gas_meter.clone();
+        MockCallManager {
+            machine,
+            gas_tracker: GasTracker::with_meter(gas_limit, Gas::ZERO, gas_meter),
+            origin,
+            nonce,
+            num_actors_created: 0,
+            num_invocations: 0,
+            sends: Vec::new(),
+            send_gas: Gas::ZERO,
+            randomness_cache: RandomnessCache::default(),
+            exec_trace: ExecutionTrace::new(),
+        }
+    }
+
+    fn send<K: Kernel<CallManager = Self>>(
+        &mut self,
+        from: ActorID,
+        to: Address,
+        method: MethodNum,
+        params: &RawBytes,
+        value: &TokenAmount,
+    ) -> Result<InvocationResult> {
+        self.sends.push(MockSend {
+            from,
+            to,
+            method,
+            params: params.clone(),
+            value: value.clone(),
+        });
+        self.gas_tracker
+            .charge_gas(GasCharge::new("OnMockSend", self.send_gas, Gas::ZERO))?;
+        if !value.is_zero() {
+            let to = self
+                .machine
+                .state_tree
+                .lookup_id(&to)?
+                .ok_or_else(|| syscall_error!(NotFound; "actor does not exist: {}", to))?;
+            self.machine.transfer(from, to, value)?;
+        }
+        Ok(InvocationResult::default())
+    }
+
+    fn with_transaction(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<InvocationResult>,
+    ) -> Result<InvocationResult> {
+        self.state_tree_mut().begin_transaction();
+        let (revert, res) = match f(self) {
+            Ok(v) => (!v.exit_code().is_success(), Ok(v)),
+            Err(e) => (true, Err(e)),
+        };
+        self.state_tree_mut().end_transaction(revert)?;
+        res
+    }
+
+    fn finish(self) -> (FinishRet, MockMachine) {
+        (
+            FinishRet {
+                gas_used: self.gas_tracker.gas_used().max(Gas::ZERO),
+                backtrace: Backtrace::default(),
+                exec_trace: self.exec_trace,
+            },
+            self.machine,
+        )
+    }
+
+    fn machine(&self) -> &MockMachine {
+        &self.machine
+    }
+
+    fn machine_mut(&mut self) -> &mut MockMachine {
+        &mut self.machine
+    }
+
+    fn gas_tracker(&self) -> &GasTracker {
+        &self.gas_tracker
+    }
+
+    fn gas_tracker_mut(&mut self) -> &mut GasTracker {
+        &mut self.gas_tracker
+    }
+
+    fn origin(&self) -> Address {
+        self.origin
+    }
+
+    fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    fn next_actor_idx(&mut self) -> u64 {
+        let ret = self.num_actors_created;
+        self.num_actors_created += 1;
+        ret
+    }
+
+    fn next_invocation_idx(&mut self) -> u32 {
+        let ret = self.num_invocations;
+        self.num_invocations = self.num_invocations.wrapping_add(1);
+        ret
+    }
+
+    fn randomness_cache_mut(&mut self) -> &mut RandomnessCache {
+        &mut self.randomness_cache
+    }
+
+    fn trace(&mut self, event: ExecutionEvent) {
+        let traced = match event {
+            ExecutionEvent::Syscall(_) => self.machine.config.trace_syscalls,
+            _ => self.machine.config.trace_calls,
+        };
+        if traced {
+            self.exec_trace.push(event);
+        }
+    }
+}
+
+/// Returns the CID of `data` as DAG-CBOR hashed with Blake2b-256, i.e. what
+/// [`BlockOps::block_link`](crate::kernel::BlockOps::block_link) produces for such a block.
+pub fn cbor_blake2b_cid(data: &[u8]) -> Cid {
+    use cid::multihash::{Code, MultihashDigest};
+    Cid::new_v1(
+        fvm_shared::encoding::DAG_CBOR,
+        Code::Blake2b256.digest(data),
+    )
+}
@@ -0,0 +1,122 @@
+//! Execution traces, recorded when tracing is enabled in the machine [`Config`](crate::Config).
+
+use cid::Cid;
+use fvm_shared::address::Address;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::{ActorID, MethodNum};
+
+use crate::gas::{Gas, GasCharge};
+
+/// The maximum length of the formatted syscall arguments recorded in a [`SyscallTrace`].
+pub const MAX_SYSCALL_ARGS_LEN: usize = 256;
+
+/// The events recorded while executing a message.
+///
+/// Events are recorded as they happen, so gas charges belong to the innermost call that hasn't
+/// returned yet. Syscalls are recorded when they return to the actor, so they follow the events of
+/// any calls they made.
+pub type ExecutionTrace = Vec<ExecutionEvent>;
+
+/// An event recorded while executing a message.
+#[derive(Clone, Debug)]
+pub enum ExecutionEvent {
+    /// An actor made a syscall.
+    Syscall(SyscallTrace),
+    /// A send is about to be made (including the top-level send of the message).
+    Call(CallTrace),
+    /// The most recent unreturned send returned.
+    CallReturn(CallReturnTrace),
+    /// Gas was charged.
+    GasCharge(GasChargeTrace),
+    /// An actor was created, deleted, or had its state root set.
+    StateChange(StateChangeTrace),
+}
+
+/// A send between two actors.
+#[derive(Clone, Debug)]
+pub struct CallTrace {
+    pub from: ActorID,
+    pub to: Address,
+    pub method: MethodNum,
+    /// The value transferred with the send.
+    pub value: TokenAmount,
+    /// The depth of the call: 1 for the message's top-level send, 2 for the sends it makes, etc.
+    pub depth: u32,
+    /// The gas remaining when the call was made.
+    pub gas_available: Gas,
+}
+
+/// The return of a send, matching the most recent unreturned [`CallTrace`].
+#[derive(Clone, Debug)]
+pub struct CallReturnTrace {
+    /// The exit code, or `None` if the send failed with an error (e.g., the receiver doesn't
+    /// exist) rather than returning or aborting.
+    pub exit_code: Option<ExitCode>,
+    /// The depth of the returning call (the same as its [`CallTrace::depth`]).
+    pub depth: u32,
+    /// The gas remaining when the call returned.
+    pub gas_available: Gas,
+}
+
+/// A gas charge. The charge may have failed with an out of gas error.
+#[derive(Clone, Debug)]
+pub struct GasChargeTrace {
+    pub name: String,
+    pub compute_gas: Gas,
+    pub storage_gas: Gas,
+}
+
+impl GasChargeTrace {
+    /// Returns the total gas charged.
+    pub fn total(&self) -> Gas {
+        self.compute_gas + self.storage_gas
+    }
+}
+
+impl From<&GasCharge<'_>> for GasChargeTrace {
+    fn from(charge: &GasCharge<'_>) -> Self {
+        GasChargeTrace {
+            name: charge.name.to_owned(),
+            compute_gas: charge.compute_gas,
+            storage_gas: charge.storage_gas,
+        }
+    }
+}
+
+/// A change to the state tree. Changes made within a call that fails (see
+/// [`CallReturnTrace::exit_code`]) are reverted along with it, but are still recorded.
+#[derive(Clone, Debug)]
+pub enum StateChangeTrace {
+    /// An actor was created, by the init actor or when sending to a new key address.
+    ActorCreated { id: ActorID, code: Cid },
+    /// An actor set its state root.
+    StateRootSet { id: ActorID, root: Cid },
+    /// An actor deleted itself.
+    ActorDeleted { id: ActorID },
+}
+
+/// A syscall made by an actor. Only syscalls that return to the actor are recorded: syscalls that
+/// abort the actor (e.g., by running out of gas) show up in the backtrace instead.
+#[derive(Clone, Debug)]
+pub struct SyscallTrace {
+    /// The syscall's module.
+    pub module: &'static str,
+    /// The syscall's name.
+    pub name: &'static str,
+    /// The syscall's raw (wasm) arguments, truncated to [`MAX_SYSCALL_ARGS_LEN`] bytes.
+    pub args: String,
+    /// The error number returned to the actor, or 0 on success.
+    pub code: u32,
+    /// The gas used before the syscall.
+    pub gas_used_before: Gas,
+    /// The gas used after the syscall.
+    pub gas_used_after: Gas,
+}
+
+impl SyscallTrace {
+    /// Returns the gas charged by this syscall (including any gas charged by nested calls).
+    pub fn gas_charged(&self) -> Gas {
+        self.gas_used_after - self.gas_used_before
+    }
+}
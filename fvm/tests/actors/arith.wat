@@ -0,0 +1,54 @@
+;; Integer arithmetic: runs a xorshift generator and mixes its output through wrapping
+;; multiplication, signed and unsigned division, shifts by out-of-range amounts, rotations and bit
+;; counting. The accumulator after every round is stored.
+
+(func (export "invoke") (param i32) (result i32)
+  (local $i i32)
+  (local $x i64)
+  (local $acc i64)
+  (local.set $x (i64.const 0x2545f4914f6cdd1d))
+  (block $done
+    (loop $round
+      (br_if $done (i32.ge_u (local.get $i) (i32.const 512)))
+      (local.set $x (i64.xor (local.get $x) (i64.shl (local.get $x) (i64.const 13))))
+      (local.set $x (i64.xor (local.get $x) (i64.shr_u (local.get $x) (i64.const 7))))
+      (local.set $x (i64.xor (local.get $x) (i64.shl (local.get $x) (i64.const 17))))
+      (local.set $acc
+        (i64.add
+          (i64.mul (local.get $acc) (i64.const 0x100000001b3))
+          (i64.rotl (local.get $x) (i64.extend_i32_u (local.get $i)))))
+      ;; Divisors are never zero, nor -1.
+      (local.set $acc
+        (i64.xor
+          (local.get $acc)
+          (i64.div_s
+            (local.get $x)
+            (i64.or (i64.and (local.get $acc) (i64.const 0xffff)) (i64.const 2)))))
+      (local.set $acc
+        (i64.add
+          (local.get $acc)
+          (i64.rem_u
+            (local.get $x)
+            (i64.or (i64.shr_u (local.get $acc) (i64.const 40)) (i64.const 1)))))
+      (local.set $acc
+        (i64.sub
+          (local.get $acc)
+          (i64.rem_s (local.get $x) (i64.const -7))))
+      ;; Shift amounts are taken modulo the bit width.
+      (local.set $acc
+        (i64.xor (local.get $acc) (i64.shr_s (local.get $acc) (local.get $x))))
+      (local.set $acc
+        (i64.add
+          (local.get $acc)
+          (i64.extend_i32_u
+            (i32.add
+              (i32.popcnt (i32.wrap_i64 (local.get $x)))
+              (i32.add
+                (i32.clz (i32.wrap_i64 (local.get $acc)))
+                (i32.ctz (i32.wrap_i64 (i64.shr_u (local.get $x) (i64.const 32)))))))))
+      (i64.store
+        (i32.add (i32.const 256) (i32.shl (local.get $i) (i32.const 3)))
+        (local.get $acc))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $round)))
+  (call $commit (i32.const 256) (i32.const 4096)))
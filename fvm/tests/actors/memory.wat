@@ -0,0 +1,59 @@
+;; Memory: grows the memory to 16 pages (after failing to grow it past the limit), fills it with
+;; overlapping unaligned stores, shifts it with an overlapping byte-by-byte copy, and folds it
+;; back with sign-extending loads of every width. The results of both grows, the final memory size
+;; and the folded value are stored.
+
+(func (export "invoke") (param i32) (result i32)
+  (local $i i32)
+  (local $end i32)
+  (local $x i64)
+  (local $sum i64)
+  (i64.store (i32.const 256) (i64.extend_i32_s (memory.grow (i32.const 100000))))
+  (i64.store (i32.const 264) (i64.extend_i32_s (memory.grow (i32.const 15))))
+  (local.set $end (i32.mul (memory.size) (i32.const 65536)))
+  (i64.store (i32.const 272) (i64.extend_i32_u (memory.size)))
+
+  ;; Fill with 8 byte values every 7 bytes.
+  (local.set $x (i64.const 0x9e3779b97f4a7c15))
+  (local.set $i (i32.const 4096))
+  (block $done
+    (loop $fill
+      (br_if $done (i32.gt_u (local.get $i) (i32.sub (local.get $end) (i32.const 8))))
+      (local.set $x (i64.xor (local.get $x) (i64.shl (local.get $x) (i64.const 13))))
+      (local.set $x (i64.xor (local.get $x) (i64.shr_u (local.get $x) (i64.const 7))))
+      (local.set $x (i64.xor (local.get $x) (i64.shl (local.get $x) (i64.const 17))))
+      (i64.store (local.get $i) (local.get $x))
+      (local.set $i (i32.add (local.get $i) (i32.const 7)))
+      (br $fill)))
+
+  ;; Move the first filled page 3 bytes up, back to front.
+  (local.set $i (i32.const 65536))
+  (block $done
+    (loop $copy
+      (br_if $done (i32.eqz (local.get $i)))
+      (local.set $i (i32.sub (local.get $i) (i32.const 1)))
+      (i32.store8
+        (i32.add (local.get $i) (i32.const 4099))
+        (i32.load8_u (i32.add (local.get $i) (i32.const 4096))))
+      (br $copy)))
+
+  ;; Fold every 13 bytes.
+  (local.set $i (i32.const 4096))
+  (block $done
+    (loop $fold
+      (br_if $done (i32.gt_u (local.get $i) (i32.sub (local.get $end) (i32.const 8))))
+      (local.set $sum
+        (i64.add
+          (i64.rotl (local.get $sum) (i64.const 5))
+          (i64.add
+            (i64.add (i64.load8_s (local.get $i)) (i64.load16_s offset=1 (local.get $i)))
+            (i64.add (i64.load32_s offset=2 (local.get $i)) (i64.load (local.get $i))))))
+      (local.set $sum
+        (i64.xor
+          (local.get $sum)
+          (i64.extend_i32_s
+            (i32.add (i32.load8_s offset=7 (local.get $i)) (i32.load16_s offset=5 (local.get $i))))))
+      (local.set $i (i32.add (local.get $i) (i32.const 13)))
+      (br $fold)))
+  (i64.store (i32.const 280) (local.get $sum))
+  (call $commit (i32.const 256) (i32.const 32)))
@@ -0,0 +1,29 @@
+;; Shared by all the test actors: the syscalls they use, their memory, and a helper to persist
+;; their results as their new state.
+;;
+;; Memory layout: syscall return values at 0, the state CID at 16, and actor data from 256 on.
+
+(import "ipld" "create" (func $ipld_create (param i32 i64 i32 i32) (result i32)))
+(import "ipld" "cid" (func $ipld_cid (param i32 i32 i64 i32 i32 i32) (result i32)))
+(import "self" "set_root" (func $set_root (param i32) (result i32)))
+(import "gas" "charge" (func $gas_charge (param i32 i32 i64) (result i32)))
+(import "crypto" "hash_blake2b" (func $hash_blake2b (param i32 i32 i32) (result i32)))
+(import "rand" "get_chain_randomness"
+  (func $get_chain_randomness (param i32 i64 i64 i32 i32) (result i32)))
+(import "rand" "get_beacon_randomness"
+  (func $get_beacon_randomness (param i32 i64 i64 i32 i32) (result i32)))
+
+(memory (export "memory") 1)
+
+;; Stores the `len` bytes at `off` as a raw block, links it with Blake2b-256 and sets it as the
+;; actor's state root. Traps if any of the syscalls fails. Returns the "no data" block ID, so that
+;; `invoke` can return its result directly.
+(func $commit (param $off i32) (param $len i32) (result i32)
+  (if (call $ipld_create (i32.const 0) (i64.const 0x55) (local.get $off) (local.get $len))
+    (then unreachable))
+  (if (call $ipld_cid (i32.const 4) (i32.load (i32.const 0)) (i64.const 0xb220) (i32.const 32)
+        (i32.const 16) (i32.const 128))
+    (then unreachable))
+  (if (call $set_root (i32.const 16))
+    (then unreachable))
+  (i32.const 0))
@@ -0,0 +1,39 @@
+;; Sorting: fills an array of 2048 signed 32-bit values from a linear congruential generator,
+;; insertion-sorts it in place, and stores the sorted array.
+
+(func (export "invoke") (param i32) (result i32)
+  (local $i i32)
+  (local $j i32)
+  (local $x i32)
+  (local $v i32)
+  (local.set $x (i32.const 12345))
+  (block $done
+    (loop $fill
+      (br_if $done (i32.ge_u (local.get $i) (i32.const 2048)))
+      (local.set $x (i32.add (i32.mul (local.get $x) (i32.const 1103515245)) (i32.const 12345)))
+      (i32.store (i32.add (i32.const 4096) (i32.shl (local.get $i) (i32.const 2))) (local.get $x))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $fill)))
+
+  (local.set $i (i32.const 1))
+  (block $sorted
+    (loop $outer
+      (br_if $sorted (i32.ge_u (local.get $i) (i32.const 2048)))
+      (local.set $v (i32.load (i32.add (i32.const 4096) (i32.shl (local.get $i) (i32.const 2)))))
+      (local.set $j (local.get $i))
+      (block $placed
+        (loop $inner
+          (br_if $placed (i32.eqz (local.get $j)))
+          (br_if $placed
+            (i32.le_s
+              (i32.load (i32.add (i32.const 4092) (i32.shl (local.get $j) (i32.const 2))))
+              (local.get $v)))
+          (i32.store
+            (i32.add (i32.const 4096) (i32.shl (local.get $j) (i32.const 2)))
+            (i32.load (i32.add (i32.const 4092) (i32.shl (local.get $j) (i32.const 2)))))
+          (local.set $j (i32.sub (local.get $j) (i32.const 1)))
+          (br $inner)))
+      (i32.store (i32.add (i32.const 4096) (i32.shl (local.get $j) (i32.const 2))) (local.get $v))
+      (local.set $i (i32.add (local.get $i) (i32.const 1)))
+      (br $outer)))
+  (call $commit (i32.const 4096) (i32.const 8192)))
@@ -0,0 +1,11 @@
+;; Traps: sets a new state, then divides the most negative 64-bit integer by -1, which must trap
+;; (rather than wrap around) on every platform. The state change is reverted.
+
+(func (export "invoke") (param i32) (result i32)
+  (i64.store (i32.const 256) (i64.const 0x7eadbeef))
+  (i64.store (i32.const 264) (i64.const -1))
+  (drop (call $commit (i32.const 256) (i32.const 8)))
+  (i64.store
+    (i32.const 256)
+    (i64.div_s (i64.const -0x8000000000000000) (i64.load (i32.const 264))))
+  (i32.const 0))
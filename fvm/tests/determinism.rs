@@ -0,0 +1,398 @@
+//! Runs a set of numeric and memory-heavy test actors (see `actors/`) and checks that they leave
+//! exactly the same state behind and charge exactly the same gas however the wasm engine is
+//! configured, whether or not their code was already compiled.
+//!
+//! The results must also be identical across platforms. When `FVM_DETERMINISM_OUT` is set, they
+//! are written to that file, and the `determinism` CI job compares the files written on each of
+//! its targets.
+//!
+//! The actors only use integer instructions: NaN bit patterns differ between platforms unless
+//! NaN canonicalization is enabled in the engine.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+
+use cid::multihash::{Code, MultihashDigest};
+use cid::Cid;
+use fvm::call_manager::{CallManager, DefaultCallManager};
+use fvm::gas::{price_list_by_network_version, Gas, PriceList};
+use fvm::kernel::{self, ExecutionError};
+use fvm::machine::Engine;
+use fvm::state_tree::ActorState;
+use fvm::testing::MockMachine;
+use fvm::{DefaultKernel, EMPTY_ARR_CID};
+use fvm_shared::address::Address;
+use fvm_shared::blockstore::Blockstore;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::encoding::RawBytes;
+use fvm_shared::error::ExitCode;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, IPLD_RAW};
+use num_traits::Zero;
+use wasmtime::OptLevel;
+
+type TestKernel = DefaultKernel<DefaultCallManager<MockMachine>>;
+
+/// The imports, memory and helpers shared by all the test actors.
+const PRELUDE: &str = include_str!("actors/prelude.wat");
+
+/// The test actors, by name. All of them succeed, except for `trap`.
+const ACTORS: &[(&str, &str)] = &[
+    ("arith", include_str!("actors/arith.wat")),
+    ("memory", include_str!("actors/memory.wat")),
+    ("sort", include_str!("actors/sort.wat")),
+    ("trap", include_str!("actors/trap.wat")),
+];
+
+const SENDER: ActorID = 100;
+const RECEIVER: ActorID = 1000;
+
+/// What invoking a test actor resulted in.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    actor: &'static str,
+    exit_code: ExitCode,
+    gas_used: Gas,
+    state_root: Cid,
+}
+
+/// Invokes a test actor in a fresh machine using the given engine and prices.
+fn invoke(
+    engine: &Engine,
+    price_list: PriceList,
+    gas_limit: Gas,
+    actor: &'static str,
+    body: &str,
+) -> kernel::Result<Outcome> {
+    let wasm = wat::parse_str(format!("(module\n{}\n{}\n)", PRELUDE, body))
+        .unwrap_or_else(|e| panic!("failed to assemble {}: {}", actor, e));
+    let code = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(&wasm));
+
+    let mut machine = MockMachine::new(NetworkVersion::V15).unwrap();
+    machine.context.price_list = price_list;
+    machine.engine = engine.clone();
+    machine.engine.load_bytecode(&code, &wasm).unwrap();
+    machine.state_tree.store().put_keyed(&code, &wasm).unwrap();
+    let state = ActorState::new(code, *EMPTY_ARR_CID, TokenAmount::zero(), 0);
+    machine.state_tree.set_actor_id(RECEIVER, state).unwrap();
+
+    let mut cm = DefaultCallManager::new(machine, gas_limit, Address::new_id(SENDER), 0);
+    let res = cm.with_transaction(|cm| {
+        cm.send::<TestKernel>(
+            SENDER,
+            Address::new_id(RECEIVER),
+            2,
+            &RawBytes::default(),
+            &TokenAmount::zero(),
+        )
+    });
+    let (ret, mut machine) = cm.finish();
+
+    Ok(Outcome {
+        actor,
+        exit_code: res?.exit_code(),
+        gas_used: ret.gas_used,
+        state_root: machine.state_tree.flush().unwrap(),
+    })
+}
+
+/// Invokes every test actor in a fresh machine using the given engine.
+fn run_actors(engine: &Engine) -> Vec<Outcome> {
+    ACTORS
+        .iter()
+        .map(|&(actor, body)| {
+            let price_list = price_list_by_network_version(NetworkVersion::V16);
+            invoke(engine, price_list, Gas::new(10_000_000_000), actor, body)
+                .unwrap_or_else(|e| panic!("failed to invoke {}: {}", actor, e))
+        })
+        .collect()
+}
+
+#[test]
+fn deterministic_across_engine_configs() {
+    let engine = Engine::default();
+    let expected = run_actors(&engine);
+    for outcome in &expected {
+        assert_eq!(
+            outcome.exit_code.is_success(),
+            outcome.actor != "trap",
+            "unexpected result: {:?}",
+            outcome
+        );
+    }
+
+    // Once with the modules already compiled and instantiated.
+    assert_eq!(run_actors(&engine), expected, "cached modules");
+
+    let mut unoptimized = wasmtime::Config::default();
+    unoptimized.cranelift_opt_level(OptLevel::None);
+    let mut size_optimized = wasmtime::Config::default();
+    size_optimized.cranelift_opt_level(OptLevel::SpeedAndSize);
+    let mut sequential = wasmtime::Config::default();
+    sequential.parallel_compilation(false);
+    for (name, config) in [
+        ("unoptimized", unoptimized),
+        ("size optimized", size_optimized),
+        ("sequential compilation", sequential),
+    ] {
+        let engine = Engine::new(&config).unwrap();
+        assert_eq!(run_actors(&engine), expected, "{}", name);
+    }
+
+    if let Some(path) = env::var_os("FVM_DETERMINISM_OUT") {
+        let mut out = String::new();
+        for o in &expected {
+            writeln!(
+                out,
+                "{} {:?} {} {}",
+                o.actor, o.exit_code, o.gas_used, o.state_root
+            )
+            .unwrap();
+        }
+        fs::write(path, out).unwrap();
+    }
+}
+
+/// Returns the given prices, with the given price changed.
+fn set_price(prices: &PriceList, field: &str, price: i64) -> PriceList {
+    let mut prices = serde_json::to_value(prices).unwrap();
+    prices[field] = price.into();
+    serde_json::from_value(prices).unwrap()
+}
+
+/// Returns the given price.
+fn get_price(prices: &PriceList, field: &str) -> i64 {
+    serde_json::to_value(prices).unwrap()[field]
+        .as_i64()
+        .unwrap()
+}
+
+/// Returns the prices of network version 15, where actors only pay for their syscalls, with the
+/// given price changed.
+fn with_price(field: &str, price: i64) -> PriceList {
+    set_price(
+        &price_list_by_network_version(NetworkVersion::V15),
+        field,
+        price,
+    )
+}
+
+fn find_actor(name: &str) -> (&'static str, &'static str) {
+    *ACTORS.iter().find(|(actor, _)| *actor == name).unwrap()
+}
+
+#[test]
+fn wasm_execution_is_charged() {
+    let engine = Engine::default();
+    let priced = with_price("wasm_exec_per_fuel", 1);
+
+    let gas_limit = Gas::new(10_000_000_000);
+    let (actor, body) = find_actor("sort");
+    let free_prices = price_list_by_network_version(NetworkVersion::V15);
+    let free = invoke(&engine, free_prices, gas_limit, actor, body).unwrap();
+    let charged = invoke(&engine, priced.clone(), gas_limit, actor, body).unwrap();
+    assert!(charged.exit_code.is_success());
+    assert!(charged.gas_used > free.gas_used);
+    assert_eq!(charged.state_root, free.state_root);
+
+    // The actor runs out of gas midway.
+    let gas_limit = free.gas_used + (charged.gas_used - free.gas_used) / 2;
+    assert!(matches!(
+        invoke(&engine, priced, gas_limit, actor, body),
+        Err(ExecutionError::OutOfGas)
+    ));
+}
+
+#[test]
+fn table_growth_is_limited() {
+    // Growing past the default limit of 65536 elements fails, but growing up to it doesn't.
+    let body = r#"(table 1 funcref)
+    (func (export "invoke") (param i32) (result i32)
+      (if (i32.ne (table.grow (ref.null func) (i32.const 65536)) (i32.const -1))
+        (then unreachable))
+      (if (i32.ne (table.grow (ref.null func) (i32.const 65535)) (i32.const 1))
+        (then unreachable))
+      (i32.const 0))"#;
+
+    let engine = Engine::default();
+    let prices = price_list_by_network_version(NetworkVersion::V15);
+    let outcome = invoke(&engine, prices, Gas::new(10_000_000_000), "table", body).unwrap();
+    assert!(outcome.exit_code.is_success());
+}
+
+#[test]
+fn looping_actor_runs_out_of_gas() {
+    let body = r#"(func (export "invoke") (param i32) (result i32)
+      (loop $forever (br $forever))
+      (i32.const 0))"#;
+
+    let engine = Engine::default();
+    let prices = price_list_by_network_version(NetworkVersion::V16);
+    assert!(get_price(&prices, "wasm_exec_per_fuel") > 0);
+    assert!(matches!(
+        invoke(&engine, prices, Gas::new(10_000_000), "loop", body),
+        Err(ExecutionError::OutOfGas)
+    ));
+}
+
+#[test]
+fn code_loading_is_charged() {
+    let engine = Engine::default();
+    let priced = with_price("code_load_per_byte", 1);
+
+    let gas_limit = Gas::new(10_000_000_000);
+    let (actor, body) = find_actor("arith");
+    let code_size = wat::parse_str(format!("(module\n{}\n{}\n)", PRELUDE, body))
+        .unwrap()
+        .len();
+    let free_prices = price_list_by_network_version(NetworkVersion::V15);
+    let free = invoke(&engine, free_prices, gas_limit, actor, body).unwrap();
+    // The code is already compiled, but charged for all the same.
+    let charged = invoke(&engine, priced, gas_limit, actor, body).unwrap();
+    assert!(charged.exit_code.is_success());
+    assert_eq!(charged.gas_used, free.gas_used + Gas::new(code_size as i64));
+    assert_eq!(charged.state_root, free.state_root);
+}
+
+#[test]
+fn code_loading_is_priced() {
+    let engine = Engine::default();
+    let prices = price_list_by_network_version(NetworkVersion::V16);
+    let per_byte = get_price(&prices, "code_load_per_byte");
+    assert!(per_byte > 0);
+    assert_eq!(prices.on_code_load(usize::MAX).total(), Gas::new(i64::MAX));
+
+    let gas_limit = Gas::new(10_000_000_000);
+    let (actor, body) = find_actor("arith");
+    let code_size = wat::parse_str(format!("(module\n{}\n{}\n)", PRELUDE, body))
+        .unwrap()
+        .len() as i64;
+    let free_prices = set_price(&prices, "code_load_per_byte", 0);
+    let free = invoke(&engine, free_prices, gas_limit, actor, body).unwrap();
+    let charged = invoke(&engine, prices, gas_limit, actor, body).unwrap();
+    assert!(charged.exit_code.is_success());
+    assert_eq!(
+        charged.gas_used,
+        free.gas_used + Gas::new(code_size * per_byte)
+    );
+}
+
+#[test]
+fn memory_growth_is_charged() {
+    let engine = Engine::default();
+    let priced = with_price("memory_grow_per_page", 1000);
+
+    let gas_limit = Gas::new(10_000_000_000);
+    let (actor, body) = find_actor("memory");
+    let free_prices = price_list_by_network_version(NetworkVersion::V15);
+    let free = invoke(&engine, free_prices, gas_limit, actor, body).unwrap();
+    let charged = invoke(&engine, priced.clone(), gas_limit, actor, body).unwrap();
+    assert!(charged.exit_code.is_success());
+    // Only the 15 pages actually grown are charged for, not the grow past the page limit.
+    assert_eq!(charged.gas_used, free.gas_used + Gas::new(15 * 1000));
+    assert_eq!(charged.state_root, free.state_root);
+
+    // The actor can't pay for the memory it needs.
+    let gas_limit = free.gas_used + Gas::new(14 * 1000);
+    assert!(matches!(
+        invoke(&engine, priced, gas_limit, actor, body),
+        Err(ExecutionError::OutOfGas)
+    ));
+}
+
+#[test]
+fn memory_growth_is_priced() {
+    let engine = Engine::default();
+    let prices = price_list_by_network_version(NetworkVersion::V16);
+    let per_page = get_price(&prices, "memory_grow_per_page");
+    assert!(per_page > 0);
+
+    let gas_limit = Gas::new(10_000_000_000);
+    let (actor, body) = find_actor("memory");
+    let free_prices = set_price(&prices, "memory_grow_per_page", 0);
+    let free = invoke(&engine, free_prices, gas_limit, actor, body).unwrap();
+    let charged = invoke(&engine, prices, gas_limit, actor, body).unwrap();
+    assert!(charged.exit_code.is_success());
+    assert_eq!(charged.gas_used, free.gas_used + Gas::new(15 * per_page));
+}
+
+/// An actor charging the given amount of gas under the name "a", then committing the name as its
+/// state.
+fn charging_actor(amount: u64) -> String {
+    format!(
+        r#"(func (export "invoke") (param i32) (result i32)
+          (i32.store8 (i32.const 256) (i32.const 0x61))
+          (if (call $gas_charge (i32.const 256) (i32.const 1) (i64.const {}))
+            (then unreachable))
+          (call $commit (i32.const 256) (i32.const 1)))"#,
+        amount
+    )
+}
+
+#[test]
+fn actor_charges_add_to_execution() {
+    let engine = Engine::default();
+    let priced = with_price("wasm_exec_per_fuel", 1);
+    let gas_limit = Gas::new(10_000_000_000);
+
+    // Both actors execute the same instructions, so they consume the same fuel.
+    let (free_body, body) = (charging_actor(0), charging_actor(12345));
+    let free = invoke(&engine, priced.clone(), gas_limit, "charge", &free_body).unwrap();
+    let charged = invoke(&engine, priced.clone(), gas_limit, "charge", &body).unwrap();
+    assert!(charged.exit_code.is_success());
+    assert_eq!(charged.gas_used, free.gas_used + Gas::new(12345));
+    assert_eq!(charged.state_root, free.state_root);
+
+    // The actor can't pay for its own charge.
+    let gas_limit = free.gas_used + Gas::new(12344);
+    assert!(matches!(
+        invoke(&engine, priced, gas_limit, "charge", &body),
+        Err(ExecutionError::OutOfGas)
+    ));
+}
+
+#[test]
+fn randomness_syscalls_are_bound() {
+    // Draws randomness from both sources with 4 bytes of entropy, and checks that unknown domain
+    // separation tags are rejected as illegal arguments (error number 1).
+    let body = r#"(func (export "invoke") (param i32) (result i32)
+      (i32.store (i32.const 512) (i32.const 0xdeadbeef))
+      (if (call $get_chain_randomness
+            (i32.const 256) (i64.const 2) (i64.const 0) (i32.const 512) (i32.const 4))
+        (then unreachable))
+      (if (call $get_beacon_randomness
+            (i32.const 288) (i64.const 2) (i64.const 0) (i32.const 512) (i32.const 4))
+        (then unreachable))
+      (if (i32.ne
+            (call $get_chain_randomness
+              (i32.const 256) (i64.const -1) (i64.const 0) (i32.const 512) (i32.const 4))
+            (i32.const 1))
+        (then unreachable))
+      (call $commit (i32.const 256) (i32.const 64)))"#;
+
+    let engine = Engine::default();
+    let price_list = price_list_by_network_version(NetworkVersion::V15);
+    let outcome = invoke(&engine, price_list, Gas::new(10_000_000_000), "rand", body).unwrap();
+    assert!(outcome.exit_code.is_success());
+}
+
+#[test]
+fn hashing_is_charged_per_byte() {
+    // Hashes 100 bytes of memory and commits the digest.
+    let body = r#"(func (export "invoke") (param i32) (result i32)
+      (if (call $hash_blake2b (i32.const 256) (i32.const 512) (i32.const 100))
+        (then unreachable))
+      (call $commit (i32.const 256) (i32.const 32)))"#;
+
+    let engine = Engine::default();
+    let gas_limit = Gas::new(10_000_000_000);
+    let run = |price| {
+        let price_list = with_price("hashing_per_byte", price);
+        invoke(&engine, price_list, gas_limit, "hash", body).unwrap()
+    };
+    let (free, charged) = (run(0), run(7));
+    assert!(charged.exit_code.is_success());
+    assert_eq!(charged.gas_used, free.gas_used + Gas::new(700));
+    assert_eq!(charged.state_root, free.state_root);
+}
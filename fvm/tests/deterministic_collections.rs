@@ -0,0 +1,87 @@
+//! Checks that consensus-critical code doesn't use randomly seeded hash containers, whose
+//! iteration order differs between nodes. Ordered containers or the fixed hash containers from
+//! `fvm_shared::collections` should be used instead.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The consensus-critical sources, relative to the crate's `src` directory.
+const CHECKED: &[&str] = &[
+    "call_manager",
+    "executor",
+    "gas",
+    "kernel",
+    "syscalls",
+    "state_tree.rs",
+];
+
+/// The randomly seeded containers and hashers (from `std` and `ahash`).
+const RANDOMLY_SEEDED: &[&str] = &[
+    "HashMap",
+    "HashSet",
+    "RandomState",
+    "AHashMap",
+    "AHashSet",
+    "AHasher",
+    "ahash",
+];
+
+/// Returns true if the line refers to a randomly seeded container or hasher, by name: the names
+/// are matched wherever they're used, not only in imports (which may span several lines).
+/// Comments are ignored.
+fn is_randomly_seeded(line: &str) -> bool {
+    let code = line.split("//").next().unwrap_or_default();
+    code.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .any(|name| RANDOMLY_SEEDED.contains(&name))
+}
+
+fn sources(path: &Path, out: &mut Vec<PathBuf>) {
+    if path.is_dir() {
+        for entry in fs::read_dir(path).unwrap() {
+            sources(&entry.unwrap().path(), out);
+        }
+    } else if path.extension().map_or(false, |ext| ext == "rs") {
+        out.push(path.to_owned());
+    }
+}
+
+#[test]
+fn detects_randomly_seeded_containers() {
+    assert!(is_randomly_seeded("use std::collections::HashMap;"));
+    assert!(is_randomly_seeded("    HashSet,"));
+    assert!(is_randomly_seeded(
+        "    let seen: HashMap<Cid, usize> = Default::default();"
+    ));
+    assert!(is_randomly_seeded("use ahash::AHashMap;"));
+    assert!(!is_randomly_seeded(
+        "    let seen = FixedHashMap::default();"
+    ));
+    assert!(!is_randomly_seeded(
+        "/// Unlike a `HashMap`, iterates in order."
+    ));
+}
+
+#[test]
+fn no_randomly_seeded_containers() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let mut files = Vec::new();
+    for path in CHECKED {
+        sources(&src.join(path), &mut files);
+    }
+    assert!(!files.is_empty());
+
+    let mut violations = Vec::new();
+    for file in files {
+        let text = fs::read_to_string(&file).unwrap();
+        for (i, line) in text.lines().enumerate() {
+            if is_randomly_seeded(line) {
+                violations.push(format!("{}:{}: {}", file.display(), i + 1, line.trim()));
+            }
+        }
+    }
+    assert!(
+        violations.is_empty(),
+        "randomly seeded containers in consensus-critical code:\n{}",
+        violations.join("\n")
+    );
+}
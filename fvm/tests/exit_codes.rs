@@ -0,0 +1,339 @@
+//! Applies messages failing at every site of the pre-validation and settlement paths of the
+//! [`DefaultExecutor`], as both explicit and implicit messages, under every supported network
+//! version, and checks the exact exit code and miner penalty of each.
+//!
+//! These are chain-visible: any change in the expectations below is a consensus change.
+//!
+//! The tests are generated by `matrix!`, one per (site, apply kind, network version).
+
+use fvm::call_manager::DefaultCallManager;
+use fvm::executor::{ApplyFailure, ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use fvm::gas::PriceList;
+use fvm::state_tree::ActorState;
+use fvm::testing::{cbor_blake2b_cid, MockMachine};
+use fvm::{DefaultKernel, EMPTY_ARR_CID};
+use fvm_shared::actor::builtin::Type;
+use fvm_shared::address::Address;
+use fvm_shared::bigint::BigInt;
+use fvm_shared::constants::{BURNT_FUNDS_ACTOR_ADDR, REWARD_ACTOR_ADDR};
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::error::ExitCode;
+use fvm_shared::message::Message;
+use fvm_shared::version::NetworkVersion;
+use fvm_shared::{ActorID, METHOD_SEND};
+use num_traits::Zero;
+
+type TestKernel = DefaultKernel<DefaultCallManager<MockMachine>>;
+
+const SENDER: ActorID = 100;
+const RECEIVER: ActorID = 101;
+/// An ID no actor lives at.
+const MISSING: ActorID = 1000;
+
+const BASE_FEE: u64 = 100;
+const FEE_CAP: u64 = 200;
+const PREMIUM: u64 = 10;
+const BALANCE: u64 = 1_000_000_000_000;
+const RAW_LENGTH: usize = 100;
+
+/// Where a message fails, if anywhere.
+#[derive(Clone, Copy, Debug)]
+enum Site {
+    /// The gas limit doesn't cover the inclusion cost.
+    InclusionOutOfGas,
+    /// The message version isn't valid at the network version.
+    UnsupportedVersion,
+    /// The sender doesn't exist.
+    UnknownSender,
+    /// The sender isn't an account actor.
+    SenderNotAccount,
+    /// The message sequence doesn't match the sender's.
+    BadNonce,
+    /// The sender can't cover `gas_fee_cap * gas_limit`.
+    InsufficientBalanceForGas,
+    /// The gas limit covers the inclusion cost, but not the invocation.
+    ExecutionOutOfGas,
+    /// The receiver doesn't exist.
+    InvalidReceiver,
+    /// The sender can't cover the value sent.
+    InsufficientFundsForValue,
+    /// The fee cap is below the base fee: the message succeeds, but the miner is penalized.
+    FeeCapBelowBaseFee,
+    /// Nothing fails.
+    Success,
+}
+
+/// The expected outcome of applying a message.
+#[derive(Debug, PartialEq)]
+struct Outcome {
+    exit_code: ExitCode,
+    penalty: TokenAmount,
+    prevalidation: bool,
+}
+
+impl Outcome {
+    fn prevalidation(exit_code: ExitCode, penalty: TokenAmount) -> Self {
+        Outcome {
+            exit_code,
+            penalty,
+            prevalidation: true,
+        }
+    }
+
+    fn applied(exit_code: ExitCode, penalty: TokenAmount) -> Self {
+        Outcome {
+            exit_code,
+            penalty,
+            prevalidation: false,
+        }
+    }
+
+    fn of(ret: &ApplyRet) -> Self {
+        Outcome {
+            exit_code: ret.msg_receipt.exit_code,
+            penalty: ret.penalty.clone(),
+            prevalidation: matches!(ret.failure_info, Some(ApplyFailure::PreValidation(_))),
+        }
+    }
+}
+
+fn inclusion_gas(pl: &PriceList) -> i64 {
+    pl.on_chain_message(RAW_LENGTH).total().as_i64()
+}
+
+/// The gas used by a message that succeeds: its inclusion and the invocation of its receiver.
+fn full_gas(pl: &PriceList, msg: &Message) -> i64 {
+    inclusion_gas(pl)
+        + pl.on_method_invocation(&msg.value, msg.method_num)
+            .total()
+            .as_i64()
+}
+
+/// Builds a machine holding the sender, the receiver and the actors fees are paid to, and a
+/// message failing at the given site.
+fn setup(site: Site, nv: NetworkVersion) -> (MockMachine, Message) {
+    let mut machine = MockMachine::new(nv).unwrap();
+    machine.context.base_fee = BASE_FEE.into();
+
+    let account = cbor_blake2b_cid(b"account");
+    let multisig = cbor_blake2b_cid(b"multisig");
+    machine.builtin_actors.insert(account, Type::Account);
+    machine.builtin_actors.insert(multisig, Type::Multisig);
+
+    let mut msg = Message {
+        version: 0,
+        from: Address::new_id(SENDER),
+        to: Address::new_id(RECEIVER),
+        sequence: 0,
+        value: TokenAmount::zero(),
+        method_num: METHOD_SEND,
+        params: Default::default(),
+        gas_limit: 0,
+        gas_fee_cap: FEE_CAP.into(),
+        gas_premium: PREMIUM.into(),
+    };
+    let mut sender_code = account;
+    let mut sender_balance = TokenAmount::from(BALANCE);
+
+    let pl = machine.context.price_list.clone();
+    match site {
+        Site::UnsupportedVersion => msg.version = 1,
+        Site::UnknownSender => msg.from = Address::new_id(MISSING),
+        Site::SenderNotAccount => sender_code = multisig,
+        Site::BadNonce => msg.sequence = 1,
+        Site::InvalidReceiver => msg.to = Address::new_id(MISSING),
+        Site::InsufficientFundsForValue => msg.value = sender_balance.clone() + 1,
+        Site::FeeCapBelowBaseFee => msg.gas_fee_cap = (BASE_FEE / 2).into(),
+        _ => {}
+    }
+    msg.gas_limit = full_gas(&pl, &msg);
+    match site {
+        Site::InclusionOutOfGas => msg.gas_limit = inclusion_gas(&pl) - 1,
+        Site::ExecutionOutOfGas => msg.gas_limit = inclusion_gas(&pl),
+        Site::InsufficientBalanceForGas => {
+            sender_balance = &msg.gas_fee_cap * msg.gas_limit - 1;
+        }
+        _ => {}
+    }
+
+    for (id, code, balance) in [
+        (SENDER, sender_code, sender_balance),
+        (RECEIVER, account, TokenAmount::zero()),
+        (
+            BURNT_FUNDS_ACTOR_ADDR.id().unwrap(),
+            account,
+            TokenAmount::zero(),
+        ),
+        (
+            REWARD_ACTOR_ADDR.id().unwrap(),
+            account,
+            TokenAmount::zero(),
+        ),
+    ] {
+        let state = ActorState::new(code, *EMPTY_ARR_CID, balance, 0);
+        machine.state_tree.set_actor_id(id, state).unwrap();
+    }
+    (machine, msg)
+}
+
+/// What applying a message failing at the given site must result in.
+fn expected(site: Site, kind: ApplyKind, pl: &PriceList, msg: &Message) -> Outcome {
+    let base_fee = TokenAmount::from(BASE_FEE);
+    // Explicit messages failing pre-validation penalize the miner for the whole gas limit, as
+    // they shouldn't have been included; implicit messages never penalize anyone.
+    let gas_limit_penalty = match kind {
+        ApplyKind::Explicit => &base_fee * msg.gas_limit,
+        ApplyKind::Implicit => TokenAmount::zero(),
+    };
+    match (site, kind) {
+        (Site::InclusionOutOfGas, ApplyKind::Explicit) => Outcome::prevalidation(
+            ExitCode::SysErrOutOfGas,
+            &base_fee * BigInt::from(inclusion_gas(pl)),
+        ),
+        (Site::UnsupportedVersion | Site::UnknownSender, _) => {
+            Outcome::prevalidation(ExitCode::SysErrSenderInvalid, gas_limit_penalty)
+        }
+        (Site::SenderNotAccount, ApplyKind::Explicit) => {
+            Outcome::prevalidation(ExitCode::SysErrSenderInvalid, gas_limit_penalty)
+        }
+        (Site::BadNonce | Site::InsufficientBalanceForGas, ApplyKind::Explicit) => {
+            Outcome::prevalidation(ExitCode::SysErrSenderStateInvalid, gas_limit_penalty)
+        }
+        (Site::ExecutionOutOfGas, ApplyKind::Explicit) => {
+            Outcome::applied(ExitCode::SysErrOutOfGas, TokenAmount::zero())
+        }
+        (Site::InvalidReceiver, _) => {
+            Outcome::applied(ExitCode::SysErrInvalidReceiver, TokenAmount::zero())
+        }
+        (Site::InsufficientFundsForValue, _) => {
+            Outcome::applied(ExitCode::SysErrInsufficientFunds, TokenAmount::zero())
+        }
+        // The gas limit is exactly the gas used, so nothing is burnt for over-estimation, and
+        // the miner pays the difference between the base fee and the fee cap on all of it.
+        (Site::FeeCapBelowBaseFee, ApplyKind::Explicit) => {
+            Outcome::applied(ExitCode::Ok, (&base_fee - &msg.gas_fee_cap) * msg.gas_limit)
+        }
+        // Implicit messages skip the sender checks and aren't charged for inclusion, nor for gas.
+        _ => Outcome::applied(ExitCode::Ok, TokenAmount::zero()),
+    }
+}
+
+fn check(site: Site, kind: ApplyKind, nv: NetworkVersion) {
+    let (machine, msg) = setup(site, nv);
+    let want = expected(site, kind, &machine.context.price_list, &msg);
+
+    let mut executor = DefaultExecutor::<TestKernel>::new(machine);
+    let ret = executor
+        .execute_message(msg, kind, RAW_LENGTH)
+        .unwrap_or_else(|e| panic!("{:?} failed to apply: {:#}", site, e));
+    assert_eq!(Outcome::of(&ret), want, "{:?}", ret.failure_info);
+    if want.prevalidation {
+        assert_eq!(ret.msg_receipt.gas_used, 0);
+        assert!(ret.miner_tip.is_zero());
+    }
+}
+
+/// Generates a module per site, with a test per apply kind and network version.
+macro_rules! matrix {
+    ($($site:ident),* $(,)?) => {
+        $(
+            #[allow(non_snake_case)]
+            mod $site {
+                use super::*;
+
+                #[test]
+                fn explicit_v14() {
+                    check(Site::$site, ApplyKind::Explicit, NetworkVersion::V14);
+                }
+
+                #[test]
+                fn explicit_v15() {
+                    check(Site::$site, ApplyKind::Explicit, NetworkVersion::V15);
+                }
+
+                #[test]
+                fn implicit_v14() {
+                    check(Site::$site, ApplyKind::Implicit, NetworkVersion::V14);
+                }
+
+                #[test]
+                fn implicit_v15() {
+                    check(Site::$site, ApplyKind::Implicit, NetworkVersion::V15);
+                }
+            }
+        )*
+    };
+}
+
+matrix!(
+    InclusionOutOfGas,
+    UnsupportedVersion,
+    UnknownSender,
+    SenderNotAccount,
+    BadNonce,
+    InsufficientBalanceForGas,
+    ExecutionOutOfGas,
+    InvalidReceiver,
+    InsufficientFundsForValue,
+    FeeCapBelowBaseFee,
+    Success,
+);
+
+/// The executor keeps its machine across messages: the messages of a tipset are applied one after
+/// the other, each on the state left by the previous ones.
+#[test]
+fn messages_share_the_machine() {
+    let (machine, msg) = setup(Site::Success, NetworkVersion::V15);
+    let mut executor = DefaultExecutor::<TestKernel>::new(machine);
+    for sequence in 0..3 {
+        let msg = Message {
+            sequence,
+            ..msg.clone()
+        };
+        let ret = executor
+            .execute_message(msg, ApplyKind::Explicit, RAW_LENGTH)
+            .unwrap();
+        assert_eq!(ret.msg_receipt.exit_code, ExitCode::Ok);
+    }
+    let sender = executor.state_tree.get_actor_id(SENDER).unwrap().unwrap();
+    assert_eq!(sender.sequence, 3);
+
+    // The first message's nonce has been used.
+    let ret = executor
+        .execute_message(msg, ApplyKind::Explicit, RAW_LENGTH)
+        .unwrap();
+    assert_eq!(
+        ret.msg_receipt.exit_code,
+        ExitCode::SysErrSenderStateInvalid
+    );
+}
+
+/// Implicit messages (cron, rewards) conventionally leave their gas limit unset: they run without
+/// one, and report the gas they used without being charged for it.
+#[test]
+fn implicit_messages_without_gas_limit() {
+    let (machine, msg) = setup(Site::Success, NetworkVersion::V15);
+    let msg = Message {
+        gas_limit: 0,
+        ..msg
+    };
+    let gas_used =
+        full_gas(&machine.context.price_list, &msg) - inclusion_gas(&machine.context.price_list);
+
+    let mut executor = DefaultExecutor::<TestKernel>::new(machine);
+    let ret = executor
+        .execute_message(msg.clone(), ApplyKind::Implicit, RAW_LENGTH)
+        .unwrap();
+    assert_eq!(ret.msg_receipt.exit_code, ExitCode::Ok);
+    assert_eq!(ret.msg_receipt.gas_used, gas_used);
+    assert!(ret.penalty.is_zero());
+    assert!(ret.miner_tip.is_zero());
+
+    let sender = executor.state_tree.get_actor_id(SENDER).unwrap().unwrap();
+    assert_eq!(sender.balance, TokenAmount::from(BALANCE));
+
+    // Explicit messages must still set a gas limit.
+    assert!(executor
+        .execute_message(msg, ApplyKind::Explicit, RAW_LENGTH)
+        .is_err());
+}
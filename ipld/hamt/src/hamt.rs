@@ -310,7 +310,20 @@ where
         V: DeserializeOwned,
         F: FnMut(&K, &V) -> anyhow::Result<()>,
     {
-        self.root.for_each(self.store.borrow(), &mut f)
+        self.root.for_each(self.store.borrow(), true, &mut f)
+    }
+
+    /// Iterates over each KV in the Hamt like [`Hamt::for_each`], but without caching the nodes
+    /// it loads from the store: only the nodes on the path to the current KV are kept in memory.
+    /// Meant for a single pass over a HAMT too large to load whole; nodes that are already cached
+    /// are still used.
+    #[inline]
+    pub fn for_each_uncached<F>(&self, mut f: F) -> Result<(), Error>
+    where
+        V: DeserializeOwned,
+        F: FnMut(&K, &V) -> anyhow::Result<()>,
+    {
+        self.root.for_each(self.store.borrow(), false, &mut f)
     }
 
     /// Consumes this HAMT and returns the Blockstore it owns.
@@ -134,7 +134,14 @@ where
         self.pointers.is_empty()
     }
 
-    pub(crate) fn for_each<S, F>(&self, store: &S, f: &mut F) -> Result<(), Error>
+    /// Calls `f` on every entry under this node. Nodes loaded from the store are cached if
+    /// `cache_nodes` is set, and dropped once iterated over otherwise.
+    pub(crate) fn for_each<S, F>(
+        &self,
+        store: &S,
+        cache_nodes: bool,
+        f: &mut F,
+    ) -> Result<(), Error>
     where
         F: FnMut(&K, &V) -> anyhow::Result<()>,
         S: Blockstore,
@@ -143,7 +150,7 @@ where
             match p {
                 Pointer::Link { cid, cache } => {
                     if let Some(cached_node) = cache.get() {
-                        cached_node.for_each(store, f)?
+                        cached_node.for_each(store, cache_nodes, f)?
                     } else {
                         let node = if let Some(node) = store.get_cbor(cid)? {
                             node
@@ -155,12 +162,16 @@ where
                             continue;
                         };
 
-                        // Ignore error intentionally, the cache value will always be the same
-                        let cache_node = cache.get_or_init(|| node);
-                        cache_node.for_each(store, f)?
+                        if cache_nodes {
+                            // Ignore error intentionally, the cache value will always be the same
+                            let cache_node = cache.get_or_init(|| node);
+                            cache_node.for_each(store, cache_nodes, f)?
+                        } else {
+                            node.for_each(store, cache_nodes, f)?
+                        }
                     }
                 }
-                Pointer::Dirty(n) => n.for_each(store, f)?,
+                Pointer::Dirty(n) => n.for_each(store, cache_nodes, f)?,
                 Pointer::Values(kvs) => {
                     for kv in kvs {
                         f(kv.0.borrow(), kv.1.borrow())?;
@@ -315,6 +315,44 @@ fn for_each() {
     assert_eq!(*store.stats.borrow(), BSStats {r: 30, w: 31, br: 3209, bw: 4529});
 }
 
+#[test]
+fn for_each_uncached() {
+    let mem = MemoryBlockstore::default();
+    let store = TrackingBlockstore::new(&mem);
+
+    let mut hamt: Hamt<_, BytesKey> = Hamt::new_with_bit_width(&store, 5);
+    for i in 0..200 {
+        hamt.set(tstring(i), tstring(i)).unwrap();
+    }
+    let c = hamt.flush().unwrap();
+
+    let hamt: Hamt<_, BytesKey> = Hamt::load_with_bit_width(&c, &store, 5).unwrap();
+    let reads = || store.stats.borrow().r;
+    let iterate = |uncached: bool| {
+        let before = reads();
+        let mut count = 0;
+        let f = |k: &BytesKey, v: &BytesKey| {
+            assert_eq!(k, v);
+            count += 1;
+            Ok(())
+        };
+        if uncached {
+            hamt.for_each_uncached(f).unwrap();
+        } else {
+            hamt.for_each(f).unwrap();
+        }
+        (count, reads() - before)
+    };
+
+    // Every pass loads the nodes again, until they're cached.
+    let (entries, node_reads) = iterate(true);
+    assert_eq!(entries, 200);
+    assert!(node_reads > 0);
+    assert_eq!(iterate(true), (200, node_reads));
+    assert_eq!(iterate(false), (200, node_reads));
+    assert_eq!(iterate(true), (200, 0));
+}
+
 #[cfg(feature = "identity")]
 fn add_and_remove_keys(
     bit_width: u32,
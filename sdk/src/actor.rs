@@ -3,6 +3,8 @@ use core::option::Option; // no_std
 use cid::Cid;
 use fvm_shared::actor::builtin::Type;
 use fvm_shared::address::{Address, Payload};
+use fvm_shared::encoding::to_vec;
+use fvm_shared::sys::out::actor::RESOLVED_ADDRESS_LEN;
 use fvm_shared::{actor, ActorID};
 use num_traits::FromPrimitive;
 
@@ -28,7 +30,32 @@ pub fn resolve_address(addr: &Address) -> Option<ActorID> {
     }
 }
 
-/// Look up the code ID at an actor address. Returns `None` if the actor cannot be found.
+/// Resolves the ID addresses of a batch of actors, like [`resolve_address`] but in a single
+/// syscall.
+pub fn resolve_addresses(addrs: &[Address]) -> Vec<Option<ActorID>> {
+    let encoded = to_vec(addrs).expect("failed to marshal addresses");
+    let mut buf = vec![0u8; addrs.len() * RESOLVED_ADDRESS_LEN];
+    unsafe {
+        sys::actor::resolve_addresses(
+            encoded.as_ptr(),
+            encoded.len() as u32,
+            buf.as_mut_ptr(),
+            buf.len() as u32,
+        )
+        // Can only happen due to memory corruption.
+        .expect("error when resolving addresses");
+    }
+    buf.chunks_exact(RESOLVED_ADDRESS_LEN)
+        .map(|entry| {
+            let mut id = [0u8; 8];
+            id.copy_from_slice(&entry[1..]);
+            (entry[0] == 1).then(|| ActorID::from_le_bytes(id))
+        })
+        .collect()
+}
+
+/// Look up the code ID at an actor address, which may be of any protocol. Returns `None` if the
+/// address cannot be resolved, or the actor cannot be found.
 pub fn get_actor_code_cid(addr: &Address) -> Option<Cid> {
     let bytes = addr.to_bytes();
     let mut buf = [0u8; MAX_CID_LEN];
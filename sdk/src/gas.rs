@@ -1,8 +1,28 @@
-use crate::sys;
+use fvm_shared::error::ErrorNumber;
+pub use fvm_shared::sys::{MAX_GAS_CHARGE, MAX_GAS_CHARGE_NAME_LEN};
+
+use crate::{sys, SyscallResult};
 
 /// Charge gas for the operation identified by name.
 pub fn charge(name: &str, compute: u64) {
-    unsafe { sys::gas::charge(name.as_ptr(), name.len() as u32, compute) }
-        // can only happen if name isn't utf8, memory corruption, etc.
+    try_charge(name, compute)
+        // can only happen if the name is too long, the charge too large, memory corruption, etc.
         .expect("failed to charge gas")
 }
+
+/// Charge gas for the operation identified by name, failing with `IllegalArgument` instead of
+/// aborting if the name is longer than [`MAX_GAS_CHARGE_NAME_LEN`] or the charge is larger than
+/// [`MAX_GAS_CHARGE`]. Running out of gas still aborts the actor.
+pub fn try_charge(name: &str, compute: u64) -> SyscallResult<()> {
+    if name.len() > MAX_GAS_CHARGE_NAME_LEN as usize || compute > MAX_GAS_CHARGE {
+        return Err(ErrorNumber::IllegalArgument);
+    }
+    unsafe { sys::gas::charge(name.as_ptr(), name.len() as u32, compute) }
+}
+
+/// Charges gas for a metered operation (e.g., a library call whose cost has been agreed upon
+/// off-chain) before running it.
+pub fn metered<T>(name: &str, compute: u64, f: impl FnOnce() -> T) -> T {
+    charge(name, compute);
+    f()
+}
@@ -66,3 +66,52 @@ pub fn put_block(
 ) -> SyscallResult<fvm_shared::sys::BlockId> {
     unsafe { sys::ipld::create(codec, data.as_ptr(), data.len() as u32) }
 }
+
+/// A block registered with the FVM, released when the handle is dropped instead of being kept
+/// around until the end of the invocation.
+pub struct BlockHandle {
+    id: fvm_shared::sys::BlockId,
+    size: u32,
+}
+
+impl BlockHandle {
+    /// Opens a block. The same rules as for [`get`] apply.
+    pub fn open(cid: &Cid) -> SyscallResult<Self> {
+        let mut cid_buf = [0u8; MAX_CID_LEN];
+        cid.write_bytes(&mut cid_buf[..])
+            .expect("CID encoding should not fail");
+        let fvm_shared::sys::out::ipld::IpldOpen { id, size, .. } =
+            unsafe { sys::ipld::open(cid_buf.as_mut_ptr())? };
+        Ok(Self { id, size })
+    }
+
+    /// Writes a new block.
+    pub fn create(codec: fvm_shared::sys::Codec, data: &[u8]) -> SyscallResult<Self> {
+        let id = put_block(codec, data)?;
+        Ok(Self {
+            id,
+            size: data.len() as u32,
+        })
+    }
+
+    /// Returns the ID of the block, valid for as long as the handle is alive.
+    pub fn id(&self) -> fvm_shared::sys::BlockId {
+        self.id
+    }
+
+    /// Returns the size of the block.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Reads the block's data.
+    pub fn data(&self) -> SyscallResult<Vec<u8>> {
+        get_block(self.id, Some(self.size))
+    }
+}
+
+impl Drop for BlockHandle {
+    fn drop(&mut self) {
+        unsafe { sys::ipld::drop(self.id) }.expect("failed to release block")
+    }
+}
@@ -6,6 +6,7 @@ pub mod gas;
 pub mod ipld;
 pub mod message;
 pub mod network;
+pub mod precompile;
 pub mod rand;
 pub mod send;
 pub mod sself;
@@ -26,8 +27,10 @@ pub(crate) fn status_code_to_bool(code: i32) -> bool {
 }
 
 /// SDK functions performing a syscall return a SyscallResult type, where the
-/// Error type is an ExitCode. ExitCode::Ok is translated to an Ok result, while
-/// error codes are propagated as Err(ExitCode).
+/// Error type is the ErrorNumber the syscall failed with. Syscall errors are
+/// always recoverable: the actor decides whether to handle them or to abort.
+/// Errors the actor can't recover from (running out of gas, fatal errors in the
+/// FVM) never return to the actor: they abort it directly.
 ///
 /// Error messages don't make it across the boundary, but are logged at the FVM
 /// level for debugging and informational purposes.
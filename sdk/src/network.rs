@@ -1,10 +1,11 @@
 use std::convert::TryInto;
 
+use cid::Cid;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::version::NetworkVersion;
 
-use crate::sys;
+use crate::{sys, SyscallResult, MAX_CID_LEN};
 
 pub fn curr_epoch() -> ChainEpoch {
     unsafe {
@@ -38,3 +39,16 @@ pub fn total_fil_circ_supply() -> TokenAmount {
             .into()
     }
 }
+
+/// Returns the CID of the tipset at the given epoch. The epoch must be before the current one, and
+/// within the last [`TIPSET_CID_LOOKBACK`](fvm_shared::clock::TIPSET_CID_LOOKBACK) epochs.
+pub fn tipset_cid(epoch: ChainEpoch) -> SyscallResult<Cid> {
+    let mut buf = [0u8; MAX_CID_LEN];
+    unsafe {
+        let len = sys::network::tipset_cid(epoch, buf.as_mut_ptr(), buf.len() as u32)? as usize;
+        if len > buf.len() {
+            panic!("CID too big: {} > {}", len, buf.len())
+        }
+        Ok(Cid::read_bytes(&buf[..len]).expect("runtime returned an invalid CID"))
+    }
+}
@@ -0,0 +1,9 @@
+use crate::{ipld, sys, SyscallResult};
+
+/// Calls the host-side precompile registered under the given ID on the input, and returns its
+/// output. Precompiles are disabled unless the machine has been configured with them.
+pub fn call_precompile(id: u64, input: &[u8]) -> SyscallResult<Vec<u8>> {
+    let block =
+        unsafe { sys::precompile::call_precompile(id, input.as_ptr(), input.len() as u32)? };
+    ipld::get_block(block, None)
+}
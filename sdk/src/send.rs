@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
 
 use fvm_shared::address::Address;
 use fvm_shared::econ::TokenAmount;
@@ -6,14 +6,16 @@ use fvm_shared::encoding::{RawBytes, DAG_CBOR};
 use fvm_shared::error::{ErrorNumber, ExitCode};
 use fvm_shared::receipt::Receipt;
 use fvm_shared::MethodNum;
-use num_traits::FromPrimitive;
 
 use crate::message::NO_DATA_BLOCK_ID;
 use crate::{sys, SyscallResult};
 
 /// Sends a message to another actor.
-// TODO: Drop the use of receipts here as we don't return the gas used. Alternatively, we _could_
-// return gas used?
+///
+/// The receipt reports the gas consumed by the receiver, including the actors it called in turn.
+///
+/// Fails with [`ErrorNumber::ParamsTooLarge`] if the parameters exceed the maximum size the
+/// machine allows between actors.
 pub fn send(
     to: &Address,
     method: MethodNum,
@@ -37,6 +39,7 @@ pub fn send(
         let fvm_shared::sys::out::send::Send {
             exit_code,
             return_id,
+            gas_used,
         } = sys::send::send(
             recipient.as_ptr(),
             recipient.len() as u32,
@@ -47,7 +50,7 @@ pub fn send(
         )?;
 
         // Process the result.
-        let exit_code = ExitCode::from_u32(exit_code).unwrap_or(ExitCode::SysErrIllegalActor);
+        let exit_code = ExitCode::try_from(exit_code).unwrap_or(ExitCode::SysErrIllegalActor);
         let return_data = match exit_code {
             ExitCode::Ok if return_id != NO_DATA_BLOCK_ID => {
                 // Allocate a buffer to read the return data.
@@ -65,7 +68,7 @@ pub fn send(
         Ok(Receipt {
             exit_code,
             return_data,
-            gas_used: 0,
+            gas_used,
         })
     }
 }
@@ -4,6 +4,16 @@ super::fvm_syscalls! {
     /// Resolves the ID address of an actor.
     pub fn resolve_address(addr_off: *const u8, addr_len: u32) -> Result<fvm_shared::sys::out::actor::ResolveAddress>;
 
+    /// Resolves the ID addresses of a CBOR encoded list of addresses, writing one
+    /// `fvm_shared::sys::out::actor::RESOLVED_ADDRESS_LEN` byte entry per address into the output
+    /// buffer. Returns the number of addresses.
+    pub fn resolve_addresses(
+        addrs_off: *const u8,
+        addrs_len: u32,
+        obuf_off: *mut u8,
+        obuf_len: u32,
+    ) -> Result<u32>;
+
     /// Gets the CodeCID of an actor by address.
     pub fn get_actor_code_cid(
         addr_off: *const u8,
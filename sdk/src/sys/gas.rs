@@ -1,10 +1,11 @@
 super::fvm_syscalls! {
     module = "gas";
 
-    // TODO: name for debugging & tracing?
-    // We could also _not_ feed that through to the outside?
-
-    /// Charge gas.
+    /// Charges gas on behalf of the actor. The charge is recorded in the execution trace under the
+    /// given name.
+    ///
+    /// Fails with `IllegalArgument` if the name isn't utf8 or is longer than
+    /// `MAX_GAS_CHARGE_NAME_LEN` bytes, or if the amount is larger than `MAX_GAS_CHARGE`.
     pub fn charge(name_off: *const u8, name_len: u32, amount: u64) -> Result<()>;
 
     // Returns the amount of gas remaining.
@@ -58,4 +58,7 @@ super::fvm_syscalls! {
         cid: *mut u8,
         cid_max_len: u32,
     ) -> Result<u32>;
+
+    /// Releases the identified block. Its ID can't be used afterwards.
+    pub fn drop(id: u32) -> Result<()>;
 }
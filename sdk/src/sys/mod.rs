@@ -6,6 +6,7 @@ pub mod gas;
 pub mod ipld;
 pub mod message;
 pub mod network;
+pub mod precompile;
 pub mod rand;
 pub mod send;
 pub mod sself;
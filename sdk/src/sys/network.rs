@@ -11,6 +11,12 @@ super::fvm_syscalls! {
     /// tuple of u64 values to be concatenated in a u128.
     pub fn base_fee() -> Result<fvm_shared::sys::TokenAmount>;
 
+    /// Gets the CID of the tipset at the given epoch, which must be within the lookback window.
+    /// The CID is written into the supplied buffer, if it's large enough.
+    ///
+    /// Returns the length of the CID, whether it was written or not.
+    pub fn tipset_cid(epoch: i64, ret_off: *mut u8, ret_len: u32) -> Result<u32>;
+
     /// Gets the circulating supply as little-Endian
     /// tuple of u64 values to be concatenated in a u128.
     /// Note that how this value is calculated is expected to change in nv15
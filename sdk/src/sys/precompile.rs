@@ -0,0 +1,10 @@
+super::fvm_syscalls! {
+    module = "precompile";
+
+    /// Calls the host-side precompile registered under the given ID on the input, charging its
+    /// gas, and returns the ID of a raw block holding its output.
+    ///
+    /// Fails with `NotFound` if no such precompile is available at the current network version,
+    /// and with `IllegalArgument` if the precompile rejects its input.
+    pub fn call_precompile(id: u64, input_off: *const u8, input_len: u32) -> Result<u32>;
+}
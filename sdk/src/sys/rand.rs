@@ -7,6 +7,10 @@ super::fvm_syscalls! {
     /// The supplied output buffer must have at least 32 bytes of capacity.
     /// If this syscall succeeds, exactly 32 bytes will be written starting at the
     /// supplied offset.
+    ///
+    /// Fails with `IllegalArgument` if `dst` isn't a known domain separation tag, or (from network
+    /// version 16 on) if the entropy is longer than `fvm_shared::sys::MAX_RANDOMNESS_ENTROPY_LEN`
+    /// bytes.
     pub fn get_chain_randomness(
         dst: i64,
         round: i64,
@@ -18,6 +22,8 @@ super::fvm_syscalls! {
     /// The supplied output buffer must have at least 32 bytes of capacity.
     /// If this syscall succeeds, exactly 32 bytes will be written starting at the
     /// supplied offset.
+    ///
+    /// Fails like `get_chain_randomness`.
     pub fn get_beacon_randomness(
         dst: i64,
         round: i64,
@@ -2,7 +2,7 @@ super::fvm_syscalls! {
     module = "send";
 
     /// Sends a message to another actor, and returns the exit code and block ID of the return
-    /// result.
+    /// result, along with the gas consumed by the receiver.
     pub fn send(
         recipient_off: *const u8,
         recipient_len: u32,
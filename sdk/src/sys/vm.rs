@@ -3,5 +3,5 @@ super::fvm_syscalls! {
 
     /// Abort execution with the given code and message. The code is recorded in the receipt, the
     /// message is for debugging only.
-    pub fn abort(code: u32, message: *const u8, message_len: u32) -> !;
+    pub fn abort(code: fvm_shared::sys::ExitCode, message: *const u8, message_len: u32) -> !;
 }
@@ -0,0 +1,88 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Helpers to predict the addresses the VM and the init actor assign, without executing messages
+//! (e.g., when building a genesis state or preparing test scenarios).
+
+use std::collections::HashMap;
+
+use super::{Address, Payload, FIRST_NON_SINGLETON_ADDR, NETWORK_DEFAULT};
+use crate::encoding::{to_vec, Error};
+use crate::ActorID;
+
+/// Derives the robust (actor protocol) address of an actor created by a message.
+///
+/// - `origin` is the key (secp256k1 or BLS) address of the message's sender.
+/// - `nonce` is the message's sequence number.
+/// - `actor_index` is the number of actors already created while executing the message.
+pub fn derive_actor_address(
+    origin: &Address,
+    nonce: u64,
+    actor_index: u64,
+) -> Result<Address, Error> {
+    let mut b = to_vec(origin)?;
+    b.extend_from_slice(&nonce.to_be_bytes());
+    b.extend_from_slice(&actor_index.to_be_bytes());
+    Ok(Address::new_actor(&b))
+}
+
+/// Predicts the ID addresses assigned by the init actor.
+///
+/// The init actor assigns sequential IDs, starting from its `next_id`, to every non-ID address it
+/// sees for the first time: the robust addresses of actors it creates, and the key addresses of
+/// account actors implicitly created by sending to them. ID addresses are never remapped.
+#[derive(Clone, Debug)]
+pub struct IdAssigner {
+    next_id: ActorID,
+    assigned: HashMap<Address, ActorID>,
+}
+
+impl Default for IdAssigner {
+    /// Starts assigning from the first ID after the singleton actors, as in a fresh genesis state.
+    fn default() -> Self {
+        Self::new(FIRST_NON_SINGLETON_ADDR)
+    }
+}
+
+impl IdAssigner {
+    /// Creates an assigner that will assign `next_id` first.
+    pub fn new(next_id: ActorID) -> Self {
+        IdAssigner {
+            next_id,
+            assigned: HashMap::new(),
+        }
+    }
+
+    /// Returns the ID that will be assigned to the next new address.
+    pub fn next_id(&self) -> ActorID {
+        self.next_id
+    }
+
+    /// Returns the ID the address resolves to, if it's an ID address or has already been assigned
+    /// an ID.
+    pub fn lookup(&self, addr: &Address) -> Option<ActorID> {
+        match addr.payload() {
+            Payload::ID(id) => Some(*id),
+            _ => self.assigned.get(&Self::key(addr)).copied(),
+        }
+    }
+
+    /// Returns the ID the address resolves to, assigning the next ID if the address hasn't been
+    /// seen before.
+    pub fn assign(&mut self, addr: &Address) -> ActorID {
+        if let Some(id) = self.lookup(addr) {
+            return id;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        self.assigned.insert(Self::key(addr), id);
+        id
+    }
+
+    /// Addresses are mapped by their payload: the network is not part of an address's identity.
+    fn key(addr: &Address) -> Address {
+        let mut key = *addr;
+        key.set_network(NETWORK_DEFAULT);
+        key
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+mod derivation;
 mod errors;
 mod network;
 mod payload;
@@ -15,12 +16,12 @@ use data_encoding::Encoding;
 use data_encoding_macro::{internal_new_encoding, new_encoding};
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
+pub use self::derivation::{derive_actor_address, IdAssigner};
 pub use self::errors::Error;
 pub use self::network::Network;
 pub use self::payload::{BLSPublicKey, Payload};
 pub use self::protocol::Protocol;
 use crate::encoding::{blake2b_variable, serde_bytes, Cbor};
-use crate::ActorID;
 
 /// defines the encoder for base32 encoding with the provided string with no padding
 const ADDRESS_ENCODER: Encoding = new_encoding! {
@@ -37,8 +38,7 @@ pub const SECP_PUB_LEN: usize = 65;
 /// BLS public key length used for validation of BLS addresses.
 pub const BLS_PUB_LEN: usize = 48;
 
-/// Defines first available ID address after builtin actors
-pub const FIRST_NON_SINGLETON_ADDR: ActorID = 100;
+pub use crate::constants::FIRST_NON_SINGLETON_ADDR;
 
 lazy_static::lazy_static! {
     static ref BLS_ZERO_ADDR_BYTES: BLSPublicKey = {
@@ -4,23 +4,15 @@
 use anyhow::{anyhow, Result};
 use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
 use cid::Cid;
-use fvm_shared::blockstore::{Blockstore, Buffered};
 
-// TODO: figure out where to put this.
-const DAG_CBOR: u64 = 0x71;
-
-// NOTE: This code doesn't currently work. It was taken from the ipld/blockstore crate but now lives here because that "blockstore" is really the actor store.
-// TODO:
-// 1. Finish converting it to a true blockstore.
-// 2. Add bulk put methods to the blockstore.
+use super::{Blockstore, Buffered};
+use crate::encoding::DAG_CBOR;
 
 // TODO: replace HashMap with DashMap like in forest?
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io::{Cursor, Read, Seek};
 
-// TODO: This is going to live in the kernel so it should be a Blockstore, not an ActorStore.
-
 /// Wrapper around `Blockstore` to limit and have control over when values are written.
 /// This type is not threadsafe and can only be used in synchronous contexts.
 #[derive(Debug)]
@@ -62,6 +54,23 @@ where
 
         Ok(())
     }
+
+    fn discard_unreachable(&self, root: &Cid) -> Result<()> {
+        let mut s = self.write.borrow_mut();
+        if !s.contains_key(root) {
+            *s = Default::default();
+            return Ok(());
+        }
+        let mut buffer = Vec::new();
+        copy_rec(&s, *root, &mut buffer)?;
+        let reachable = buffer
+            .into_iter()
+            .map(|(k, block)| (k, block.to_vec()))
+            .collect();
+        *s = reachable;
+
+        Ok(())
+    }
 }
 
 /// Given a CBOR encoded Buffer, returns a tuple of:
@@ -121,7 +130,7 @@ fn cbor_read_header_buf<B: Read>(br: &mut B, scratch: &mut [u8]) -> anyhow::Resu
 /// Given a CBOR serialized IPLD buffer, read through all of it and return all the Links.
 /// This function is useful because it is quite a bit more fast than doing this recursively on a
 /// deserialized IPLD object.
-fn scan_for_links<B: Read + Seek, F>(buf: &mut B, mut callback: F) -> Result<()>
+pub fn scan_for_links<B: Read + Seek, F>(buf: &mut B, mut callback: F) -> Result<()>
 where
     F: FnMut(Cid) -> anyhow::Result<()>,
 {
@@ -249,11 +258,11 @@ where
 #[cfg(test)]
 mod tests {
     use cid::multihash::{Code, Multihash};
-    use fvm_shared::blockstore::{Blockstore, CborStore, MemoryBlockstore};
-    use fvm_shared::{commcid, IDENTITY_HASH};
     use serde::{Deserialize, Serialize};
 
     use super::*;
+    use crate::blockstore::{CborStore, MemoryBlockstore};
+    use crate::{commcid, IDENTITY_HASH};
 
     const RAW: u64 = 0x55;
 
@@ -272,6 +281,24 @@ mod tests {
         assert!(buf_store.write.borrow().get(&cid).is_none());
     }
 
+    #[test]
+    fn discard_unreachable() {
+        let mem = MemoryBlockstore::default();
+        let buf_store = BufferedBlockstore::new(&mem);
+        let leaf = buf_store.put_cbor(&1u8, Code::Blake2b256).unwrap();
+        let root = buf_store.put_cbor(&(leaf, 2u8), Code::Blake2b256).unwrap();
+        let garbage = buf_store.put_cbor(&3u8, Code::Blake2b256).unwrap();
+
+        buf_store.discard_unreachable(&root).unwrap();
+        assert_eq!(buf_store.get_cbor::<u8>(&leaf).unwrap(), Some(1));
+        assert_eq!(buf_store.get_cbor::<u8>(&garbage).unwrap(), None);
+
+        // A root that isn't buffered (e.g., already flushed) leaves nothing to keep.
+        buf_store.discard_unreachable(&garbage).unwrap();
+        assert!(buf_store.write.borrow().is_empty());
+        assert_eq!(mem.get_cbor::<u8>(&leaf).unwrap(), None);
+    }
+
     #[test]
     fn buffered_store_with_links() {
         let mem = MemoryBlockstore::default();
@@ -0,0 +1,172 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::collections::HashSet;
+use std::io::Cursor;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+
+use super::buffered::scan_for_links;
+use super::Blockstore;
+use crate::encoding::DAG_CBOR;
+
+/// A blockstore whose blocks can be listed and deleted, and which can therefore be garbage
+/// collected with [`collect_garbage`].
+pub trait Collectable: Blockstore {
+    /// Returns the CIDs of all the blocks in the store.
+    fn keys(&self) -> Result<Vec<Cid>>;
+
+    /// Deletes a block from the store. Deleting a missing block isn't an error.
+    fn delete(&self, k: &Cid) -> Result<()>;
+}
+
+impl<BS> Collectable for &BS
+where
+    BS: Collectable,
+{
+    fn keys(&self) -> Result<Vec<Cid>> {
+        (*self).keys()
+    }
+
+    fn delete(&self, k: &Cid) -> Result<()> {
+        (*self).delete(k)
+    }
+}
+
+impl<BS> Collectable for Rc<BS>
+where
+    BS: Collectable,
+{
+    fn keys(&self) -> Result<Vec<Cid>> {
+        (**self).keys()
+    }
+
+    fn delete(&self, k: &Cid) -> Result<()> {
+        (**self).delete(k)
+    }
+}
+
+impl<BS> Collectable for Arc<BS>
+where
+    BS: Collectable,
+{
+    fn keys(&self) -> Result<Vec<Cid>> {
+        (**self).keys()
+    }
+
+    fn delete(&self, k: &Cid) -> Result<()> {
+        (**self).delete(k)
+    }
+}
+
+/// What a garbage collection found (and, unless it was a dry run, removed).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+    /// Number of blocks reachable from the roots.
+    pub live_blocks: usize,
+    /// Total size of the blocks reachable from the roots.
+    pub live_bytes: usize,
+    /// Number of unreachable blocks.
+    pub garbage_blocks: usize,
+    /// Total size of the unreachable blocks.
+    pub garbage_bytes: usize,
+}
+
+/// Deletes the blocks not reachable from any of the given roots (mark and sweep). With `dry_run`,
+/// nothing is deleted, and the returned stats only report what would be.
+///
+/// Links are only followed through DAG-CBOR blocks; blocks in other codecs (e.g. actor code) are
+/// kept when linked to, but never scanned. Links to blocks missing from the store are ignored, but
+/// every root must be present.
+pub fn collect_garbage<BS, I>(bs: &BS, roots: I, dry_run: bool) -> Result<GcStats>
+where
+    BS: Collectable,
+    I: IntoIterator<Item = Cid>,
+{
+    let mut stats = GcStats::default();
+
+    // Mark.
+    let mut live = HashSet::new();
+    let mut stack = Vec::new();
+    for root in roots {
+        if !bs.has(&root)? {
+            return Err(anyhow!("garbage collection root {} not found", root));
+        }
+        stack.push(root);
+    }
+    while let Some(cid) = stack.pop() {
+        if live.contains(&cid) {
+            continue;
+        }
+        let block = match bs.get(&cid)? {
+            Some(block) => block,
+            None => continue,
+        };
+        live.insert(cid);
+        stats.live_blocks += 1;
+        stats.live_bytes += block.len();
+        if cid.codec() == DAG_CBOR {
+            scan_for_links(&mut Cursor::new(&block), |link| {
+                if !live.contains(&link) {
+                    stack.push(link);
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    // Sweep.
+    for k in bs.keys()? {
+        if live.contains(&k) {
+            continue;
+        }
+        stats.garbage_blocks += 1;
+        stats.garbage_bytes += bs.get(&k)?.map_or(0, |b| b.len());
+        if !dry_run {
+            bs.delete(&k)?;
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::{Code, MultihashDigest};
+
+    use super::*;
+    use crate::blockstore::{CborStore, MemoryBlockstore};
+    use crate::IPLD_RAW;
+
+    #[test]
+    fn sweeps_unreachable_blocks() {
+        let bs = MemoryBlockstore::default();
+        let code = b"actor code";
+        let code_cid = Cid::new_v1(IPLD_RAW, Code::Blake2b256.digest(code));
+        bs.put_keyed(&code_cid, code).unwrap();
+        let leaf = bs.put_cbor(&"leaf", Code::Blake2b256).unwrap();
+        let root = bs.put_cbor(&(leaf, code_cid), Code::Blake2b256).unwrap();
+        let old_root = bs.put_cbor(&(leaf, 1u8), Code::Blake2b256).unwrap();
+        let garbage = bs.put_cbor(&"garbage", Code::Blake2b256).unwrap();
+        let garbage_bytes =
+            bs.get(&old_root).unwrap().unwrap().len() + bs.get(&garbage).unwrap().unwrap().len();
+
+        let dry_run = collect_garbage(&bs, [root], true).unwrap();
+        assert_eq!(dry_run.live_blocks, 3);
+        assert_eq!(dry_run.garbage_blocks, 2);
+        assert_eq!(dry_run.garbage_bytes, garbage_bytes);
+        assert_eq!(bs.keys().unwrap().len(), 5);
+
+        assert_eq!(collect_garbage(&bs, [root], false).unwrap(), dry_run);
+        for cid in [root, leaf, code_cid] {
+            assert!(bs.has(&cid).unwrap());
+        }
+        for cid in [old_root, garbage] {
+            assert!(!bs.has(&cid).unwrap());
+        }
+
+        assert!(collect_garbage(&bs, [garbage], true).is_err());
+    }
+}
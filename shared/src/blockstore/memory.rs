@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use anyhow::Result;
 use cid::Cid;
 
-use super::Blockstore;
+use super::{Blockstore, Collectable};
 
 #[derive(Debug, Default, Clone)]
 pub struct MemoryBlockstore {
@@ -31,3 +31,14 @@ impl Blockstore for MemoryBlockstore {
         Ok(())
     }
 }
+
+impl Collectable for MemoryBlockstore {
+    fn keys(&self) -> Result<Vec<Cid>> {
+        Ok(self.blocks.borrow().keys().copied().collect())
+    }
+
+    fn delete(&self, k: &Cid) -> Result<()> {
+        self.blocks.borrow_mut().remove(k);
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 use std::rc::Rc;
+use std::sync::Arc;
 
 use anyhow::Result;
 use cid::{multihash, Cid};
 
 pub mod tracking;
+pub use tracking::{BSStats, TrackingBlockstore};
 
 mod cbor;
 pub use cbor::CborStore;
@@ -11,6 +13,18 @@ pub use cbor::CborStore;
 mod memory;
 pub use memory::MemoryBlockstore;
 
+mod buffered;
+pub use buffered::{scan_for_links, BufferedBlockstore};
+
+mod read_only;
+pub use read_only::ReadOnlyBlockstore;
+
+mod sync;
+pub use self::sync::SyncBlockstore;
+
+mod gc;
+pub use gc::{collect_garbage, Collectable, GcStats};
+
 mod block;
 pub use block::*;
 
@@ -86,6 +100,10 @@ pub trait Blockstore {
 
 pub trait Buffered: Blockstore {
     fn flush(&self, root: &Cid) -> Result<()>;
+
+    /// Drops the buffered blocks that aren't reachable from the given root, without writing
+    /// anything to the underlying store.
+    fn discard_unreachable(&self, root: &Cid) -> Result<()>;
 }
 
 impl<BS> Blockstore for &BS
@@ -173,3 +191,46 @@ where
         (**self).put_many_keyed(blocks)
     }
 }
+
+impl<BS> Blockstore for Arc<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        (**self).get(k)
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        (**self).put_keyed(k, block)
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        (**self).has(k)
+    }
+
+    fn put<D>(&self, mh_code: multihash::Code, block: &Block<D>) -> Result<Cid>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+    {
+        (**self).put(mh_code, block)
+    }
+
+    fn put_many<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (multihash::Code, Block<D>)>,
+    {
+        (**self).put_many(blocks)
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        (**self).put_many_keyed(blocks)
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use anyhow::Result;
+use cid::Cid;
+
+use super::Blockstore;
+
+/// Wrapper around a `Blockstore` that never writes to it.
+///
+/// Writes go to an in-memory buffer instead, where they can be read back, and are discarded along
+/// with the wrapper. Use it to execute messages against past state (e.g., to serve state queries)
+/// without persisting anything to the node's blockstore.
+#[derive(Debug)]
+pub struct ReadOnlyBlockstore<BS> {
+    base: BS,
+    discarded: RefCell<HashMap<Cid, Vec<u8>>>,
+}
+
+impl<BS> ReadOnlyBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    pub fn new(base: BS) -> Self {
+        Self {
+            base,
+            discarded: Default::default(),
+        }
+    }
+
+    /// Drops any buffered writes and returns the underlying blockstore.
+    pub fn consume(self) -> BS {
+        self.base
+    }
+}
+
+impl<BS> Blockstore for ReadOnlyBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, cid: &Cid) -> Result<Option<Vec<u8>>> {
+        match self.discarded.borrow().get(cid) {
+            Some(data) => Ok(Some(data.clone())),
+            None => self.base.get(cid),
+        }
+    }
+
+    fn put_keyed(&self, cid: &Cid, buf: &[u8]) -> Result<()> {
+        self.discarded.borrow_mut().insert(*cid, Vec::from(buf));
+        Ok(())
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        if self.discarded.borrow().contains_key(k) {
+            Ok(true)
+        } else {
+            self.base.has(k)
+        }
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.discarded
+            .borrow_mut()
+            .extend(blocks.into_iter().map(|(k, v)| (k, v.as_ref().into())));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cid::multihash::Code;
+
+    use super::*;
+    use crate::blockstore::{Buffered, BufferedBlockstore, CborStore, MemoryBlockstore};
+
+    #[test]
+    fn writes_never_reach_the_base_store() {
+        let mem = MemoryBlockstore::default();
+        let existing = mem.put_cbor(&1u8, Code::Blake2b256).unwrap();
+
+        let store = BufferedBlockstore::new(ReadOnlyBlockstore::new(&mem));
+        assert_eq!(store.get_cbor::<u8>(&existing).unwrap(), Some(1));
+
+        let cid = store.put_cbor(&2u8, Code::Blake2b256).unwrap();
+        store.flush(&cid).unwrap();
+        assert_eq!(store.get_cbor::<u8>(&cid).unwrap(), Some(2));
+        assert!(!mem.has(&cid).unwrap());
+
+        store.consume().consume();
+        assert!(!mem.has(&cid).unwrap());
+    }
+}
@@ -0,0 +1,96 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Result};
+use cid::Cid;
+
+use super::Blockstore;
+
+/// Wrapper around a `Blockstore` that serializes access to it, making it shareable between threads
+/// (as long as the wrapped blockstore can be sent between them).
+#[derive(Debug, Default)]
+pub struct SyncBlockstore<BS> {
+    base: Mutex<BS>,
+}
+
+impl<BS> SyncBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    pub fn new(base: BS) -> Self {
+        Self {
+            base: Mutex::new(base),
+        }
+    }
+
+    pub fn consume(self) -> BS {
+        // A poisoned lock only means another thread panicked while using the blockstore.
+        self.base.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn with_base<T>(&self, f: impl FnOnce(&BS) -> Result<T>) -> Result<T> {
+        let base = self
+            .base
+            .lock()
+            .map_err(|_| anyhow!("blockstore lock poisoned"))?;
+        f(&base)
+    }
+}
+
+impl<BS> Blockstore for SyncBlockstore<BS>
+where
+    BS: Blockstore,
+{
+    fn get(&self, k: &Cid) -> Result<Option<Vec<u8>>> {
+        self.with_base(|base| base.get(k))
+    }
+
+    fn put_keyed(&self, k: &Cid, block: &[u8]) -> Result<()> {
+        self.with_base(|base| base.put_keyed(k, block))
+    }
+
+    fn has(&self, k: &Cid) -> Result<bool> {
+        self.with_base(|base| base.has(k))
+    }
+
+    fn put_many_keyed<D, I>(&self, blocks: I) -> Result<()>
+    where
+        Self: Sized,
+        D: AsRef<[u8]>,
+        I: IntoIterator<Item = (Cid, D)>,
+    {
+        self.with_base(|base| base.put_many_keyed(blocks))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use cid::multihash::Code;
+
+    use super::*;
+    use crate::blockstore::{CborStore, MemoryBlockstore};
+
+    #[test]
+    fn shared_between_threads() {
+        let store = Arc::new(SyncBlockstore::new(MemoryBlockstore::default()));
+        let cids: Vec<_> = (0..4u8)
+            .map(|i| {
+                let store = store.clone();
+                thread::spawn(move || store.put_cbor(&i, Code::Blake2b256).unwrap())
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect();
+
+        let store = Arc::try_unwrap(store).unwrap().consume();
+        for (i, cid) in cids.iter().enumerate() {
+            assert_eq!(store.get_cbor::<u8>(cid).unwrap(), Some(i as u8));
+        }
+    }
+}
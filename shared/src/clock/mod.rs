@@ -14,3 +14,6 @@ pub type ChainEpoch = i64;
 
 /// Const used within the VM to denote an unset `ChainEpoch`
 pub const EPOCH_UNDEFINED: ChainEpoch = -1;
+
+/// How far back (in epochs) actors can look up tipset CIDs.
+pub const TIPSET_CID_LOOKBACK: ChainEpoch = 900;
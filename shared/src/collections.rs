@@ -0,0 +1,130 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Containers for consensus-critical code.
+//!
+//! The standard `HashMap` and `HashSet` are seeded randomly, per process: anything derived from
+//! their iteration order (the order state is written in, which error is reported first, etc.)
+//! differs from one node to the next. Consensus-critical code should use ordered containers
+//! (`BTreeMap` and `BTreeSet`) where it iterates, and the hash containers defined here, whose
+//! hasher is fixed, where the keys aren't ordered.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// A map hashed with [`FixedHasher`]: its iteration order only depends on its contents and the
+/// order they were inserted in.
+pub type FixedHashMap<K, V> = HashMap<K, V, FixedState>;
+
+/// A set hashed with [`FixedHasher`]: its iteration order only depends on its contents and the
+/// order they were inserted in.
+pub type FixedHashSet<T> = HashSet<T, FixedState>;
+
+/// Builds [`FixedHasher`]s.
+pub type FixedState = BuildHasherDefault<FixedHasher>;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// A 64-bit FNV-1a hasher, without a seed. Integers are hashed as little-endian bytes and sizes
+/// as 64-bit integers, so hashes are the same on every platform and with every Rust release.
+///
+/// FNV isn't resistant to collision attacks: containers keyed by values an attacker controls must
+/// only be accessed while charging gas.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedHasher(u64);
+
+impl Default for FixedHasher {
+    fn default() -> Self {
+        FixedHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for FixedHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= u64::from(*b);
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::hash::{BuildHasher, Hash};
+
+    use super::*;
+
+    fn hash<T: Hash>(value: T) -> u64 {
+        let mut hasher = FixedState::default().build_hasher();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn fixed_hashes() {
+        // FNV-1a test vectors.
+        assert_eq!(hash(()), FNV_OFFSET_BASIS);
+        let mut hasher = FixedHasher::default();
+        hasher.write(b"a");
+        assert_eq!(hasher.finish(), 0xaf63dc4c8601ec8c);
+
+        let mut hasher = FixedHasher::default();
+        hasher.write(&1u64.to_le_bytes());
+        assert_eq!(hash(1u64), hasher.finish());
+        assert_eq!(hash(vec![1u8]), hash((1u64, 1u8)));
+        assert_eq!(hash(-1i32), hash(u32::MAX));
+    }
+
+    #[test]
+    fn iteration_order_is_reproducible() {
+        let keys = || (0u64..64).map(|i| i.wrapping_mul(0x9e3779b97f4a7c15));
+        let a: FixedHashSet<u64> = keys().collect();
+        let b: FixedHashSet<u64> = keys().collect();
+        assert!(a.iter().eq(b.iter()));
+    }
+}
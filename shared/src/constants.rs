@@ -0,0 +1,43 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Protocol constants shared by the FVM, actors and nodes: the addresses of the singleton actors,
+//! the method numbers every actor understands, and the exit codes reserved to the system.
+//!
+//! These are the authoritative definitions; other crates (including the FVM) re-export them
+//! rather than defining their own.
+
+use crate::address::Address;
+use crate::{ActorID, MethodNum};
+
+/// The system actor, which holds the builtin actors manifest.
+pub const SYSTEM_ACTOR_ADDR: Address = Address::new_id(0);
+/// The init actor, which assigns ID addresses and creates actors.
+pub const INIT_ACTOR_ADDR: Address = Address::new_id(1);
+/// The reward actor, which receives miner tips and pays out block rewards.
+pub const REWARD_ACTOR_ADDR: Address = Address::new_id(2);
+/// The cron actor, which runs the end-of-epoch jobs of the other builtin actors.
+pub const CRON_ACTOR_ADDR: Address = Address::new_id(3);
+/// The storage power actor.
+pub const STORAGE_POWER_ACTOR_ADDR: Address = Address::new_id(4);
+/// The storage market actor.
+pub const STORAGE_MARKET_ACTOR_ADDR: Address = Address::new_id(5);
+/// The verified registry actor.
+pub const VERIFIED_REGISTRY_ACTOR_ADDR: Address = Address::new_id(6);
+/// The chaos actor, only deployed on test networks.
+pub const CHAOS_ACTOR_ADDR: Address = Address::new_id(98);
+/// Distinguished Account actor that is the destination of all burnt funds.
+pub const BURNT_FUNDS_ACTOR_ADDR: Address = Address::new_id(99);
+
+/// Defines first available ID address after builtin actors
+pub const FIRST_NON_SINGLETON_ADDR: ActorID = 100;
+
+/// Base actor send method.
+pub const METHOD_SEND: MethodNum = 0;
+/// Base actor constructor method.
+pub const METHOD_CONSTRUCTOR: MethodNum = 1;
+
+/// Exit codes below this one are reserved to the system (see
+/// [`ExitCode::is_system_error`](crate::error::ExitCode::is_system_error)); actors may only abort
+/// with codes starting from here, with the exception of `SysErrInvalidMethod`.
+pub const FIRST_ACTOR_EXIT_CODE: u32 = 16;
@@ -0,0 +1,175 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+use super::errors::Error;
+use crate::encoding::{de, from_slice, CodecProtocol};
+
+/// The default maximum nesting depth of untrusted CBOR input.
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+
+/// The default maximum size, in bytes, of untrusted CBOR input.
+pub const DEFAULT_MAX_SIZE: usize = 4 << 20;
+
+/// Limits on CBOR input decoded on behalf of untrusted code (e.g., by the host, from actor
+/// memory). The decoder recurses into nested arrays, maps and tags, so deeply nested input could
+/// otherwise exhaust the stack.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecodeLimits {
+    /// The maximum number of arrays, maps and tags an item can be nested in.
+    pub max_depth: usize,
+    /// The maximum size of the input, in bytes.
+    pub max_size: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        DecodeLimits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Checks that the given CBOR input is within the limits, without decoding it. The input is
+    /// walked iteratively, item header by item header, so this can't blow the stack. The input
+    /// isn't otherwise validated.
+    pub fn check(&self, bytes: &[u8]) -> Result<(), Error> {
+        if bytes.len() > self.max_size {
+            return Err(decode_error(format!(
+                "input of {} bytes exceeds the maximum size of {} bytes",
+                bytes.len(),
+                self.max_size
+            )));
+        }
+
+        let mut pos = 0;
+        // The number of items left to read at every level of nesting.
+        let mut remaining = vec![1usize];
+        while let Some(left) = remaining.last_mut() {
+            if *left == 0 {
+                remaining.pop();
+                continue;
+            }
+            *left -= 1;
+            let (maj, extra) = read_header(bytes, &mut pos)?;
+            let items = match maj {
+                // Byte and text strings.
+                2 | 3 => {
+                    pos = pos
+                        .checked_add(extra)
+                        .filter(|&end| end <= bytes.len())
+                        .ok_or_else(truncated)?;
+                    continue;
+                }
+                // Arrays.
+                4 => extra,
+                // Maps.
+                5 => extra.checked_mul(2).ok_or_else(truncated)?,
+                // Tags.
+                6 => 1,
+                // Integers, simple values and floats.
+                _ => continue,
+            };
+            if remaining.len() > self.max_depth {
+                return Err(decode_error(format!(
+                    "input exceeds the maximum nesting depth of {}",
+                    self.max_depth
+                )));
+            }
+            remaining.push(items);
+        }
+        Ok(())
+    }
+}
+
+/// Decodes a value from untrusted CBOR input, after checking it against the given limits.
+pub fn from_slice_limited<T>(bytes: &[u8], limits: &DecodeLimits) -> Result<T, Error>
+where
+    T: de::DeserializeOwned,
+{
+    limits.check(bytes)?;
+    Ok(from_slice(bytes)?)
+}
+
+/// Reads the header of the item at `pos`, returning its major type and argument (the length of
+/// strings, arrays and maps, the value of integers, the tag number), and advancing `pos` to its
+/// content.
+fn read_header(bytes: &[u8], pos: &mut usize) -> Result<(u8, usize), Error> {
+    let first = *bytes.get(*pos).ok_or_else(truncated)?;
+    *pos += 1;
+    let maj = first >> 5;
+    let len = match first & 0x1f {
+        low @ 0..=23 => return Ok((maj, low as usize)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        31 => return Err(decode_error("indefinite length item".into())),
+        low => return Err(decode_error(format!("invalid additional info {}", low))),
+    };
+    let arg = bytes.get(*pos..*pos + len).ok_or_else(truncated)?;
+    *pos += len;
+    let value = arg.iter().fold(0u64, |acc, b| (acc << 8) | u64::from(*b));
+    // Arguments that don't fit in a usize can't be a valid length: the input would be truncated.
+    Ok((maj, usize::try_from(value).unwrap_or(usize::MAX)))
+}
+
+fn truncated() -> Error {
+    decode_error("unexpected end of input".into())
+}
+
+fn decode_error(description: String) -> Error {
+    Error {
+        description,
+        protocol: CodecProtocol::Cbor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_vec;
+
+    /// Returns `depth` nested single-element arrays around an integer.
+    fn nested(depth: usize) -> Vec<u8> {
+        let mut bytes = vec![0x81; depth];
+        bytes.push(0x01);
+        bytes
+    }
+
+    #[test]
+    fn depth_limit() {
+        let limits = DecodeLimits {
+            max_depth: 3,
+            ..Default::default()
+        };
+        let v: Vec<Vec<Vec<u8>>> = from_slice_limited(&nested(3), &limits).unwrap();
+        assert_eq!(v, [[[1]]]);
+        assert!(limits.check(&nested(4)).is_err());
+        // Far deeper than the decoder could handle.
+        assert!(DecodeLimits::default().check(&nested(1 << 20)).is_err());
+    }
+
+    #[test]
+    fn size_limit() {
+        let bytes = to_vec(&(1u8, "text", vec![1u64 << 40; 4])).unwrap();
+        let limits = DecodeLimits {
+            max_size: bytes.len(),
+            ..Default::default()
+        };
+        limits.check(&bytes).unwrap();
+        assert!(limits.check(&[&bytes[..], &[0]].concat()).is_err());
+    }
+
+    #[test]
+    fn malformed_input() {
+        let limits = DecodeLimits::default();
+        // A truncated array, string and argument.
+        for bytes in [&[0x82, 0x01][..], &[0x45, 0x01], &[0x19, 0x01]] {
+            assert!(limits.check(bytes).is_err());
+        }
+        // An indefinite length array.
+        assert!(limits.check(&[0x9f, 0x01, 0xff]).is_err());
+    }
+}
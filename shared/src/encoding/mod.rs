@@ -5,6 +5,7 @@ mod bytes;
 mod cbor;
 mod errors;
 mod hash;
+mod limits;
 mod vec;
 
 pub use serde::{de, ser};
@@ -15,6 +16,7 @@ pub use self::bytes::*;
 pub use self::cbor::*;
 pub use self::errors::*;
 pub use self::hash::*;
+pub use self::limits::*;
 pub use self::vec::*;
 
 // TODO: these really don't work all that well in a shared context like this as anyone importing
@@ -6,6 +6,7 @@ use std::fmt::Formatter;
 use num_derive::FromPrimitive;
 use thiserror::Error;
 
+use crate::constants::FIRST_ACTOR_EXIT_CODE;
 use crate::encoding::repr::*;
 
 /// ExitCode defines the exit code from the VM execution.
@@ -27,6 +28,8 @@ pub enum ExitCode {
     SysErrSenderStateInvalid = 2,
 
     /// Indicates failure to find a method in an actor.
+    ///
+    /// Actors abort with this exit code when invoked with a method number they don't handle.
     SysErrInvalidMethod = 3,
 
     /// Used for catching panics currently.
@@ -88,7 +91,22 @@ impl ExitCode {
 
     /// Returns true if the error code is a system error.
     pub fn is_system_error(self) -> bool {
-        (self as u32) < (ExitCode::ErrIllegalArgument as u32)
+        (self as u32) < FIRST_ACTOR_EXIT_CODE
+    }
+}
+
+impl From<ExitCode> for crate::sys::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        code as crate::sys::ExitCode
+    }
+}
+
+impl TryFrom<crate::sys::ExitCode> for ExitCode {
+    /// The value, which isn't a known exit code.
+    type Error = crate::sys::ExitCode;
+
+    fn try_from(value: crate::sys::ExitCode) -> Result<Self, Self::Error> {
+        num_traits::FromPrimitive::from_u32(value).ok_or(value)
     }
 }
 
@@ -112,6 +130,7 @@ pub enum ErrorNumber {
     IllegalCodec = 9,
     Serialization = 10,
     Forbidden = 11,
+    ParamsTooLarge = 12,
 }
 
 impl std::fmt::Display for ErrorNumber {
@@ -129,6 +148,32 @@ impl std::fmt::Display for ErrorNumber {
             IllegalCodec => "illegal ipld codec",
             Serialization => "serialization error",
             Forbidden => "operation forbidden",
+            ParamsTooLarge => "send parameters too large",
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ExitCode;
+    use crate::sys;
+
+    #[test]
+    fn exit_code_round_trip() {
+        let mut known = 0;
+        for value in 0..=2000 {
+            if let Ok(code) = ExitCode::try_from(value) {
+                assert_eq!(sys::ExitCode::from(code), value);
+                known += 1;
+            }
+        }
+        // Every variant, up to ErrPlaceholder.
+        assert_eq!(known, 24);
+
+        for value in [22, 33, 1001, i32::MAX as u32 + 1, u32::MAX] {
+            assert_eq!(ExitCode::try_from(value), Err(value));
+        }
+        assert_eq!(ExitCode::try_from(0), Ok(ExitCode::Ok));
+        assert_eq!(ExitCode::try_from(1000), Ok(ExitCode::ErrPlaceholder));
+    }
+}
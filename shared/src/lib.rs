@@ -13,8 +13,10 @@ pub mod address;
 pub mod bigint;
 pub mod blockstore;
 pub mod clock;
+pub mod collections;
 pub mod commcid;
 pub mod consensus;
+pub mod constants;
 pub mod crypto;
 pub mod deal;
 pub mod econ;
@@ -106,7 +108,4 @@ impl NetworkParams for DefaultNetworkParams {
 /// Method number indicator for calling actor methods.
 pub type MethodNum = u64;
 
-/// Base actor send method.
-pub const METHOD_SEND: MethodNum = 0;
-/// Base actor constructor method.
-pub const METHOD_CONSTRUCTOR: MethodNum = 1;
+pub use constants::{METHOD_CONSTRUCTOR, METHOD_SEND};
@@ -2,15 +2,27 @@
 // SPDX-License-Identifier: Apache-2.0, MIT
 
 use anyhow::anyhow;
+use cid::Cid;
 
 use crate::address::Address;
 use crate::bigint::bigint_ser::{BigIntDe, BigIntSer};
+use crate::crypto::signature::{Signature, SignatureType, SECP_SIG_LEN};
 use crate::econ::TokenAmount;
 use crate::encoding::de::{Deserialize, Deserializer};
 use crate::encoding::ser::{Serialize, Serializer};
-use crate::encoding::{Cbor, RawBytes};
+use crate::encoding::tuple::{Deserialize_tuple, Serialize_tuple};
+use crate::encoding::{Cbor, Error as EncodingError, RawBytes};
+use crate::version::NetworkVersion;
 use crate::MethodNum;
 
+/// The message format versions, with the network version each was introduced at. Messages of any
+/// other version are invalid.
+const MESSAGE_VERSIONS: &[(NetworkVersion, i64)] = &[(NetworkVersion::V0, 0)];
+
+/// The bytes a secp256k1 signature adds to a serialized message when wrapped in a signed envelope:
+/// the envelope's array header, then the signature and its type byte, as a byte string.
+const SECP_ENVELOPE_OVERHEAD: usize = 1 + 2 + 1 + SECP_SIG_LEN;
+
 /// Default Unsigned VM message type which includes all data needed for a state transition
 #[derive(PartialEq, Clone, Debug, Hash, Eq)]
 pub struct Message {
@@ -46,6 +58,13 @@ impl Message {
         }
         Ok(())
     }
+
+    /// Returns true if the message's format version is valid at the given network version.
+    pub fn is_version_supported(&self, nv: NetworkVersion) -> bool {
+        MESSAGE_VERSIONS
+            .iter()
+            .any(|&(since, version)| nv >= since && version == self.version)
+    }
 }
 
 impl Serialize for Message {
@@ -100,3 +119,174 @@ impl<'de> Deserialize<'de> for Message {
         })
     }
 }
+
+/// A [`Message`] together with its sender's signature, as included on chain.
+#[derive(PartialEq, Clone, Debug, Hash, Eq, Serialize_tuple, Deserialize_tuple)]
+pub struct SignedMessage {
+    pub message: Message,
+    pub signature: Signature,
+}
+
+impl SignedMessage {
+    /// Wraps a message and signature without checking the signature.
+    pub fn new_unchecked(message: Message, signature: Signature) -> Self {
+        SignedMessage { message, signature }
+    }
+
+    /// Returns true if the message was signed with a BLS key.
+    pub fn is_bls(&self) -> bool {
+        self.signature.signature_type() == SignatureType::BLS
+    }
+
+    /// Returns true if the message was signed with a secp256k1 key.
+    pub fn is_secp256k1(&self) -> bool {
+        self.signature.signature_type() == SignatureType::Secp256k1
+    }
+
+    /// Splits the envelope into the message and its signature.
+    pub fn into_parts(self) -> (Message, Signature) {
+        (self.message, self.signature)
+    }
+
+    /// Returns the length a message is charged for when included on chain, given the length of the
+    /// serialized unsigned message and the type of signature it's (or will be) signed with.
+    ///
+    /// BLS signatures are aggregated per block, so BLS messages are charged for the unsigned message
+    /// only. Secp256k1 messages are charged for the whole signed envelope.
+    pub fn chain_length(unsigned_len: usize, sig_type: SignatureType) -> usize {
+        match sig_type {
+            SignatureType::BLS => unsigned_len,
+            SignatureType::Secp256k1 => unsigned_len + SECP_ENVELOPE_OVERHEAD,
+        }
+    }
+}
+
+#[cfg(feature = "crypto")]
+impl SignedMessage {
+    /// Wraps a message and signature, verifying that the signature was made by the message's
+    /// sender over its signing bytes.
+    pub fn new_from_parts(message: Message, signature: Signature) -> anyhow::Result<Self> {
+        let msg = SignedMessage { message, signature };
+        msg.verify()?;
+        Ok(msg)
+    }
+
+    /// Verifies the signature against the sender address. The sender must be a BLS or secp256k1
+    /// address; ID addresses need to be resolved to their key address first.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        self.signature
+            .verify(&self.message.to_signing_bytes(), &self.message.from)
+            .map_err(|e| anyhow!("invalid message signature: {}", e))
+    }
+}
+
+impl Cbor for SignedMessage {
+    /// BLS signatures are aggregated per block, so a BLS signed message is identified by the CID of
+    /// the unsigned message. Secp256k1 signed messages are identified by the CID of the envelope.
+    fn cid(&self) -> Result<Cid, EncodingError> {
+        if self.is_bls() {
+            return self.message.cid();
+        }
+        use multihash::MultihashDigest;
+        Ok(Cid::new_v1(
+            crate::encoding::DAG_CBOR,
+            multihash::Code::Blake2b256.digest(&self.marshal_cbor()?),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{from_slice, to_vec};
+
+    fn message() -> Message {
+        Message {
+            version: 0,
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            sequence: 3,
+            value: TokenAmount::from(10),
+            method_num: 2,
+            params: RawBytes::new(vec![0x80]),
+            gas_limit: 1_000_000,
+            gas_fee_cap: TokenAmount::from(100),
+            gas_premium: TokenAmount::from(1),
+        }
+    }
+
+    #[test]
+    fn supported_versions() {
+        assert!(message().is_version_supported(NetworkVersion::V0));
+        assert!(message().is_version_supported(NetworkVersion::V15));
+        for version in [-1, 1] {
+            let msg = Message {
+                version,
+                ..message()
+            };
+            assert!(!msg.is_version_supported(NetworkVersion::V15));
+        }
+    }
+
+    #[test]
+    fn signed_message_cbor_round_trip() {
+        let msg = SignedMessage::new_unchecked(message(), Signature::new_secp256k1(vec![7; 65]));
+        let bytes = to_vec(&msg).unwrap();
+        assert_eq!(from_slice::<SignedMessage>(&bytes).unwrap(), msg);
+        assert_eq!(SignedMessage::unmarshal_cbor(&bytes).unwrap(), msg);
+    }
+
+    #[test]
+    fn chain_length() {
+        let unsigned_len = message().marshal_cbor().unwrap().len();
+        let secp = SignedMessage::new_unchecked(message(), Signature::new_secp256k1(vec![7; 65]));
+        assert_eq!(
+            SignedMessage::chain_length(unsigned_len, SignatureType::Secp256k1),
+            secp.marshal_cbor().unwrap().len()
+        );
+        assert_eq!(
+            SignedMessage::chain_length(unsigned_len, SignatureType::BLS),
+            unsigned_len
+        );
+    }
+
+    #[test]
+    fn signed_message_cid() {
+        let secp = SignedMessage::new_unchecked(message(), Signature::new_secp256k1(vec![7; 65]));
+        assert!(secp.is_secp256k1());
+        assert_ne!(secp.cid().unwrap(), message().cid().unwrap());
+
+        let bls = SignedMessage::new_unchecked(message(), Signature::new_bls(vec![7; 96]));
+        assert!(bls.is_bls());
+        assert_eq!(bls.cid().unwrap(), message().cid().unwrap());
+    }
+
+    #[cfg(feature = "crypto")]
+    #[test]
+    fn signed_message_verify() {
+        use libsecp256k1::{sign, Message as SecpMessage, PublicKey, SecretKey};
+
+        use crate::crypto::signature::SECP_SIG_LEN;
+        use crate::encoding::blake2b_256;
+
+        let sk = SecretKey::parse(&[1; 32]).unwrap();
+        let from = Address::new_secp256k1(&PublicKey::from_secret_key(&sk).serialize()).unwrap();
+        let msg = Message { from, ..message() };
+
+        let digest = blake2b_256(&msg.to_signing_bytes());
+        let (sig, recovery_id) = sign(&SecpMessage::parse(&digest), &sk);
+        let mut sig_bytes = Vec::with_capacity(SECP_SIG_LEN);
+        sig_bytes.extend_from_slice(&sig.serialize());
+        sig_bytes.push(recovery_id.serialize());
+        let signature = Signature::new_secp256k1(sig_bytes);
+
+        let signed = SignedMessage::new_from_parts(msg.clone(), signature.clone()).unwrap();
+        signed.verify().unwrap();
+
+        let tampered = Message {
+            sequence: msg.sequence + 1,
+            ..msg
+        };
+        assert!(SignedMessage::new_from_parts(tampered, signature).is_err());
+    }
+}
@@ -1,17 +1,37 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+use serde::de::Error as _;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-use crate::encoding::{BytesDe, BytesSer};
+use crate::encoding::{BytesDe, BytesSer, Cbor};
+
+pub const RANDOMNESS_LENGTH: usize = 32;
 
-// TODO: turn this back into a 32byte array once we no longer need go compat. It's a vec so that the
-// errors match.
 /// String of random bytes usually generated from a randomness beacon or from tickets on chain.
-#[derive(PartialEq, Eq, Default, Clone, Debug)]
-pub struct Randomness(pub Vec<u8>);
+/// Always [`RANDOMNESS_LENGTH`] bytes long: other lengths are rejected when decoding.
+#[derive(PartialEq, Eq, Default, Clone, Copy, Debug)]
+pub struct Randomness(pub [u8; RANDOMNESS_LENGTH]);
 
-pub const RANDOMNESS_LENGTH: usize = 32;
+impl Cbor for Randomness {}
+
+impl From<[u8; RANDOMNESS_LENGTH]> for Randomness {
+    fn from(bytes: [u8; RANDOMNESS_LENGTH]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl From<Randomness> for [u8; RANDOMNESS_LENGTH] {
+    fn from(r: Randomness) -> Self {
+        r.0
+    }
+}
+
+impl AsRef<[u8]> for Randomness {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
 
 impl Serialize for Randomness {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -27,7 +47,39 @@ impl<'de> Deserialize<'de> for Randomness {
     where
         D: Deserializer<'de>,
     {
-        let bytes = BytesDe::deserialize(deserializer)?;
-        Ok(Self(bytes.0))
+        let BytesDe(bytes) = BytesDe::deserialize(deserializer)?;
+        let len = bytes.len();
+        bytes
+            .try_into()
+            .map(Self)
+            .map_err(|_| D::Error::invalid_length(len, &"32 bytes of randomness"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::to_vec;
+
+    #[test]
+    fn fixed_size_conversions() {
+        let bytes = [7u8; RANDOMNESS_LENGTH];
+        let r = Randomness::from(bytes);
+        assert_eq!(<[u8; RANDOMNESS_LENGTH]>::from(r), bytes);
+    }
+
+    #[test]
+    fn cbor_roundtrip() {
+        let r = Randomness::from([3u8; RANDOMNESS_LENGTH]);
+        let bz = r.marshal_cbor().unwrap();
+        assert_eq!(Randomness::unmarshal_cbor(&bz).unwrap(), r);
+    }
+
+    #[test]
+    fn rejects_wrong_lengths() {
+        for len in [0, RANDOMNESS_LENGTH - 1, RANDOMNESS_LENGTH + 1] {
+            let bz = to_vec(&BytesSer(&vec![3u8; len])).unwrap();
+            assert!(Randomness::unmarshal_cbor(&bz).is_err());
+        }
     }
 }
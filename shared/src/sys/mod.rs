@@ -5,6 +5,20 @@ pub mod out;
 
 pub type BlockId = u32;
 pub type Codec = u64;
+/// An [`ExitCode`](crate::error::ExitCode), as passed to `vm::abort` and returned by `send::send`.
+/// Exit codes are always exchanged as their `u32` value; unknown values are not valid exit codes.
+pub type ExitCode = u32;
+
+/// The maximum length (in bytes) of the name of an explicit gas charge (`gas::charge`).
+pub const MAX_GAS_CHARGE_NAME_LEN: u32 = 64;
+
+/// The maximum amount of gas a single explicit gas charge (`gas::charge`) may charge: no more
+/// than a whole block's worth.
+pub const MAX_GAS_CHARGE: u64 = crate::BLOCK_GAS_LIMIT as u64;
+
+/// The maximum length (in bytes) of the entropy mixed into randomness drawn by an actor, from
+/// network version 16 on.
+pub const MAX_RANDOMNESS_ENTROPY_LEN: u32 = 1024;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
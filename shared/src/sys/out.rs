@@ -8,6 +8,10 @@
 //! Read more at https://github.com/rust-lang/rust/issues/73755.
 
 pub mod actor {
+    /// The size of each entry written by the `actor::resolve_addresses` syscall: a byte set to 1 if
+    /// the address was resolved (0 otherwise), followed by the little-endian actor ID.
+    pub const RESOLVED_ADDRESS_LEN: usize = 9;
+
     #[repr(C)]
     #[derive(Debug, Copy, Clone)]
     pub struct ResolveAddress {
@@ -40,8 +44,10 @@ pub mod send {
     #[repr(C)]
     #[derive(Debug, Copy, Clone)]
     pub struct Send {
-        pub exit_code: u32,
+        pub exit_code: crate::sys::ExitCode,
         pub return_id: BlockId,
+        /// The gas consumed by the receiver (and the actors it called in turn).
+        pub gas_used: i64,
     }
 }
 
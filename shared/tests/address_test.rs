@@ -5,10 +5,10 @@ use std::str::FromStr;
 
 use data_encoding::{DecodeError, DecodeKind};
 use fvm_shared::address::{
-    checksum, validate_checksum, Address, Error, Network, Protocol, BLS_PUB_LEN, PAYLOAD_HASH_LEN,
-    SECP_PUB_LEN,
+    checksum, derive_actor_address, validate_checksum, Address, Error, IdAssigner, Network,
+    Protocol, BLS_PUB_LEN, FIRST_NON_SINGLETON_ADDR, PAYLOAD_HASH_LEN, SECP_PUB_LEN,
 };
-use fvm_shared::encoding::{from_slice, Cbor};
+use fvm_shared::encoding::{from_slice, to_vec, Cbor};
 
 #[test]
 fn bytes() {
@@ -591,3 +591,40 @@ fn invalid_strings_tests() {
         assert!(Address::from_str(st).is_err());
     }
 }
+
+#[test]
+fn actor_address_derivation() {
+    let origin = Address::new_secp256k1(&[1; SECP_PUB_LEN]).unwrap();
+
+    let mut expected = to_vec(&origin).unwrap();
+    expected.extend_from_slice(&7u64.to_be_bytes());
+    expected.extend_from_slice(&2u64.to_be_bytes());
+    let addr = derive_actor_address(&origin, 7, 2).unwrap();
+    assert_eq!(addr, Address::new_actor(&expected));
+    assert_eq!(addr.protocol(), Protocol::Actor);
+
+    assert_ne!(addr, derive_actor_address(&origin, 7, 3).unwrap());
+    assert_ne!(addr, derive_actor_address(&origin, 8, 2).unwrap());
+}
+
+#[test]
+fn id_assignment() {
+    let mut assigner = IdAssigner::default();
+    assert_eq!(assigner.next_id(), FIRST_NON_SINGLETON_ADDR);
+
+    let secp = Address::new_secp256k1(&[1; SECP_PUB_LEN]).unwrap();
+    let bls = Address::new_bls(&[2; BLS_PUB_LEN]).unwrap();
+    assert_eq!(assigner.lookup(&secp), None);
+    assert_eq!(assigner.assign(&secp), 100);
+    assert_eq!(assigner.assign(&bls), 101);
+
+    // Known and ID addresses don't consume IDs.
+    assert_eq!(assigner.assign(&secp), 100);
+    assert_eq!(assigner.assign(&Address::new_id(5)), 5);
+    assert_eq!(assigner.next_id(), 102);
+
+    // The network isn't part of an address's identity.
+    let mut testnet_secp = secp;
+    testnet_secp.set_network(Network::Testnet);
+    assert_eq!(assigner.lookup(&testnet_secp), Some(100));
+}
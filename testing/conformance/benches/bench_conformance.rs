@@ -9,6 +9,7 @@ use std::time::Duration;
 use colored::Colorize;
 use criterion::*;
 use fvm::machine::Engine;
+use fvm::Profile;
 use fvm_conformance_tests::driver::*;
 use fvm_conformance_tests::report;
 use fvm_conformance_tests::vector::MessageVector;
@@ -16,7 +17,7 @@ use walkdir::WalkDir;
 
 mod bench_drivers;
 
-use crate::bench_drivers::{bench_vector_file, CheckStrength};
+use crate::bench_drivers::{bench_vector_file, CheckStrength, EngineReuse};
 
 /// Either grabs an environment variable called VECTOR and benches that test vector using criterion, or runs all of them in sequence. Displays output for results of benchmarking.
 fn bench_conformance(c: &mut Criterion) {
@@ -39,7 +40,7 @@ fn bench_conformance(c: &mut Criterion) {
         ),
     };
 
-    let engine = Engine::default();
+    let engine = Engine::new(&Profile::Validator.engine_config()).unwrap();
 
     // TODO: this is 30 seconds per benchmark... yeesh! once we get the setup running faster (by cloning VMs more efficiently), we can probably bring this down.
     let mut group = c.benchmark_group("conformance-tests");
@@ -75,6 +76,7 @@ fn bench_conformance(c: &mut Criterion) {
             CheckStrength::FullTest,
             &vector_path.display().to_string(),
             &engine,
+            EngineReuse::Shared,
         ) {
             Ok(()) => report!(
                 "SUCCESSFULLY BENCHED TEST FILE".on_green(),
@@ -4,7 +4,8 @@ use std::path::Path;
 use std::time::Duration;
 
 use criterion::*;
-use fvm::machine::{Engine, BURNT_FUNDS_ACTOR_ADDR};
+use fvm::constants::BURNT_FUNDS_ACTOR_ADDR;
+use fvm::machine::Engine;
 use fvm_conformance_tests::driver::*;
 use fvm_conformance_tests::vector::{ApplyMessage, MessageVector};
 use fvm_shared::econ::TokenAmount;
@@ -13,7 +14,7 @@ use fvm_shared::message::Message;
 use walkdir::WalkDir;
 
 mod bench_drivers;
-use crate::bench_drivers::{bench_vector_file, CheckStrength};
+use crate::bench_drivers::{bench_vector_file, CheckStrength, EngineReuse};
 
 /// benches only machine setup, no messages get sent. This is basically overhead of the benchmarks themselves.
 fn bench_init_only(
@@ -36,14 +37,17 @@ fn bench_init_only(
         CheckStrength::OnlyCheckSuccess,
         "bench_init_only",
         engine,
+        EngineReuse::Shared,
     )
 }
 
 /// benchmarks calling 500 simple state accesses. This benchmark computes the overhead of the message plus state access itself, doing a minimal amount of computation within the FVM.
+/// With `EngineReuse::Fresh`, every iteration has to re-link the actor code, which shows what the cached `InstancePre`s save.
 fn bench_500_simple_state_access(
     group: &mut BenchmarkGroup<measurement::WallTime>,
     path_to_setup: &Path,
     engine: &Engine,
+    engine_reuse: EngineReuse,
 ) -> anyhow::Result<()> {
     let five_hundred_state_accesses = (0..500)
         .map(|i| ApplyMessage {
@@ -77,8 +81,9 @@ fn bench_500_simple_state_access(
         group,
         &message_vector,
         CheckStrength::OnlyCheckSuccess,
-        "bench_500_simple_state_access",
+        &format!("bench_500_simple_state_access ({:?} engine)", engine_reuse),
         engine,
+        engine_reuse,
     )
 }
 /// runs overhead benchmarks, using the contents of the environment variable VECTOR as the starting FVM state
@@ -103,7 +108,9 @@ fn bench_conformance_overhead(c: &mut Criterion) {
 
     let engine = Engine::default();
     bench_init_only(&mut group, &path_to_setup, &engine).unwrap();
-    bench_500_simple_state_access(&mut group, &path_to_setup, &engine).unwrap();
+    bench_500_simple_state_access(&mut group, &path_to_setup, &engine, EngineReuse::Shared)
+        .unwrap();
+    bench_500_simple_state_access(&mut group, &path_to_setup, &engine, EngineReuse::Fresh).unwrap();
     group.finish();
 }
 
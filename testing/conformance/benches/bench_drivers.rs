@@ -1,5 +1,8 @@
 extern crate criterion;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+
 use criterion::*;
 use fvm::executor::{ApplyKind, DefaultExecutor, Executor};
 use fvm::machine::Engine;
@@ -28,6 +31,40 @@ pub fn apply_messages(messages: Vec<(Message, usize)>, mut exec: DefaultExecutor
     }
 }
 
+thread_local! {
+    /// Blockstores seeded from vector CARs, keyed by the (compressed) CAR itself.
+    static SEEDED_BLOCKSTORES: RefCell<HashMap<Vec<u8>, MemoryBlockstore>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Returns a blockstore seeded with the vector's CAR. The CAR is only decompressed and loaded the
+/// first time a given CAR is seen; later calls (e.g., for other benchmarks over the same vector)
+/// get a copy of the cached blockstore, so that seeding never shows up in the measurements.
+pub fn seeded_blockstore(vector: &MessageVector) -> MemoryBlockstore {
+    SEEDED_BLOCKSTORES.with(|cache| {
+        cache
+            .borrow_mut()
+            .entry(vector.car.clone())
+            .or_insert_with(|| {
+                async_std::task::block_on(vector.seed_blockstore())
+                    .unwrap()
+                    .0
+            })
+            .clone()
+    })
+}
+
+/// Controls whether benchmark iterations share the engine's module and pre-instantiation caches.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum EngineReuse {
+    /// Every iteration reuses the same engine, so modules are compiled and linked once.
+    Shared,
+    /// Every iteration gets a fresh engine. Modules are compiled during (untimed) machine setup,
+    /// but the linker and the per-code `InstancePre`s are rebuilt within the timed section.
+    Fresh,
+}
+
 /// Benches one vector variant using criterion. Clones `MessageVector`, clones `Blockstore`, clones a prepared list of message bytes with lengths, creates a new machine, initializes its wasm cache by loading some code, creates an executor, then times applying the messages.
 /// Currently needs some serious speedup, probably with respect to WASM caching and also machine setup/teardown.
 pub fn bench_vector_variant(
@@ -38,14 +75,19 @@ pub fn bench_vector_variant(
     messages_with_lengths: Vec<(Message, usize)>,
     bs: &MemoryBlockstore,
     engine: &Engine,
+    engine_reuse: EngineReuse,
 ) {
     group.bench_function(name, move |b| {
         b.iter_batched(
             || {
                 let vector = &(*vector).clone();
                 let bs = bs.clone();
+                let engine = match engine_reuse {
+                    EngineReuse::Shared => engine.clone(),
+                    EngineReuse::Fresh => Engine::default(),
+                };
                 // TODO next few lines don't impact the benchmarks, but it might make them run waaaay more slowly... ought to make a base copy of the machine and exec and deepcopy them each time.
-                let machine = TestMachine::new_for_vector(vector, variant, bs, engine.clone());
+                let machine = TestMachine::new_for_vector(vector, variant, bs, engine);
                 // can assume this works because it passed a test before this ran
                 let exec: DefaultExecutor<TestKernel> = DefaultExecutor::new(machine);
                 (messages_with_lengths.clone(), exec)
@@ -81,8 +123,9 @@ pub fn bench_vector_file(
     check_strength: CheckStrength,
     name: &str,
     engine: &Engine,
+    engine_reuse: EngineReuse,
 ) -> anyhow::Result<()> {
-    let (bs, _) = async_std::task::block_on(vector.seed_blockstore()).unwrap();
+    let bs = seeded_blockstore(vector);
 
     for variant in vector.preconditions.variants.iter() {
         let name = format!("{} | {}", name, variant.id);
@@ -100,6 +143,7 @@ pub fn bench_vector_file(
             }
             CheckStrength::NoChecks => VariantResult::Ok {
                 id: variant.id.clone(),
+                stats: Default::default(),
             },
         };
 
@@ -125,6 +169,7 @@ pub fn bench_vector_file(
                 messages_with_lengths,
                 &bs,
                 engine,
+                engine_reuse,
             );
         } else {
             return Err(anyhow::anyhow!("a test failed, get the tests passing/running before running benchmarks in {:?} mode: {}", check_strength, name));
@@ -0,0 +1,123 @@
+extern crate criterion;
+
+use std::env::var;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use criterion::*;
+use fvm::call_manager::DefaultCallManager;
+use fvm::executor::{ApplyKind, SpeculativeExecutor};
+use fvm::machine::{Engine, ReadOnlyMachine};
+use fvm::DefaultKernel;
+use fvm_conformance_tests::driver::*;
+use fvm_conformance_tests::externs::TestExterns;
+use fvm_conformance_tests::vector::MessageVector;
+use fvm_conformance_tests::vm::TestMachine;
+use fvm_shared::address::Protocol;
+use fvm_shared::blockstore::{MemoryBlockstore, ReadOnlyBlockstore, SyncBlockstore};
+use fvm_shared::crypto::signature::SECP_SIG_LEN;
+use fvm_shared::encoding::Cbor;
+use fvm_shared::message::Message;
+use walkdir::WalkDir;
+
+type SharedBlockstore = Arc<SyncBlockstore<MemoryBlockstore>>;
+type SpeculativeKernel =
+    DefaultKernel<DefaultCallManager<ReadOnlyMachine<SharedBlockstore, TestExterns>>>;
+
+/// Applies every message of the batch on top of the base state, as a mempool would when checking
+/// pending messages against the head of the chain.
+fn validate(exec: &SpeculativeExecutor<SharedBlockstore, TestExterns>, msgs: &[(Message, usize)]) {
+    for (msg, raw_length) in msgs {
+        exec.execute_message::<SpeculativeKernel>(msg.clone(), ApplyKind::Explicit, *raw_length)
+            .unwrap();
+    }
+}
+
+/// Benches speculatively applying the messages of the vector at `path` from several threads at
+/// once, all sharing the same snapshot of the vector's initial state.
+fn bench_speculative(c: &mut Criterion) {
+    pretty_env_logger::init();
+
+    let path = match var("VECTOR") {
+        Ok(v) => Path::new(v.as_str()).to_path_buf(),
+        Err(_) => WalkDir::new("test-vectors/corpus")
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(is_runnable)
+            .map(|e| e.path().to_path_buf())
+            .next()
+            .unwrap(),
+    };
+    let vector = MessageVector::from_file(&path).unwrap();
+    assert!(
+        vector.is_supported(),
+        "chosen vector was filtered out by selector"
+    );
+    let variant = &vector.preconditions.variants[0];
+
+    let (bs, _) = async_std::task::block_on(vector.seed_blockstore()).unwrap();
+    // The base machine can only read from the blockstore, so the actors bundles have to be in there
+    // already.
+    TestMachine::import_actors(&bs);
+    let bs: SharedBlockstore = Arc::new(SyncBlockstore::new(bs));
+
+    let machine = TestMachine::default_machine_for_vector(
+        &vector,
+        variant,
+        ReadOnlyBlockstore::new(bs),
+        Engine::default(),
+        TestMachine::config_for_vector(&vector),
+    );
+    let exec = SpeculativeExecutor::new(machine);
+
+    let msgs: Arc<Vec<(Message, usize)>> = Arc::new(
+        vector
+            .apply_messages
+            .iter()
+            .map(|m| {
+                let msg = Message::unmarshal_cbor(&m.bytes).unwrap();
+                let mut raw_length = m.bytes.len();
+                if msg.from.protocol() == Protocol::Secp256k1 {
+                    // 65 bytes signature + 1 byte type + 3 bytes for field info.
+                    raw_length += SECP_SIG_LEN + 4;
+                }
+                (msg, raw_length)
+            })
+            .collect(),
+    );
+
+    // The first message is applied on top of the initial state in the vector too, so it must get
+    // the same receipt.
+    if let Some((msg, raw_length)) = msgs.first() {
+        let ret = exec
+            .execute_message::<SpeculativeKernel>(msg.clone(), ApplyKind::Explicit, *raw_length)
+            .unwrap();
+        assert_eq!(ret.msg_receipt, vector.postconditions.receipts[0]);
+    }
+
+    let mut group = c.benchmark_group("speculative-execution");
+    group.measurement_time(Duration::new(30, 0));
+    for threads in [1, 2, 4, 8] {
+        group.throughput(Throughput::Elements((threads * msgs.len()) as u64));
+        group.bench_function(format!("{} thread(s)", threads), |b| {
+            b.iter(|| {
+                let handles: Vec<_> = (0..threads)
+                    .map(|_| {
+                        let exec = exec.clone();
+                        let msgs = msgs.clone();
+                        thread::spawn(move || validate(&exec, &msgs))
+                    })
+                    .collect();
+                for handle in handles {
+                    handle.join().unwrap();
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches_speculative, bench_speculative);
+criterion_main!(benches_speculative);
@@ -0,0 +1,268 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Applies test vectors under two machine configurations and checks that both produce the same
+//! receipts and state roots, to catch nondeterminism introduced by features that are only supposed
+//! to affect performance or observability.
+//!
+//! ```text
+//! diff-exec <left> <right> <vector or directory>...
+//! ```
+//!
+//! Where `left` and `right` are one of:
+//!
+//! - `default`: the configuration the conformance tests run with.
+//! - `tracing`: records syscalls and calls in the execution trace.
+//! - `no-debug`: disables debug mode.
+//! - `no-opt`: compiles actors without cranelift optimizations.
+//! - `warm-cache`: applies the vector once to warm up the engine's module caches, then applies it
+//!   again on the same engine.
+//! - `price-list=<file>`: uses the price lists defined in the given TOML or JSON file.
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::exit;
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm::executor::{ApplyKind, ApplyRet, DefaultExecutor, Executor};
+use fvm::gas::PriceListRegistry;
+use fvm::machine::Engine;
+use fvm::Config;
+use fvm_conformance_tests::driver::is_runnable;
+use fvm_conformance_tests::vector::{MessageVector, Selector, Variant};
+use fvm_conformance_tests::vm::{TestKernel, TestMachine};
+use fvm_shared::address::Protocol;
+use fvm_shared::blockstore::MemoryBlockstore;
+use fvm_shared::crypto::signature::SECP_SIG_LEN;
+use fvm_shared::encoding::Cbor;
+use fvm_shared::message::Message;
+use walkdir::WalkDir;
+
+/// A machine configuration to apply vectors with.
+enum Setup {
+    Default,
+    Tracing,
+    NoDebug,
+    NoOpt,
+    WarmCache,
+    PriceList(PriceListRegistry),
+}
+
+impl Setup {
+    fn parse(name: &str) -> anyhow::Result<Setup> {
+        Ok(match name {
+            "default" => Setup::Default,
+            "tracing" => Setup::Tracing,
+            "no-debug" => Setup::NoDebug,
+            "no-opt" => Setup::NoOpt,
+            "warm-cache" => Setup::WarmCache,
+            _ => match name.strip_prefix("price-list=") {
+                Some(path) => {
+                    let definition = fs::read_to_string(path)
+                        .with_context(|| format!("failed to read price lists from {}", path))?;
+                    let registry = if path.ends_with(".json") {
+                        PriceListRegistry::from_json(&definition)?
+                    } else {
+                        PriceListRegistry::from_toml(&definition)?
+                    };
+                    Setup::PriceList(registry)
+                }
+                None => return Err(anyhow!("unknown configuration: {}", name)),
+            },
+        })
+    }
+
+    fn engine(&self) -> Engine {
+        match self {
+            Setup::NoOpt => Engine::new(
+                wasmtime::Config::default().cranelift_opt_level(wasmtime::OptLevel::None),
+            )
+            .expect("failed to construct engine"),
+            _ => Engine::default(),
+        }
+    }
+
+    fn config(&self, v: &MessageVector) -> Config {
+        let mut config = TestMachine::config_for_vector(v);
+        match self {
+            Setup::Tracing => {
+                config.trace_syscalls = true;
+                config.trace_calls = true;
+            }
+            Setup::NoDebug => config.debug = false,
+            Setup::PriceList(registry) => config.price_lists = registry.clone(),
+            Setup::Default | Setup::NoOpt | Setup::WarmCache => {}
+        }
+        config
+    }
+
+    /// Applies all messages of the variant, returning their results and the final state root.
+    fn apply(
+        &self,
+        bs: &MemoryBlockstore,
+        v: &MessageVector,
+        variant: &Variant,
+    ) -> anyhow::Result<(Vec<ApplyRet>, Cid)> {
+        let engine = self.engine();
+        if let Setup::WarmCache = self {
+            apply_with(bs.clone(), v, variant, engine.clone(), self.config(v))?;
+        }
+        apply_with(bs.clone(), v, variant, engine, self.config(v))
+    }
+}
+
+fn apply_with(
+    bs: MemoryBlockstore,
+    v: &MessageVector,
+    variant: &Variant,
+    engine: Engine,
+    config: Config,
+) -> anyhow::Result<(Vec<ApplyRet>, Cid)> {
+    let machine = TestMachine::new_for_vector_with_config(v, variant, bs, engine, config);
+    let mut exec: DefaultExecutor<TestKernel> = DefaultExecutor::new(machine);
+
+    let mut rets = Vec::with_capacity(v.apply_messages.len());
+    for (i, m) in v.apply_messages.iter().enumerate() {
+        let msg = Message::unmarshal_cbor(&m.bytes)?;
+
+        let mut raw_length = m.bytes.len();
+        if msg.from.protocol() == Protocol::Secp256k1 {
+            // 65 bytes signature + 1 byte type + 3 bytes for field info.
+            raw_length += SECP_SIG_LEN + 4;
+        }
+
+        let ret = exec
+            .execute_message(msg, ApplyKind::Explicit, raw_length)
+            .with_context(|| format!("failed to apply msg {}", i))?;
+        rets.push(ret);
+    }
+    let root = exec.flush().context("flushing executor failed")?;
+    Ok((rets, root))
+}
+
+/// Returns a description of the first difference between the two executions, if any.
+fn diff(left: &(Vec<ApplyRet>, Cid), right: &(Vec<ApplyRet>, Cid)) -> Option<String> {
+    let ((left_rets, left_root), (right_rets, right_root)) = (left, right);
+    for (i, (l, r)) in left_rets.iter().zip(right_rets).enumerate() {
+        if l.msg_receipt != r.msg_receipt {
+            return Some(format!(
+                "receipt of msg {}: {:?} != {:?}",
+                i, l.msg_receipt, r.msg_receipt
+            ));
+        }
+        if l.penalty != r.penalty || l.miner_tip != r.miner_tip {
+            return Some(format!(
+                "penalty and miner tip of msg {}: ({}, {}) != ({}, {})",
+                i, l.penalty, l.miner_tip, r.penalty, r.miner_tip
+            ));
+        }
+    }
+    if left_root != right_root {
+        return Some(format!("state root: {} != {}", left_root, right_root));
+    }
+    None
+}
+
+enum Outcome {
+    Same,
+    Skipped(String),
+    Different(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Same => write!(f, "same"),
+            Outcome::Skipped(reason) => write!(f, "skipped: {}", reason),
+            Outcome::Different(diff) => write!(f, "DIFFERENT: {}", diff),
+        }
+    }
+}
+
+fn diff_vector(path: &Path, left: &Setup, right: &Setup) -> anyhow::Result<Vec<(String, Outcome)>> {
+    let vector = MessageVector::from_file(path)?;
+    if !vector.selector.as_ref().map_or(true, Selector::supported) {
+        return Ok(vec![(
+            String::new(),
+            Outcome::Skipped("selector not supported".into()),
+        )]);
+    }
+
+    let (bs, _) = async_std::task::block_on(vector.seed_blockstore())?;
+    let mut outcomes = Vec::new();
+    for variant in vector.preconditions.variants.iter() {
+        let id = variant.id.clone();
+        match variant.network_version() {
+            Some(nv) if TestMachine::supports_network_version(nv) => {}
+            _ => {
+                let reason = format!("network version {} not supported", variant.nv);
+                outcomes.push((id, Outcome::Skipped(reason)));
+                continue;
+            }
+        }
+
+        let outcome = match (
+            left.apply(&bs, &vector, variant),
+            right.apply(&bs, &vector, variant),
+        ) {
+            (Ok(l), Ok(r)) => diff(&l, &r).map_or(Outcome::Same, Outcome::Different),
+            // Both executions failing the same way is just as deterministic.
+            (Err(l), Err(r)) if l.to_string() == r.to_string() => Outcome::Same,
+            (l, r) => Outcome::Different(format!(
+                "left {}, right {}",
+                l.map_or_else(|e| format!("failed: {:#}", e), |_| "succeeded".into()),
+                r.map_or_else(|e| format!("failed: {:#}", e), |_| "succeeded".into()),
+            )),
+        };
+        outcomes.push((id, outcome));
+    }
+    Ok(outcomes)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 3 {
+        eprintln!("usage: diff-exec <left> <right> <vector or directory>...");
+        exit(2);
+    }
+    let (left, right) = match (Setup::parse(&args[0]), Setup::parse(&args[1])) {
+        (Ok(left), Ok(right)) => (left, right),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{:#}", e);
+            exit(2);
+        }
+    };
+
+    let mut differences = 0;
+    for root in &args[2..] {
+        for entry in WalkDir::new(root)
+            .into_iter()
+            .filter_map(Result::ok)
+            .filter(|e| e.file_type().is_file() && is_runnable(e))
+        {
+            let path = entry.path();
+            match diff_vector(path, &left, &right) {
+                Ok(outcomes) => {
+                    for (id, outcome) in outcomes {
+                        if let Outcome::Different(_) = outcome {
+                            differences += 1;
+                        }
+                        println!("{} {}: {}", path.display(), id, outcome);
+                    }
+                }
+                Err(e) => {
+                    differences += 1;
+                    println!("{}: failed to load vector: {:#}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    if differences > 0 {
+        eprintln!("{} variant(s) executed differently", differences);
+        exit(1);
+    }
+}
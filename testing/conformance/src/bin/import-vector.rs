@@ -0,0 +1,44 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Converts a message exported from Lotus into a test vector (see
+//! [`fvm_conformance_tests::import`] for the export format).
+//!
+//! ```text
+//! import-vector <message context> [<output vector>]
+//! ```
+//!
+//! The vector is written to standard output if no output file is given.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::process::exit;
+
+use anyhow::Context as _;
+use fvm_conformance_tests::import::MessageContext;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 2 {
+        eprintln!("usage: import-vector <message context> [<output vector>]");
+        exit(2);
+    }
+
+    if let Err(e) = run(&args[0], args.get(1)) {
+        eprintln!("error: {:#}", e);
+        exit(1);
+    }
+}
+
+fn run(context: &str, output: Option<&String>) -> anyhow::Result<()> {
+    let context = MessageContext::from_file(Path::new(context))?;
+    match output {
+        Some(path) => {
+            let file = File::create(path).with_context(|| format!("failed to create {}", path))?;
+            context.write_vector(BufWriter::new(file))
+        }
+        None => context.write_vector(io::stdout().lock()),
+    }
+}
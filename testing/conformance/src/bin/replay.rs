@@ -0,0 +1,43 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Replays a message log exported from a node (see [`fvm_conformance_tests::replay`] for the
+//! format), and reports the first epoch where the computed state root diverges from the recorded
+//! one.
+//!
+//! ```text
+//! replay <message log>
+//! ```
+//!
+//! Exits with 1 if the replay diverged, and with 2 if it couldn't be run.
+
+use std::env;
+use std::path::Path;
+use std::process::exit;
+
+use fvm_conformance_tests::replay::MessageLog;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() != 1 {
+        eprintln!("usage: replay <message log>");
+        exit(2);
+    }
+
+    let result = MessageLog::from_file(Path::new(&args[0]))
+        .and_then(|log| log.replay(|epoch, root| println!("epoch {}: {}", epoch, root)));
+    match result {
+        Ok(None) => println!("no divergence"),
+        Ok(Some(d)) => {
+            println!(
+                "diverged at epoch {}: computed state root {}, expected {}",
+                d.epoch, d.actual, d.expected
+            );
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            exit(2);
+        }
+    }
+}
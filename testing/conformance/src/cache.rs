@@ -0,0 +1,94 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! A local record of the vectors that passed, so that re-running the corpus while developing only
+//! runs the vectors that may have changed outcome.
+//!
+//! A pass is recorded under the hash of the vector and the hash of the running executable, which
+//! embeds the FVM, the runner and their configuration: changing any of them, or the vector,
+//! invalidates it. Failures are never recorded.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context as _;
+use fvm_shared::encoding::blake2b_256;
+
+/// A directory of recorded passes.
+pub struct ResultCache {
+    dir: PathBuf,
+    build: String,
+}
+
+impl ResultCache {
+    /// Opens (creating it if needed) the cache in the given directory, for the running executable.
+    pub fn open(dir: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let exe = std::env::current_exe().context("failed to locate the running executable")?;
+        let exe = fs::read(&exe).with_context(|| format!("failed to read {}", exe.display()))?;
+        Self::for_build(dir, &exe)
+    }
+
+    /// Opens the cache in the given directory, for the given build of the runner.
+    fn for_build(dir: impl Into<PathBuf>, build: &[u8]) -> anyhow::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create the cache {}", dir.display()))?;
+        Ok(ResultCache {
+            dir,
+            build: hex_hash(build),
+        })
+    }
+
+    /// Returns true if the vector at the given path already passed with this build.
+    pub fn passed(&self, vector: &Path) -> anyhow::Result<bool> {
+        Ok(self.entry(vector)?.exists())
+    }
+
+    /// Records that the vector at the given path passed with this build.
+    pub fn record_pass(&self, vector: &Path) -> anyhow::Result<()> {
+        let entry = self.entry(vector)?;
+        fs::write(&entry, vector.display().to_string())
+            .with_context(|| format!("failed to write {}", entry.display()))
+    }
+
+    fn entry(&self, vector: &Path) -> anyhow::Result<PathBuf> {
+        let json =
+            fs::read(vector).with_context(|| format!("failed to read {}", vector.display()))?;
+        Ok(self
+            .dir
+            .join(format!("{}-{}", &self.build[..16], hex_hash(&json))))
+    }
+}
+
+fn hex_hash(data: &[u8]) -> String {
+    blake2b_256(data).iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{:02x}", b);
+        s
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_are_per_build_and_vector() {
+        let dir = std::env::temp_dir().join(format!("result-cache-{}", std::process::id()));
+        let vector = dir.join("vector.json");
+        let cache = ResultCache::for_build(&dir, b"build 1").unwrap();
+        fs::write(&vector, "{}").unwrap();
+
+        assert!(!cache.passed(&vector).unwrap());
+        cache.record_pass(&vector).unwrap();
+        assert!(cache.passed(&vector).unwrap());
+
+        let rebuilt = ResultCache::for_build(&dir, b"build 2").unwrap();
+        assert!(!rebuilt.passed(&vector).unwrap());
+
+        fs::write(&vector, "{\"changed\": true}").unwrap();
+        assert!(!cache.passed(&vector).unwrap());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,4 +1,6 @@
 use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Result};
 use cid::Cid;
@@ -8,7 +10,7 @@ use fvm::kernel::Context;
 use fvm::machine::{Engine, Machine};
 use fvm::state_tree::{ActorState, StateTree};
 use fvm_shared::address::Protocol;
-use fvm_shared::blockstore::{CborStore, MemoryBlockstore};
+use fvm_shared::blockstore::{Blockstore, CborStore, MemoryBlockstore};
 use fvm_shared::crypto::signature::SECP_SIG_LEN;
 use fvm_shared::encoding::Cbor;
 use fvm_shared::message::Message;
@@ -18,6 +20,7 @@ use libipld_core::ipld::Ipld;
 use regex::Regex;
 use walkdir::DirEntry;
 
+use crate::lotus_trace::compare_traces;
 use crate::vector::{MessageVector, Variant};
 use crate::vm::{TestKernel, TestMachine};
 
@@ -29,19 +32,25 @@ lazy_static! {
 
 /// Checks if the file is a runnable vector.
 pub fn is_runnable(entry: &DirEntry) -> bool {
-    let file_name = match entry.path().to_str() {
+    is_runnable_path(entry.path())
+}
+
+/// Checks if the file at the given path is a runnable vector.
+pub fn is_runnable_path(path: &Path) -> bool {
+    let file_name = match path.to_str() {
         Some(file) => file,
         None => return false,
     };
 
     for rx in SKIP_TESTS.iter() {
         if rx.is_match(file_name) {
-            println!("SKIPPING: {}", file_name);
+            tracing::info!(file = file_name, "skipping vector");
             return false;
         }
     }
 
-    file_name.ends_with(".json")
+    // Lotus traces live next to the vectors, but aren't vectors themselves.
+    file_name.ends_with(".json") && !file_name.ends_with(".trace.json")
 }
 
 /// Compares the result of running a message with the expected result.
@@ -95,7 +104,7 @@ fn compare_actors(
     if actual == expected {
         return Ok(());
     }
-    log::error!(
+    tracing::error!(
         "{} actor state differs: {:?} != {:?}",
         identifier,
         actual,
@@ -107,11 +116,11 @@ fn compare_actors(
             let a_root: Vec<Ipld> = bs.get_cbor(&a.state)?.unwrap();
             let e_root: Vec<Ipld> = bs.get_cbor(&e.state)?.unwrap();
             if a_root.len() != e_root.len() {
-                log::error!("states have different numbers of fields")
+                tracing::error!("states have different numbers of fields")
             } else {
                 for (f, (af, ef)) in a_root.iter().zip(e_root.iter()).enumerate() {
                     if af != ef {
-                        log::error!("mismatched field {}: {:#?} != {:#?}", f, af, ef);
+                        tracing::error!("mismatched field {}: {:#?} != {:#?}", f, af, ef);
                     }
                 }
             }
@@ -170,14 +179,135 @@ fn compare_state_roots(bs: &MemoryBlockstore, root: &Cid, vector: &MessageVector
     ));
 }
 
+/// How long a variant took to execute, and how much gas it used.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct VariantStats {
+    /// The wall time spent applying the messages and flushing the state tree, excluding the
+    /// checks against the postconditions.
+    pub wall_time: Duration,
+    /// The total gas used by the messages.
+    pub gas_used: u64,
+}
+
+impl VariantStats {
+    /// Returns the gas used per second of wall time.
+    pub fn gas_per_second(&self) -> f64 {
+        let secs = self.wall_time.as_secs_f64();
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.gas_used as f64 / secs
+    }
+}
+
+impl Display for VariantStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3?}, {} gas ({:.2} Mgas/s)",
+            self.wall_time,
+            self.gas_used,
+            self.gas_per_second() / 1e6
+        )
+    }
+}
+
+/// The stage of running a variant at which it failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FailureStage {
+    /// A message of the vector couldn't be decoded.
+    Parse,
+    /// The blockstore isn't seeded with the vector's preconditions.
+    Seed,
+    /// Applying a message, or flushing the resulting state, failed with an error.
+    Execute,
+    /// The receipts or the final state don't match the vector's postconditions.
+    Postcondition,
+}
+
+impl Display for FailureStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FailureStage::Parse => "parse",
+            FailureStage::Seed => "seed",
+            FailureStage::Execute => "execute",
+            FailureStage::Postcondition => "postcondition",
+        })
+    }
+}
+
+/// A postcondition of a vector that a variant didn't meet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Mismatch {
+    /// The receipt of the message at the given index.
+    Receipt {
+        index: usize,
+        expected: Receipt,
+        actual: Receipt,
+    },
+    /// The state root after applying all the messages.
+    StateRoot { expected: Cid, actual: Cid },
+}
+
+/// Why a variant failed, structured so that failures can be categorized without parsing the
+/// reason.
+#[derive(Debug)]
+pub struct VariantFailure {
+    /// The stage at which the variant failed.
+    pub stage: FailureStage,
+    /// The index of the message being decoded, applied or checked, if the failure is specific to
+    /// one.
+    pub message: Option<usize>,
+    /// The postcondition that wasn't met, if any.
+    pub mismatch: Option<Mismatch>,
+    /// A description of the failure, including the details not captured by the other fields.
+    pub reason: anyhow::Error,
+}
+
+impl VariantFailure {
+    /// Creates a failure at the given stage, not specific to any message.
+    pub fn new(stage: FailureStage, reason: anyhow::Error) -> Self {
+        VariantFailure {
+            stage,
+            message: None,
+            mismatch: None,
+            reason,
+        }
+    }
+
+    fn at_message(self, index: usize) -> Self {
+        VariantFailure {
+            message: Some(index),
+            ..self
+        }
+    }
+
+    fn with_mismatch(self, mismatch: Mismatch) -> Self {
+        VariantFailure {
+            mismatch: Some(mismatch),
+            ..self
+        }
+    }
+}
+
+impl Display for VariantFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failure", self.stage)?;
+        if let Some(index) = self.message {
+            write!(f, " at message {}", index)?;
+        }
+        write!(f, ": {:#}", self.reason)
+    }
+}
+
 /// Represents the result from running a vector.
 pub enum VariantResult {
     /// The vector succeeded.
-    Ok { id: String },
+    Ok { id: String, stats: VariantStats },
     /// A variant was skipped, due to the specified reason.
     Skipped { reason: String, id: String },
-    /// A variant failed, due to the specified error.
-    Failed { reason: anyhow::Error, id: String },
+    /// A variant failed.
+    Failed { failure: VariantFailure, id: String },
 }
 
 pub fn run_variant(
@@ -188,14 +318,59 @@ pub fn run_variant(
     check_correctness: bool,
 ) -> anyhow::Result<VariantResult> {
     let id = variant.id.clone();
+    let _span = tracing::info_span!("variant", id = %id).entered();
+
+    // Skip variants for network versions we can't execute.
+    match variant.network_version() {
+        Some(nv) if TestMachine::supports_network_version(nv) => {}
+        _ => {
+            return Ok(VariantResult::Skipped {
+                reason: format!("network version {} not supported", variant.nv),
+                id,
+            })
+        }
+    }
+
+    // The FVM doesn't emit actor events yet, so they can't be compared. Vectors that don't
+    // expect any are checked as usual.
+    if check_correctness && v.postconditions.expects_events() {
+        return Ok(VariantResult::Skipped {
+            reason: "actor events not supported".into(),
+            id,
+        });
+    }
+
+    let failed = |failure: VariantFailure| -> anyhow::Result<VariantResult> {
+        Ok(VariantResult::Failed {
+            id: id.clone(),
+            failure,
+        })
+    };
+
+    if !bs.has(&v.preconditions.state_tree.root_cid)? {
+        return failed(VariantFailure::new(
+            FailureStage::Seed,
+            anyhow!(
+                "precondition state root {} not in the blockstore",
+                v.preconditions.state_tree.root_cid
+            ),
+        ));
+    }
 
     // Construct the Machine.
     let machine = TestMachine::new_for_vector(v, variant, bs, engine.clone());
     let mut exec: DefaultExecutor<TestKernel> = DefaultExecutor::new(machine);
+    let mut stats = VariantStats::default();
 
     // Apply all messages in the vector.
     for (i, m) in v.apply_messages.iter().enumerate() {
-        let msg = Message::unmarshal_cbor(&m.bytes)?;
+        let msg = match Message::unmarshal_cbor(&m.bytes) {
+            Ok(msg) => msg,
+            Err(e) => {
+                let failure = VariantFailure::new(FailureStage::Parse, e.into()).at_message(i);
+                return failed(failure);
+            }
+        };
 
         // Execute the message.
         let mut raw_length = m.bytes.len();
@@ -204,51 +379,74 @@ pub fn run_variant(
             raw_length += SECP_SIG_LEN + 4;
         }
 
+        let start = Instant::now();
         let ret = match exec.execute_message(msg, ApplyKind::Explicit, raw_length) {
             Ok(ret) => ret,
-            Err(e) => return Ok(VariantResult::Failed { id, reason: e }),
+            Err(e) => return failed(VariantFailure::new(FailureStage::Execute, e).at_message(i)),
         };
+        stats.wall_time += start.elapsed();
+        stats.gas_used += ret.msg_receipt.gas_used as u64;
 
         if check_correctness {
             // Compare the actual receipt with the expected receipt.
             let expected_receipt = &v.postconditions.receipts[i];
-            if let Err(err) = check_msg_result(expected_receipt, &ret, i) {
-                return Ok(VariantResult::Failed { id, reason: err });
+            if let Err(mut err) = check_msg_result(expected_receipt, &ret, i) {
+                let lotus_trace = v.lotus_traces.as_ref().and_then(|traces| traces.get(i));
+                if let Some(divergence) =
+                    lotus_trace.and_then(|t| compare_traces(t, &ret.exec_trace))
+                {
+                    err = err.context(format!("diverged from lotus trace at {}", divergence));
+                }
+                let mismatch = Mismatch::Receipt {
+                    index: i,
+                    expected: expected_receipt.clone(),
+                    actual: ret.msg_receipt.clone(),
+                };
+                let failure = VariantFailure::new(FailureStage::Postcondition, err)
+                    .at_message(i)
+                    .with_mismatch(mismatch);
+                return failed(failure);
             }
         }
     }
 
     // Flush the machine, obtain the blockstore, and compare the
     // resulting state root with the expected state root.
+    let start = Instant::now();
     let final_root = match exec.flush() {
         Ok(cid) => cid,
         Err(err) => {
-            return Ok(VariantResult::Failed {
-                id,
-                reason: err.context("flushing executor failed"),
-            });
+            let err = err.context("flushing executor failed");
+            return failed(VariantFailure::new(FailureStage::Execute, err));
         }
     };
+    stats.wall_time += start.elapsed();
 
     let machine = match exec.consume() {
         Some(machine) => machine,
         None => {
-            return Ok(VariantResult::Failed {
-                id,
-                reason: anyhow!("machine poisoned"),
-            })
+            let err = anyhow!("machine poisoned");
+            return failed(VariantFailure::new(FailureStage::Execute, err));
         }
     };
     if check_correctness {
-        let bs = machine.consume().consume();
+        let bs = machine.into_store();
 
         if let Err(err) = compare_state_roots(&bs, &final_root, v) {
-            return Ok(VariantResult::Failed {
-                id,
-                reason: err.context("comparing state roots failed"),
-            });
+            let mut failure = VariantFailure::new(
+                FailureStage::Postcondition,
+                err.context("comparing state roots failed"),
+            );
+            let expected = v.postconditions.state_tree.root_cid;
+            if final_root != expected {
+                failure = failure.with_mismatch(Mismatch::StateRoot {
+                    expected,
+                    actual: final_root,
+                });
+            }
+            return failed(failure);
         }
     }
 
-    Ok(VariantResult::Ok { id })
+    Ok(VariantResult::Ok { id, stats })
 }
@@ -1,35 +1,57 @@
-use fvm::externs::{Consensus, Externs, Rand};
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+use cid::Cid;
+use fvm::externs::{Chain, CircSupply, CodePolicy, Consensus, Externs, Rand};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
 
 use crate::rand::ReplayingRand;
-use crate::vector::Randomness;
+use crate::vector::{Randomness, TipsetCid};
 
 /// The externs stub for testing. Forwards randomness requests to the randomness
-/// replayer, which replays randomness stored in the vector.
+/// replayer, which replays randomness stored in the vector, and replays the tipset
+/// CIDs stored in the vector.
+#[derive(Clone)]
 pub struct TestExterns {
     rand: ReplayingRand,
+    tipset_cids: HashMap<ChainEpoch, Cid>,
 }
 
 impl TestExterns {
-    /// Creates a new TestExterns from randomness contained in a vector.
-    pub fn new(r: &Randomness) -> Self {
+    /// Creates a new TestExterns from randomness and tipset CIDs contained in a vector.
+    pub fn new(r: &Randomness, tipset_cids: &[TipsetCid]) -> Self {
         TestExterns {
             rand: ReplayingRand::new(r.as_slice()),
+            tipset_cids: tipset_cids.iter().map(|t| (t.epoch, t.cid)).collect(),
         }
     }
 }
 
 impl Externs for TestExterns {}
 
+impl CodePolicy for TestExterns {}
+
+impl CircSupply for TestExterns {}
+
+impl Chain for TestExterns {
+    fn get_tipset_cid(&self, epoch: ChainEpoch) -> anyhow::Result<Cid> {
+        self.tipset_cids
+            .get(&epoch)
+            .copied()
+            .ok_or_else(|| anyhow!("no tipset cid recorded for epoch {}", epoch))
+    }
+}
+
 impl Rand for TestExterns {
     fn get_chain_randomness(
         &self,
         pers: DomainSeparationTag,
         round: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         self.rand.get_chain_randomness(pers, round, entropy)
     }
 
@@ -38,7 +60,7 @@ impl Rand for TestExterns {
         pers: DomainSeparationTag,
         round: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         self.rand.get_beacon_randomness(pers, round, entropy)
     }
 }
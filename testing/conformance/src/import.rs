@@ -0,0 +1,380 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Conversion of messages exported from Lotus into test vectors, so that consensus divergences
+//! reported by node operators can be turned into regression vectors.
+//!
+//! The export is described by a JSON "message context", which refers to files next to it:
+//!
+//! ```json
+//! {
+//!   "id": "divergence-1234",
+//!   "description": "optional",
+//!   "car": "prestate.car",
+//!   "state_root": {"/": "bafy2bza..."},
+//!   "message": "message.cbor",
+//!   "epoch": 1594000,
+//!   "nv": 15,
+//!   "basefee": "100",
+//!   "circ_supply": "1000000000000000000000000",
+//!   "randomness": "randomness.json",
+//!   "receipt": {"exit_code": 0, "return": "", "gas_used": 1234567},
+//!   "post_state_root": {"/": "bafy2bza..."}
+//! }
+//! ```
+//!
+//! - `car` is a CAR export of the state the message is applied on, gzipped or not.
+//! - `state_root` defaults to the first root of the CAR.
+//! - `message` is the CBOR encoded message, signed or not.
+//! - `basefee` and `circ_supply` are in attoFIL, as strings.
+//! - `randomness` (optional) is the randomness drawn while applying the message, recorded in the
+//!   test vector format.
+//! - `receipt` and `post_state_root` are the results Lotus got, which the vector expects.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use fvm::state_tree::StateTree;
+use fvm_ipld_car::load_car;
+use fvm_shared::blockstore::{Blockstore, MemoryBlockstore};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::encoding::Cbor;
+use fvm_shared::message::{Message, SignedMessage};
+use fvm_shared::version::NetworkVersion;
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::cidjson::{self, CidJsonRef};
+use crate::vector::Randomness;
+
+/// A message exported from Lotus, with everything needed to apply it again.
+#[derive(Debug, Deserialize)]
+pub struct MessageContext {
+    pub id: String,
+    #[serde(default)]
+    pub description: String,
+    pub car: PathBuf,
+    #[serde(default, with = "cidjson::opt")]
+    pub state_root: Option<Cid>,
+    pub message: PathBuf,
+    pub epoch: ChainEpoch,
+    pub nv: u32,
+    #[serde(deserialize_with = "u128_from_str")]
+    pub basefee: u128,
+    #[serde(deserialize_with = "u128_from_str")]
+    pub circ_supply: u128,
+    #[serde(default)]
+    pub randomness: Option<PathBuf>,
+    pub receipt: ReceiptJson,
+    #[serde(with = "cidjson")]
+    pub post_state_root: Cid,
+}
+
+/// A receipt, as found in test vectors.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReceiptJson {
+    pub exit_code: u32,
+    /// Base64 encoded.
+    #[serde(rename = "return")]
+    pub return_value: String,
+    pub gas_used: i64,
+}
+
+pub(crate) fn u128_from_str<'de, D>(deserializer: D) -> Result<u128, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+impl MessageContext {
+    /// Loads a message context, resolving the files it refers to relative to it.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read message context {}", path.display()))?;
+        let mut context: MessageContext =
+            serde_json::from_str(&json).context("invalid message context")?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        context.car = dir.join(&context.car);
+        context.message = dir.join(&context.message);
+        context.randomness = context.randomness.map(|p| dir.join(p));
+        Ok(context)
+    }
+
+    /// Checks the exported data and writes it out as a JSON test vector.
+    pub fn write_vector<W: Write>(&self, out: W) -> anyhow::Result<()> {
+        if NetworkVersion::try_from(self.nv).is_err() {
+            return Err(anyhow!("unknown network version {}", self.nv));
+        }
+        base64::decode(&self.receipt.return_value).context("invalid receipt return value")?;
+
+        let car = read_car(&self.car)?;
+        let bs = MemoryBlockstore::new();
+        let roots = async_std::task::block_on(load_car(&bs, futures::io::Cursor::new(&car)))
+            .context("invalid CAR")?;
+        let state_root = match self.state_root.or_else(|| roots.first().copied()) {
+            Some(root) => root,
+            None => return Err(anyhow!("no state root given, and the CAR has no roots")),
+        };
+        if !bs.has(&state_root)? {
+            return Err(anyhow!(
+                "the CAR doesn't contain the state root {}",
+                state_root
+            ));
+        }
+        StateTree::new_from_root(&bs, &state_root)
+            .map_err(|e| anyhow!("failed to load the state tree: {}", e))?;
+
+        let message = read_message(&self.message)?;
+
+        let randomness = match &self.randomness {
+            Some(path) => {
+                let json = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read randomness {}", path.display()))?;
+                let randomness: serde_json::Value = serde_json::from_str(&json)?;
+                serde_json::from_value::<Randomness>(randomness.clone())
+                    .context("invalid randomness")?;
+                randomness
+            }
+            None => serde_json::Value::Array(Vec::new()),
+        };
+
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&car)?;
+
+        let vector = VectorJson {
+            class: "message",
+            meta: MetaJson {
+                id: &self.id,
+                description: &self.description,
+                gen: [GenerationJson {
+                    source: "import-vector",
+                    version: env!("CARGO_PKG_VERSION"),
+                }],
+            },
+            car: base64::encode(gz.finish()?),
+            preconditions: PreConditionsJson {
+                state_tree: StateTreeJson {
+                    root_cid: CidJsonRef(&state_root),
+                },
+                basefee: self.basefee,
+                circ_supply: self.circ_supply,
+                variants: [VariantJson {
+                    id: format!("nv{}", self.nv),
+                    epoch: self.epoch,
+                    nv: self.nv,
+                }],
+            },
+            apply_messages: [ApplyMessageJson {
+                bytes: base64::encode(message.marshal_cbor()?),
+            }],
+            postconditions: PostConditionsJson {
+                state_tree: StateTreeJson {
+                    root_cid: CidJsonRef(&self.post_state_root),
+                },
+                receipts: [&self.receipt],
+            },
+            randomness,
+        };
+        serde_json::to_writer_pretty(out, &vector)?;
+        Ok(())
+    }
+}
+
+/// Reads a CAR file, decompressing it if it's gzipped.
+pub(crate) fn read_car(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let bytes = fs::read(path).with_context(|| format!("failed to read CAR {}", path.display()))?;
+    if !bytes.starts_with(&[0x1f, 0x8b]) {
+        return Ok(bytes);
+    }
+    let mut car = Vec::new();
+    GzDecoder::new(&*bytes)
+        .read_to_end(&mut car)
+        .context("invalid gzipped CAR")?;
+    Ok(car)
+}
+
+/// Reads a CBOR encoded message, stripping the signature of signed messages (vectors contain
+/// unsigned messages).
+fn read_message(path: &Path) -> anyhow::Result<Message> {
+    let bytes =
+        fs::read(path).with_context(|| format!("failed to read message {}", path.display()))?;
+    Message::unmarshal_cbor(&bytes)
+        .or_else(|_| SignedMessage::unmarshal_cbor(&bytes).map(|m| m.message))
+        .map_err(|e| anyhow!("invalid message: {}", e))
+}
+
+#[derive(Serialize)]
+struct VectorJson<'a> {
+    class: &'static str,
+    #[serde(rename = "_meta")]
+    meta: MetaJson<'a>,
+    car: String,
+    preconditions: PreConditionsJson<'a>,
+    apply_messages: [ApplyMessageJson; 1],
+    postconditions: PostConditionsJson<'a>,
+    randomness: serde_json::Value,
+}
+
+#[derive(Serialize)]
+struct MetaJson<'a> {
+    id: &'a str,
+    description: &'a str,
+    gen: [GenerationJson; 1],
+}
+
+#[derive(Serialize)]
+struct GenerationJson {
+    source: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct StateTreeJson<'a> {
+    root_cid: CidJsonRef<'a>,
+}
+
+#[derive(Serialize)]
+struct PreConditionsJson<'a> {
+    state_tree: StateTreeJson<'a>,
+    basefee: u128,
+    circ_supply: u128,
+    variants: [VariantJson; 1],
+}
+
+#[derive(Serialize)]
+struct VariantJson {
+    id: String,
+    epoch: ChainEpoch,
+    nv: u32,
+}
+
+#[derive(Serialize)]
+struct ApplyMessageJson {
+    bytes: String,
+}
+
+#[derive(Serialize)]
+struct PostConditionsJson<'a> {
+    state_tree: StateTreeJson<'a>,
+    receipts: [&'a ReceiptJson; 1],
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use fvm_ipld_car::CarHeader;
+    use fvm_shared::address::Address;
+    use fvm_shared::crypto::signature::Signature;
+    use fvm_shared::encoding::RawBytes;
+    use fvm_shared::error::ExitCode;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::vector::MessageVector;
+
+    /// Records blocks in the order they're put, to export them as a CAR.
+    #[derive(Default)]
+    struct RecordingBlockstore(RefCell<Vec<(Cid, Vec<u8>)>>);
+
+    impl Blockstore for RecordingBlockstore {
+        fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+            let blocks = self.0.borrow();
+            Ok(blocks.iter().find(|(c, _)| c == k).map(|(_, b)| b.clone()))
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+            self.0.borrow_mut().push((*k, block.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn imports_message_context() {
+        let dir = std::env::temp_dir().join(format!("import-vector-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let bs = RecordingBlockstore::default();
+        let mut state_tree = StateTree::new(&bs, StateTreeVersion::V4).unwrap();
+        let root = state_tree.flush().unwrap();
+        let mut car = futures::io::Cursor::new(Vec::new());
+        let blocks = bs.0.borrow().clone();
+        async_std::task::block_on(
+            CarHeader::from(vec![root])
+                .write_stream_async(&mut car, &mut futures::stream::iter(blocks)),
+        )
+        .unwrap();
+        fs::write(dir.join("prestate.car"), car.into_inner()).unwrap();
+
+        let message = Message {
+            version: 0,
+            from: Address::new_id(100),
+            to: Address::new_id(101),
+            sequence: 3,
+            value: 10.into(),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit: 1_000_000,
+            gas_fee_cap: 100.into(),
+            gas_premium: 1.into(),
+        };
+        let signed =
+            SignedMessage::new_unchecked(message.clone(), Signature::new_secp256k1(vec![0; 65]));
+        fs::write(dir.join("message.cbor"), signed.marshal_cbor().unwrap()).unwrap();
+
+        fs::write(
+            dir.join("context.json"),
+            format!(
+                r#"{{
+                    "id": "divergence",
+                    "car": "prestate.car",
+                    "message": "message.cbor",
+                    "epoch": 1594000,
+                    "nv": 15,
+                    "basefee": "100",
+                    "circ_supply": "400000000000000000000000000",
+                    "receipt": {{"exit_code": 6, "return": "", "gas_used": 1234}},
+                    "post_state_root": {{"/": "{}"}}
+                }}"#,
+                root
+            ),
+        )
+        .unwrap();
+
+        let context = MessageContext::from_file(&dir.join("context.json")).unwrap();
+        let mut out = Vec::new();
+        context.write_vector(&mut out).unwrap();
+        fs::write(dir.join("vector.json"), out).unwrap();
+
+        let vector = MessageVector::from_file(&dir.join("vector.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(vector.preconditions.state_tree.root_cid, root);
+        assert_eq!(vector.preconditions.basefee, Some(100));
+        assert_eq!(
+            vector.preconditions.circ_supply,
+            Some(400_000_000_000_000_000_000_000_000)
+        );
+        let variant = &vector.preconditions.variants[0];
+        assert_eq!((variant.epoch, variant.nv), (1594000, 15));
+        assert_eq!(
+            Message::unmarshal_cbor(&vector.apply_messages[0].bytes).unwrap(),
+            message
+        );
+        assert_eq!(
+            vector.postconditions.receipts[0].exit_code,
+            ExitCode::SysErrInsufficientFunds
+        );
+        assert_eq!(vector.postconditions.receipts[0].gas_used, 1234);
+
+        let (bs, _) = async_std::task::block_on(vector.seed_blockstore()).unwrap();
+        assert!(bs.has(&root).unwrap());
+    }
+}
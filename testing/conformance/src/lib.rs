@@ -1,10 +1,14 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
+pub mod cache;
 pub mod cidjson;
 pub mod driver;
 pub mod externs;
+pub mod import;
+pub mod lotus_trace;
 pub mod rand;
+pub mod replay;
 pub mod vector;
 pub mod vm;
 
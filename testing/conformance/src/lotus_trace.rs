@@ -0,0 +1,377 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Comparison of our execution traces against execution traces produced by Lotus.
+//!
+//! Lotus traces (as returned by `StateReplay`/`StateCompute`) can be stored next to a vector, as a
+//! JSON array with one trace per applied message: the traces for `foo.json` are loaded from
+//! `foo.trace.json`. When present, the runner records sends and gas charges and reports the first
+//! point at which the two executions diverge.
+
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context as _;
+use fvm::trace::{ExecutionEvent, ExecutionTrace};
+use fvm_shared::address::Address;
+use fvm_shared::bigint::BigInt;
+use fvm_shared::econ::TokenAmount;
+use fvm_shared::MethodNum;
+use serde::{Deserialize, Deserializer};
+
+/// A Lotus `ExecutionTrace`, restricted to the fields we compare.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct LotusExecutionTrace {
+    pub msg: LotusMessage,
+    pub msg_rct: LotusReceipt,
+    #[serde(default)]
+    pub error: String,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub gas_charges: Vec<LotusGasCharge>,
+    #[serde(default, deserialize_with = "null_as_default")]
+    pub subcalls: Vec<LotusExecutionTrace>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct LotusMessage {
+    #[serde(deserialize_with = "from_str")]
+    pub to: Address,
+    #[serde(deserialize_with = "from_str")]
+    pub from: Address,
+    #[serde(deserialize_with = "from_str")]
+    pub value: BigInt,
+    pub method: MethodNum,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(rename_all = "PascalCase")]
+pub struct LotusReceipt {
+    pub exit_code: i64,
+    pub gas_used: i64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LotusGasCharge {
+    #[serde(rename = "Name")]
+    pub name: String,
+    #[serde(rename = "tg")]
+    pub total_gas: i64,
+}
+
+fn from_str<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+}
+
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+/// Returns the path of the Lotus traces for the vector at `vector_path`.
+pub fn lotus_trace_path(vector_path: &Path) -> PathBuf {
+    vector_path.with_extension("trace.json")
+}
+
+/// Loads the Lotus traces stored alongside the vector at `vector_path`, if any.
+pub fn load_lotus_traces(vector_path: &Path) -> anyhow::Result<Option<Vec<LotusExecutionTrace>>> {
+    let path = lotus_trace_path(vector_path);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = File::open(&path)?;
+    let traces = serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("failed to parse lotus traces in {}", path.display()))?;
+    Ok(Some(traces))
+}
+
+/// A send and everything that happened within it, reconstructed from an [`ExecutionTrace`].
+#[derive(Debug, Clone)]
+pub struct CallFrame {
+    pub to: Address,
+    pub method: MethodNum,
+    pub value: TokenAmount,
+    /// `None` if the send failed with an error, or never returned (e.g., we ran out of gas).
+    pub exit_code: Option<u32>,
+    /// The (name, total gas) of each charge made directly within this call.
+    pub gas_charges: Vec<(String, i64)>,
+    pub subcalls: Vec<CallFrame>,
+}
+
+impl CallFrame {
+    /// Reconstructs the top-level call from an execution trace recorded with `trace_calls`
+    /// enabled. Gas charged outside of any call (e.g., the message inclusion cost) is attributed
+    /// to the top-level call, like Lotus does.
+    pub fn from_trace(trace: &ExecutionTrace) -> Option<CallFrame> {
+        let mut stack: Vec<CallFrame> = Vec::new();
+        let mut root: Option<CallFrame> = None;
+        let mut orphan_charges = Vec::new();
+
+        for event in trace {
+            match event {
+                ExecutionEvent::Call(call) => stack.push(CallFrame {
+                    to: call.to,
+                    method: call.method,
+                    value: call.value.clone(),
+                    exit_code: None,
+                    gas_charges: Vec::new(),
+                    subcalls: Vec::new(),
+                }),
+                ExecutionEvent::CallReturn(ret) => {
+                    let mut frame = match stack.pop() {
+                        Some(frame) => frame,
+                        None => continue,
+                    };
+                    frame.exit_code = ret.exit_code.map(|c| c as u32);
+                    match stack.last_mut() {
+                        Some(parent) => parent.subcalls.push(frame),
+                        None => root = Some(frame),
+                    }
+                }
+                ExecutionEvent::GasCharge(charge) => {
+                    let charge = (charge.name.clone(), charge.total().as_i64());
+                    match stack.last_mut().or(root.as_mut()) {
+                        Some(frame) => frame.gas_charges.push(charge),
+                        None => orphan_charges.push(charge),
+                    }
+                }
+                ExecutionEvent::Syscall(_) | ExecutionEvent::StateChange(_) => {}
+            }
+        }
+
+        // Unwind calls that never returned.
+        while let Some(frame) = stack.pop() {
+            match stack.last_mut() {
+                Some(parent) => parent.subcalls.push(frame),
+                None => root = Some(frame),
+            }
+        }
+
+        let mut root = root?;
+        orphan_charges.append(&mut root.gas_charges);
+        root.gas_charges = orphan_charges;
+        Some(root)
+    }
+}
+
+/// The first difference found between a Lotus trace and ours.
+#[derive(Debug, Clone)]
+pub struct TraceDivergence {
+    /// The indices of the subcalls leading to the divergent call; empty for the top-level call.
+    pub path: Vec<usize>,
+    pub description: String,
+}
+
+impl fmt::Display for TraceDivergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "top-level call: {}", self.description)
+        } else {
+            let path: Vec<String> = self.path.iter().map(|i| i.to_string()).collect();
+            write!(f, "subcall {}: {}", path.join("."), self.description)
+        }
+    }
+}
+
+/// Compares our execution trace of a message with Lotus's, returning the first divergent send or
+/// gas charge (in depth-first order), if any.
+pub fn compare_traces(
+    expected: &LotusExecutionTrace,
+    actual: &ExecutionTrace,
+) -> Option<TraceDivergence> {
+    match CallFrame::from_trace(actual) {
+        Some(frame) => compare_frames(expected, &frame, &mut Vec::new()),
+        None => Some(TraceDivergence {
+            path: Vec::new(),
+            description: "no calls were recorded".into(),
+        }),
+    }
+}
+
+fn compare_frames(
+    expected: &LotusExecutionTrace,
+    actual: &CallFrame,
+    path: &mut Vec<usize>,
+) -> Option<TraceDivergence> {
+    let diverged = |path: &Vec<usize>, description: String| {
+        Some(TraceDivergence {
+            path: path.clone(),
+            description,
+        })
+    };
+
+    let msg = &expected.msg;
+    if msg.to.payload() != actual.to.payload()
+        || msg.method != actual.method
+        || msg.value != actual.value
+    {
+        return diverged(
+            path,
+            format!(
+                "expected send to {} method {} value {}, got send to {} method {} value {}",
+                msg.to, msg.method, msg.value, actual.to, actual.method, actual.value
+            ),
+        );
+    }
+
+    let first_mismatch = expected
+        .gas_charges
+        .iter()
+        .zip(&actual.gas_charges)
+        .enumerate()
+        .find(|(_, (e, (name, total)))| &e.name != name || e.total_gas != *total);
+    if let Some((i, (e, (name, total)))) = first_mismatch {
+        return diverged(
+            path,
+            format!(
+                "gas charge #{}: expected {} ({}), got {} ({})",
+                i, e.name, e.total_gas, name, total
+            ),
+        );
+    }
+    if expected.gas_charges.len() != actual.gas_charges.len() {
+        return diverged(
+            path,
+            format!(
+                "expected {} gas charges, got {}",
+                expected.gas_charges.len(),
+                actual.gas_charges.len()
+            ),
+        );
+    }
+
+    for (i, (e, a)) in expected.subcalls.iter().zip(&actual.subcalls).enumerate() {
+        path.push(i);
+        if let Some(divergence) = compare_frames(e, a, path) {
+            return Some(divergence);
+        }
+        path.pop();
+    }
+    if expected.subcalls.len() != actual.subcalls.len() {
+        return diverged(
+            path,
+            format!(
+                "expected {} subcalls, got {}",
+                expected.subcalls.len(),
+                actual.subcalls.len()
+            ),
+        );
+    }
+
+    let expected_code = expected.msg_rct.exit_code;
+    if actual.exit_code.map(i64::from) != Some(expected_code) {
+        return diverged(
+            path,
+            format!(
+                "expected exit code {}, got {:?} (lotus error: {:?})",
+                expected_code, actual.exit_code, expected.error
+            ),
+        );
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use fvm::gas::Gas;
+    use fvm::trace::{CallReturnTrace, CallTrace, GasChargeTrace};
+    use fvm_shared::error::ExitCode;
+
+    use super::*;
+
+    fn lotus_trace() -> LotusExecutionTrace {
+        serde_json::from_str(
+            r#"{
+                "Msg": {"From": "f0100", "To": "f0101", "Value": "10", "Method": 2},
+                "MsgRct": {"ExitCode": 0, "Return": null, "GasUsed": 1000},
+                "Error": "",
+                "Duration": 12345,
+                "GasCharges": [
+                    {"Name": "OnChainMessage", "loc": null, "tg": 100, "cg": 100, "sg": 0},
+                    {"Name": "OnMethodInvocation", "tg": 50, "cg": 50, "sg": 0}
+                ],
+                "Subcalls": [{
+                    "Msg": {"From": "f0101", "To": "f0102", "Value": "0", "Method": 3},
+                    "MsgRct": {"ExitCode": 16, "Return": null, "GasUsed": 0},
+                    "GasCharges": [{"Name": "OnIpldGet", "tg": 20, "cg": 20, "sg": 0}],
+                    "Subcalls": null
+                }]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn call(to: u64, method: MethodNum, value: u64, depth: u32) -> ExecutionEvent {
+        ExecutionEvent::Call(CallTrace {
+            from: 0,
+            to: Address::new_id(to),
+            method,
+            value: TokenAmount::from(value),
+            depth,
+            gas_available: Gas::ZERO,
+        })
+    }
+
+    fn call_return(exit_code: ExitCode, depth: u32) -> ExecutionEvent {
+        ExecutionEvent::CallReturn(CallReturnTrace {
+            exit_code: Some(exit_code),
+            depth,
+            gas_available: Gas::ZERO,
+        })
+    }
+
+    fn charge(name: &str, gas: i64) -> ExecutionEvent {
+        ExecutionEvent::GasCharge(GasChargeTrace {
+            name: name.into(),
+            compute_gas: Gas::new(gas),
+            storage_gas: Gas::ZERO,
+        })
+    }
+
+    fn our_trace(subcall_ipld_gas: i64) -> ExecutionTrace {
+        vec![
+            charge("OnChainMessage", 100),
+            call(101, 2, 10, 1),
+            charge("OnMethodInvocation", 50),
+            call(102, 3, 0, 2),
+            charge("OnIpldGet", subcall_ipld_gas),
+            call_return(ExitCode::ErrIllegalArgument, 2),
+            call_return(ExitCode::Ok, 1),
+        ]
+    }
+
+    #[test]
+    fn matching_traces() {
+        assert!(compare_traces(&lotus_trace(), &our_trace(20)).is_none());
+    }
+
+    #[test]
+    fn divergent_gas_charge() {
+        let divergence = compare_traces(&lotus_trace(), &our_trace(21)).unwrap();
+        assert_eq!(divergence.path, vec![0]);
+        assert!(divergence.description.contains("OnIpldGet"));
+    }
+
+    #[test]
+    fn missing_subcall() {
+        let mut trace = our_trace(20);
+        trace.drain(3..6);
+        let divergence = compare_traces(&lotus_trace(), &trace).unwrap();
+        assert!(divergence.path.is_empty());
+        assert_eq!(divergence.description, "expected 1 subcalls, got 0");
+    }
+}
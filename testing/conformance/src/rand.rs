@@ -4,12 +4,14 @@
 use fvm::externs::Rand;
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
+use fvm_shared::randomness::RANDOMNESS_LENGTH;
 
 use crate::vector::{RandomnessKind, RandomnessMatch, RandomnessRule};
 
 /// Takes recorded randomness and replays it when input parameters match.
 /// When there's no match, it falls back to TestFallbackRand, which returns a
 /// fixed output.
+#[derive(Clone)]
 pub struct ReplayingRand {
     pub recorded: Vec<RandomnessMatch>,
     pub fallback: TestFallbackRand,
@@ -17,6 +19,7 @@ pub struct ReplayingRand {
 
 /// Implements the Rand extern and returns static values as randomness outputs
 /// when there's a vector miss.
+#[derive(Clone)]
 pub struct TestFallbackRand;
 
 impl Rand for TestFallbackRand {
@@ -25,7 +28,7 @@ impl Rand for TestFallbackRand {
         _: DomainSeparationTag,
         _: ChainEpoch,
         _: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         Ok(*b"i_am_random_____i_am_random_____")
     }
 
@@ -34,7 +37,7 @@ impl Rand for TestFallbackRand {
         _: DomainSeparationTag,
         _: ChainEpoch,
         _: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         Ok(*b"i_am_random_____i_am_random_____")
     }
 }
@@ -47,15 +50,17 @@ impl ReplayingRand {
         }
     }
 
-    pub fn matches(&self, requested: RandomnessRule) -> Option<[u8; 32]> {
-        for other in &self.recorded {
-            if other.on == requested {
-                let mut randomness = [0u8; 32];
-                randomness.copy_from_slice(&other.ret);
-                return Some(randomness);
-            }
-        }
-        None
+    pub fn matches(&self, requested: RandomnessRule) -> Option<[u8; RANDOMNESS_LENGTH]> {
+        self.recorded
+            .iter()
+            .find(|other| other.on == requested)
+            .map(|other| {
+                other
+                    .ret
+                    .as_slice()
+                    .try_into()
+                    .expect("recorded randomness must be 32 bytes")
+            })
     }
 }
 
@@ -65,7 +70,7 @@ impl Rand for ReplayingRand {
         dst: DomainSeparationTag,
         epoch: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         let rule = RandomnessRule {
             kind: RandomnessKind::Chain,
             dst,
@@ -83,7 +88,7 @@ impl Rand for ReplayingRand {
         dst: DomainSeparationTag,
         epoch: ChainEpoch,
         entropy: &[u8],
-    ) -> anyhow::Result<[u8; 32]> {
+    ) -> anyhow::Result<[u8; RANDOMNESS_LENGTH]> {
         let rule = RandomnessRule {
             kind: RandomnessKind::Beacon,
             dst,
@@ -0,0 +1,303 @@
+// Copyright 2019-2022 ChainSafe Systems
+// SPDX-License-Identifier: Apache-2.0, MIT
+
+//! Replays a stretch of chain history, epoch by epoch, to find the first epoch where the FVM
+//! computes a different state root than the one recorded on chain.
+//!
+//! The history is described by a JSON "message log", which refers to files next to it:
+//!
+//! ```json
+//! {
+//!   "car": "snapshot.car",
+//!   "state_root": {"/": "bafy2bza..."},
+//!   "nv": 15,
+//!   "randomness": "randomness.json",
+//!   "epochs": [
+//!     {
+//!       "epoch": 1594000,
+//!       "basefee": "100",
+//!       "circ_supply": "1000000000000000000000000",
+//!       "messages": [
+//!         {"bytes": "base64..."},
+//!         {"bytes": "base64...", "implicit": true}
+//!       ],
+//!       "state_root": {"/": "bafy2bza..."}
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `car` is a CAR export of the state before the first epoch, gzipped or not.
+//! - `state_root` defaults to the first root of the CAR.
+//! - `randomness` (optional) is the randomness drawn while applying the messages, recorded in the
+//!   test vector format.
+//! - `epochs` are in chain order. Each lists the messages of its tipset in the order they were
+//!   applied, as CBOR (signed or not). Implicit messages (rewards and cron) must be included, as
+//!   they are in the recorded `state_root` of the epoch.
+//!
+//! The whole history is executed under the same network version: network upgrades (and their
+//! migrations) can't be replayed.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{anyhow, Context as _};
+use cid::Cid;
+use fvm::call_manager::DefaultCallManager;
+use fvm::executor::{ApplyKind, DefaultExecutor, Executor};
+use fvm::machine::{DefaultMachine, Engine};
+use fvm::{Config, DefaultKernel, Profile};
+use fvm_ipld_car::load_car;
+use fvm_shared::address::Protocol;
+use fvm_shared::blockstore::{Blockstore, MemoryBlockstore};
+use fvm_shared::clock::ChainEpoch;
+use fvm_shared::crypto::signature::SECP_SIG_LEN;
+use fvm_shared::encoding::Cbor;
+use fvm_shared::message::{Message, SignedMessage};
+use fvm_shared::version::NetworkVersion;
+use serde::Deserialize;
+
+use crate::cidjson;
+use crate::externs::TestExterns;
+use crate::import::{read_car, u128_from_str};
+use crate::vector::{base64_bytes, Randomness};
+use crate::vm::TestMachine;
+
+type ReplayMachine = DefaultMachine<Rc<MemoryBlockstore>, TestExterns>;
+type ReplayKernel = DefaultKernel<DefaultCallManager<ReplayMachine>>;
+
+/// A stretch of chain history to replay.
+#[derive(Debug, Deserialize)]
+pub struct MessageLog {
+    pub car: PathBuf,
+    #[serde(default, with = "cidjson::opt")]
+    pub state_root: Option<Cid>,
+    pub nv: u32,
+    #[serde(default)]
+    pub randomness: Option<PathBuf>,
+    pub epochs: Vec<EpochLog>,
+}
+
+/// The messages applied at an epoch, and the state root they resulted in.
+#[derive(Debug, Deserialize)]
+pub struct EpochLog {
+    pub epoch: ChainEpoch,
+    #[serde(deserialize_with = "u128_from_str")]
+    pub basefee: u128,
+    #[serde(deserialize_with = "u128_from_str")]
+    pub circ_supply: u128,
+    #[serde(default)]
+    pub messages: Vec<LoggedMessage>,
+    #[serde(with = "cidjson")]
+    pub state_root: Cid,
+}
+
+/// A message applied at an epoch.
+#[derive(Debug, Deserialize)]
+pub struct LoggedMessage {
+    /// The CBOR encoded message, signed or not.
+    #[serde(with = "base64_bytes")]
+    pub bytes: Vec<u8>,
+    /// Whether the message is an implicit (system) message.
+    #[serde(default)]
+    pub implicit: bool,
+}
+
+impl LoggedMessage {
+    /// Decodes the message, returning it along with its size on chain.
+    fn decode(&self) -> anyhow::Result<(Message, usize)> {
+        if let Ok(signed) = SignedMessage::unmarshal_cbor(&self.bytes) {
+            return Ok((signed.message, self.bytes.len()));
+        }
+        let msg =
+            Message::unmarshal_cbor(&self.bytes).map_err(|e| anyhow!("invalid message: {}", e))?;
+        let mut raw_length = self.bytes.len();
+        if !self.implicit && msg.from.protocol() == Protocol::Secp256k1 {
+            // 65 bytes signature + 1 byte type + 3 bytes for field info.
+            raw_length += SECP_SIG_LEN + 4;
+        }
+        Ok((msg, raw_length))
+    }
+}
+
+/// The first epoch where the computed state root differs from the recorded one.
+#[derive(Debug)]
+pub struct Divergence {
+    pub epoch: ChainEpoch,
+    pub expected: Cid,
+    pub actual: Cid,
+}
+
+impl MessageLog {
+    /// Loads a message log, resolving the files it refers to relative to it.
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read message log {}", path.display()))?;
+        let mut log: MessageLog = serde_json::from_str(&json).context("invalid message log")?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        log.car = dir.join(&log.car);
+        log.randomness = log.randomness.map(|p| dir.join(p));
+        Ok(log)
+    }
+
+    /// Applies the epochs in order, calling `on_epoch` with the state root of every epoch that
+    /// matches the recorded one. Stops at the first divergence, and returns it.
+    pub fn replay(
+        &self,
+        mut on_epoch: impl FnMut(ChainEpoch, &Cid),
+    ) -> anyhow::Result<Option<Divergence>> {
+        let nv = NetworkVersion::try_from(self.nv)
+            .map_err(|_| anyhow!("unknown network version {}", self.nv))?;
+
+        let bs = Rc::new(MemoryBlockstore::new());
+        let car = read_car(&self.car)?;
+        let roots = async_std::task::block_on(load_car(&*bs, futures::io::Cursor::new(&car)))
+            .context("invalid CAR")?;
+        let mut root = self
+            .state_root
+            .or_else(|| roots.first().copied())
+            .ok_or_else(|| anyhow!("no state root given, and the CAR has no roots"))?;
+        if !bs.has(&root)? {
+            return Err(anyhow!("the CAR doesn't contain the state root {}", root));
+        }
+        let manifest = *TestMachine::import_actors(&*bs)
+            .get(&nv)
+            .ok_or_else(|| anyhow!("no builtin actors for network version {}", nv))?;
+
+        let randomness: Randomness = match &self.randomness {
+            Some(path) => {
+                let json = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read randomness {}", path.display()))?;
+                serde_json::from_str(&json).context("invalid randomness")?
+            }
+            None => Randomness::new(),
+        };
+
+        let engine = Engine::new(&Profile::Replay.engine_config())?;
+        for epoch in &self.epochs {
+            let machine = DefaultMachine::new(
+                Config::preset(Profile::Replay),
+                engine.clone(),
+                epoch.epoch,
+                epoch.basefee.into(),
+                epoch.circ_supply.into(),
+                nv,
+                root,
+                (0, Some(manifest)),
+                bs.clone(),
+                TestExterns::new(&randomness, &[]),
+            )
+            .with_context(|| format!("failed to construct the machine at epoch {}", epoch.epoch))?;
+            let mut exec = DefaultExecutor::<ReplayKernel>::new(machine);
+
+            for (i, m) in epoch.messages.iter().enumerate() {
+                let (msg, raw_length) = m
+                    .decode()
+                    .with_context(|| format!("message {} of epoch {}", i, epoch.epoch))?;
+                let apply_kind = if m.implicit {
+                    ApplyKind::Implicit
+                } else {
+                    ApplyKind::Explicit
+                };
+                exec.execute_message(msg, apply_kind, raw_length)
+                    .with_context(|| {
+                        format!("failed to apply message {} of epoch {}", i, epoch.epoch)
+                    })?;
+            }
+
+            root = exec.flush()?;
+            if root != epoch.state_root {
+                return Ok(Some(Divergence {
+                    epoch: epoch.epoch,
+                    expected: epoch.state_root,
+                    actual: root,
+                }));
+            }
+            on_epoch(epoch.epoch, &root);
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use fvm::state_tree::StateTree;
+    use fvm_ipld_car::CarHeader;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+
+    /// Records blocks in the order they're put, to export them as a CAR.
+    #[derive(Default)]
+    struct RecordingBlockstore(RefCell<Vec<(Cid, Vec<u8>)>>);
+
+    impl Blockstore for RecordingBlockstore {
+        fn get(&self, k: &Cid) -> anyhow::Result<Option<Vec<u8>>> {
+            let blocks = self.0.borrow();
+            Ok(blocks.iter().find(|(c, _)| c == k).map(|(_, b)| b.clone()))
+        }
+
+        fn put_keyed(&self, k: &Cid, block: &[u8]) -> anyhow::Result<()> {
+            self.0.borrow_mut().push((*k, block.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reports_first_divergent_epoch() {
+        let dir = std::env::temp_dir().join(format!("replay-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let bs = RecordingBlockstore::default();
+        let root = StateTree::new(&bs, StateTreeVersion::V4)
+            .unwrap()
+            .flush()
+            .unwrap();
+        let mut car = futures::io::Cursor::new(Vec::new());
+        let blocks = bs.0.borrow().clone();
+        async_std::task::block_on(
+            CarHeader::from(vec![root])
+                .write_stream_async(&mut car, &mut futures::stream::iter(blocks)),
+        )
+        .unwrap();
+        fs::write(dir.join("snapshot.car"), car.into_inner()).unwrap();
+
+        // Empty tipsets leave the state alone, so the last epoch can't match.
+        let epoch = |epoch: ChainEpoch, root: &Cid| {
+            format!(
+                r#"{{"epoch": {}, "basefee": "100", "circ_supply": "0", "state_root": {{"/": "{}"}}}}"#,
+                epoch, root
+            )
+        };
+        let wrong_root = TestMachine::import_actors(&MemoryBlockstore::new())[&NetworkVersion::V15];
+        fs::write(
+            dir.join("log.json"),
+            format!(
+                r#"{{"car": "snapshot.car", "nv": 15, "epochs": [{}, {}, {}]}}"#,
+                epoch(10, &root),
+                epoch(11, &root),
+                epoch(12, &wrong_root)
+            ),
+        )
+        .unwrap();
+
+        let log = MessageLog::from_file(&dir.join("log.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let mut matched = Vec::new();
+        let divergence = log
+            .replay(|epoch, r| {
+                assert_eq!(*r, root);
+                matched.push(epoch)
+            })
+            .unwrap()
+            .unwrap();
+        assert_eq!(matched, [10, 11]);
+        assert_eq!(divergence.epoch, 12);
+        assert_eq!(divergence.expected, wrong_root);
+        assert_eq!(divergence.actual, root);
+    }
+}
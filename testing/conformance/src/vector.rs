@@ -18,8 +18,11 @@ use fvm_shared::clock::ChainEpoch;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
 use fvm_shared::encoding::tuple::*;
 use fvm_shared::receipt::Receipt;
+use fvm_shared::version::NetworkVersion;
 use serde::{Deserialize, Deserializer};
 
+use crate::lotus_trace::LotusExecutionTrace;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct StateTreeVector {
     #[serde(with = "super::cidjson")]
@@ -55,6 +58,20 @@ pub struct PreConditions {
     pub circ_supply: Option<u128>,
     #[serde(default)]
     pub variants: Vec<Variant>,
+    /// The builtin actors bundle to execute the vector with. If absent, the bundle embedded in the
+    /// runner for the variant's network version is used.
+    #[serde(default)]
+    pub actors_bundle: Option<ActorsBundle>,
+}
+
+/// A reference to a builtin actors bundle. The bundle's manifest and actors code must be included
+/// in the vector's CAR.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ActorsBundle {
+    #[serde(with = "super::cidjson")]
+    pub manifest: Cid,
+    #[serde(default)]
+    pub manifest_version: u32,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -64,6 +81,17 @@ pub struct PostConditions {
     pub receipts: Vec<Receipt>,
     #[serde(default, with = "super::cidjson::vec")]
     pub receipts_roots: Vec<Cid>,
+    /// The roots of the actor events emitted by each applied message. Empty if the vector
+    /// predates actor events, or doesn't check them.
+    #[serde(default, with = "super::cidjson::vec")]
+    pub events_roots: Vec<Cid>,
+}
+
+impl PostConditions {
+    /// Returns true if the vector checks the actor events emitted by its messages.
+    pub fn expects_events(&self) -> bool {
+        !self.events_roots.is_empty()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -91,6 +119,13 @@ pub struct Variant {
     pub nv: u32,
 }
 
+impl Variant {
+    /// Returns the network version this variant should be executed under, if it's a known one.
+    pub fn network_version(&self) -> Option<NetworkVersion> {
+        NetworkVersion::try_from(self.nv).ok()
+    }
+}
+
 /// Encoded VM randomness used to be replayed.
 pub type Randomness = Vec<RandomnessMatch>;
 
@@ -119,6 +154,14 @@ pub struct RandomnessRule {
     pub entropy: Vec<u8>,
 }
 
+/// The CID of a tipset looked up by an actor, to be replayed.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TipsetCid {
+    pub epoch: ChainEpoch,
+    #[serde(with = "super::cidjson")]
+    pub cid: Cid,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct MessageVector {
     pub selector: Option<Selector>,
@@ -133,6 +176,13 @@ pub struct MessageVector {
 
     #[serde(default)]
     pub randomness: Randomness,
+
+    #[serde(default)]
+    pub tipset_cids: Vec<TipsetCid>,
+
+    /// Lotus execution traces of the applied messages, if stored alongside the vector.
+    #[serde(skip)]
+    pub lotus_traces: Option<Vec<LotusExecutionTrace>>,
 }
 
 impl MessageVector {
@@ -212,7 +262,7 @@ pub struct ApplyMessage {
     pub epoch_offset: Option<ChainEpoch>,
 }
 
-mod base64_bytes {
+pub(crate) mod base64_bytes {
     use std::borrow::Cow;
 
     use serde::de;
@@ -284,3 +334,52 @@ mod message_receipt_vec {
 //         ChainMessage::Unsigned(msg)
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATE_ROOT: &str = "bafy2bzacecfw453g5eumomysjqwfl7s3fbogjzxm7tbpcewjvobjnbergnpm6";
+
+    #[test]
+    fn preconditions_with_actors_bundle() {
+        let json = format!(
+            r#"{{
+                "state_tree": {{"root_cid": {{"/": "{root}"}}}},
+                "actors_bundle": {{"manifest": {{"/": "{root}"}}, "manifest_version": 1}}
+            }}"#,
+            root = STATE_ROOT
+        );
+        let preconditions: PreConditions = serde_json::from_str(&json).unwrap();
+        let bundle = preconditions.actors_bundle.unwrap();
+        assert_eq!(bundle.manifest.to_string(), STATE_ROOT);
+        assert_eq!(bundle.manifest_version, 1);
+    }
+
+    #[test]
+    fn preconditions_without_actors_bundle() {
+        let json = format!(
+            r#"{{"state_tree": {{"root_cid": {{"/": "{}"}}}}}}"#,
+            STATE_ROOT
+        );
+        let preconditions: PreConditions = serde_json::from_str(&json).unwrap();
+        assert!(preconditions.actors_bundle.is_none());
+    }
+
+    #[test]
+    fn postconditions_events() {
+        let parse = |events: &str| {
+            let json = format!(
+                r#"{{"state_tree": {{"root_cid": {{"/": "{}"}}}}, "receipts": []{}}}"#,
+                STATE_ROOT, events
+            );
+            serde_json::from_str::<PostConditions>(&json).unwrap()
+        };
+        assert!(!parse("").expects_events());
+
+        let events = format!(r#", "events_roots": [{{"/": "{}"}}]"#, STATE_ROOT);
+        let postconditions = parse(&events);
+        assert!(postconditions.expects_events());
+        assert_eq!(postconditions.events_roots[0].to_string(), STATE_ROOT);
+    }
+}
@@ -1,19 +1,21 @@
 use std::collections::BTreeMap;
-use std::convert::TryFrom;
 
 use cid::Cid;
 use futures::executor::block_on;
-use fvm::call_manager::{Backtrace, CallManager, DefaultCallManager, InvocationResult};
-use fvm::gas::{GasTracker, PriceList};
+use fvm::call_manager::{
+    CallManager, DefaultCallManager, FinishRet, InvocationResult, RandomnessCache,
+};
+use fvm::gas::{Gas, GasTracker, PriceList};
 use fvm::kernel::*;
 use fvm::machine::{DefaultMachine, Engine, Machine, MachineContext};
 use fvm::state_tree::{ActorState, StateTree};
-use fvm::{Config, DefaultKernel};
+use fvm::trace::ExecutionEvent;
+use fvm::{Config, DefaultKernel, Profile};
 use fvm_ipld_car::load_car;
 use fvm_shared::actor::builtin::Manifest;
 use fvm_shared::address::Address;
 use fvm_shared::bigint::BigInt;
-use fvm_shared::blockstore::MemoryBlockstore;
+use fvm_shared::blockstore::{Blockstore, MemoryBlockstore};
 use fvm_shared::clock::ChainEpoch;
 use fvm_shared::consensus::ConsensusFault;
 use fvm_shared::crypto::randomness::DomainSeparationTag;
@@ -21,6 +23,7 @@ use fvm_shared::crypto::signature::Signature;
 use fvm_shared::econ::TokenAmount;
 use fvm_shared::piece::PieceInfo;
 use fvm_shared::randomness::RANDOMNESS_LENGTH;
+use fvm_shared::receipt::Receipt;
 use fvm_shared::sector::{
     AggregateSealVerifyProofAndInfos, RegisteredSealProof, ReplicaUpdateInfo, SealVerifyInfo,
     WindowPoStVerifyInfo,
@@ -34,10 +37,15 @@ use crate::vector::{MessageVector, Variant};
 
 const DEFAULT_BASE_FEE: u64 = 100;
 
+/// The builtin actors bundles, by the network version they are deployed in.
+const ACTOR_BUNDLES: [(NetworkVersion, &[u8]); 2] = [
+    (NetworkVersion::V14, actors_v6::BUNDLE_CAR),
+    (NetworkVersion::V15, actors_v7::BUNDLE_CAR),
+];
+
 #[derive(Clone)]
 pub struct TestData {
     circ_supply: TokenAmount,
-    price_list: PriceList,
 }
 
 pub struct TestMachine<M = Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
@@ -52,8 +60,61 @@ impl TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
         blockstore: MemoryBlockstore,
         engine: Engine,
     ) -> TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
-        let network_version =
-            NetworkVersion::try_from(variant.nv).expect("unrecognized network version");
+        Self::new_for_vector_with_config(v, variant, blockstore, engine, Self::config_for_vector(v))
+    }
+
+    /// Consumes the machine and returns its blockstore, without the blocks written since the
+    /// last flush.
+    pub fn into_store(self) -> MemoryBlockstore {
+        self.machine.into_store()
+    }
+
+    /// Returns the machine configuration vectors are executed with by default.
+    pub fn config_for_vector(v: &MessageVector) -> Config {
+        // Vectors don't contain valid proofs, and debug mode is enabled by default.
+        Config {
+            // Only needed to compare against Lotus traces.
+            trace_calls: v.lotus_traces.is_some(),
+            ..Config::preset(Profile::Devnet)
+        }
+    }
+
+    /// Like [`TestMachine::new_for_vector`], but with a custom machine configuration.
+    pub fn new_for_vector_with_config(
+        v: &MessageVector,
+        variant: &Variant,
+        blockstore: MemoryBlockstore,
+        engine: Engine,
+        config: Config,
+    ) -> TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
+        // The machine context (and so the price list, the syscalls and the account creation
+        // semantics) is derived from the variant's network version.
+        let machine = Self::default_machine_for_vector(v, variant, blockstore, engine, config);
+
+        TestMachine::<Box<DefaultMachine<_, _>>> {
+            machine: Box::new(machine),
+            data: TestData {
+                circ_supply: v
+                    .preconditions
+                    .circ_supply
+                    .map(|i| i.into())
+                    .unwrap_or_else(|| TOTAL_FILECOIN.clone()),
+            },
+        }
+    }
+
+    /// Constructs the unwrapped machine a vector variant is executed with, over any blockstore
+    /// seeded with the vector's CAR.
+    pub fn default_machine_for_vector<B: Blockstore + 'static>(
+        v: &MessageVector,
+        variant: &Variant,
+        blockstore: B,
+        engine: Engine,
+        config: Config,
+    ) -> DefaultMachine<B, TestExterns> {
+        let network_version = variant
+            .network_version()
+            .expect("unrecognized network version");
         let base_fee = v
             .preconditions
             .basefee
@@ -62,56 +123,46 @@ impl TestMachine<Box<DefaultMachine<MemoryBlockstore, TestExterns>>> {
         let epoch = variant.epoch;
         let state_root = v.preconditions.state_tree.root_cid;
 
-        let externs = TestExterns::new(&v.randomness);
-
-        // Load the builtin actors bundles into the blockstore.
-        let nv_actors = TestMachine::import_actors(&blockstore);
-
-        // Get the builtin actors index for the concrete network version.
-        let builtin_actors = *nv_actors
-            .get(&network_version)
-            .expect("no builtin actors index for nv");
-
-        let machine = DefaultMachine::new(
-            Config {
-                max_call_depth: 4096,
-                initial_pages: 0,
-                max_pages: 1024,
-                debug: true, // Enable debug mode by default.
-            },
+        let externs = TestExterns::new(&v.randomness, &v.tipset_cids);
+
+        let builtin_actors = match &v.preconditions.actors_bundle {
+            // The vector carries its own bundle.
+            Some(bundle) => (bundle.manifest_version, Some(bundle.manifest)),
+            None => {
+                // Load the builtin actors bundles into the blockstore.
+                let nv_actors = Self::import_actors(&blockstore);
+
+                // Get the builtin actors index for the concrete network version.
+                let manifest = *nv_actors
+                    .get(&network_version)
+                    .expect("no builtin actors index for nv");
+                (0, Some(manifest))
+            }
+        };
+
+        DefaultMachine::new(
+            config,
             engine,
             epoch,
             base_fee,
             BigInt::zero(),
             network_version,
             state_root,
-            (0, Some(builtin_actors)),
+            builtin_actors,
             blockstore,
             externs,
         )
-        .unwrap();
-
-        let price_list = machine.context().price_list.clone();
+        .unwrap()
+    }
 
-        TestMachine::<Box<DefaultMachine<_, _>>> {
-            machine: Box::new(machine),
-            data: TestData {
-                circ_supply: v
-                    .preconditions
-                    .circ_supply
-                    .map(|i| i.into())
-                    .unwrap_or_else(|| TOTAL_FILECOIN.clone()),
-                price_list,
-            },
-        }
+    /// Returns true if variants targeting the given network version can be executed, i.e. if we
+    /// have a builtin actors bundle for it.
+    pub fn supports_network_version(nv: NetworkVersion) -> bool {
+        ACTOR_BUNDLES.iter().any(|(bundle_nv, _)| *bundle_nv == nv)
     }
 
-    pub fn import_actors(blockstore: &MemoryBlockstore) -> BTreeMap<NetworkVersion, Cid> {
-        let bundles = [
-            (NetworkVersion::V14, actors_v6::BUNDLE_CAR),
-            (NetworkVersion::V15, actors_v7::BUNDLE_CAR),
-        ];
-        bundles
+    pub fn import_actors<B: Blockstore>(blockstore: &B) -> BTreeMap<NetworkVersion, Cid> {
+        ACTOR_BUNDLES
             .into_iter()
             .map(|(nv, car)| {
                 let roots = block_on(async { load_car(blockstore, car).await.unwrap() });
@@ -176,6 +227,10 @@ where
     fn flush(&mut self) -> Result<Cid> {
         self.machine.flush()
     }
+
+    fn reset_to(&mut self, state_root: Cid, epoch: ChainEpoch) -> Result<()> {
+        self.machine.reset_to(state_root, epoch)
+    }
 }
 
 /// A CallManager that wraps kernels in an InterceptKernel.
@@ -190,7 +245,7 @@ where
 {
     type Machine = C::Machine;
 
-    fn new(machine: Self::Machine, gas_limit: i64, origin: Address, nonce: u64) -> Self {
+    fn new(machine: Self::Machine, gas_limit: Gas, origin: Address, nonce: u64) -> Self {
         TestCallManager(C::new(machine, gas_limit, origin, nonce))
     }
 
@@ -224,7 +279,7 @@ where
         })
     }
 
-    fn finish(self) -> (i64, Backtrace, Self::Machine) {
+    fn finish(self) -> (FinishRet, Self::Machine) {
         self.0.finish()
     }
 
@@ -256,6 +311,18 @@ where
         self.0.next_actor_idx()
     }
 
+    fn next_invocation_idx(&mut self) -> u32 {
+        self.0.next_invocation_idx()
+    }
+
+    fn randomness_cache_mut(&mut self) -> &mut RandomnessCache {
+        self.0.randomness_cache_mut()
+    }
+
+    fn trace(&mut self, event: ExecutionEvent) {
+        self.0.trace(event)
+    }
+
     fn price_list(&self) -> &fvm::gas::PriceList {
         self.0.price_list()
     }
@@ -296,6 +363,10 @@ where
 {
     type CallManager = C;
 
+    fn trace(&mut self, event: ExecutionEvent) {
+        self.0.trace(event)
+    }
+
     fn take(self) -> Self::CallManager
     where
         Self: Sized,
@@ -303,6 +374,10 @@ where
         self.0.take().0
     }
 
+    fn flush_committed(&mut self) -> Result<()> {
+        self.0.flush_committed()
+    }
+
     fn new(
         mgr: Self::CallManager,
         caller: ActorID,
@@ -339,8 +414,12 @@ where
         self.0.resolve_address(address)
     }
 
-    fn get_actor_code_cid(&self, addr: &Address) -> Result<Option<Cid>> {
-        self.0.get_actor_code_cid(addr)
+    fn resolve_addresses(&mut self, addresses: &[Address]) -> Result<Vec<Option<ActorID>>> {
+        self.0.resolve_addresses(addresses)
+    }
+
+    fn get_actor_code_cid(&self, id: ActorID) -> Result<Option<Cid>> {
+        self.0.get_actor_code_cid(id)
     }
 
     fn new_actor_address(&mut self) -> Result<Address> {
@@ -389,6 +468,10 @@ where
     fn block_get(&self, id: BlockId) -> Result<(u64, Vec<u8>)> {
         self.0.block_get(id)
     }
+
+    fn block_drop(&mut self, id: BlockId) -> Result<()> {
+        self.0.block_drop(id)
+    }
 }
 
 impl<M, C, K> CircSupplyOps for TestKernel<K>
@@ -433,23 +516,19 @@ where
         self.0.verify_signature(signature, signer, plaintext)
     }
 
-    // NOT forwarded
+    // forwarded (proofs aren't verified)
     fn batch_verify_seals(&mut self, vis: &[SealVerifyInfo]) -> Result<Vec<bool>> {
-        Ok(vec![true; vis.len()])
+        self.0.batch_verify_seals(vis)
     }
 
-    // NOT forwarded
+    // forwarded (proofs aren't verified)
     fn verify_seal(&mut self, vi: &SealVerifyInfo) -> Result<bool> {
-        let charge = self.1.price_list.on_verify_seal(vi);
-        self.0.charge_gas(charge.name, charge.total())?;
-        Ok(true)
+        self.0.verify_seal(vi)
     }
 
-    // NOT forwarded
+    // forwarded (proofs aren't verified)
     fn verify_post(&mut self, vi: &WindowPoStVerifyInfo) -> Result<bool> {
-        let charge = self.1.price_list.on_verify_post(vi);
-        self.0.charge_gas(charge.name, charge.total())?;
-        Ok(true)
+        self.0.verify_post(vi)
     }
 
     // NOT forwarded
@@ -459,24 +538,20 @@ where
         _h2: &[u8],
         _extra: &[u8],
     ) -> Result<Option<ConsensusFault>> {
-        let charge = self.1.price_list.on_verify_consensus_fault();
+        let charge = self.0.price_list().on_verify_consensus_fault();
         self.0.charge_gas(charge.name, charge.total())?;
         // TODO this seems wrong, should probably be parameterized.
         Ok(None)
     }
 
-    // NOT forwarded
+    // forwarded (proofs aren't verified)
     fn verify_aggregate_seals(&mut self, agg: &AggregateSealVerifyProofAndInfos) -> Result<bool> {
-        let charge = self.1.price_list.on_verify_aggregate_seals(agg);
-        self.0.charge_gas(charge.name, charge.total())?;
-        Ok(true)
+        self.0.verify_aggregate_seals(agg)
     }
 
-    // NOT forwarded
+    // forwarded (proofs aren't verified)
     fn verify_replica_update(&mut self, rep: &ReplicaUpdateInfo) -> Result<bool> {
-        let charge = self.1.price_list.on_verify_replica_update(rep);
-        self.0.charge_gas(charge.name, charge.total())?;
-        Ok(true)
+        self.0.verify_replica_update(rep)
     }
 }
 
@@ -501,9 +576,21 @@ where
     C: CallManager<Machine = TestMachine<M>>,
     K: Kernel<CallManager = TestCallManager<C>>,
 {
-    fn charge_gas(&mut self, name: &str, compute: i64) -> Result<()> {
+    fn charge_gas(&mut self, name: &str, compute: Gas) -> Result<()> {
         self.0.charge_gas(name, compute)
     }
+
+    fn gas_used(&self) -> Gas {
+        self.0.gas_used()
+    }
+
+    fn gas_available(&self) -> Gas {
+        self.0.gas_available()
+    }
+
+    fn price_list(&self) -> &PriceList {
+        self.0.price_list()
+    }
 }
 
 impl<M, C, K> MessageOps for TestKernel<K>
@@ -527,6 +614,14 @@ where
     fn msg_value_received(&self) -> TokenAmount {
         self.0.msg_value_received()
     }
+
+    fn msg_origin(&self) -> Address {
+        self.0.msg_origin()
+    }
+
+    fn msg_nonce(&self) -> u64 {
+        self.0.msg_nonce()
+    }
 }
 
 impl<M, C, K> NetworkOps for TestKernel<K>
@@ -546,6 +641,21 @@ where
     fn network_base_fee(&self) -> &TokenAmount {
         self.0.network_base_fee()
     }
+
+    fn tipset_cid(&mut self, epoch: ChainEpoch) -> Result<Cid> {
+        self.0.tipset_cid(epoch)
+    }
+}
+
+impl<M, C, K> PrecompileOps for TestKernel<K>
+where
+    M: Machine,
+    C: CallManager<Machine = TestMachine<M>>,
+    K: Kernel<CallManager = TestCallManager<C>>,
+{
+    fn call_precompile(&mut self, id: u64, input: &[u8]) -> Result<BlockId> {
+        self.0.call_precompile(id, input)
+    }
 }
 
 impl<M, C, K> RandomnessOps for TestKernel<K>
@@ -555,7 +665,7 @@ where
     K: Kernel<CallManager = TestCallManager<C>>,
 {
     fn get_randomness_from_tickets(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
@@ -565,7 +675,7 @@ where
     }
 
     fn get_randomness_from_beacon(
-        &self,
+        &mut self,
         personalization: DomainSeparationTag,
         rand_epoch: ChainEpoch,
         entropy: &[u8],
@@ -610,7 +720,117 @@ where
         method: u64,
         params: &fvm_shared::encoding::RawBytes,
         value: &TokenAmount,
-    ) -> Result<InvocationResult> {
+    ) -> Result<Receipt> {
         self.0.send(recipient, method, params, value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use fvm::executor::{ApplyKind, DefaultExecutor, Executor};
+    use fvm::gas::{price_list_by_network_version, PriceListRegistry};
+    use fvm::EMPTY_ARR_CID;
+    use fvm_shared::actor::builtin::{load_manifest, Type};
+    use fvm_shared::encoding::RawBytes;
+    use fvm_shared::message::Message;
+    use fvm_shared::state::StateTreeVersion;
+
+    use super::*;
+    use crate::vector::{PostConditions, PreConditions, StateTreeVector};
+
+    const SENDER: ActorID = 100;
+
+    /// Returns a vector whose state only holds a funded account actor, and a variant executing it
+    /// under the given network version.
+    fn vector(bs: &MemoryBlockstore, nv: NetworkVersion) -> (MessageVector, Variant) {
+        let manifest = TestMachine::import_actors(bs)[&nv];
+        let account = *load_manifest(bs, &manifest, 0)
+            .unwrap()
+            .get_by_right(&Type::Account)
+            .unwrap();
+
+        let mut state_tree = StateTree::new(bs, StateTreeVersion::V4).unwrap();
+        let actor = ActorState::new(account, *EMPTY_ARR_CID, TokenAmount::from(1_000_000), 0);
+        state_tree
+            .set_actor(&Address::new_id(SENDER), actor)
+            .unwrap();
+        let root_cid = state_tree.flush().unwrap();
+
+        let vector = MessageVector {
+            selector: None,
+            meta: None,
+            car: Vec::new(),
+            preconditions: PreConditions {
+                state_tree: StateTreeVector { root_cid },
+                basefee: Some(0),
+                circ_supply: None,
+                variants: Vec::new(),
+                actors_bundle: None,
+            },
+            apply_messages: Vec::new(),
+            postconditions: PostConditions {
+                state_tree: StateTreeVector { root_cid },
+                receipts: Vec::new(),
+                receipts_roots: Vec::new(),
+                events_roots: Vec::new(),
+            },
+            randomness: Vec::new(),
+            tipset_cids: Vec::new(),
+            lotus_traces: None,
+        };
+        let variant = Variant {
+            id: format!("nv{}", nv as u32),
+            epoch: 1,
+            nv: nv as u32,
+        };
+        (vector, variant)
+    }
+
+    /// Returns the gas used by a self-send under the given network version.
+    fn self_send_gas(nv: NetworkVersion, config: &Config) -> i64 {
+        let bs = MemoryBlockstore::new();
+        let (vector, variant) = vector(&bs, nv);
+        let machine = TestMachine::new_for_vector_with_config(
+            &vector,
+            &variant,
+            bs,
+            Engine::default(),
+            config.clone(),
+        );
+        let mut exec: DefaultExecutor<TestKernel> = DefaultExecutor::new(machine);
+
+        let msg = Message {
+            version: 0,
+            from: Address::new_id(SENDER),
+            to: Address::new_id(SENDER),
+            sequence: 0,
+            value: TokenAmount::zero(),
+            method_num: 0,
+            params: RawBytes::default(),
+            gas_limit: 10_000_000,
+            gas_fee_cap: TokenAmount::zero(),
+            gas_premium: TokenAmount::zero(),
+        };
+        let ret = exec.execute_message(msg, ApplyKind::Explicit, 100).unwrap();
+        assert!(ret.msg_receipt.exit_code.is_success());
+        ret.msg_receipt.gas_used
+    }
+
+    #[test]
+    fn gas_follows_variant_network_version() {
+        // Make sends twice as expensive from network version 15.
+        let mut prices =
+            serde_json::to_value(price_list_by_network_version(NetworkVersion::V15)).unwrap();
+        let send_base = prices["send_base"].as_i64().unwrap();
+        prices["send_base"] = (2 * send_base).into();
+        let config = Config {
+            price_lists: PriceListRegistry::default()
+                .with_price_list(NetworkVersion::V15, serde_json::from_value(prices).unwrap()),
+            ..Config::preset(Profile::Devnet)
+        };
+
+        let v14 = self_send_gas(NetworkVersion::V14, &config);
+        let v15 = self_send_gas(NetworkVersion::V15, &config);
+        assert_eq!(v15 - v14, send_base);
+    }
+}
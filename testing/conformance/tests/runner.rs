@@ -1,23 +1,29 @@
 // Copyright 2019-2022 ChainSafe Systems
 // SPDX-License-Identifier: Apache-2.0, MIT
 
-use std::collections::HashMap;
+use std::cell::Cell;
+use std::collections::{BTreeMap, HashMap};
 use std::env::var;
 use std::fs::File;
 use std::io::BufReader;
 use std::iter;
 use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context as _};
 use async_std::{stream, sync, task};
 use colored::*;
 use futures::{Future, StreamExt, TryFutureExt, TryStreamExt};
 use fvm::machine::Engine;
+use fvm_conformance_tests::cache::ResultCache;
 use fvm_conformance_tests::driver::*;
+use fvm_conformance_tests::lotus_trace::load_lotus_traces;
 use fvm_conformance_tests::report;
 use fvm_conformance_tests::vector::{MessageVector, Selector};
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use notify::{DebouncedEvent, RecursiveMode, Watcher};
 use walkdir::WalkDir;
 
 lazy_static! {
@@ -27,6 +33,13 @@ lazy_static! {
             let s = s.to_str().unwrap();
             s.parse().expect("parallelism must be an integer")
         }).unwrap_or_else(num_cpus::get);
+
+    /// The number of slowest variants listed in the summary.
+    static ref SLOWEST_VARIANTS: usize = std::env::var_os("SLOWEST_VARIANTS")
+        .map(|s| {
+            let s = s.to_str().unwrap();
+            s.parse().expect("the number of slowest variants must be an integer")
+        }).unwrap_or(10);
 }
 
 #[async_std::test]
@@ -35,6 +48,17 @@ async fn conformance_test_runner() -> anyhow::Result<()> {
 
     let engine = Engine::default();
 
+    if let Ok(dir) = var("WATCH") {
+        return watch_vectors(PathBuf::from(dir), engine).await;
+    }
+
+    // Vectors that already passed with this build are skipped.
+    let cache = var("RESULT_CACHE")
+        .ok()
+        .map(ResultCache::open)
+        .transpose()?;
+    let cached = Cell::new(0);
+
     let vector_results = match var("VECTOR") {
         Ok(v) => either::Either::Left(
             iter::once(async move {
@@ -48,6 +72,13 @@ async fn conformance_test_runner() -> anyhow::Result<()> {
             WalkDir::new("test-vectors/corpus")
                 .into_iter()
                 .filter_ok(is_runnable)
+                .filter_ok(|e| match &cache {
+                    Some(cache) if cache.passed(e.path()).unwrap_or(false) => {
+                        cached.set(cached.get() + 1);
+                        false
+                    }
+                    _ => true,
+                })
                 .map(|e| {
                     let engine = engine.clone();
                     async move {
@@ -81,23 +112,28 @@ async fn conformance_test_runner() -> anyhow::Result<()> {
     let mut succeeded = 0;
     let mut failed = 0;
     let mut skipped = 0;
+    let mut total = VariantStats::default();
+    let mut slowest = Vec::new();
+    let mut failures_by_stage = BTreeMap::new();
+    // Whether none of the variants of each vector failed.
+    let mut vectors_passed = HashMap::new();
 
     while let Some((path, res)) = results.next().await.transpose()? {
+        print_result(&path, &res);
+        let passed = vectors_passed.entry(path.clone()).or_insert(true);
+        *passed &= !matches!(res, VariantResult::Failed { .. });
         match res {
-            VariantResult::Ok { id } => {
-                report!("OK".on_green(), path.display(), id);
+            VariantResult::Ok { id, stats } => {
                 succeeded += 1;
+                total.wall_time += stats.wall_time;
+                total.gas_used += stats.gas_used;
+                slowest.push((stats, format!("{} | {}", path.display(), id)));
             }
-            VariantResult::Failed { reason, id } => {
-                report!("FAIL".white().on_red(), path.display(), id);
-                println!("\t|> reason: {:#}", reason);
+            VariantResult::Failed { failure, .. } => {
                 failed += 1;
+                *failures_by_stage.entry(failure.stage).or_insert(0) += 1;
             }
-            VariantResult::Skipped { reason, id } => {
-                report!("SKIP".on_yellow(), path.display(), id);
-                println!("\t|> reason: {}", reason);
-                skipped += 1;
-            }
+            VariantResult::Skipped { .. } => skipped += 1,
         }
     }
 
@@ -112,6 +148,29 @@ async fn conformance_test_runner() -> anyhow::Result<()> {
         )
         .bold()
     );
+    if !failures_by_stage.is_empty() {
+        let by_stage = failures_by_stage
+            .iter()
+            .map(|(stage, n)| format!("{} {}", n, stage))
+            .join(", ");
+        println!("failures by stage: {}", by_stage);
+    }
+    println!("executed {} successful variants: {}", succeeded, total);
+    if let Some(cache) = &cache {
+        println!("skipped {} vectors that already passed", cached.get());
+        for (path, _) in vectors_passed.iter().filter(|(_, passed)| **passed) {
+            cache.record_pass(path)?;
+        }
+    }
+
+    slowest.sort_by_key(|(stats, _)| std::cmp::Reverse(stats.wall_time));
+    slowest.truncate(*SLOWEST_VARIANTS);
+    if !slowest.is_empty() {
+        println!("slowest variants:");
+        for (stats, name) in &slowest {
+            println!("\t{}: {}", name, stats);
+        }
+    }
 
     if failed > 0 {
         Err(anyhow!("some vectors failed"))
@@ -120,6 +179,74 @@ async fn conformance_test_runner() -> anyhow::Result<()> {
     }
 }
 
+fn print_result(path: &Path, res: &VariantResult) {
+    match res {
+        VariantResult::Ok { id, stats } => {
+            report!("OK".on_green(), path.display(), id);
+            println!("\t|> {}", stats);
+        }
+        VariantResult::Failed { failure, id } => {
+            report!("FAIL".white().on_red(), path.display(), id);
+            println!("\t|> {}", failure);
+        }
+        VariantResult::Skipped { reason, id } => {
+            report!("SKIP".on_yellow(), path.display(), id);
+            println!("\t|> reason: {}", reason);
+        }
+    }
+}
+
+/// Watches a directory of vectors, and re-runs every vector created or modified in it, until
+/// interrupted. Vectors that fail to load are reported, and don't stop the watch.
+async fn watch_vectors(dir: PathBuf, engine: Engine) -> anyhow::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    // Editors often write a file in several steps: wait for them to settle.
+    let mut watcher = notify::watcher(tx, Duration::from_millis(500))?;
+    watcher
+        .watch(&dir, RecursiveMode::Recursive)
+        .with_context(|| format!("failed to watch {}", dir.display()))?;
+    println!("watching {} for changed vectors", dir.display());
+
+    loop {
+        let path = match rx.recv()? {
+            DebouncedEvent::Create(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Rename(_, path) => path,
+            DebouncedEvent::Error(e, _) => return Err(e.into()),
+            _ => continue,
+        };
+        if !is_runnable_path(&path) {
+            continue;
+        }
+
+        println!();
+        let variants = match run_vector(path.clone(), engine.clone()).await {
+            Ok(variants) => variants,
+            Err(e) => {
+                report!("FAIL".white().on_red(), path.display(), "-");
+                println!("\t|> failed to load the vector: {:#}", e);
+                continue;
+            }
+        };
+        let (mut passed, mut total) = (0, 0);
+        for variant in variants {
+            let res = match variant.await {
+                Ok(res) => res,
+                Err(e) => VariantResult::Failed {
+                    id: "-".to_owned(),
+                    failure: VariantFailure::new(FailureStage::Execute, e),
+                },
+            };
+            print_result(&path, &res);
+            total += 1;
+            if let VariantResult::Ok { .. } = res {
+                passed += 1;
+            }
+        }
+        println!("{}", format!("{}/{} variants passed", passed, total).bold());
+    }
+}
+
 /// Runs a single test vector and returns a list of VectorResults,
 /// one per variant.
 async fn run_vector(
@@ -157,7 +284,8 @@ async fn run_vector(
 
     match class {
         "message" => {
-            let v: MessageVector = serde_json::from_str(&vector_json)?;
+            let mut v: MessageVector = serde_json::from_str(&vector_json)?;
+            v.lotus_traces = load_lotus_traces(&path)?;
             let skip = !v.selector.as_ref().map_or(true, Selector::supported);
             if skip {
                 Ok(either::Either::Left(